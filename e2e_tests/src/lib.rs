@@ -13,7 +13,7 @@ use parsec_client::auth::AuthenticationData;
 use parsec_client::core::basic_client::BasicClient;
 use parsec_client::core::interface::operations::list_providers::ProviderInfo;
 use parsec_client::core::interface::operations::psa_algorithm::{
-    Algorithm, AsymmetricSignature, Hash,
+    Aead, Algorithm, AsymmetricEncryption, AsymmetricSignature, Cipher, Hash,
 };
 use parsec_client::core::interface::operations::psa_key_attributes::{
     Attributes, Lifetime, Policy, Type, UsageFlags,
@@ -284,6 +284,100 @@ impl TestClient {
         )
     }
 
+    /// Encrypts a short message with a public key. The salt is only used with RSA OAEP, as its
+    /// label.
+    pub fn asymmetric_encrypt_message(
+        &mut self,
+        key_name: String,
+        alg: AsymmetricEncryption,
+        plaintext: &[u8],
+        salt: Option<&[u8]>,
+    ) -> Result<Vec<u8>> {
+        self.basic_client
+            .psa_asymmetric_encrypt(key_name, alg, plaintext, salt)
+            .map_err(convert_error)
+    }
+
+    /// Decrypts a short message with a private key.
+    pub fn asymmetric_decrypt_message(
+        &mut self,
+        key_name: String,
+        alg: AsymmetricEncryption,
+        ciphertext: &[u8],
+        salt: Option<&[u8]>,
+    ) -> Result<Vec<u8>> {
+        self.basic_client
+            .psa_asymmetric_decrypt(key_name, alg, ciphertext, salt)
+            .map_err(convert_error)
+    }
+
+    /// Encrypts and authenticates a short message with a symmetric key. The ciphertext is followed
+    /// by the authentication tag.
+    pub fn aead_encrypt_message(
+        &mut self,
+        key_name: String,
+        alg: Aead,
+        nonce: &[u8],
+        additional_data: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>> {
+        self.basic_client
+            .psa_aead_encrypt(key_name, alg, nonce, additional_data, plaintext)
+            .map_err(convert_error)
+    }
+
+    /// Authenticates and decrypts a short message with a symmetric key.
+    pub fn aead_decrypt_message(
+        &mut self,
+        key_name: String,
+        alg: Aead,
+        nonce: &[u8],
+        additional_data: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>> {
+        self.basic_client
+            .psa_aead_decrypt(key_name, alg, nonce, additional_data, ciphertext)
+            .map_err(convert_error)
+    }
+
+    /// Encrypts a short message with a symmetric key. The ciphertext starts with the generated IV.
+    pub fn cipher_encrypt_message(
+        &mut self,
+        key_name: String,
+        alg: Cipher,
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>> {
+        self.basic_client
+            .psa_cipher_encrypt(key_name, alg, plaintext)
+            .map_err(convert_error)
+    }
+
+    /// Decrypts a short message, starting with its IV, with a symmetric key.
+    pub fn cipher_decrypt_message(
+        &mut self,
+        key_name: String,
+        alg: Cipher,
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>> {
+        self.basic_client
+            .psa_cipher_decrypt(key_name, alg, ciphertext)
+            .map_err(convert_error)
+    }
+
+    /// Computes the hash of a message.
+    pub fn hash_compute(&mut self, alg: Hash, input: &[u8]) -> Result<Vec<u8>> {
+        self.basic_client
+            .psa_hash_compute(alg, input)
+            .map_err(convert_error)
+    }
+
+    /// Computes the hash of a message and compares it with a reference hash.
+    pub fn hash_compare(&mut self, alg: Hash, input: &[u8], hash: &[u8]) -> Result<()> {
+        self.basic_client
+            .psa_hash_compare(alg, input, hash)
+            .map_err(convert_error)
+    }
+
     /// Lists the provider available for the Parsec service.
     pub fn list_providers(&mut self) -> Result<Vec<ProviderInfo>> {
         self.basic_client.list_providers().map_err(convert_error)
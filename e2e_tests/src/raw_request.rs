@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 use parsec_client::core::interface::requests::request::RawHeader;
 use parsec_client::core::interface::requests::{Response, Result};
+use std::env;
 use std::io::Write;
 use std::os::unix::net::UnixStream;
 use std::thread;
@@ -13,9 +14,17 @@ const MAX_BODY_SIZE: usize = 1 << 31;
 #[derive(Copy, Clone, Debug)]
 pub struct RawRequestClient;
 
-static SOCKET_PATH: &str = "/tmp/security-daemon-socket";
+/// Socket path used if `SOCKET_PATH_ENV_VAR` is not set, equal to the default of the service.
+static DEFAULT_SOCKET_PATH: &str = "/tmp/security-daemon-socket";
+/// Environment variable which can be set to the `socket_path` of the service configuration.
+static SOCKET_PATH_ENV_VAR: &str = "PARSEC_SOCKET_PATH";
 const TIMEOUT: Duration = Duration::from_secs(5);
 
+/// Path of the socket to connect to.
+fn socket_path() -> String {
+    env::var(SOCKET_PATH_ENV_VAR).unwrap_or_else(|_| DEFAULT_SOCKET_PATH.to_string())
+}
+
 #[allow(clippy::new_without_default)]
 impl RawRequestClient {
     /// Send a raw request.
@@ -23,10 +32,11 @@ impl RawRequestClient {
     /// Send a raw request header and a collection of bytes.
     pub fn send_raw_request(&mut self, request_hdr: RawHeader, bytes: Vec<u8>) -> Result<Response> {
         // Try to connect once, wait for a timeout until trying again.
-        let mut stream = UnixStream::connect(SOCKET_PATH);
+        let socket_path = socket_path();
+        let mut stream = UnixStream::connect(&socket_path);
         if stream.is_err() {
             thread::sleep(TIMEOUT);
-            stream = UnixStream::connect(SOCKET_PATH);
+            stream = UnixStream::connect(&socket_path);
         }
         let mut stream = stream.expect("Failed to connect to Unix socket");
 
@@ -34,9 +34,27 @@ fn list_opcodes() {
     let _ = crypto_providers_opcodes.insert(Opcode::PsaImportKey);
     let _ = crypto_providers_opcodes.insert(Opcode::PsaExportPublicKey);
 
+    // The TPM provider does not support the encryption and hash operations.
+    let mut encryption_providers_opcodes = crypto_providers_opcodes.clone();
+    let _ = encryption_providers_opcodes.insert(Opcode::PsaAsymmetricEncrypt);
+    let _ = encryption_providers_opcodes.insert(Opcode::PsaAsymmetricDecrypt);
+    let _ = encryption_providers_opcodes.insert(Opcode::PsaAeadEncrypt);
+    let _ = encryption_providers_opcodes.insert(Opcode::PsaAeadDecrypt);
+    let _ = encryption_providers_opcodes.insert(Opcode::PsaHashCompute);
+    let _ = encryption_providers_opcodes.insert(Opcode::PsaHashCompare);
+
+    // Only the Mbed Crypto provider supports the cipher operations.
+    let mut mbed_crypto_provider_opcodes = encryption_providers_opcodes.clone();
+    let _ = mbed_crypto_provider_opcodes.insert(Opcode::PsaCipherEncrypt);
+    let _ = mbed_crypto_provider_opcodes.insert(Opcode::PsaCipherDecrypt);
+
     let _ = core_provider_opcodes.insert(Opcode::Ping);
     let _ = core_provider_opcodes.insert(Opcode::ListProviders);
     let _ = core_provider_opcodes.insert(Opcode::ListOpcodes);
+    let _ = core_provider_opcodes.insert(Opcode::ListAuthenticators);
+    let _ = core_provider_opcodes.insert(Opcode::ListKeys);
+    let _ = core_provider_opcodes.insert(Opcode::ListClients);
+    let _ = core_provider_opcodes.insert(Opcode::DeleteClient);
 
     assert_eq!(
         client
@@ -54,13 +72,13 @@ fn list_opcodes() {
         client
             .list_opcodes(ProviderID::Pkcs11)
             .expect("list providers failed"),
-        crypto_providers_opcodes
+        encryption_providers_opcodes
     );
     assert_eq!(
         client
             .list_opcodes(ProviderID::MbedCrypto)
             .expect("list providers failed"),
-        crypto_providers_opcodes
+        mbed_crypto_provider_opcodes
     );
 }
 
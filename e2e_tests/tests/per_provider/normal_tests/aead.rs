@@ -0,0 +1,253 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+use e2e_tests::TestClient;
+use parsec_client::core::interface::operations::psa_algorithm::*;
+use parsec_client::core::interface::operations::psa_key_attributes::*;
+use parsec_client::core::interface::requests::{ProviderID, ResponseStatus, Result};
+
+const PLAINTEXT_MESSAGE: [u8; 20] = [
+    0x69, 0x3E, 0xDB, 0x1B, 0x22, 0x79, 0x03, 0xF4, 0xC0, 0xBF, 0xD6, 0x91, 0x76, 0x37, 0x84, 0xA2,
+    0x94, 0x8E, 0x92, 0x50,
+];
+const NONCE: [u8; 12] = [
+    0xCA, 0xFE, 0xBA, 0xBE, 0xFA, 0xCE, 0xDB, 0xAD, 0xDE, 0xCA, 0xF8, 0x88,
+];
+const ADDITIONAL_DATA: [u8; 8] = [0xFE, 0xED, 0xFA, 0xCE, 0xDE, 0xAD, 0xBE, 0xEF];
+
+const GCM: Aead = Aead::AeadWithDefaultLengthTag(AeadWithDefaultLengthTag::Gcm);
+const CCM: Aead = Aead::AeadWithDefaultLengthTag(AeadWithDefaultLengthTag::Ccm);
+
+fn aes_key_attributes(alg: Aead, encrypt: bool, decrypt: bool) -> Attributes {
+    Attributes {
+        lifetime: Lifetime::Persistent,
+        key_type: Type::Aes,
+        bits: 128,
+        policy: Policy {
+            usage_flags: UsageFlags {
+                sign_hash: false,
+                verify_hash: false,
+                sign_message: false,
+                verify_message: false,
+                export: false,
+                encrypt,
+                decrypt,
+                cache: false,
+                copy: false,
+                derive: false,
+            },
+            permitted_algorithms: Algorithm::Aead(alg),
+        },
+    }
+}
+
+/// Creates a client for the providers supporting the AEAD operations, or returns `None` for the
+/// TPM provider.
+fn aead_client() -> Option<TestClient> {
+    let client = TestClient::new();
+    if client.provider().unwrap() == ProviderID::Tpm {
+        None
+    } else {
+        Some(client)
+    }
+}
+
+fn encrypt_and_decrypt(client: &mut TestClient, key_name: &str, alg: Aead) -> Result<()> {
+    let key_name = String::from(key_name);
+
+    client.generate_key(key_name.clone(), aes_key_attributes(alg, true, true))?;
+
+    let ciphertext = client.aead_encrypt_message(
+        key_name.clone(),
+        alg,
+        &NONCE,
+        &ADDITIONAL_DATA,
+        &PLAINTEXT_MESSAGE,
+    )?;
+    // The ciphertext is followed by a tag of 16 bytes.
+    assert_eq!(ciphertext.len(), PLAINTEXT_MESSAGE.len() + 16);
+    assert_ne!(
+        &ciphertext[..PLAINTEXT_MESSAGE.len()],
+        &PLAINTEXT_MESSAGE[..]
+    );
+
+    let plaintext =
+        client.aead_decrypt_message(key_name, alg, &NONCE, &ADDITIONAL_DATA, &ciphertext)?;
+    assert_eq!(&plaintext[..], &PLAINTEXT_MESSAGE[..]);
+    Ok(())
+}
+
+#[test]
+fn aead_encrypt_and_decrypt_gcm() -> Result<()> {
+    let mut client = match aead_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+    encrypt_and_decrypt(&mut client, "aead_encrypt_and_decrypt_gcm", GCM)
+}
+
+#[test]
+fn aead_encrypt_and_decrypt_ccm() -> Result<()> {
+    let mut client = TestClient::new();
+
+    // The PKCS 11 provider only supports GCM.
+    if client.provider().unwrap() != ProviderID::MbedCrypto {
+        return Ok(());
+    }
+    encrypt_and_decrypt(&mut client, "aead_encrypt_and_decrypt_ccm", CCM)
+}
+
+#[test]
+fn aead_encrypt_gcm_test_vector() -> Result<()> {
+    let key_name = String::from("aead_encrypt_gcm_test_vector");
+    let mut client = match aead_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    // Test case 2 of the GCM specification: a zero key, nonce and plaintext block.
+    let ciphertext_and_tag = [
+        0x03, 0x88, 0xDA, 0xCE, 0x60, 0xB6, 0xA3, 0x92, 0xF3, 0x28, 0xC2, 0xB9, 0x71, 0xB2, 0xFE,
+        0x78, 0xAB, 0x6E, 0x47, 0xD4, 0x2C, 0xEC, 0x13, 0xBD, 0xF5, 0x3A, 0x67, 0xB2, 0x12, 0x57,
+        0xBD, 0xDF,
+    ];
+    client.import_key(
+        key_name.clone(),
+        aes_key_attributes(GCM, true, true),
+        vec![0; 16],
+    )?;
+
+    let ciphertext = client.aead_encrypt_message(key_name.clone(), GCM, &[0; 12], &[], &[0; 16])?;
+    assert_eq!(&ciphertext[..], &ciphertext_and_tag[..]);
+    let plaintext =
+        client.aead_decrypt_message(key_name, GCM, &[0; 12], &[], &ciphertext_and_tag)?;
+    assert_eq!(&plaintext[..], &[0; 16][..]);
+    Ok(())
+}
+
+#[test]
+fn aead_encrypt_and_decrypt_shortened_tag() -> Result<()> {
+    let key_name = String::from("aead_encrypt_and_decrypt_shortened_tag");
+    let alg = Aead::AeadWithShortenedTag {
+        aead_alg: AeadWithDefaultLengthTag::Gcm,
+        tag_length: 12,
+    };
+    let mut client = match aead_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    client.generate_key(key_name.clone(), aes_key_attributes(alg, true, true))?;
+
+    let ciphertext = client.aead_encrypt_message(
+        key_name.clone(),
+        alg,
+        &NONCE,
+        &ADDITIONAL_DATA,
+        &PLAINTEXT_MESSAGE,
+    )?;
+    assert_eq!(ciphertext.len(), PLAINTEXT_MESSAGE.len() + 12);
+    let plaintext =
+        client.aead_decrypt_message(key_name, alg, &NONCE, &ADDITIONAL_DATA, &ciphertext)?;
+    assert_eq!(&plaintext[..], &PLAINTEXT_MESSAGE[..]);
+    Ok(())
+}
+
+#[test]
+fn aead_decrypt_tampered_ciphertext() -> Result<()> {
+    let key_name = String::from("aead_decrypt_tampered_ciphertext");
+    let mut client = match aead_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    client.generate_key(key_name.clone(), aes_key_attributes(GCM, true, true))?;
+
+    let mut ciphertext = client.aead_encrypt_message(
+        key_name.clone(),
+        GCM,
+        &NONCE,
+        &ADDITIONAL_DATA,
+        &PLAINTEXT_MESSAGE,
+    )?;
+    ciphertext[4] ^= 0xff;
+    let _ = client
+        .aead_decrypt_message(key_name.clone(), GCM, &NONCE, &ADDITIONAL_DATA, &ciphertext)
+        .expect_err("A tampered ciphertext should not be authenticated.");
+    ciphertext[4] ^= 0xff;
+    let _ = client
+        .aead_decrypt_message(key_name, GCM, &NONCE, &[], &ciphertext)
+        .expect_err("Different additional data should not be authenticated.");
+    Ok(())
+}
+
+#[test]
+fn aead_encrypt_no_key() {
+    let key_name = String::from("aead_encrypt_no_key");
+    let mut client = match aead_client() {
+        Some(client) => client,
+        None => return,
+    };
+
+    let status = client
+        .aead_encrypt_message(key_name, GCM, &NONCE, &ADDITIONAL_DATA, &PLAINTEXT_MESSAGE)
+        .expect_err("Key should not exist.");
+    assert_eq!(status, ResponseStatus::PsaErrorDoesNotExist);
+}
+
+#[test]
+fn aead_encrypt_not_permitted() -> Result<()> {
+    let key_name = String::from("aead_encrypt_not_permitted");
+    let mut client = match aead_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    client.generate_key(key_name.clone(), aes_key_attributes(GCM, false, true))?;
+
+    let status = client
+        .aead_encrypt_message(key_name, GCM, &NONCE, &ADDITIONAL_DATA, &PLAINTEXT_MESSAGE)
+        .unwrap_err();
+    assert_eq!(status, ResponseStatus::PsaErrorNotPermitted);
+    Ok(())
+}
+
+#[test]
+fn aead_decrypt_not_permitted() -> Result<()> {
+    let key_name = String::from("aead_decrypt_not_permitted");
+    let mut client = match aead_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    client.generate_key(key_name.clone(), aes_key_attributes(GCM, true, false))?;
+
+    let ciphertext = client.aead_encrypt_message(
+        key_name.clone(),
+        GCM,
+        &NONCE,
+        &ADDITIONAL_DATA,
+        &PLAINTEXT_MESSAGE,
+    )?;
+    let status = client
+        .aead_decrypt_message(key_name, GCM, &NONCE, &ADDITIONAL_DATA, &ciphertext)
+        .unwrap_err();
+    assert_eq!(status, ResponseStatus::PsaErrorNotPermitted);
+    Ok(())
+}
+
+#[test]
+fn aead_encrypt_wrong_algorithm() -> Result<()> {
+    let key_name = String::from("aead_encrypt_wrong_algorithm");
+    let mut client = match aead_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    client.generate_key(key_name.clone(), aes_key_attributes(GCM, true, true))?;
+
+    let status = client
+        .aead_encrypt_message(key_name, CCM, &NONCE, &ADDITIONAL_DATA, &PLAINTEXT_MESSAGE)
+        .unwrap_err();
+    assert_eq!(status, ResponseStatus::PsaErrorNotPermitted);
+    Ok(())
+}
@@ -0,0 +1,260 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+use e2e_tests::TestClient;
+use parsec_client::core::interface::operations::psa_algorithm::*;
+use parsec_client::core::interface::operations::psa_key_attributes::*;
+use parsec_client::core::interface::requests::{ProviderID, ResponseStatus, Result};
+
+const PLAINTEXT_MESSAGE: [u8; 32] = [
+    0x69, 0x3E, 0xDB, 0x1B, 0x22, 0x79, 0x03, 0xF4, 0xC0, 0xBF, 0xD6, 0x91, 0x76, 0x37, 0x84, 0xA2,
+    0x94, 0x8E, 0x92, 0x50, 0x35, 0xC2, 0x8C, 0x5C, 0x3C, 0xCA, 0xFE, 0x18, 0xE8, 0x81, 0x37, 0x78,
+];
+
+const OAEP_SHA256: AsymmetricEncryption = AsymmetricEncryption::RsaOaep {
+    hash_alg: Hash::Sha256,
+};
+
+fn rsa_encryption_key_attributes(
+    key_type: Type,
+    alg: AsymmetricEncryption,
+    encrypt: bool,
+    decrypt: bool,
+) -> Attributes {
+    Attributes {
+        lifetime: Lifetime::Persistent,
+        key_type,
+        bits: 1024,
+        policy: Policy {
+            usage_flags: UsageFlags {
+                sign_hash: false,
+                verify_hash: false,
+                sign_message: false,
+                verify_message: false,
+                export: false,
+                encrypt,
+                decrypt,
+                cache: false,
+                copy: false,
+                derive: false,
+            },
+            permitted_algorithms: Algorithm::AsymmetricEncryption(alg),
+        },
+    }
+}
+
+/// Creates a client for the providers supporting the asymmetric encryption operations, or returns
+/// `None` for the TPM provider.
+fn encryption_client() -> Option<TestClient> {
+    let client = TestClient::new();
+    if client.provider().unwrap() == ProviderID::Tpm {
+        None
+    } else {
+        Some(client)
+    }
+}
+
+fn encrypt_and_decrypt(key_name: &str, alg: AsymmetricEncryption) -> Result<()> {
+    let key_name = String::from(key_name);
+    let mut client = match encryption_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    client.generate_key(
+        key_name.clone(),
+        rsa_encryption_key_attributes(Type::RsaKeyPair, alg, true, true),
+    )?;
+
+    let ciphertext =
+        client.asymmetric_encrypt_message(key_name.clone(), alg, &PLAINTEXT_MESSAGE, None)?;
+    assert_eq!(ciphertext.len(), 128);
+    assert_ne!(
+        &ciphertext[..PLAINTEXT_MESSAGE.len()],
+        &PLAINTEXT_MESSAGE[..]
+    );
+
+    let plaintext = client.asymmetric_decrypt_message(key_name, alg, &ciphertext, None)?;
+    assert_eq!(&plaintext[..], &PLAINTEXT_MESSAGE[..]);
+    Ok(())
+}
+
+#[test]
+fn asym_encrypt_and_decrypt_rsa_pkcs1v15() -> Result<()> {
+    encrypt_and_decrypt(
+        "asym_encrypt_and_decrypt_rsa_pkcs1v15",
+        AsymmetricEncryption::RsaPkcs1v15Crypt,
+    )
+}
+
+#[test]
+fn asym_encrypt_and_decrypt_rsa_oaep() -> Result<()> {
+    encrypt_and_decrypt("asym_encrypt_and_decrypt_rsa_oaep", OAEP_SHA256)
+}
+
+#[test]
+fn asym_encrypt_no_key() {
+    let key_name = String::from("asym_encrypt_no_key");
+    let mut client = match encryption_client() {
+        Some(client) => client,
+        None => return,
+    };
+
+    let status = client
+        .asymmetric_encrypt_message(key_name, OAEP_SHA256, &PLAINTEXT_MESSAGE, None)
+        .expect_err("Key should not exist.");
+    assert_eq!(status, ResponseStatus::PsaErrorDoesNotExist);
+}
+
+#[test]
+fn asym_encrypt_with_public_key() -> Result<()> {
+    let key_name = String::from("asym_encrypt_with_public_key");
+    let public_key_name = String::from("asym_encrypt_with_public_key_public");
+    let mut client = match encryption_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    client.generate_key(
+        key_name.clone(),
+        rsa_encryption_key_attributes(Type::RsaKeyPair, OAEP_SHA256, false, true),
+    )?;
+    let public_key = client.export_public_key(key_name.clone())?;
+    client.import_key(
+        public_key_name.clone(),
+        rsa_encryption_key_attributes(Type::RsaPublicKey, OAEP_SHA256, true, false),
+        public_key,
+    )?;
+
+    let ciphertext = client.asymmetric_encrypt_message(
+        public_key_name,
+        OAEP_SHA256,
+        &PLAINTEXT_MESSAGE,
+        None,
+    )?;
+    let plaintext = client.asymmetric_decrypt_message(key_name, OAEP_SHA256, &ciphertext, None)?;
+    assert_eq!(&plaintext[..], &PLAINTEXT_MESSAGE[..]);
+    Ok(())
+}
+
+#[test]
+fn asym_encrypt_not_permitted() -> Result<()> {
+    let key_name = String::from("asym_encrypt_not_permitted");
+    let mut client = match encryption_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    client.generate_key(
+        key_name.clone(),
+        rsa_encryption_key_attributes(Type::RsaKeyPair, OAEP_SHA256, false, true),
+    )?;
+
+    let status = client
+        .asymmetric_encrypt_message(key_name, OAEP_SHA256, &PLAINTEXT_MESSAGE, None)
+        .unwrap_err();
+    assert_eq!(status, ResponseStatus::PsaErrorNotPermitted);
+    Ok(())
+}
+
+#[test]
+fn asym_decrypt_not_permitted() -> Result<()> {
+    let key_name = String::from("asym_decrypt_not_permitted");
+    let mut client = match encryption_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    client.generate_key(
+        key_name.clone(),
+        rsa_encryption_key_attributes(Type::RsaKeyPair, OAEP_SHA256, true, false),
+    )?;
+
+    let ciphertext = client.asymmetric_encrypt_message(
+        key_name.clone(),
+        OAEP_SHA256,
+        &PLAINTEXT_MESSAGE,
+        None,
+    )?;
+    let status = client
+        .asymmetric_decrypt_message(key_name, OAEP_SHA256, &ciphertext, None)
+        .unwrap_err();
+    assert_eq!(status, ResponseStatus::PsaErrorNotPermitted);
+    Ok(())
+}
+
+#[test]
+fn asym_encrypt_wrong_algorithm() -> Result<()> {
+    let key_name = String::from("asym_encrypt_wrong_algorithm");
+    let mut client = match encryption_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    client.generate_key(
+        key_name.clone(),
+        rsa_encryption_key_attributes(Type::RsaKeyPair, OAEP_SHA256, true, true),
+    )?;
+
+    let status = client
+        .asymmetric_encrypt_message(
+            key_name,
+            AsymmetricEncryption::RsaPkcs1v15Crypt,
+            &PLAINTEXT_MESSAGE,
+            None,
+        )
+        .unwrap_err();
+    assert_eq!(status, ResponseStatus::PsaErrorNotPermitted);
+    Ok(())
+}
+
+#[test]
+fn asym_encrypt_rsa_pkcs1v15_with_salt() -> Result<()> {
+    let key_name = String::from("asym_encrypt_rsa_pkcs1v15_with_salt");
+    let alg = AsymmetricEncryption::RsaPkcs1v15Crypt;
+    let mut client = match encryption_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    client.generate_key(
+        key_name.clone(),
+        rsa_encryption_key_attributes(Type::RsaKeyPair, alg, true, true),
+    )?;
+
+    let status = client
+        .asymmetric_encrypt_message(key_name, alg, &PLAINTEXT_MESSAGE, Some(&[0x5a; 16]))
+        .unwrap_err();
+    assert_eq!(status, ResponseStatus::PsaErrorInvalidArgument);
+    Ok(())
+}
+
+#[test]
+fn asym_encrypt_and_decrypt_rsa_oaep_with_label() -> Result<()> {
+    let key_name = String::from("asym_encrypt_and_decrypt_rsa_oaep_with_label");
+    let label = [0x5a; 16];
+    let mut client = TestClient::new();
+
+    // Not all PKCS 11 tokens support OAEP labels.
+    if client.provider().unwrap() != ProviderID::MbedCrypto {
+        return Ok(());
+    }
+
+    client.generate_key(
+        key_name.clone(),
+        rsa_encryption_key_attributes(Type::RsaKeyPair, OAEP_SHA256, true, true),
+    )?;
+
+    let ciphertext = client.asymmetric_encrypt_message(
+        key_name.clone(),
+        OAEP_SHA256,
+        &PLAINTEXT_MESSAGE,
+        Some(&label),
+    )?;
+    let _ = client
+        .asymmetric_decrypt_message(key_name.clone(), OAEP_SHA256, &ciphertext, None)
+        .expect_err("Decryption without the label should fail.");
+    let plaintext =
+        client.asymmetric_decrypt_message(key_name, OAEP_SHA256, &ciphertext, Some(&label))?;
+    assert_eq!(&plaintext[..], &PLAINTEXT_MESSAGE[..]);
+    Ok(())
+}
@@ -0,0 +1,239 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+use e2e_tests::TestClient;
+use parsec_client::core::interface::operations::psa_algorithm::*;
+use parsec_client::core::interface::operations::psa_key_attributes::*;
+use parsec_client::core::interface::requests::{ProviderID, ResponseStatus, Result};
+
+const PLAINTEXT_MESSAGE: [u8; 20] = [
+    0x69, 0x3E, 0xDB, 0x1B, 0x22, 0x79, 0x03, 0xF4, 0xC0, 0xBF, 0xD6, 0x91, 0x76, 0x37, 0x84, 0xA2,
+    0x94, 0x8E, 0x92, 0x50,
+];
+
+// Key, IV or initial counter and first blocks of the AES-128 examples of NIST SP 800-38A.
+const KEY: [u8; 16] = [
+    0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6, 0xAB, 0xF7, 0x15, 0x88, 0x09, 0xCF, 0x4F, 0x3C,
+];
+const PLAINTEXT_BLOCK: [u8; 16] = [
+    0x6B, 0xC1, 0xBE, 0xE2, 0x2E, 0x40, 0x9F, 0x96, 0xE9, 0x3D, 0x7E, 0x11, 0x73, 0x93, 0x17, 0x2A,
+];
+
+fn aes_key_attributes(alg: Cipher, encrypt: bool, decrypt: bool) -> Attributes {
+    Attributes {
+        lifetime: Lifetime::Persistent,
+        key_type: Type::Aes,
+        bits: 128,
+        policy: Policy {
+            usage_flags: UsageFlags {
+                sign_hash: false,
+                verify_hash: false,
+                sign_message: false,
+                verify_message: false,
+                export: false,
+                encrypt,
+                decrypt,
+                cache: false,
+                copy: false,
+                derive: false,
+            },
+            permitted_algorithms: Algorithm::Cipher(alg),
+        },
+    }
+}
+
+/// Creates a client for the Mbed Crypto provider, the only one supporting the cipher operations,
+/// or returns `None`.
+fn cipher_client() -> Option<TestClient> {
+    let client = TestClient::new();
+    if client.provider().unwrap() == ProviderID::MbedCrypto {
+        Some(client)
+    } else {
+        None
+    }
+}
+
+/// Encrypts and decrypts the message, checking that the ciphertext is the IV followed by the
+/// encrypted message, of the given length.
+fn encrypt_and_decrypt(
+    key_name: &str,
+    alg: Cipher,
+    plaintext: &[u8],
+    encrypted_length: usize,
+) -> Result<()> {
+    let key_name = String::from(key_name);
+    let mut client = match cipher_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    client.generate_key(key_name.clone(), aes_key_attributes(alg, true, true))?;
+
+    let ciphertext = client.cipher_encrypt_message(key_name.clone(), alg, plaintext)?;
+    assert_eq!(ciphertext.len(), 16 + encrypted_length);
+    // A new IV is generated for each encryption.
+    assert_ne!(
+        client.cipher_encrypt_message(key_name.clone(), alg, plaintext)?,
+        ciphertext
+    );
+
+    let decrypted = client.cipher_decrypt_message(key_name, alg, &ciphertext)?;
+    assert_eq!(&decrypted[..], plaintext);
+    Ok(())
+}
+
+#[test]
+fn cipher_encrypt_and_decrypt_ctr() -> Result<()> {
+    encrypt_and_decrypt(
+        "cipher_encrypt_and_decrypt_ctr",
+        Cipher::Ctr,
+        &PLAINTEXT_MESSAGE,
+        PLAINTEXT_MESSAGE.len(),
+    )
+}
+
+#[test]
+fn cipher_encrypt_and_decrypt_cbc_no_padding() -> Result<()> {
+    encrypt_and_decrypt(
+        "cipher_encrypt_and_decrypt_cbc_no_padding",
+        Cipher::CbcNoPadding,
+        &PLAINTEXT_MESSAGE[..16],
+        16,
+    )
+}
+
+#[test]
+fn cipher_encrypt_and_decrypt_cbc_pkcs7() -> Result<()> {
+    // The message is padded to the next block.
+    encrypt_and_decrypt(
+        "cipher_encrypt_and_decrypt_cbc_pkcs7",
+        Cipher::CbcPkcs7,
+        &PLAINTEXT_MESSAGE,
+        32,
+    )
+}
+
+fn decrypt_test_vector(key_name: &str, alg: Cipher, ciphertext: &[u8]) -> Result<()> {
+    let key_name = String::from(key_name);
+    let mut client = match cipher_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    client.import_key(
+        key_name.clone(),
+        aes_key_attributes(alg, false, true),
+        KEY.to_vec(),
+    )?;
+
+    let plaintext = client.cipher_decrypt_message(key_name, alg, ciphertext)?;
+    assert_eq!(&plaintext[..], &PLAINTEXT_BLOCK[..]);
+    Ok(())
+}
+
+#[test]
+fn cipher_decrypt_cbc_test_vector() -> Result<()> {
+    let iv_and_ciphertext = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E,
+        0x0F, 0x76, 0x49, 0xAB, 0xAC, 0x81, 0x19, 0xB2, 0x46, 0xCE, 0xE9, 0x8E, 0x9B, 0x12, 0xE9,
+        0x19, 0x7D,
+    ];
+    decrypt_test_vector(
+        "cipher_decrypt_cbc_test_vector",
+        Cipher::CbcNoPadding,
+        &iv_and_ciphertext,
+    )
+}
+
+#[test]
+fn cipher_decrypt_ctr_test_vector() -> Result<()> {
+    let counter_and_ciphertext = [
+        0xF0, 0xF1, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8, 0xF9, 0xFA, 0xFB, 0xFC, 0xFD, 0xFE,
+        0xFF, 0x87, 0x4D, 0x61, 0x91, 0xB6, 0x20, 0xE3, 0x26, 0x1B, 0xEF, 0x68, 0x64, 0x99, 0x0D,
+        0xB6, 0xCE,
+    ];
+    decrypt_test_vector(
+        "cipher_decrypt_ctr_test_vector",
+        Cipher::Ctr,
+        &counter_and_ciphertext,
+    )
+}
+
+#[test]
+fn cipher_encrypt_cbc_no_padding_partial_block() -> Result<()> {
+    let key_name = String::from("cipher_encrypt_cbc_no_padding_partial_block");
+    let mut client = match cipher_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    client.generate_key(
+        key_name.clone(),
+        aes_key_attributes(Cipher::CbcNoPadding, true, true),
+    )?;
+
+    let status = client
+        .cipher_encrypt_message(key_name, Cipher::CbcNoPadding, &PLAINTEXT_MESSAGE)
+        .unwrap_err();
+    assert_eq!(status, ResponseStatus::PsaErrorInvalidArgument);
+    Ok(())
+}
+
+#[test]
+fn cipher_decrypt_shorter_than_iv() -> Result<()> {
+    let key_name = String::from("cipher_decrypt_shorter_than_iv");
+    let mut client = match cipher_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    client.generate_key(
+        key_name.clone(),
+        aes_key_attributes(Cipher::Ctr, true, true),
+    )?;
+
+    let status = client
+        .cipher_decrypt_message(key_name, Cipher::Ctr, &PLAINTEXT_MESSAGE[..8])
+        .unwrap_err();
+    assert_eq!(status, ResponseStatus::PsaErrorInvalidArgument);
+    Ok(())
+}
+
+#[test]
+fn cipher_encrypt_not_permitted() -> Result<()> {
+    let key_name = String::from("cipher_encrypt_not_permitted");
+    let mut client = match cipher_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    client.generate_key(
+        key_name.clone(),
+        aes_key_attributes(Cipher::Ctr, false, true),
+    )?;
+
+    let status = client
+        .cipher_encrypt_message(key_name, Cipher::Ctr, &PLAINTEXT_MESSAGE)
+        .unwrap_err();
+    assert_eq!(status, ResponseStatus::PsaErrorNotPermitted);
+    Ok(())
+}
+
+#[test]
+fn cipher_encrypt_wrong_algorithm() -> Result<()> {
+    let key_name = String::from("cipher_encrypt_wrong_algorithm");
+    let mut client = match cipher_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    client.generate_key(
+        key_name.clone(),
+        aes_key_attributes(Cipher::Ctr, true, true),
+    )?;
+
+    let status = client
+        .cipher_encrypt_message(key_name, Cipher::CbcPkcs7, &PLAINTEXT_MESSAGE)
+        .unwrap_err();
+    assert_eq!(status, ResponseStatus::PsaErrorNotPermitted);
+    Ok(())
+}
@@ -2,13 +2,13 @@
 // SPDX-License-Identifier: Apache-2.0
 use e2e_tests::TestClient;
 use parsec_client::core::interface::operations::psa_algorithm::{
-    Algorithm, AsymmetricSignature, Hash,
+    Algorithm, AsymmetricSignature, FullLengthMac, Hash, Mac,
 };
 use parsec_client::core::interface::operations::psa_key_attributes::{
     Attributes, Lifetime, Policy, Type, UsageFlags,
 };
 use parsec_client::core::interface::requests::ResponseStatus;
-use parsec_client::core::interface::requests::Result;
+use parsec_client::core::interface::requests::{ProviderID, Result};
 use picky_asn1::wrapper::IntegerAsn1;
 use serde::{Deserialize, Serialize};
 
@@ -145,3 +145,45 @@ fn failed_created_key_should_be_removed() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn create_and_destroy_hmac_key() -> Result<()> {
+    let mut client = TestClient::new();
+    client.do_not_destroy_keys();
+    let key_name = String::from("create_and_destroy_hmac_key");
+
+    let attributes = Attributes {
+        lifetime: Lifetime::Persistent,
+        key_type: Type::Hmac,
+        bits: 256,
+        policy: Policy {
+            usage_flags: UsageFlags {
+                sign_hash: true,
+                verify_hash: true,
+                sign_message: true,
+                verify_message: true,
+                export: false,
+                encrypt: false,
+                decrypt: false,
+                cache: false,
+                copy: false,
+                derive: false,
+            },
+            permitted_algorithms: Algorithm::Mac(Mac::FullLength(FullLengthMac::Hmac {
+                hash_alg: Hash::Sha256,
+            })),
+        },
+    };
+
+    // Only the Mbed Crypto provider supports HMAC keys.
+    if client.provider().unwrap() != ProviderID::MbedCrypto {
+        let status = client
+            .generate_key(key_name, attributes)
+            .expect_err("HMAC keys should not be supported.");
+        assert_eq!(status, ResponseStatus::PsaErrorNotSupported);
+        return Ok(());
+    }
+
+    client.generate_key(key_name.clone(), attributes)?;
+    client.destroy_key(key_name)
+}
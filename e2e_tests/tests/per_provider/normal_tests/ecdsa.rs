@@ -0,0 +1,198 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+use e2e_tests::TestClient;
+use parsec_client::core::interface::operations::psa_algorithm::*;
+use parsec_client::core::interface::operations::psa_key_attributes::*;
+use parsec_client::core::interface::requests::{ProviderID, ResponseStatus, Result};
+use sha2::{Digest, Sha256};
+
+fn ecc_key_attributes(key_type: Type, bits: usize, alg: AsymmetricSignature) -> Attributes {
+    Attributes {
+        lifetime: Lifetime::Persistent,
+        key_type,
+        bits,
+        policy: Policy {
+            usage_flags: UsageFlags {
+                sign_hash: true,
+                verify_hash: true,
+                sign_message: true,
+                verify_message: true,
+                export: false,
+                encrypt: false,
+                decrypt: false,
+                cache: false,
+                copy: false,
+                derive: false,
+            },
+            permitted_algorithms: Algorithm::AsymmetricSignature(alg),
+        },
+    }
+}
+
+fn ecc_key_pair_attributes(bits: usize, alg: AsymmetricSignature) -> Attributes {
+    ecc_key_attributes(
+        Type::EccKeyPair {
+            curve_family: EccFamily::SecpR1,
+        },
+        bits,
+        alg,
+    )
+}
+
+fn ecc_public_key_attributes(bits: usize, alg: AsymmetricSignature) -> Attributes {
+    let mut attributes = ecc_key_attributes(
+        Type::EccPublicKey {
+            curve_family: EccFamily::SecpR1,
+        },
+        bits,
+        alg,
+    );
+    attributes.policy.usage_flags.sign_hash = false;
+    attributes.policy.usage_flags.sign_message = false;
+    attributes
+}
+
+fn message_hash() -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.input(b"Bob wrote this message.");
+    hasher.result().to_vec()
+}
+
+/// Signs with a key pair generated on the curve, then verifies the signature with the key pair and
+/// with its public part, exported and imported again.
+fn sign_verify_export(key_name: &str, bits: usize, alg: AsymmetricSignature) -> Result<()> {
+    let mut client = TestClient::new();
+
+    // Only the Mbed Crypto provider supports all the ECDSA variants on both curves.
+    if client.provider().unwrap() != ProviderID::MbedCrypto {
+        return Ok(());
+    }
+
+    let key_name = String::from(key_name);
+    let public_key_name = key_name.clone() + "_public";
+    let hash = message_hash();
+
+    client.generate_key(key_name.clone(), ecc_key_pair_attributes(bits, alg))?;
+    let signature = client.sign(key_name.clone(), alg, hash.clone())?;
+    // The signature is the concatenation of r and s.
+    assert_eq!(signature.len(), 2 * (bits / 8));
+    client.verify(key_name.clone(), alg, hash.clone(), signature.clone())?;
+
+    // The public key is exported as an uncompressed point: 0x04 followed by the coordinates.
+    let public_key = client.export_public_key(key_name)?;
+    assert_eq!(public_key.len(), 2 * (bits / 8) + 1);
+    assert_eq!(public_key[0], 0x04);
+
+    client.import_key(
+        public_key_name.clone(),
+        ecc_public_key_attributes(bits, alg),
+        public_key.clone(),
+    )?;
+    assert_eq!(client.export_public_key(public_key_name.clone())?, public_key);
+    client.verify(public_key_name, alg, hash, signature)
+}
+
+#[test]
+fn ecdsa_secp256r1_sign_verify_export() -> Result<()> {
+    sign_verify_export(
+        "ecdsa_secp256r1_sign_verify_export",
+        256,
+        AsymmetricSignature::Ecdsa {
+            hash_alg: Hash::Sha256.into(),
+        },
+    )
+}
+
+#[test]
+fn ecdsa_secp384r1_sign_verify_export() -> Result<()> {
+    sign_verify_export(
+        "ecdsa_secp384r1_sign_verify_export",
+        384,
+        AsymmetricSignature::Ecdsa {
+            hash_alg: Hash::Sha256.into(),
+        },
+    )
+}
+
+#[test]
+fn deterministic_ecdsa_secp256r1_sign_verify_export() -> Result<()> {
+    sign_verify_export(
+        "deterministic_ecdsa_secp256r1_sign_verify_export",
+        256,
+        AsymmetricSignature::DeterministicEcdsa {
+            hash_alg: Hash::Sha256.into(),
+        },
+    )
+}
+
+#[test]
+fn deterministic_ecdsa_secp384r1_sign_verify_export() -> Result<()> {
+    sign_verify_export(
+        "deterministic_ecdsa_secp384r1_sign_verify_export",
+        384,
+        AsymmetricSignature::DeterministicEcdsa {
+            hash_alg: Hash::Sha256.into(),
+        },
+    )
+}
+
+#[test]
+fn deterministic_ecdsa_same_signature() -> Result<()> {
+    let key_name = String::from("deterministic_ecdsa_same_signature");
+    let mut client = TestClient::new();
+    let alg = AsymmetricSignature::DeterministicEcdsa {
+        hash_alg: Hash::Sha256.into(),
+    };
+
+    if client.provider().unwrap() != ProviderID::MbedCrypto {
+        return Ok(());
+    }
+
+    client.generate_key(key_name.clone(), ecc_key_pair_attributes(256, alg))?;
+    let signature1 = client.sign(key_name.clone(), alg, message_hash())?;
+    let signature2 = client.sign(key_name, alg, message_hash())?;
+    assert_eq!(signature1, signature2);
+
+    Ok(())
+}
+
+#[test]
+fn ecdsa_fail_verify_hash() -> Result<()> {
+    let key_name = String::from("ecdsa_fail_verify_hash");
+    let mut client = TestClient::new();
+    let alg = AsymmetricSignature::Ecdsa {
+        hash_alg: Hash::Sha256.into(),
+    };
+
+    if client.provider().unwrap() != ProviderID::MbedCrypto {
+        return Ok(());
+    }
+
+    client.generate_key(key_name.clone(), ecc_key_pair_attributes(256, alg))?;
+    let mut hash = message_hash();
+    let signature = client.sign(key_name.clone(), alg, hash.clone())?;
+    // Modify hash
+    hash[4] ^= 0xff;
+    let status = client.verify(key_name, alg, hash, signature).unwrap_err();
+    assert_eq!(status, ResponseStatus::PsaErrorInvalidSignature);
+
+    Ok(())
+}
+
+#[test]
+fn ecdsa_unsupported_curve_size() {
+    let key_name = String::from("ecdsa_unsupported_curve_size");
+    let mut client = TestClient::new();
+    let alg = AsymmetricSignature::Ecdsa {
+        hash_alg: Hash::Sha256.into(),
+    };
+
+    if client.provider().unwrap() != ProviderID::MbedCrypto {
+        return;
+    }
+
+    let status = client
+        .generate_key(key_name, ecc_key_pair_attributes(255, alg))
+        .unwrap_err();
+    assert_eq!(status, ResponseStatus::PsaErrorNotSupported);
+}
@@ -0,0 +1,93 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+use e2e_tests::TestClient;
+use parsec_client::core::interface::operations::psa_algorithm::Hash;
+use parsec_client::core::interface::requests::{ProviderID, ResponseStatus, Result};
+use sha2::{Digest, Sha256, Sha512};
+
+const MESSAGE: &[u8] = b"Bob wrote this message.";
+
+/// Creates a client for the providers supporting the hash operations, or returns `None` for the
+/// TPM provider.
+fn hash_client() -> Option<TestClient> {
+    let client = TestClient::new();
+    if client.provider().unwrap() == ProviderID::Tpm {
+        None
+    } else {
+        Some(client)
+    }
+}
+
+#[test]
+fn hash_compute_sha256_test_vector() -> Result<()> {
+    let mut client = match hash_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    // SHA-256 of "abc", from FIPS 180-2.
+    let expected_hash = [
+        0xBA, 0x78, 0x16, 0xBF, 0x8F, 0x01, 0xCF, 0xEA, 0x41, 0x41, 0x40, 0xDE, 0x5D, 0xAE, 0x22,
+        0x23, 0xB0, 0x03, 0x61, 0xA3, 0x96, 0x17, 0x7A, 0x9C, 0xB4, 0x10, 0xFF, 0x61, 0xF2, 0x00,
+        0x15, 0xAD,
+    ];
+    let hash = client.hash_compute(Hash::Sha256, b"abc")?;
+    assert_eq!(&hash[..], &expected_hash[..]);
+    Ok(())
+}
+
+#[test]
+fn hash_compute_sha256() -> Result<()> {
+    let mut client = match hash_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    let hash = client.hash_compute(Hash::Sha256, MESSAGE)?;
+    assert_eq!(&hash[..], &Sha256::digest(MESSAGE)[..]);
+    Ok(())
+}
+
+#[test]
+fn hash_compute_sha512() -> Result<()> {
+    let mut client = match hash_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    let hash = client.hash_compute(Hash::Sha512, MESSAGE)?;
+    assert_eq!(hash.len(), 64);
+    assert_eq!(&hash[..], &Sha512::digest(MESSAGE)[..]);
+    Ok(())
+}
+
+#[test]
+fn hash_compare_sha256() -> Result<()> {
+    let mut client = match hash_client() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    client.hash_compare(Hash::Sha256, MESSAGE, &Sha256::digest(MESSAGE))
+}
+
+#[test]
+fn hash_compare_wrong_hash() {
+    let mut client = match hash_client() {
+        Some(client) => client,
+        None => return,
+    };
+
+    let mut hash = Sha256::digest(MESSAGE).to_vec();
+    hash[4] ^= 0xff;
+    let status = client
+        .hash_compare(Hash::Sha256, MESSAGE, &hash)
+        .unwrap_err();
+    assert_eq!(status, ResponseStatus::PsaErrorInvalidSignature);
+
+    // A hash of another algorithm does not match either.
+    let status = client
+        .hash_compare(Hash::Sha256, MESSAGE, &Sha512::digest(MESSAGE))
+        .unwrap_err();
+    assert_eq!(status, ResponseStatus::PsaErrorInvalidSignature);
+}
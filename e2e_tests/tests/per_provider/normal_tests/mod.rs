@@ -1,10 +1,16 @@
 // Copyright 2019 Contributors to the Parsec project.
 // SPDX-License-Identifier: Apache-2.0
+mod aead;
 mod asym_sign_verify;
+mod asym_encryption;
 mod auth;
 mod basic;
+mod cipher;
 mod create_destroy_key;
+mod ecdsa;
 mod export_public_key;
+mod hash;
 mod import_key;
 mod key_attributes;
 mod ping;
+mod rsa_pss;
@@ -0,0 +1,118 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+use e2e_tests::TestClient;
+use parsec_client::core::interface::operations::psa_algorithm::*;
+use parsec_client::core::interface::operations::psa_key_attributes::*;
+use parsec_client::core::interface::requests::{ResponseStatus, Result};
+use sha2::{Digest, Sha256};
+
+const PSS_SHA256: AsymmetricSignature = AsymmetricSignature::RsaPss {
+    hash_alg: SignHash::Specific(Hash::Sha256),
+};
+
+fn rsa_pss_key_attributes(key_type: Type) -> Attributes {
+    Attributes {
+        lifetime: Lifetime::Persistent,
+        key_type,
+        bits: 1024,
+        policy: Policy {
+            usage_flags: UsageFlags {
+                sign_hash: key_type == Type::RsaKeyPair,
+                verify_hash: true,
+                sign_message: key_type == Type::RsaKeyPair,
+                verify_message: true,
+                export: false,
+                encrypt: false,
+                decrypt: false,
+                cache: false,
+                copy: false,
+                derive: false,
+            },
+            permitted_algorithms: Algorithm::AsymmetricSignature(PSS_SHA256),
+        },
+    }
+}
+
+fn message_hash() -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.input(b"Bob wrote this message.");
+    hasher.result().to_vec()
+}
+
+#[test]
+fn rsa_pss_sign_and_verify() -> Result<()> {
+    let key_name = String::from("rsa_pss_sign_and_verify");
+    let mut client = TestClient::new();
+    let hash = message_hash();
+
+    client.generate_key(key_name.clone(), rsa_pss_key_attributes(Type::RsaKeyPair))?;
+
+    let signature = client.sign(key_name.clone(), PSS_SHA256, hash.clone())?;
+    assert_eq!(signature.len(), 128);
+    client.verify(key_name, PSS_SHA256, hash, signature)
+}
+
+#[test]
+fn rsa_pss_verify_with_public_key() -> Result<()> {
+    let key_name = String::from("rsa_pss_verify_with_public_key");
+    let public_key_name = String::from("rsa_pss_verify_with_public_key_public");
+    let mut client = TestClient::new();
+    let hash = message_hash();
+
+    client.generate_key(key_name.clone(), rsa_pss_key_attributes(Type::RsaKeyPair))?;
+    let signature = client.sign(key_name.clone(), PSS_SHA256, hash.clone())?;
+
+    let public_key = client.export_public_key(key_name)?;
+    client.import_key(
+        public_key_name.clone(),
+        rsa_pss_key_attributes(Type::RsaPublicKey),
+        public_key,
+    )?;
+    client.verify(public_key_name, PSS_SHA256, hash, signature)
+}
+
+#[test]
+fn rsa_pss_fail_verify_hash() -> Result<()> {
+    let key_name = String::from("rsa_pss_fail_verify_hash");
+    let mut client = TestClient::new();
+    let mut hash = message_hash();
+
+    client.generate_key(key_name.clone(), rsa_pss_key_attributes(Type::RsaKeyPair))?;
+
+    let signature = client.sign(key_name.clone(), PSS_SHA256, hash.clone())?;
+    // Modify hash
+    hash[4] ^= 0xff;
+    let status = client
+        .verify(key_name, PSS_SHA256, hash, signature)
+        .unwrap_err();
+    assert_eq!(status, ResponseStatus::PsaErrorInvalidSignature);
+    Ok(())
+}
+
+#[test]
+fn rsa_pss_not_permitted_with_pkcs1v15_key() -> Result<()> {
+    let key_name = String::from("rsa_pss_not_permitted_with_pkcs1v15_key");
+    let mut client = TestClient::new();
+
+    client.generate_rsa_sign_key(key_name.clone())?;
+
+    let status = client
+        .sign(key_name, PSS_SHA256, message_hash())
+        .unwrap_err();
+    assert_eq!(status, ResponseStatus::PsaErrorNotPermitted);
+    Ok(())
+}
+
+#[test]
+fn pkcs1v15_not_permitted_with_pss_key() -> Result<()> {
+    let key_name = String::from("pkcs1v15_not_permitted_with_pss_key");
+    let mut client = TestClient::new();
+
+    client.generate_key(key_name.clone(), rsa_pss_key_attributes(Type::RsaKeyPair))?;
+
+    let status = client
+        .sign_with_rsa_sha256(key_name, message_hash())
+        .unwrap_err();
+    assert_eq!(status, ResponseStatus::PsaErrorNotPermitted);
+    Ok(())
+}
@@ -4,6 +4,7 @@
 
 use parsec::utils::{ServiceBuilder, ServiceConfig};
 use parsec::front::front_end::FrontEndHandler;
+use parsec::front::listener::Connection;
 use std::path::PathBuf;
 use libfuzzer_sys::fuzz_target;
 use lazy_static::lazy_static;
@@ -51,7 +52,12 @@ impl Write for MockStream {
 }
 
 fuzz_target!(|stream: MockStream| {
-    FRONT_END_HANDLER.handle_request(stream);
+    let connection = Connection {
+        stream: Box::from(stream),
+        metadata: None,
+        admin: false,
+    };
+    FRONT_END_HANDLER.handle_request(connection);
 });
 
 
@@ -10,21 +10,40 @@
 
 use super::ApplicationName;
 use super::Authenticate;
+use crate::front::listener::ConnectionMetadata;
 use log::error;
+use parsec_interface::operations::list_authenticators;
 use parsec_interface::requests::request::RequestAuth;
-use parsec_interface::requests::{ResponseStatus, Result};
+use parsec_interface::requests::{AuthType, ResponseStatus, Result};
+use parsec_interface::secrecy::ExposeSecret;
 use std::str;
 
 #[derive(Copy, Clone, Debug)]
 pub struct DirectAuthenticator;
 
 impl Authenticate for DirectAuthenticator {
-    fn authenticate(&self, auth: &RequestAuth) -> Result<ApplicationName> {
-        if auth.is_empty() {
+    fn describe(&self) -> Result<list_authenticators::AuthenticatorInfo> {
+        Ok(list_authenticators::AuthenticatorInfo {
+            description: String::from(
+                "Direct authenticator that uses the authentication field as the application name",
+            ),
+            version_maj: 0,
+            version_min: 1,
+            version_rev: 0,
+            id: AuthType::Direct,
+        })
+    }
+
+    fn authenticate(
+        &self,
+        auth: &RequestAuth,
+        _: Option<ConnectionMetadata>,
+    ) -> Result<ApplicationName> {
+        if auth.buffer.expose_secret().is_empty() {
             error!("The direct authenticator does not expect empty authentication values.");
             Err(ResponseStatus::AuthenticationError)
         } else {
-            match str::from_utf8(auth.bytes()) {
+            match str::from_utf8(auth.buffer.expose_secret()) {
                 Ok(str) => Ok(ApplicationName(String::from(str))),
                 Err(_) => {
                     error!("Error parsing the authentication value as a UTF-8 string.");
@@ -45,12 +64,13 @@ mod test {
     #[test]
     fn successful_authentication() {
         let authenticator = DirectAuthenticator {};
+        let conn_metadata = None;
 
         let app_name = "app_name".to_string();
-        let req_auth = RequestAuth::from_bytes(app_name.clone().into_bytes());
+        let req_auth = RequestAuth::new(app_name.clone().into_bytes());
 
         let auth_name = authenticator
-            .authenticate(&req_auth)
+            .authenticate(&req_auth, conn_metadata)
             .expect("Failed to authenticate");
 
         assert_eq!(auth_name.get_name(), app_name);
@@ -59,8 +79,9 @@ mod test {
     #[test]
     fn failed_authentication() {
         let authenticator = DirectAuthenticator {};
+        let conn_metadata = None;
         let status = authenticator
-            .authenticate(&RequestAuth::from_bytes(vec![0xff; 5]))
+            .authenticate(&RequestAuth::new(vec![0xff; 5]), conn_metadata)
             .expect_err("Authentication should have failed");
 
         assert_eq!(status, ResponseStatus::AuthenticationError);
@@ -69,8 +90,9 @@ mod test {
     #[test]
     fn empty_auth() {
         let authenticator = DirectAuthenticator {};
+        let conn_metadata = None;
         let status = authenticator
-            .authenticate(&RequestAuth::from_bytes(Vec::new()))
+            .authenticate(&RequestAuth::new(Vec::new()), conn_metadata)
             .expect_err("Empty auth should have failed");
 
         assert_eq!(status, ResponseStatus::AuthenticationError);
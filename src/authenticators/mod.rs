@@ -9,27 +9,116 @@
 //! is the `RequestAuth` field of a request, which is parsed by the authenticator specified in the header.
 //! The authentication functionality is abstracted through an `Authenticate` trait.
 //!
-//! Currently only a simple Direct Authenticator component is implemented.
+//! The authenticators enabled in the service are chosen through the `[[authenticator]]` tables of
+//! the configuration file.
+//!
+//! Some authenticators also make use of the metadata gathered by the listener about the
+//! connection the request was received on, such as the credentials of the peer process.
 
 pub mod direct_authenticator;
 
+pub mod jwt_svid_authenticator;
+
+pub mod unix_peer_credentials_authenticator;
+
+use crate::front::listener::ConnectionMetadata;
+use parsec_interface::operations::list_authenticators;
 use parsec_interface::requests::request::RequestAuth;
-use parsec_interface::requests::Result;
+use parsec_interface::requests::{AuthType, Result};
+use picky::signature::SignatureHashType;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
 /// String wrapper for app names
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct ApplicationName(String);
 
+/// Application allowed to perform the administrative operations of the service, as defined in
+/// the `admins` list of the core settings.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Admin {
+    name: String,
+}
+
+impl Admin {
+    pub fn new(name: String) -> Admin {
+        Admin { name }
+    }
+}
+
+/// Set of the application names having administrator privileges
+#[derive(Debug, Clone, Default)]
+pub struct AdminList(HashSet<ApplicationName>);
+
+impl AdminList {
+    /// Check if the application is an administrator of the service.
+    pub fn is_admin(&self, app_name: &ApplicationName) -> bool {
+        self.0.contains(app_name)
+    }
+}
+
+impl From<Vec<Admin>> for AdminList {
+    fn from(admins: Vec<Admin>) -> Self {
+        AdminList(
+            admins
+                .into_iter()
+                .map(|admin| ApplicationName(admin.name))
+                .collect(),
+        )
+    }
+}
+
+#[derive(Deserialize, Debug)]
+// For authenticators configs in parsec config.toml we use a format similar
+// to the one described in the Internally Tagged Enum representation
+// where "auth_type" is the tag field. For details see:
+// https://serde.rs/enum-representations.html
+#[serde(tag = "auth_type")]
+pub enum AuthenticatorConfig {
+    Direct,
+    UnixPeerCredentials {
+        include_gid: Option<bool>,
+    },
+    JwtSvid {
+        jwks_path: String,
+        audience: String,
+        allowed_algorithms: Option<Vec<SignatureHashType>>,
+    },
+}
+
+impl AuthenticatorConfig {
+    pub fn auth_type(&self) -> AuthType {
+        match *self {
+            AuthenticatorConfig::Direct => AuthType::Direct,
+            AuthenticatorConfig::UnixPeerCredentials { .. } => AuthType::UnixPeerCredentials,
+            AuthenticatorConfig::JwtSvid { .. } => AuthType::JwtSvid,
+        }
+    }
+}
+
 /// Authentication interface
 ///
 /// Interface that must be implemented for each authentication type available for the service.
 pub trait Authenticate {
-    /// Authenticates a `RequestAuth` payload and returns the `ApplicationName` if successfull.
+    /// Return a description of the authenticator.
+    ///
+    /// The descriptions are gathered in the Core Provider and returned for a ListAuthenticators
+    /// operation.
+    fn describe(&self) -> Result<list_authenticators::AuthenticatorInfo>;
+
+    /// Authenticates a `RequestAuth` payload and returns the `ApplicationName` if successfull. A
+    /// optional `ConnectionMetadata` object is passed in too, since it is sometimes possible to
+    /// perform authentication based on the connection's metadata (i.e. as is the case for UNIX
+    /// domain sockets with peer credentials).
     ///
     /// # Errors
     ///
     /// If the authentification fails, returns a `ResponseStatus::AuthenticationError`.
-    fn authenticate(&self, auth: &RequestAuth) -> Result<ApplicationName>;
+    fn authenticate(
+        &self,
+        auth: &RequestAuth,
+        meta: Option<ConnectionMetadata>,
+    ) -> Result<ApplicationName>;
 }
 
 impl ApplicationName {
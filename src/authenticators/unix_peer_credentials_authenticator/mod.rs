@@ -0,0 +1,171 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+//! Unix peer credentials authenticator
+//!
+//! The `UnixPeerCredentialsAuthenticator` uses Unix peer credentials to perform authentication.
+//! The credentials (UID, GID and PID) of the process at the other end of the Unix domain socket
+//! are gathered by the kernel when the connection is established and can not be forged by the
+//! client. The client still has to put its own UID, as a native endian 32-bit unsigned integer,
+//! in the authentication field of the request: it is checked against the peer credentials to make
+//! sure both sides agree on the identity used.
+//!
+//! The `ApplicationName` returned is the string representation of the UID of the peer or, if the
+//! GID is included, the UID and GID separated by a colon (e.g. `1000:1000`).
+
+use super::ApplicationName;
+use super::Authenticate;
+use crate::front::listener::ConnectionMetadata;
+use log::error;
+use parsec_interface::operations::list_authenticators;
+use parsec_interface::requests::request::RequestAuth;
+use parsec_interface::requests::{AuthType, ResponseStatus, Result};
+use parsec_interface::secrecy::ExposeSecret;
+use std::convert::TryInto;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct UnixPeerCredentialsAuthenticator {
+    include_gid: bool,
+}
+
+impl UnixPeerCredentialsAuthenticator {
+    /// Create a new authenticator. If `include_gid` is set, the GID of the peer is part of the
+    /// application name, making the same user under different groups different applications.
+    pub fn new(include_gid: bool) -> Self {
+        UnixPeerCredentialsAuthenticator { include_gid }
+    }
+}
+
+impl Authenticate for UnixPeerCredentialsAuthenticator {
+    fn describe(&self) -> Result<list_authenticators::AuthenticatorInfo> {
+        Ok(list_authenticators::AuthenticatorInfo {
+            description: String::from(
+                "Uses Unix peer credentials to authenticate the client. Verifies that the self-declared \
+                Unix user identifier (UID) in the request's authentication header matches that which is \
+                found from the peer credentials.",
+            ),
+            version_maj: 0,
+            version_min: 1,
+            version_rev: 0,
+            id: AuthType::UnixPeerCredentials,
+        })
+    }
+
+    fn authenticate(
+        &self,
+        auth: &RequestAuth,
+        meta: Option<ConnectionMetadata>,
+    ) -> Result<ApplicationName> {
+        // Parse authentication request.
+        let expected_uid_bytes = auth.buffer.expose_secret();
+        let expected_uid: [u8; 4] = expected_uid_bytes.as_slice().try_into().map_err(|_| {
+            error!(
+                "UID in authentication request is not the right size (expected: 4, got: {}).",
+                expected_uid_bytes.len()
+            );
+            ResponseStatus::AuthenticationError
+        })?;
+        let expected_uid = u32::from_ne_bytes(expected_uid);
+
+        let meta = meta.ok_or_else(|| {
+            error!("Authenticator did not receive any metadata; cannot perform authentication.");
+            ResponseStatus::AuthenticationError
+        })?;
+
+        let (uid, gid) = match meta {
+            ConnectionMetadata::UnixPeerCredentials { uid, gid, .. } => (uid, gid),
+        };
+
+        // Authentication is successful if the _actual_ UID from the Unix peer credentials equals
+        // the self-declared UID in the authentication request.
+        if uid == expected_uid {
+            if self.include_gid {
+                Ok(ApplicationName(format!("{}:{}", uid, gid)))
+            } else {
+                Ok(ApplicationName(uid.to_string()))
+            }
+        } else {
+            error!(
+                "Declared UID in authentication request does not match the process's UID ({} != {}).",
+                expected_uid, uid
+            );
+            Err(ResponseStatus::AuthenticationError)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Authenticate;
+    use super::UnixPeerCredentialsAuthenticator;
+    use crate::front::listener::ConnectionMetadata;
+    use parsec_interface::requests::request::RequestAuth;
+    use parsec_interface::requests::ResponseStatus;
+
+    fn peer_credentials(uid: u32, gid: u32) -> Option<ConnectionMetadata> {
+        Some(ConnectionMetadata::UnixPeerCredentials {
+            uid,
+            gid,
+            pid: None,
+        })
+    }
+
+    #[test]
+    fn successful_authentication() {
+        let authenticator = UnixPeerCredentialsAuthenticator::new(false);
+        let req_auth = RequestAuth::new(1000u32.to_ne_bytes().to_vec());
+
+        let auth_name = authenticator
+            .authenticate(&req_auth, peer_credentials(1000, 100))
+            .expect("Failed to authenticate");
+
+        assert_eq!(auth_name.get_name(), "1000");
+    }
+
+    #[test]
+    fn successful_authentication_with_gid() {
+        let authenticator = UnixPeerCredentialsAuthenticator::new(true);
+        let req_auth = RequestAuth::new(1000u32.to_ne_bytes().to_vec());
+
+        let auth_name = authenticator
+            .authenticate(&req_auth, peer_credentials(1000, 100))
+            .expect("Failed to authenticate");
+
+        assert_eq!(auth_name.get_name(), "1000:100");
+    }
+
+    #[test]
+    fn declared_uid_mismatch() {
+        let authenticator = UnixPeerCredentialsAuthenticator::new(false);
+        let req_auth = RequestAuth::new(0u32.to_ne_bytes().to_vec());
+
+        let status = authenticator
+            .authenticate(&req_auth, peer_credentials(1000, 100))
+            .expect_err("Authentication should have failed");
+
+        assert_eq!(status, ResponseStatus::AuthenticationError);
+    }
+
+    #[test]
+    fn wrong_size_auth() {
+        let authenticator = UnixPeerCredentialsAuthenticator::new(false);
+        let req_auth = RequestAuth::new(vec![0xff; 5]);
+
+        let status = authenticator
+            .authenticate(&req_auth, peer_credentials(1000, 100))
+            .expect_err("Authentication should have failed");
+
+        assert_eq!(status, ResponseStatus::AuthenticationError);
+    }
+
+    #[test]
+    fn missing_metadata() {
+        let authenticator = UnixPeerCredentialsAuthenticator::new(false);
+        let req_auth = RequestAuth::new(1000u32.to_ne_bytes().to_vec());
+
+        let status = authenticator
+            .authenticate(&req_auth, None)
+            .expect_err("Authentication should have failed");
+
+        assert_eq!(status, ResponseStatus::AuthenticationError);
+    }
+}
@@ -0,0 +1,290 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+//! Administrative requests
+//!
+//! The operations acting on the keys of all the applications do not have an opcode in the wire
+//! protocol. They are sent on the admin socket, if the listener is configured with one, and are
+//! routed by the dispatcher to the backend handler of the core provider, which only accepts them
+//! from the applications of the admin list.
+//!
+//! Requests and responses are JSON documents preceded by their length, as a little endian 32-bit
+//! unsigned integer. Only one request is handled per connection. The connections of the admin
+//! socket are authenticated with the Unix peer credentials authenticator: the client puts its UID
+//! in the authentication field of the request.
+use crate::key_info_managers::backup::{ArchiveProtection, ConflictPolicy, ImportReport};
+use crate::key_info_managers::{self, KeyTriple};
+use crate::providers::core_provider::ConsistencyReport;
+use crate::providers::reconciliation::{ReconciliationMode, ReconciliationReport};
+use derivative::Derivative;
+use log::error;
+use parsec_interface::requests::{ProviderId, ResponseStatus, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+/// Operation sent on the admin socket
+#[derive(Derivative, Serialize, Deserialize, Clone, PartialEq)]
+#[derivative(Debug)]
+pub enum AdminOperation {
+    /// List the key triples of all the applications, in all the providers.
+    ListAllKeys,
+    /// Compare the key mappings with the keys stored in the backends of the providers.
+    CheckConsistency,
+    /// Reconcile the key mappings of a provider, or of all the providers if none is given, with
+    /// the keys stored in their backends.
+    Reconcile {
+        #[serde(with = "key_info_managers::provider_id_serde::option")]
+        provider_id: Option<ProviderId>,
+        mode: ReconciliationMode,
+    },
+    /// Export the mappings of all the key info managers to an archive protected with the backup
+    /// key.
+    ExportMappings {
+        /// Raw bytes of the backup key
+        #[derivative(Debug = "ignore")]
+        backup_key: Vec<u8>,
+        protection: ArchiveProtection,
+    },
+    /// Import the mappings of an archive protected with the backup key. The archive has to fit,
+    /// with the rest of the request, in the body length limit of the service.
+    ImportMappings {
+        #[derivative(Debug = "ignore")]
+        archive: Vec<u8>,
+        /// Raw bytes of the backup key
+        #[derivative(Debug = "ignore")]
+        backup_key: Vec<u8>,
+        policy: ConflictPolicy,
+    },
+}
+
+/// Result of an operation sent on the admin socket
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AdminResult {
+    ListAllKeys(Vec<KeyTriple>),
+    CheckConsistency(ConsistencyReport),
+    Reconcile(Vec<ReconciliationReport>),
+    ExportMappings(Vec<u8>),
+    ImportMappings(ImportReport),
+}
+
+/// Request read from the admin socket
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AdminRequest {
+    /// Authentication field given to the Unix peer credentials authenticator.
+    pub auth: Vec<u8>,
+    /// Operation to perform.
+    pub operation: AdminOperation,
+}
+
+/// Response written to the admin socket
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AdminResponse {
+    /// Numerical value of the `ResponseStatus` of the operation.
+    pub status: u16,
+    /// Result of the operation, if it succeeded.
+    pub result: Option<AdminResult>,
+}
+
+impl AdminRequest {
+    /// Read a request from the stream, refusing requests longer than `body_len_limit`.
+    ///
+    /// # Errors
+    /// - if the request is too long, returns `ResponseStatus::BodySizeExceedsLimit`
+    /// - if the request can not be parsed, returns `ResponseStatus::DeserializingBodyFailed`
+    /// - if reading from the stream fails, returns `ResponseStatus::ConnectionError`
+    pub fn read_from_stream(stream: &mut impl Read, body_len_limit: usize) -> Result<Self> {
+        read_message(stream, body_len_limit)
+    }
+
+    /// Write the request to the stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ResponseStatus::ConnectionError` if writing to the stream fails.
+    pub fn write_to_stream(&self, stream: &mut impl Write) -> Result<()> {
+        write_message(stream, self)
+    }
+}
+
+impl AdminResponse {
+    /// Create a response for an operation which failed.
+    pub fn from_status(status: ResponseStatus) -> Self {
+        AdminResponse {
+            status: status as u16,
+            result: None,
+        }
+    }
+
+    /// Create a response for an operation which succeeded.
+    pub fn from_result(result: AdminResult) -> Self {
+        AdminResponse {
+            status: ResponseStatus::Success as u16,
+            result: Some(result),
+        }
+    }
+
+    /// Read a response from the stream, refusing responses longer than `body_len_limit`.
+    ///
+    /// # Errors
+    /// - if the response is too long, returns `ResponseStatus::BodySizeExceedsLimit`
+    /// - if the response can not be parsed, returns `ResponseStatus::DeserializingBodyFailed`
+    /// - if reading from the stream fails, returns `ResponseStatus::ConnectionError`
+    pub fn read_from_stream(stream: &mut impl Read, body_len_limit: usize) -> Result<Self> {
+        read_message(stream, body_len_limit)
+    }
+
+    /// Write the response to the stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ResponseStatus::ConnectionError` if writing to the stream fails.
+    pub fn write_to_stream(&self, stream: &mut impl Write) -> Result<()> {
+        write_message(stream, self)
+    }
+
+    /// Convert the response to the result of the operation or to its error status.
+    pub fn into_result(self) -> Result<AdminResult> {
+        let status = ResponseStatus::try_from(self.status)?;
+        match (status, self.result) {
+            (ResponseStatus::Success, Some(result)) => Ok(result),
+            (ResponseStatus::Success, None) => Err(ResponseStatus::InvalidEncoding),
+            (status, _) => Err(status),
+        }
+    }
+}
+
+/// Send an operation on the admin socket of the service and return its result. The UID of the
+/// process is used for authentication.
+///
+/// # Errors
+///
+/// Returns an error if the service could not be reached or if the operation failed.
+pub fn send_admin_operation(
+    socket_path: &Path,
+    operation: AdminOperation,
+) -> std::io::Result<AdminResult> {
+    let mut stream = UnixStream::connect(socket_path)?;
+    // Safe as getuid can not fail.
+    let uid = unsafe { libc::getuid() };
+    let request = AdminRequest {
+        auth: uid.to_ne_bytes().to_vec(),
+        operation,
+    };
+
+    request
+        .write_to_stream(&mut stream)
+        // The responses of the service are not limited in size.
+        .and_then(|_| AdminResponse::read_from_stream(&mut stream, usize::MAX))
+        .and_then(AdminResponse::into_result)
+        .map_err(|status| {
+            Error::new(
+                ErrorKind::Other,
+                format!("Admin operation failed ({})", status),
+            )
+        })
+}
+
+fn read_message<T: DeserializeOwned>(stream: &mut impl Read, body_len_limit: usize) -> Result<T> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len).map_err(|e| {
+        error!("Failed to read the admin message length ({}).", e);
+        ResponseStatus::ConnectionError
+    })?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > body_len_limit {
+        error!(
+            "Admin message too long ({} bytes, limit is {}).",
+            len, body_len_limit
+        );
+        return Err(ResponseStatus::BodySizeExceedsLimit);
+    }
+
+    // The message can contain a backup key.
+    let mut message = Zeroizing::new(vec![0; len]);
+    stream.read_exact(&mut message).map_err(|e| {
+        error!("Failed to read the admin message ({}).", e);
+        ResponseStatus::ConnectionError
+    })?;
+    serde_json::from_slice(&message).map_err(|e| {
+        error!("Failed to parse the admin message ({}).", e);
+        ResponseStatus::DeserializingBodyFailed
+    })
+}
+
+fn write_message<T: Serialize>(stream: &mut impl Write, message: &T) -> Result<()> {
+    let message = Zeroizing::new(serde_json::to_vec(message).map_err(|e| {
+        error!("Failed to serialize the admin message ({}).", e);
+        ResponseStatus::SerializingBodyFailed
+    })?);
+    let len = u32::try_from(message.len()).map_err(|_| ResponseStatus::BodySizeExceedsLimit)?;
+
+    stream
+        .write_all(&len.to_le_bytes())
+        .and_then(|_| stream.write_all(&message))
+        .map_err(|e| {
+            error!("Failed to write the admin message ({}).", e);
+            ResponseStatus::ConnectionError
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::authenticators::ApplicationName;
+
+    #[test]
+    fn request_round_trip() {
+        let request = AdminRequest {
+            auth: 1000u32.to_ne_bytes().to_vec(),
+            operation: AdminOperation::Reconcile {
+                provider_id: Some(ProviderId::Pkcs11),
+                mode: ReconciliationMode::Adopt(ApplicationName::new(String::from("admin"))),
+            },
+        };
+        let mut buffer = Vec::new();
+        request.write_to_stream(&mut buffer).unwrap();
+
+        assert_eq!(
+            AdminRequest::read_from_stream(&mut &buffer[..], 1024).unwrap(),
+            request
+        );
+        assert_eq!(
+            AdminRequest::read_from_stream(&mut &buffer[..], 4).unwrap_err(),
+            ResponseStatus::BodySizeExceedsLimit
+        );
+        assert_eq!(
+            AdminRequest::read_from_stream(&mut &buffer[..buffer.len() - 1], 1024).unwrap_err(),
+            ResponseStatus::ConnectionError
+        );
+    }
+
+    #[test]
+    fn response_round_trip() {
+        let key_triple = KeyTriple::new(
+            ApplicationName::new(String::from("app")),
+            ProviderId::Pkcs11,
+            String::from("key"),
+        );
+        let response = AdminResponse::from_result(AdminResult::ListAllKeys(vec![key_triple]));
+        let mut buffer = Vec::new();
+        response.write_to_stream(&mut buffer).unwrap();
+
+        let read_response = AdminResponse::read_from_stream(&mut &buffer[..], 1024).unwrap();
+        assert_eq!(read_response, response);
+        assert_eq!(
+            read_response.into_result().unwrap(),
+            response.result.unwrap()
+        );
+
+        assert_eq!(
+            AdminResponse::from_status(ResponseStatus::AdminOperation)
+                .into_result()
+                .unwrap_err(),
+            ResponseStatus::AdminOperation
+        );
+    }
+}
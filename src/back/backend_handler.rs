@@ -5,16 +5,19 @@
 //! The backend handler embodies the last processing step from external request
 //! to internal function call - parsing of the request body and conversion to a
 //! native operation which is then passed to the provider.
-use crate::authenticators::ApplicationName;
+use super::admin::{AdminOperation, AdminResponse};
+use crate::authenticators::{AdminList, ApplicationName};
 use crate::providers::Provide;
 use derivative::Derivative;
+use log::warn;
 use parsec_interface::operations::Convert;
 use parsec_interface::operations::{NativeOperation, NativeResult};
 use parsec_interface::requests::{
     request::RequestHeader, Request, Response, ResponseStatus, Result,
 };
-use parsec_interface::requests::{BodyType, ProviderID};
+use parsec_interface::requests::{BodyType, ProviderId};
 use std::io::{Error, ErrorKind};
+use std::sync::Arc;
 
 /// Back end handler component
 ///
@@ -28,12 +31,13 @@ use std::io::{Error, ErrorKind};
 pub struct BackEndHandler {
     // Send and Sync are required for Arc<FrontEndHandler> to be Send.
     #[derivative(Debug = "ignore")]
-    provider: Box<dyn Provide + Send + Sync>,
+    provider: Arc<dyn Provide + Send + Sync>,
     #[derivative(Debug = "ignore")]
     converter: Box<dyn Convert + Send + Sync>,
-    provider_id: ProviderID,
+    provider_id: ProviderId,
     content_type: BodyType,
     accept_type: BodyType,
+    admin_list: AdminList,
 }
 
 impl BackEndHandler {
@@ -51,7 +55,7 @@ impl BackEndHandler {
     /// the request.
     ///
     /// # Errors
-    /// - if the provider ID does not match, returns `ResponseStatus::WrongProviderID`
+    /// - if the provider ID does not match, returns `ResponseStatus::WrongProviderId`
     /// - if the content type does not match, returns `ResponseStatus::ContentTypeNotSupported`
     /// - if the accept type does not match, returns `ResponseStatus::AcceptTypeNotSupported`
     pub fn is_capable(&self, request: &Request) -> Result<()> {
@@ -62,7 +66,7 @@ impl BackEndHandler {
         // TODO: if these two don't match the service should probably panic,
         // but I think it's reasonable to assume they do match
         if header.provider != self.provider_id {
-            Err(ResponseStatus::WrongProviderID)
+            Err(ResponseStatus::WrongProviderId)
         } else if header.content_type != self.content_type {
             Err(ResponseStatus::ContentTypeNotSupported)
         } else if header.accept_type != self.accept_type {
@@ -72,15 +76,55 @@ impl BackEndHandler {
         }
     }
 
+    /// Pass an operation received on the admin socket to the provider, if the application is part
+    /// of the admin list, and return its result. Otherwise the operation is rejected with
+    /// `ResponseStatus::AdminOperation`.
+    pub fn execute_admin_request(
+        &self,
+        operation: AdminOperation,
+        app_name: ApplicationName,
+    ) -> AdminResponse {
+        if !self.is_admin(Some(&app_name)) {
+            warn!(
+                "Application {:?} tried to perform the admin operation {:?}.",
+                app_name.get_name(),
+                operation
+            );
+            return AdminResponse::from_status(ResponseStatus::AdminOperation);
+        }
+
+        match self.provider.admin_operation(app_name, operation) {
+            Ok(result) => AdminResponse::from_result(result),
+            Err(status) => AdminResponse::from_status(status),
+        }
+    }
+
+    fn is_admin(&self, app_name: Option<&ApplicationName>) -> bool {
+        match app_name {
+            Some(app_name) => self.admin_list.is_admin(app_name),
+            None => false,
+        }
+    }
+
     /// Unmarshall the request body, pass the operation to the provider and marshall
     /// the result back.
     ///
     /// If any of the steps fails, a response containing an appropriate status code is
-    /// returned.
+    /// returned. Administrative operations are rejected with `ResponseStatus::AdminOperation`
+    /// unless the application is part of the admin list.
     pub fn execute_request(&self, request: Request, app_name: Option<ApplicationName>) -> Response {
         let opcode = request.header.opcode;
         let header = request.header;
 
+        if opcode.is_admin() && !self.is_admin(app_name.as_ref()) {
+            warn!(
+                "Application {:?} tried to perform the admin operation {:?}.",
+                app_name.as_ref().map(ApplicationName::get_name),
+                opcode
+            );
+            return Response::from_request_header(header, ResponseStatus::AdminOperation);
+        }
+
         macro_rules! unwrap_or_else_return {
             ($result:expr) => {
                 match $result {
@@ -100,6 +144,27 @@ impl BackEndHandler {
                 let result = unwrap_or_else_return!(self.provider.list_opcodes(op_list_opcodes));
                 self.result_to_response(NativeResult::ListOpcodes(result), header)
             }
+            NativeOperation::ListAuthenticators(op_list_authenticators) => {
+                let result = unwrap_or_else_return!(self
+                    .provider
+                    .list_authenticators(op_list_authenticators));
+                self.result_to_response(NativeResult::ListAuthenticators(result), header)
+            }
+            NativeOperation::ListKeys(op_list_keys) => {
+                let app_name =
+                    unwrap_or_else_return!(app_name.ok_or(ResponseStatus::NotAuthenticated));
+                let result =
+                    unwrap_or_else_return!(self.provider.list_keys(app_name, op_list_keys));
+                self.result_to_response(NativeResult::ListKeys(result), header)
+            }
+            NativeOperation::ListClients(op_list_clients) => {
+                let result = unwrap_or_else_return!(self.provider.list_clients(op_list_clients));
+                self.result_to_response(NativeResult::ListClients(result), header)
+            }
+            NativeOperation::DeleteClient(op_delete_client) => {
+                let result = unwrap_or_else_return!(self.provider.delete_client(op_delete_client));
+                self.result_to_response(NativeResult::DeleteClient(result), header)
+            }
             NativeOperation::Ping(op_ping) => {
                 let result = unwrap_or_else_return!(self.provider.ping(op_ping));
                 self.result_to_response(NativeResult::Ping(result), header)
@@ -148,6 +213,92 @@ impl BackEndHandler {
                     unwrap_or_else_return!(self.provider.psa_verify_hash(app_name, op_verify_hash));
                 self.result_to_response(NativeResult::PsaVerifyHash(result), header)
             }
+            NativeOperation::PsaExportKey(op_export_key) => {
+                let app_name =
+                    unwrap_or_else_return!(app_name.ok_or(ResponseStatus::NotAuthenticated));
+                let result =
+                    unwrap_or_else_return!(self.provider.psa_export_key(app_name, op_export_key));
+                self.result_to_response(NativeResult::PsaExportKey(result), header)
+            }
+            NativeOperation::PsaHashCompute(op_hash_compute) => {
+                let result =
+                    unwrap_or_else_return!(self.provider.psa_hash_compute(op_hash_compute));
+                self.result_to_response(NativeResult::PsaHashCompute(result), header)
+            }
+            NativeOperation::PsaHashCompare(op_hash_compare) => {
+                let result =
+                    unwrap_or_else_return!(self.provider.psa_hash_compare(op_hash_compare));
+                self.result_to_response(NativeResult::PsaHashCompare(result), header)
+            }
+            NativeOperation::PsaAsymmetricEncrypt(op_asymmetric_encrypt) => {
+                let app_name =
+                    unwrap_or_else_return!(app_name.ok_or(ResponseStatus::NotAuthenticated));
+                let result = unwrap_or_else_return!(self
+                    .provider
+                    .psa_asymmetric_encrypt(app_name, op_asymmetric_encrypt));
+                self.result_to_response(NativeResult::PsaAsymmetricEncrypt(result), header)
+            }
+            NativeOperation::PsaAsymmetricDecrypt(op_asymmetric_decrypt) => {
+                let app_name =
+                    unwrap_or_else_return!(app_name.ok_or(ResponseStatus::NotAuthenticated));
+                let result = unwrap_or_else_return!(self
+                    .provider
+                    .psa_asymmetric_decrypt(app_name, op_asymmetric_decrypt));
+                self.result_to_response(NativeResult::PsaAsymmetricDecrypt(result), header)
+            }
+            NativeOperation::PsaAeadEncrypt(op_aead_encrypt) => {
+                let app_name =
+                    unwrap_or_else_return!(app_name.ok_or(ResponseStatus::NotAuthenticated));
+                let result = unwrap_or_else_return!(self
+                    .provider
+                    .psa_aead_encrypt(app_name, op_aead_encrypt));
+                self.result_to_response(NativeResult::PsaAeadEncrypt(result), header)
+            }
+            NativeOperation::PsaAeadDecrypt(op_aead_decrypt) => {
+                let app_name =
+                    unwrap_or_else_return!(app_name.ok_or(ResponseStatus::NotAuthenticated));
+                let result = unwrap_or_else_return!(self
+                    .provider
+                    .psa_aead_decrypt(app_name, op_aead_decrypt));
+                self.result_to_response(NativeResult::PsaAeadDecrypt(result), header)
+            }
+            NativeOperation::PsaCipherEncrypt(op_cipher_encrypt) => {
+                let app_name =
+                    unwrap_or_else_return!(app_name.ok_or(ResponseStatus::NotAuthenticated));
+                let result = unwrap_or_else_return!(self
+                    .provider
+                    .psa_cipher_encrypt(app_name, op_cipher_encrypt));
+                self.result_to_response(NativeResult::PsaCipherEncrypt(result), header)
+            }
+            NativeOperation::PsaCipherDecrypt(op_cipher_decrypt) => {
+                let app_name =
+                    unwrap_or_else_return!(app_name.ok_or(ResponseStatus::NotAuthenticated));
+                let result = unwrap_or_else_return!(self
+                    .provider
+                    .psa_cipher_decrypt(app_name, op_cipher_decrypt));
+                self.result_to_response(NativeResult::PsaCipherDecrypt(result), header)
+            }
+            NativeOperation::PsaGenerateRandom(op_generate_random) => {
+                let result =
+                    unwrap_or_else_return!(self.provider.psa_generate_random(op_generate_random));
+                self.result_to_response(NativeResult::PsaGenerateRandom(result), header)
+            }
+            NativeOperation::PsaRawKeyAgreement(op_raw_key_agreement) => {
+                let app_name =
+                    unwrap_or_else_return!(app_name.ok_or(ResponseStatus::NotAuthenticated));
+                let result = unwrap_or_else_return!(self
+                    .provider
+                    .psa_raw_key_agreement(app_name, op_raw_key_agreement));
+                self.result_to_response(NativeResult::PsaRawKeyAgreement(result), header)
+            }
+            // None of the providers implement these operations yet.
+            NativeOperation::PsaSignMessage(_)
+            | NativeOperation::PsaVerifyMessage(_)
+            | NativeOperation::AttestKey(_)
+            | NativeOperation::PrepareKeyAttestation(_)
+            | NativeOperation::CanDoCrypto(_) => {
+                Response::from_request_header(header, ResponseStatus::PsaErrorNotSupported)
+            }
         }
     }
 }
@@ -157,12 +308,13 @@ impl BackEndHandler {
 #[derivative(Debug)]
 pub struct BackEndHandlerBuilder {
     #[derivative(Debug = "ignore")]
-    provider: Option<Box<dyn Provide + Send + Sync>>,
+    provider: Option<Arc<dyn Provide + Send + Sync>>,
     #[derivative(Debug = "ignore")]
     converter: Option<Box<dyn Convert + Send + Sync>>,
-    provider_id: Option<ProviderID>,
+    provider_id: Option<ProviderId>,
     content_type: Option<BodyType>,
     accept_type: Option<BodyType>,
+    admin_list: AdminList,
 }
 
 impl BackEndHandlerBuilder {
@@ -173,10 +325,11 @@ impl BackEndHandlerBuilder {
             provider_id: None,
             content_type: None,
             accept_type: None,
+            admin_list: AdminList::default(),
         }
     }
 
-    pub fn with_provider(mut self, provider: Box<dyn Provide + Send + Sync>) -> Self {
+    pub fn with_provider(mut self, provider: Arc<dyn Provide + Send + Sync>) -> Self {
         self.provider = Some(provider);
         self
     }
@@ -186,7 +339,7 @@ impl BackEndHandlerBuilder {
         self
     }
 
-    pub fn with_provider_id(mut self, provider_id: ProviderID) -> Self {
+    pub fn with_provider_id(mut self, provider_id: ProviderId) -> Self {
         self.provider_id = Some(provider_id);
         self
     }
@@ -201,6 +354,11 @@ impl BackEndHandlerBuilder {
         self
     }
 
+    pub fn with_admin_list(mut self, admin_list: AdminList) -> Self {
+        self.admin_list = admin_list;
+        self
+    }
+
     pub fn build(self) -> std::io::Result<BackEndHandler> {
         Ok(BackEndHandler {
             provider: self
@@ -218,6 +376,7 @@ impl BackEndHandlerBuilder {
             accept_type: self
                 .accept_type
                 .ok_or_else(|| Error::new(ErrorKind::InvalidData, "accept_type is missing"))?,
+            admin_list: self.admin_list,
         })
     }
 }
@@ -4,10 +4,11 @@
 //!
 //! The dispatcher's role is to direct requests to the provider they specify, if
 //! said provider is available on the system, thus acting as a multiplexer.
+use super::admin::{AdminOperation, AdminResponse};
 use super::backend_handler::BackEndHandler;
 use crate::authenticators::ApplicationName;
 use parsec_interface::requests::request::Request;
-use parsec_interface::requests::ProviderID;
+use parsec_interface::requests::ProviderId;
 use parsec_interface::requests::{Response, ResponseStatus};
 use std::collections::HashMap;
 use std::io::{Error, ErrorKind, Result};
@@ -21,7 +22,7 @@ use std::io::{Error, ErrorKind, Result};
 /// the fields in the request header to the properties of the handlers.
 #[derive(Debug)]
 pub struct Dispatcher {
-    backends: HashMap<ProviderID, BackEndHandler>,
+    backends: HashMap<ProviderId, BackEndHandler>,
 }
 
 impl Dispatcher {
@@ -46,12 +47,26 @@ impl Dispatcher {
             Response::from_request_header(request.header, ResponseStatus::ProviderNotRegistered)
         }
     }
+
+    /// Pass an operation received on the admin socket to the backend handler of the core
+    /// provider, which implements the admin operations.
+    pub fn dispatch_admin_request(
+        &self,
+        operation: AdminOperation,
+        app_name: ApplicationName,
+    ) -> AdminResponse {
+        if let Some(backend) = self.backends.get(&ProviderId::Core) {
+            backend.execute_admin_request(operation, app_name)
+        } else {
+            AdminResponse::from_status(ResponseStatus::ProviderNotRegistered)
+        }
+    }
 }
 
 /// `Dispatcher` builder
 #[derive(Debug, Default)]
 pub struct DispatcherBuilder {
-    backends: Option<HashMap<ProviderID, BackEndHandler>>,
+    backends: Option<HashMap<ProviderId, BackEndHandler>>,
 }
 
 impl DispatcherBuilder {
@@ -61,7 +76,7 @@ impl DispatcherBuilder {
 
     pub fn with_backend(
         mut self,
-        provider_id: ProviderID,
+        provider_id: ProviderId,
         backend_handler: BackEndHandler,
     ) -> Self {
         let mut backends = self.backends.unwrap_or_default();
@@ -71,7 +86,7 @@ impl DispatcherBuilder {
         self
     }
 
-    pub fn with_backends(mut self, new_backends: HashMap<ProviderID, BackEndHandler>) -> Self {
+    pub fn with_backends(mut self, new_backends: HashMap<ProviderId, BackEndHandler>) -> Self {
         let mut backends = self.backends.unwrap_or_default();
         backends.extend(new_backends);
         self.backends = Some(backends);
@@ -1,5 +1,6 @@
 // Copyright 2019 Contributors to the Parsec project.
 // SPDX-License-Identifier: Apache-2.0
 //! Routing and parsing requests for processing by providers
+pub mod admin;
 pub mod backend_handler;
 pub mod dispatcher;
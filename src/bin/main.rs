@@ -31,15 +31,27 @@
 // This one is hard to avoid.
 #![allow(clippy::multiple_crate_versions)]
 
-use log::info;
+use log::{info, warn};
+use parsec_interface::requests::ProviderId;
+use parsec_service::authenticators::ApplicationName;
+use parsec_service::back::admin::{self, AdminOperation, AdminResult};
+use parsec_service::front::connection_limit::ConnectionLimit;
+use parsec_service::key_info_managers::backup::{
+    self, ArchiveProtection, BackupKey, ConflictPolicy, ImportReport,
+};
+use parsec_service::key_info_managers::encryption::KeyEncryptionKey;
+use parsec_service::key_info_managers::on_disk_manager::OnDiskKeyInfoManagerBuilder;
+use parsec_service::key_info_managers::record_format::CURRENT_VERSION;
+use parsec_service::providers::reconciliation::{self, ReconciliationMode, ReconciliationReport};
 use parsec_service::utils::{ServiceBuilder, ServiceConfig};
-use signal_hook::{flag, SIGHUP, SIGTERM};
+use signal_hook::{flag, pipe, SIGHUP, SIGTERM};
 use std::io::{Error, ErrorKind, Result};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
-use std::time::Duration;
 use structopt::StructOpt;
 
 /// Parsec is the Platform AbstRaction for SECurity, a new open-source initiative to provide a
@@ -56,20 +68,204 @@ struct Opts {
     /// Sets the configuration file path
     #[structopt(short, long, default_value = "config.toml")]
     config: String,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
 }
 
-const MAIN_LOOP_DEFAULT_SLEEP: u64 = 10;
+#[derive(StructOpt)]
+enum Command {
+    /// Converts the mappings of an OnDisk key info manager stored in an older record format, or in
+    /// the legacy file layout, to the current format and reports the mappings converted. The
+    /// service must not be using the mappings directory at the same time.
+    Migrate {
+        /// Path of the mappings directory
+        #[structopt(parse(from_os_str))]
+        mappings_dir: PathBuf,
+
+        /// Path of the file containing the key-encryption key, if the mappings are encrypted
+        #[structopt(long, parse(from_os_str))]
+        key_encryption_key: Option<PathBuf>,
+
+        /// Only reports the mappings that would be converted
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Compares the key mappings with the keys stored in the backends of the providers of the
+    /// configuration and reports the mappings pointing to missing keys and the keys without a
+    /// mapping. Nothing is changed unless a repair option is given. The service must not be running
+    /// at the same time: use the admin reconcile command instead if it is.
+    Reconcile {
+        /// Only reconciles the given provider: "MbedCrypto", "Pkcs11" or "Tpm"
+        #[structopt(long, parse(try_from_str = provider_from_name))]
+        provider: Option<ProviderId>,
+
+        /// Removes the mappings pointing to missing keys and destroys the keys without a mapping
+        #[structopt(long, conflicts_with = "adopt")]
+        delete: bool,
+
+        /// Removes the mappings pointing to missing keys and gives the keys without a mapping to the
+        /// application named, under the name "adopted-" followed by their hexadecimal identifier
+        #[structopt(long, value_name = "application")]
+        adopt: Option<String>,
+    },
+    /// Exports the key mappings of all the key info managers of the configuration to an archive
+    /// encrypted, or only signed, with the backup key. The service must not be running at the same
+    /// time: use "admin export-mappings" while it is running.
+    ExportMappings {
+        /// Path of the archive to write
+        #[structopt(parse(from_os_str))]
+        archive: PathBuf,
+
+        /// Path of the file containing the backup key, 32 bytes long
+        #[structopt(long, parse(from_os_str))]
+        backup_key: PathBuf,
+
+        /// Only signs the archive: the key information can then be read by anyone
+        #[structopt(long)]
+        sign_only: bool,
+    },
+    /// Imports the key mappings of an archive in the key info managers of the configuration and
+    /// reports the mappings conflicting with the existing ones. The service must not be running at
+    /// the same time: use "admin import-mappings" while it is running.
+    ImportMappings {
+        /// Path of the archive to read
+        #[structopt(parse(from_os_str))]
+        archive: PathBuf,
+
+        /// Path of the file containing the backup key, 32 bytes long
+        #[structopt(long, parse(from_os_str))]
+        backup_key: PathBuf,
+
+        /// What to do if conflicts are found: "abort" to import nothing, "skip" to import the
+        /// other mappings or "overwrite" to also replace the existing mappings of the same keys
+        #[structopt(long, default_value = "abort", parse(try_from_str = conflict_policy_from_name))]
+        on_conflict: ConflictPolicy,
+    },
+    /// Sends an admin request to the running service, on the admin socket of the configuration. The
+    /// application name of the user running the command, as given by the UnixPeerCredentials
+    /// authenticator, must be in the admins list of the configuration.
+    Admin {
+        #[structopt(subcommand)]
+        operation: AdminCommand,
+    },
+}
+
+#[derive(StructOpt)]
+enum AdminCommand {
+    /// Lists the keys of all the applications, in all the providers
+    ListKeys,
+    /// Compares the key mappings with the keys stored in the backends of the providers and reports
+    /// the inconsistencies found, without repairing them
+    CheckConsistency,
+    /// Reconciles the key mappings with the keys stored in the backends of the providers, as the
+    /// reconcile command but while the service is running
+    Reconcile {
+        /// Only reconciles the given provider: "MbedCrypto", "Pkcs11" or "Tpm"
+        #[structopt(long, parse(try_from_str = provider_from_name))]
+        provider: Option<ProviderId>,
+
+        /// Removes the mappings pointing to missing keys and destroys the keys without a mapping
+        #[structopt(long, conflicts_with = "adopt")]
+        delete: bool,
+
+        /// Removes the mappings pointing to missing keys and gives the keys without a mapping to the
+        /// application named, under the name "adopted-" followed by their hexadecimal identifier
+        #[structopt(long, value_name = "application")]
+        adopt: Option<String>,
+    },
+    /// Exports the key mappings of the running service to an archive encrypted, or only signed,
+    /// with the backup key, as the export-mappings command
+    ExportMappings {
+        /// Path of the archive to write
+        #[structopt(parse(from_os_str))]
+        archive: PathBuf,
+
+        /// Path of the file containing the backup key, 32 bytes long
+        #[structopt(long, parse(from_os_str))]
+        backup_key: PathBuf,
+
+        /// Only signs the archive: the key information can then be read by anyone
+        #[structopt(long)]
+        sign_only: bool,
+    },
+    /// Imports the key mappings of an archive in the running service, as the import-mappings
+    /// command. The archive must fit in the body length limit of the service.
+    ImportMappings {
+        /// Path of the archive to read
+        #[structopt(parse(from_os_str))]
+        archive: PathBuf,
+
+        /// Path of the file containing the backup key, 32 bytes long
+        #[structopt(long, parse(from_os_str))]
+        backup_key: PathBuf,
+
+        /// What to do if conflicts are found: "abort" to import nothing, "skip" to import the
+        /// other mappings or "overwrite" to also replace the existing mappings of the same keys
+        #[structopt(long, default_value = "abort", parse(try_from_str = conflict_policy_from_name))]
+        on_conflict: ConflictPolicy,
+    },
+}
 
 fn main() -> Result<()> {
     // Parsing the command line arguments.
     let opts: Opts = Opts::from_args();
 
+    match &opts.command {
+        Some(Command::Migrate {
+            mappings_dir,
+            key_encryption_key,
+            dry_run,
+        }) => {
+            env_logger::init();
+            return migrate(mappings_dir, key_encryption_key.as_deref(), *dry_run);
+        }
+        Some(Command::Reconcile {
+            provider,
+            delete,
+            adopt,
+        }) => {
+            return reconcile(
+                &opts.config,
+                *provider,
+                &reconciliation_mode(*delete, adopt),
+            );
+        }
+        Some(Command::ExportMappings {
+            archive,
+            backup_key,
+            sign_only,
+        }) => {
+            return export_mappings(
+                &opts.config,
+                archive,
+                backup_key,
+                archive_protection(*sign_only),
+            );
+        }
+        Some(Command::ImportMappings {
+            archive,
+            backup_key,
+            on_conflict,
+        }) => {
+            return import_mappings(&opts.config, archive, backup_key, *on_conflict);
+        }
+        Some(Command::Admin { operation }) => return admin(&opts.config, operation),
+        None => (),
+    }
+
     // Register a boolean set to true when the SIGTERM signal is received.
     let kill_signal = Arc::new(AtomicBool::new(false));
     // Register a boolean set to true when the SIGHUP signal is received.
     let reload_signal = Arc::new(AtomicBool::new(false));
     let _ = flag::register(SIGTERM, kill_signal.clone())?;
     let _ = flag::register(SIGHUP, reload_signal.clone())?;
+    // The same signals also write to a self-pipe to wake up the listener blocked waiting for
+    // connections. They are registered after the flags so that the flags are already set when
+    // the listener wakes up.
+    let (wake_up_reader, wake_up_writer) = UnixStream::pair()?;
+    let _ = pipe::register(SIGTERM, wake_up_writer.try_clone()?)?;
+    let _ = pipe::register(SIGHUP, wake_up_writer)?;
 
     let mut config_file = ::std::fs::read_to_string(opts.config.clone())?;
     let mut config: ServiceConfig = toml::from_str(&config_file).or_else(|e| {
@@ -88,8 +284,10 @@ fn main() -> Result<()> {
     // outlive the run function. It is needed to give them all ownership of the front end handler
     // through an Arc.
     let mut front_end_handler = Arc::from(front_end_handler);
-    let mut listener = ServiceBuilder::start_listener(config.listener)?;
+    let mut listener =
+        ServiceBuilder::start_listener(&config.listener, wake_up_reader.try_clone()?)?;
     let mut threadpool = ServiceBuilder::build_threadpool(config.core_settings.thread_pool_size);
+    let connection_limit = ConnectionLimit::new(ServiceBuilder::max_connections(&config));
 
     // Notify systemd that the daemon is ready, the start command will block until this point.
     let _ = sd_notify::notify(false, &[sd_notify::NotifyState::Ready]);
@@ -118,25 +316,37 @@ fn main() -> Result<()> {
                 ))
             })?;
             front_end_handler = Arc::from(ServiceBuilder::build_service(&config)?);
-            listener = ServiceBuilder::start_listener(config.listener)?;
+            listener =
+                ServiceBuilder::start_listener(&config.listener, wake_up_reader.try_clone()?)?;
             threadpool = ServiceBuilder::build_threadpool(config.core_settings.thread_pool_size);
+            connection_limit.set_max_connections(ServiceBuilder::max_connections(&config));
 
             let _ = sd_notify::notify(false, &[sd_notify::NotifyState::Ready]);
             info!("Parsec configuration reloaded.");
         }
 
-        if let Some(stream) = listener.accept() {
-            let front_end_handler = front_end_handler.clone();
-            threadpool.execute(move || {
-                front_end_handler.handle_request(stream);
-            });
-        } else {
-            ::std::thread::sleep(Duration::from_millis(
-                config
-                    .core_settings
-                    .idle_listener_sleep_duration
-                    .unwrap_or(MAIN_LOOP_DEFAULT_SLEEP),
-            ));
+        if let Some(connection) = listener.accept() {
+            if let Some(permit) = connection_limit.try_acquire() {
+                let front_end_handler = front_end_handler.clone();
+                threadpool.execute(move || {
+                    front_end_handler.handle_request(connection);
+                    drop(permit);
+                });
+            } else {
+                let rejected_connections = connection_limit.rejected_connections();
+                warn!(
+                    "Too many connections, rejecting the new one ({} rejected so far).",
+                    rejected_connections
+                );
+                front_end_handler.reject_connection(connection);
+                let _ = sd_notify::notify(
+                    false,
+                    &[sd_notify::NotifyState::Status(format!(
+                        "{} connections rejected because the service was busy",
+                        rejected_connections
+                    ))],
+                );
+            }
         }
     }
 
@@ -148,6 +358,268 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+fn migrate(mappings_dir: &Path, key_encryption_key: Option<&Path>, dry_run: bool) -> Result<()> {
+    let mut builder =
+        OnDiskKeyInfoManagerBuilder::new().with_mappings_dir_path(mappings_dir.to_path_buf());
+    if let Some(key_encryption_key) = key_encryption_key {
+        builder = builder.with_key_encryption_key(KeyEncryptionKey::from_file(key_encryption_key)?);
+    }
+    let mut manager = builder.build()?;
+
+    let migrated = manager.migrate_mappings(dry_run)?;
+    for (key_triple, format) in migrated.iter() {
+        println!("{} ({})", key_triple, format);
+    }
+    if dry_run {
+        println!(
+            "{} mappings would be converted to record version {} and to the hashed layout.",
+            migrated.len(),
+            CURRENT_VERSION
+        );
+    } else {
+        println!(
+            "{} mappings converted to record version {} and to the hashed layout.",
+            migrated.len(),
+            CURRENT_VERSION
+        );
+    }
+
+    Ok(())
+}
+
+fn reconcile(
+    config_path: &str,
+    provider: Option<ProviderId>,
+    mode: &ReconciliationMode,
+) -> Result<()> {
+    let config = read_config(config_path)?;
+
+    let providers = ServiceBuilder::build_providers(&config)?;
+    let reports = reconciliation::reconcile_providers(&providers, provider, mode).map_err(|e| {
+        Error::new(
+            ErrorKind::Other,
+            format!("Failed to reconcile the providers ({})", e),
+        )
+    })?;
+    print_reconciliation_reports(&reports, mode);
+
+    Ok(())
+}
+
+fn reconciliation_mode(delete: bool, adopt: &Option<String>) -> ReconciliationMode {
+    match (delete, adopt) {
+        (true, _) => ReconciliationMode::Delete,
+        (false, Some(app_name)) => {
+            ReconciliationMode::Adopt(ApplicationName::new(app_name.clone()))
+        }
+        (false, None) => ReconciliationMode::DryRun,
+    }
+}
+
+fn print_reconciliation_reports(reports: &[ReconciliationReport], mode: &ReconciliationMode) {
+    for report in reports.iter() {
+        print!("{}", report);
+    }
+    if *mode == ReconciliationMode::DryRun && reports.iter().any(|report| !report.is_consistent()) {
+        println!("Run again with --delete or --adopt to repair the inconsistencies.");
+    }
+}
+
+fn export_mappings(
+    config_path: &str,
+    archive_path: &Path,
+    backup_key: &Path,
+    protection: ArchiveProtection,
+) -> Result<()> {
+    let config = read_config(config_path)?;
+    let key = BackupKey::from_file(backup_key)?;
+    let key_info_managers = ServiceBuilder::build_provider_key_info_managers(&config)?;
+
+    let archive = backup::export_archive(&key_info_managers, &key, protection)?;
+    ::std::fs::write(archive_path, archive)?;
+    println!("Key mappings exported to {}.", archive_path.display());
+
+    Ok(())
+}
+
+fn import_mappings(
+    config_path: &str,
+    archive_path: &Path,
+    backup_key: &Path,
+    policy: ConflictPolicy,
+) -> Result<()> {
+    let config = read_config(config_path)?;
+    let key = BackupKey::from_file(backup_key)?;
+    let key_info_managers = ServiceBuilder::build_provider_key_info_managers(&config)?;
+
+    let archive = ::std::fs::read(archive_path)?;
+    let report = backup::import_archive(&key_info_managers, &archive, &key, policy)?;
+    print_import_report(&report, policy)
+}
+
+fn archive_protection(sign_only: bool) -> ArchiveProtection {
+    if sign_only {
+        ArchiveProtection::Signed
+    } else {
+        ArchiveProtection::Encrypted
+    }
+}
+
+fn print_import_report(report: &ImportReport, policy: ConflictPolicy) -> Result<()> {
+    for conflict in report.conflicts.iter() {
+        println!("Conflict: {}", conflict);
+    }
+    for key_triple in report.skipped.iter() {
+        println!(
+            "Skipped, no key info manager for the provider: {}",
+            key_triple
+        );
+    }
+    println!(
+        "{} mappings imported, {} unchanged, {} conflicts, {} skipped.",
+        report.imported.len(),
+        report.unchanged.len(),
+        report.conflicts.len(),
+        report.skipped.len()
+    );
+    if policy == ConflictPolicy::Abort && !report.conflicts.is_empty() {
+        return Err(Error::new(
+            ErrorKind::Other,
+            "Conflicts found, nothing was imported",
+        ));
+    }
+
+    Ok(())
+}
+
+fn admin(config_path: &str, command: &AdminCommand) -> Result<()> {
+    let config = read_config(config_path)?;
+
+    let socket_path = config.listener.admin_socket_path.ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "No admin socket in the configuration",
+        )
+    })?;
+    let socket_path = Path::new(&socket_path);
+
+    match command {
+        AdminCommand::ListKeys => {
+            match admin::send_admin_operation(socket_path, AdminOperation::ListAllKeys)? {
+                AdminResult::ListAllKeys(key_triples) => {
+                    for key_triple in key_triples.iter() {
+                        println!("{}", key_triple);
+                    }
+                    println!("{} keys.", key_triples.len());
+                }
+                result => return Err(unexpected_result(result)),
+            }
+        }
+        AdminCommand::CheckConsistency => {
+            match admin::send_admin_operation(socket_path, AdminOperation::CheckConsistency)? {
+                AdminResult::CheckConsistency(report) => {
+                    print!("{}", report);
+                    if report.is_consistent() {
+                        println!("The key mappings are consistent.");
+                    }
+                }
+                result => return Err(unexpected_result(result)),
+            }
+        }
+        AdminCommand::Reconcile {
+            provider,
+            delete,
+            adopt,
+        } => {
+            let mode = reconciliation_mode(*delete, adopt);
+            let operation = AdminOperation::Reconcile {
+                provider_id: *provider,
+                mode: mode.clone(),
+            };
+            match admin::send_admin_operation(socket_path, operation)? {
+                AdminResult::Reconcile(reports) => {
+                    print_reconciliation_reports(&reports, &mode);
+                }
+                result => return Err(unexpected_result(result)),
+            }
+        }
+        AdminCommand::ExportMappings {
+            archive,
+            backup_key,
+            sign_only,
+        } => {
+            let operation = AdminOperation::ExportMappings {
+                backup_key: backup::read_key_file(backup_key)?.to_vec(),
+                protection: archive_protection(*sign_only),
+            };
+            match admin::send_admin_operation(socket_path, operation)? {
+                AdminResult::ExportMappings(archive_bytes) => {
+                    ::std::fs::write(archive, archive_bytes)?;
+                    println!("Key mappings exported to {}.", archive.display());
+                }
+                result => return Err(unexpected_result(result)),
+            }
+        }
+        AdminCommand::ImportMappings {
+            archive,
+            backup_key,
+            on_conflict,
+        } => {
+            let operation = AdminOperation::ImportMappings {
+                archive: ::std::fs::read(archive)?,
+                backup_key: backup::read_key_file(backup_key)?.to_vec(),
+                policy: *on_conflict,
+            };
+            match admin::send_admin_operation(socket_path, operation)? {
+                AdminResult::ImportMappings(report) => {
+                    print_import_report(&report, *on_conflict)?;
+                }
+                result => return Err(unexpected_result(result)),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn unexpected_result(result: AdminResult) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Unexpected result of the admin operation ({:?})", result),
+    )
+}
+
+fn read_config(config_path: &str) -> Result<ServiceConfig> {
+    let config_file = ::std::fs::read_to_string(config_path)?;
+    let config: ServiceConfig = toml::from_str(&config_file).map_err(|e| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("Failed to parse service configuration ({})", e),
+        )
+    })?;
+    log_setup(&config);
+
+    Ok(config)
+}
+
+fn conflict_policy_from_name(name: &str) -> std::result::Result<ConflictPolicy, String> {
+    match name {
+        "abort" => Ok(ConflictPolicy::Abort),
+        "skip" => Ok(ConflictPolicy::Skip),
+        "overwrite" => Ok(ConflictPolicy::Overwrite),
+        _ => Err(format!("unknown conflict policy \"{}\"", name)),
+    }
+}
+
+fn provider_from_name(name: &str) -> std::result::Result<ProviderId, String> {
+    match name {
+        "MbedCrypto" => Ok(ProviderId::MbedCrypto),
+        "Pkcs11" => Ok(ProviderId::Pkcs11),
+        "Tpm" => Ok(ProviderId::Tpm),
+        _ => Err(format!("unknown provider \"{}\"", name)),
+    }
+}
+
 fn log_setup(config: &ServiceConfig) {
     let mut env_log_builder = env_logger::builder();
 
@@ -0,0 +1,457 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+//! Per-application access control
+//!
+//! The access policy decides, once a request has been authenticated and before it is dispatched,
+//! whether the application is allowed to perform the requested operation on the requested
+//! provider. The policy is made of an ordered list of rules, each matching an application name, a
+//! provider and a set of opcodes. The first rule matching the request gives the decision; if none
+//! matches, the default decision of the policy is used.
+//!
+//! Application names can be given exactly, with `*` to match any application (including
+//! unauthenticated requests) or with a trailing `*` to match all names starting with the given
+//! prefix (e.g. `spiffe://example.org/ci/*`). Providers and opcodes can also be given as `*`.
+//!
+//! There is no response status dedicated to access control denials in the wire protocol: the
+//! closest existing one, `PsaErrorNotPermitted`, is returned.
+use crate::authenticators::ApplicationName;
+use log::{error, warn};
+use parsec_interface::requests::{Opcode, ProviderId, ResponseStatus, Result};
+use serde::Deserialize;
+use std::io::{Error, ErrorKind};
+
+/// Wildcard matching any value in a rule.
+const WILDCARD: &str = "*";
+
+/// Decision taken for a request matching a rule.
+#[derive(Copy, Clone, Deserialize, Debug, PartialEq)]
+pub enum Decision {
+    Allow,
+    Deny,
+}
+
+/// Configuration of an access rule, as found in the service configuration file.
+#[derive(Deserialize, Debug)]
+pub struct AccessRuleConfig {
+    pub application: String,
+    pub provider: String,
+    pub opcodes: Vec<String>,
+    pub decision: Decision,
+}
+
+/// Configuration of the access control policy, as found in the service configuration file.
+#[derive(Deserialize, Debug)]
+pub struct AccessControlConfig {
+    pub default_decision: Option<Decision>,
+    pub rule: Option<Vec<AccessRuleConfig>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum ApplicationPattern {
+    Any,
+    Exact(String),
+    Prefix(String),
+}
+
+impl ApplicationPattern {
+    fn new(pattern: &str) -> Self {
+        if pattern == WILDCARD {
+            ApplicationPattern::Any
+        } else if pattern.ends_with(WILDCARD) {
+            ApplicationPattern::Prefix(pattern.trim_end_matches(WILDCARD).to_string())
+        } else {
+            ApplicationPattern::Exact(pattern.to_string())
+        }
+    }
+
+    fn matches(&self, app_name: Option<&ApplicationName>) -> bool {
+        match (self, app_name) {
+            (ApplicationPattern::Any, _) => true,
+            (ApplicationPattern::Exact(name), Some(app_name)) => app_name.get_name() == name,
+            (ApplicationPattern::Prefix(prefix), Some(app_name)) => {
+                app_name.get_name().starts_with(prefix.as_str())
+            }
+            (_, None) => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct AccessRule {
+    application: ApplicationPattern,
+    // `None` matches all the providers.
+    provider: Option<ProviderId>,
+    // `None` matches all the opcodes.
+    opcodes: Option<Vec<Opcode>>,
+    decision: Decision,
+}
+
+impl AccessRule {
+    fn from_config(config: &AccessRuleConfig) -> std::io::Result<Self> {
+        let provider = if config.provider == WILDCARD {
+            None
+        } else {
+            Some(provider_from_name(&config.provider)?)
+        };
+
+        let opcodes = if config.opcodes.iter().any(|opcode| opcode == WILDCARD) {
+            None
+        } else {
+            Some(
+                config
+                    .opcodes
+                    .iter()
+                    .map(|opcode| opcode_from_name(opcode))
+                    .collect::<std::io::Result<Vec<Opcode>>>()?,
+            )
+        };
+
+        Ok(AccessRule {
+            application: ApplicationPattern::new(&config.application),
+            provider,
+            opcodes,
+            decision: config.decision,
+        })
+    }
+
+    fn matches(
+        &self,
+        app_name: Option<&ApplicationName>,
+        provider: ProviderId,
+        opcode: Opcode,
+    ) -> bool {
+        let opcode_matches = match &self.opcodes {
+            Some(opcodes) => opcodes.contains(&opcode),
+            None => true,
+        };
+
+        self.application.matches(app_name)
+            && self.provider.unwrap_or(provider) == provider
+            && opcode_matches
+    }
+}
+
+fn provider_from_name(name: &str) -> std::io::Result<ProviderId> {
+    match name {
+        "Core" => Ok(ProviderId::Core),
+        "MbedCrypto" => Ok(ProviderId::MbedCrypto),
+        "Pkcs11" => Ok(ProviderId::Pkcs11),
+        "Tpm" => Ok(ProviderId::Tpm),
+        _ => {
+            error!("Unknown provider \"{}\" in access control rule.", name);
+            Err(Error::new(
+                ErrorKind::InvalidData,
+                "unknown provider in access control rule",
+            ))
+        }
+    }
+}
+
+fn opcode_from_name(name: &str) -> std::io::Result<Opcode> {
+    match name {
+        "Ping" => Ok(Opcode::Ping),
+        "ListProviders" => Ok(Opcode::ListProviders),
+        "ListOpcodes" => Ok(Opcode::ListOpcodes),
+        "ListAuthenticators" => Ok(Opcode::ListAuthenticators),
+        "ListKeys" => Ok(Opcode::ListKeys),
+        "ListClients" => Ok(Opcode::ListClients),
+        "DeleteClient" => Ok(Opcode::DeleteClient),
+        "PsaGenerateKey" => Ok(Opcode::PsaGenerateKey),
+        "PsaDestroyKey" => Ok(Opcode::PsaDestroyKey),
+        "PsaSignHash" => Ok(Opcode::PsaSignHash),
+        "PsaVerifyHash" => Ok(Opcode::PsaVerifyHash),
+        "PsaImportKey" => Ok(Opcode::PsaImportKey),
+        "PsaExportPublicKey" => Ok(Opcode::PsaExportPublicKey),
+        "PsaExportKey" => Ok(Opcode::PsaExportKey),
+        "PsaAsymmetricEncrypt" => Ok(Opcode::PsaAsymmetricEncrypt),
+        "PsaAsymmetricDecrypt" => Ok(Opcode::PsaAsymmetricDecrypt),
+        "PsaGenerateRandom" => Ok(Opcode::PsaGenerateRandom),
+        "PsaHashCompute" => Ok(Opcode::PsaHashCompute),
+        "PsaHashCompare" => Ok(Opcode::PsaHashCompare),
+        "PsaAeadEncrypt" => Ok(Opcode::PsaAeadEncrypt),
+        "PsaAeadDecrypt" => Ok(Opcode::PsaAeadDecrypt),
+        "PsaRawKeyAgreement" => Ok(Opcode::PsaRawKeyAgreement),
+        "PsaCipherEncrypt" => Ok(Opcode::PsaCipherEncrypt),
+        "PsaCipherDecrypt" => Ok(Opcode::PsaCipherDecrypt),
+        "PsaSignMessage" => Ok(Opcode::PsaSignMessage),
+        "PsaVerifyMessage" => Ok(Opcode::PsaVerifyMessage),
+        "AttestKey" => Ok(Opcode::AttestKey),
+        "PrepareKeyAttestation" => Ok(Opcode::PrepareKeyAttestation),
+        "CanDoCrypto" => Ok(Opcode::CanDoCrypto),
+        _ => {
+            error!("Unknown opcode \"{}\" in access control rule.", name);
+            Err(Error::new(
+                ErrorKind::InvalidData,
+                "unknown opcode in access control rule",
+            ))
+        }
+    }
+}
+
+/// Access control policy
+///
+/// Ordered list of rules evaluated for each request before it is dispatched.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccessPolicy {
+    rules: Vec<AccessRule>,
+    default_decision: Decision,
+}
+
+impl AccessPolicy {
+    /// Policy allowing every request, used when no access control is configured.
+    pub fn allow_all() -> Self {
+        AccessPolicy {
+            rules: Vec::new(),
+            default_decision: Decision::Allow,
+        }
+    }
+
+    /// Check if the application is allowed to perform the operation on the provider.
+    ///
+    /// # Errors
+    ///
+    /// If the request is denied by the policy, returns `ResponseStatus::PsaErrorNotPermitted`.
+    pub fn check(
+        &self,
+        app_name: Option<&ApplicationName>,
+        provider: ProviderId,
+        opcode: Opcode,
+    ) -> Result<()> {
+        let decision = self
+            .rules
+            .iter()
+            .find(|rule| rule.matches(app_name, provider, opcode))
+            .map_or(self.default_decision, |rule| rule.decision);
+
+        match decision {
+            Decision::Allow => Ok(()),
+            Decision::Deny => {
+                warn!(
+                    "Access denied to application {} for opcode {:?} on provider {}.",
+                    app_name.map_or("(unauthenticated)", |app_name| app_name.get_name()),
+                    opcode,
+                    provider
+                );
+                Err(ResponseStatus::PsaErrorNotPermitted)
+            }
+        }
+    }
+}
+
+/// Builder for `AccessPolicy`
+#[derive(Debug, Default)]
+pub struct AccessPolicyBuilder {
+    default_decision: Option<Decision>,
+    rules: Vec<AccessRule>,
+}
+
+impl AccessPolicyBuilder {
+    pub fn new() -> Self {
+        AccessPolicyBuilder {
+            default_decision: None,
+            rules: Vec::new(),
+        }
+    }
+
+    pub fn with_default_decision(mut self, default_decision: Decision) -> Self {
+        self.default_decision = Some(default_decision);
+        self
+    }
+
+    pub fn with_rule(mut self, rule: &AccessRuleConfig) -> std::io::Result<Self> {
+        self.rules.push(AccessRule::from_config(rule)?);
+        Ok(self)
+    }
+
+    pub fn with_config(mut self, config: &AccessControlConfig) -> std::io::Result<Self> {
+        if let Some(default_decision) = config.default_decision {
+            self = self.with_default_decision(default_decision);
+        }
+        for rule in config.rule.as_ref().unwrap_or(&Vec::new()) {
+            self = self.with_rule(rule)?;
+        }
+        Ok(self)
+    }
+
+    pub fn build(self) -> std::io::Result<AccessPolicy> {
+        Ok(AccessPolicy {
+            rules: self.rules,
+            default_decision: self
+                .default_decision
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "default decision is missing"))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rule(
+        application: &str,
+        provider: &str,
+        opcodes: &[&str],
+        decision: Decision,
+    ) -> AccessRuleConfig {
+        AccessRuleConfig {
+            application: application.to_string(),
+            provider: provider.to_string(),
+            opcodes: opcodes.iter().map(|opcode| opcode.to_string()).collect(),
+            decision,
+        }
+    }
+
+    fn app(name: &str) -> ApplicationName {
+        ApplicationName::new(name.to_string())
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let policy = AccessPolicyBuilder::new()
+            .with_default_decision(Decision::Deny)
+            .with_rule(&rule("ci-job", "Tpm", &["PsaVerifyHash"], Decision::Allow))
+            .unwrap()
+            .with_rule(&rule("ci-job", "*", &["*"], Decision::Deny))
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert!(policy
+            .check(Some(&app("ci-job")), ProviderId::Tpm, Opcode::PsaVerifyHash)
+            .is_ok());
+        assert_eq!(
+            policy.check(
+                Some(&app("ci-job")),
+                ProviderId::Tpm,
+                Opcode::PsaGenerateKey
+            ),
+            Err(ResponseStatus::PsaErrorNotPermitted)
+        );
+        assert_eq!(
+            policy.check(Some(&app("ci-job")), ProviderId::Tpm, Opcode::PsaDestroyKey),
+            Err(ResponseStatus::PsaErrorNotPermitted)
+        );
+    }
+
+    #[test]
+    fn default_decision() {
+        let policy = AccessPolicyBuilder::new()
+            .with_default_decision(Decision::Allow)
+            .with_rule(&rule("ci-job", "*", &["PsaGenerateKey"], Decision::Deny))
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert!(policy
+            .check(
+                Some(&app("other")),
+                ProviderId::Pkcs11,
+                Opcode::PsaGenerateKey
+            )
+            .is_ok());
+        assert_eq!(
+            policy.check(
+                Some(&app("ci-job")),
+                ProviderId::Pkcs11,
+                Opcode::PsaGenerateKey
+            ),
+            Err(ResponseStatus::PsaErrorNotPermitted)
+        );
+    }
+
+    #[test]
+    fn application_wildcards() {
+        let policy = AccessPolicyBuilder::new()
+            .with_default_decision(Decision::Deny)
+            .with_rule(&rule(
+                "spiffe://example.org/ci/*",
+                "*",
+                &["PsaSignHash"],
+                Decision::Allow,
+            ))
+            .unwrap()
+            .with_rule(&rule("*", "Core", &["Ping"], Decision::Allow))
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert!(policy
+            .check(
+                Some(&app("spiffe://example.org/ci/job-1")),
+                ProviderId::MbedCrypto,
+                Opcode::PsaSignHash
+            )
+            .is_ok());
+        assert_eq!(
+            policy.check(
+                Some(&app("spiffe://example.org/web")),
+                ProviderId::MbedCrypto,
+                Opcode::PsaSignHash
+            ),
+            Err(ResponseStatus::PsaErrorNotPermitted)
+        );
+        // Unauthenticated requests are only matched by the `*` application pattern.
+        assert!(policy.check(None, ProviderId::Core, Opcode::Ping).is_ok());
+        assert_eq!(
+            policy.check(None, ProviderId::MbedCrypto, Opcode::PsaSignHash),
+            Err(ResponseStatus::PsaErrorNotPermitted)
+        );
+    }
+
+    #[test]
+    fn all_opcode_names() {
+        let opcodes = [
+            ("Ping", Opcode::Ping),
+            ("ListProviders", Opcode::ListProviders),
+            ("ListOpcodes", Opcode::ListOpcodes),
+            ("ListAuthenticators", Opcode::ListAuthenticators),
+            ("ListKeys", Opcode::ListKeys),
+            ("ListClients", Opcode::ListClients),
+            ("DeleteClient", Opcode::DeleteClient),
+            ("PsaGenerateKey", Opcode::PsaGenerateKey),
+            ("PsaDestroyKey", Opcode::PsaDestroyKey),
+            ("PsaSignHash", Opcode::PsaSignHash),
+            ("PsaVerifyHash", Opcode::PsaVerifyHash),
+            ("PsaImportKey", Opcode::PsaImportKey),
+            ("PsaExportPublicKey", Opcode::PsaExportPublicKey),
+            ("PsaExportKey", Opcode::PsaExportKey),
+            ("PsaAsymmetricEncrypt", Opcode::PsaAsymmetricEncrypt),
+            ("PsaAsymmetricDecrypt", Opcode::PsaAsymmetricDecrypt),
+            ("PsaGenerateRandom", Opcode::PsaGenerateRandom),
+            ("PsaHashCompute", Opcode::PsaHashCompute),
+            ("PsaHashCompare", Opcode::PsaHashCompare),
+            ("PsaAeadEncrypt", Opcode::PsaAeadEncrypt),
+            ("PsaAeadDecrypt", Opcode::PsaAeadDecrypt),
+            ("PsaRawKeyAgreement", Opcode::PsaRawKeyAgreement),
+            ("PsaCipherEncrypt", Opcode::PsaCipherEncrypt),
+            ("PsaCipherDecrypt", Opcode::PsaCipherDecrypt),
+            ("PsaSignMessage", Opcode::PsaSignMessage),
+            ("PsaVerifyMessage", Opcode::PsaVerifyMessage),
+            ("AttestKey", Opcode::AttestKey),
+            ("PrepareKeyAttestation", Opcode::PrepareKeyAttestation),
+            ("CanDoCrypto", Opcode::CanDoCrypto),
+        ];
+
+        for (name, opcode) in opcodes.iter() {
+            let policy = AccessPolicyBuilder::new()
+                .with_default_decision(Decision::Deny)
+                .with_rule(&rule("app", "*", &[name], Decision::Allow))
+                .unwrap()
+                .build()
+                .unwrap();
+            assert!(policy
+                .check(Some(&app("app")), ProviderId::Core, *opcode)
+                .is_ok());
+        }
+    }
+
+    #[test]
+    fn invalid_rules() {
+        assert!(AccessPolicyBuilder::new()
+            .with_rule(&rule("app", "Unknown", &["Ping"], Decision::Allow))
+            .is_err());
+        assert!(AccessPolicyBuilder::new()
+            .with_rule(&rule("app", "Tpm", &["PsaDoSomething"], Decision::Allow))
+            .is_err());
+    }
+}
@@ -0,0 +1,129 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+//! Limit on the number of connections handled at the same time
+//!
+//! Each accepted connection takes a permit from the `ConnectionLimit` before being given to the
+//! thread pool and releases it once it has been handled. When all the permits are taken, new
+//! connections are rejected instead of being queued, which bounds the memory and the file
+//! descriptors used by a burst of clients. The number of rejected connections is kept for
+//! monitoring.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Counter of the connections being handled or waiting to be handled
+#[derive(Debug)]
+pub struct ConnectionLimit {
+    max_connections: AtomicUsize,
+    active_connections: AtomicUsize,
+    rejected_connections: AtomicUsize,
+}
+
+/// Permit for a connection to be handled, released when dropped
+#[derive(Debug)]
+pub struct ConnectionPermit {
+    limit: Arc<ConnectionLimit>,
+}
+
+impl ConnectionLimit {
+    /// Create a limit allowing `max_connections` connections at the same time.
+    pub fn new(max_connections: usize) -> Arc<ConnectionLimit> {
+        Arc::new(ConnectionLimit {
+            max_connections: AtomicUsize::new(max_connections),
+            active_connections: AtomicUsize::new(0),
+            rejected_connections: AtomicUsize::new(0),
+        })
+    }
+
+    /// Change the maximum number of connections, for example after the configuration has been
+    /// reloaded. The connections already accepted are not affected.
+    pub fn set_max_connections(&self, max_connections: usize) {
+        self.max_connections
+            .store(max_connections, Ordering::Relaxed);
+    }
+
+    /// Take a permit for a new connection. Returns `None`, and counts the connection as rejected,
+    /// if the maximum number of connections is reached.
+    pub fn try_acquire(self: &Arc<Self>) -> Option<ConnectionPermit> {
+        let max_connections = self.max_connections.load(Ordering::Relaxed);
+        let mut active_connections = self.active_connections.load(Ordering::Relaxed);
+        loop {
+            if active_connections >= max_connections {
+                let _ = self.rejected_connections.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+            match self.active_connections.compare_exchange_weak(
+                active_connections,
+                active_connections + 1,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    return Some(ConnectionPermit {
+                        limit: self.clone(),
+                    })
+                }
+                Err(current) => active_connections = current,
+            }
+        }
+    }
+
+    /// Number of connections currently being handled or waiting to be handled.
+    pub fn active_connections(&self) -> usize {
+        self.active_connections.load(Ordering::Relaxed)
+    }
+
+    /// Number of connections rejected since the service started.
+    pub fn rejected_connections(&self) -> usize {
+        self.rejected_connections.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let _ = self.limit.active_connections.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ConnectionLimit;
+
+    #[test]
+    fn connections_over_limit_rejected() {
+        let limit = ConnectionLimit::new(2);
+
+        let first = limit
+            .try_acquire()
+            .expect("First connection should be accepted");
+        let _second = limit
+            .try_acquire()
+            .expect("Second connection should be accepted");
+        assert!(limit.try_acquire().is_none());
+        assert_eq!(limit.active_connections(), 2);
+        assert_eq!(limit.rejected_connections(), 1);
+
+        // Handling a connection frees a slot.
+        drop(first);
+        assert_eq!(limit.active_connections(), 1);
+        let _third = limit
+            .try_acquire()
+            .expect("Third connection should be accepted");
+        assert_eq!(limit.rejected_connections(), 1);
+    }
+
+    #[test]
+    fn max_connections_changed() {
+        let limit = ConnectionLimit::new(1);
+
+        let _first = limit
+            .try_acquire()
+            .expect("First connection should be accepted");
+        assert!(limit.try_acquire().is_none());
+
+        limit.set_max_connections(2);
+        let _second = limit
+            .try_acquire()
+            .expect("Second connection should be accepted");
+        assert_eq!(limit.rejected_connections(), 1);
+    }
+}
@@ -3,58 +3,85 @@
 //! Service front using Unix domain sockets
 //!
 //! Expose Parsec functionality using Unix domain sockets as an IPC layer.
-//! The local socket is created at a predefined location.
+//! The local socket is created at the location given in the configuration, with the
+//! requested permissions and owning group.
 use super::listener;
+use listener::Connection;
+use listener::ConnectionMetadata;
 use listener::Listen;
-use listener::ReadWrite;
-use log::error;
+use log::{error, warn};
+use std::ffi::CString;
 use std::fs;
+use std::fs::{DirBuilder, Permissions};
+use std::io::Read;
 use std::io::{Error, ErrorKind, Result};
-use std::os::unix::io::FromRawFd;
-use std::os::unix::net::UnixListener;
-use std::path::Path;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
-static SOCKET_PATH: &str = "/tmp/security-daemon-socket";
+/// Path of the socket used when none is given in the configuration.
+pub const DEFAULT_SOCKET_PATH: &str = "/tmp/security-daemon-socket";
+
+/// Permissions of the socket parent directory when it is created by the listener: only the owner
+/// can modify its content but clients can still reach the socket.
+const SOCKET_DIR_MODE: u32 = 0o755;
+/// Permissions of the socket parent directory if it is created with an owning group: other users
+/// can not reach the socket.
+const SOCKET_DIR_GROUP_MODE: u32 = 0o750;
 
 /// Unix Domain Socket IPC manager
 ///
 /// Listener implementation for Unix sockets as the underlying IPC mechanism.
 ///
-/// Holds references to a `UnixListener`.
+/// Holds references to a `UnixListener`, to the one of the admin socket if configured and to the
+/// stream used to wake them up.
 #[derive(Debug)]
 pub struct DomainSocketListener {
     listener: UnixListener,
+    admin_listener: Option<UnixListener>,
     timeout: Duration,
+    wake_up: Option<UnixStream>,
 }
 
 impl DomainSocketListener {
     /// Initialise the connection to the Unix socket.
     ///
-    /// # Panics
-    /// - if a file/socket exists at the path specified for the socket and `remove_file`
-    /// fails
-    /// - if binding to the socket path fails
-    pub fn new(timeout: Duration) -> Result<Self> {
+    /// The parent directory of the socket is created if it does not exist. If a socket already
+    /// exists at `socket_path` (e.g. left by a previous instance), it is removed. The mode and
+    /// the group of the socket are set if given. The admin socket, if any, is created in the same
+    /// way, with the same mode and group.
+    ///
+    /// # Errors
+    /// - if something which is not a socket exists at the path specified for the socket
+    /// - if the socket group does not exist
+    /// - if creating, binding or changing the permissions of the socket fails
+    pub fn new(
+        timeout: Duration,
+        socket_path: &Path,
+        socket_mode: Option<u32>,
+        socket_group: Option<&str>,
+        admin_socket_path: Option<&Path>,
+        wake_up: Option<UnixStream>,
+    ) -> Result<Self> {
+        let gid = match socket_group {
+            Some(group) => Some(group_id(group)?),
+            None => None,
+        };
+
         // If this Parsec instance was socket activated (see the `parsec.socket`
         // file), the listener will be opened by systemd and passed to the
         // process.
         // If Parsec was service activated or not started under systemd, this
         // will return `0`.
         let listener = match sd_notify::listen_fds()? {
-            0 => {
-                let socket = Path::new(SOCKET_PATH);
-
-                if socket.exists() {
-                    fs::remove_file(&socket)?;
-                }
-
-                let listener = UnixListener::bind(SOCKET_PATH)?;
-                listener.set_nonblocking(true)?;
-
-                listener
-            }
+            0 => bind_socket(socket_path, socket_mode, gid)?,
             1 => {
+                if socket_mode.is_some() || socket_group.is_some() {
+                    warn!("The socket was created by systemd, its mode and group are set by the socket unit and not by the configuration.");
+                }
                 // No need to set the socket as non-blocking, parsec.service
                 // already requests that.
                 let nfd = sd_notify::SD_LISTEN_FDS_START;
@@ -74,7 +101,78 @@ impl DomainSocketListener {
             }
         };
 
-        Ok(Self { listener, timeout })
+        let admin_listener = match admin_socket_path {
+            Some(admin_socket_path) => Some(bind_socket(admin_socket_path, socket_mode, gid)?),
+            None => None,
+        };
+
+        if let Some(wake_up) = &wake_up {
+            wake_up.set_nonblocking(true)?;
+        }
+
+        Ok(Self {
+            listener,
+            admin_listener,
+            timeout,
+            wake_up,
+        })
+    }
+
+    /// Block until a connection is pending on one of the sockets or until the listener is woken
+    /// up. Returns the listener of the socket on which a connection can be accepted and whether it
+    /// is the admin socket.
+    fn wait_for_connection(&self) -> Option<(&UnixListener, bool)> {
+        let mut listeners = vec![(&self.listener, false)];
+        if let Some(admin_listener) = &self.admin_listener {
+            listeners.push((admin_listener, true));
+        }
+        let mut fds: Vec<libc::pollfd> = listeners
+            .iter()
+            .map(|(listener, _)| libc::pollfd {
+                fd: listener.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+        if let Some(wake_up) = &self.wake_up {
+            fds.push(libc::pollfd {
+                fd: wake_up.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            });
+        }
+
+        // Safe as the pointer and the length given describe a valid array of pollfd structures
+        // for the duration of the call. A negative timeout makes the call wait indefinitely.
+        let ret = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) };
+        if ret < 0 {
+            let err = Error::last_os_error();
+            // Being interrupted by a signal is expected.
+            if err.kind() != ErrorKind::Interrupted {
+                error!("Failed to wait for connections ({})", err);
+            }
+            return None;
+        }
+
+        if let Some(wake_up) = &self.wake_up {
+            if fds[listeners.len()].revents != 0 {
+                // Empty the wake up stream so that the next call blocks again.
+                let mut buffer = [0; 64];
+                let mut wake_up = wake_up;
+                while let Ok(n) = wake_up.read(&mut buffer) {
+                    if n == 0 {
+                        break;
+                    }
+                }
+                return None;
+            }
+        }
+
+        listeners
+            .into_iter()
+            .zip(fds)
+            .find(|(_, fd)| fd.revents != 0)
+            .map(|(listener, _)| listener)
     }
 }
 
@@ -83,8 +181,10 @@ impl Listen for DomainSocketListener {
         self.timeout = duration;
     }
 
-    fn accept(&self) -> Option<Box<dyn ReadWrite + Send>> {
-        let stream_result = self.listener.accept();
+    fn accept(&self) -> Option<Connection> {
+        let (listener, admin) = self.wait_for_connection()?;
+
+        let stream_result = listener.accept();
         match stream_result {
             Ok((stream, _)) => {
                 if let Err(err) = stream.set_read_timeout(Some(self.timeout)) {
@@ -97,11 +197,21 @@ impl Listen for DomainSocketListener {
                     error!("Failed to set stream as blocking ({})", err);
                     None
                 } else {
-                    Some(Box::from(stream))
+                    match peer_credentials::peer_cred(&stream) {
+                        Ok(metadata) => Some(Connection {
+                            stream: Box::from(stream),
+                            metadata: Some(metadata),
+                            admin,
+                        }),
+                        Err(err) => {
+                            error!("Failed to get the peer credentials ({})", err);
+                            None
+                        }
+                    }
                 }
             }
             Err(err) => {
-                // Check if the error is because no connections are currently present.
+                // The pending connection might have been closed before being accepted.
                 if err.kind() != ErrorKind::WouldBlock {
                     // Only log the real errors.
                     error!("Failed to connect with a UnixStream ({})", err);
@@ -112,15 +222,211 @@ impl Listen for DomainSocketListener {
     }
 }
 
+/// Create a non-blocking socket at the given path, with the mode and group given.
+fn bind_socket(
+    socket_path: &Path,
+    socket_mode: Option<u32>,
+    gid: Option<libc::gid_t>,
+) -> Result<UnixListener> {
+    prepare_socket_path(socket_path, gid)?;
+
+    let listener = UnixListener::bind(socket_path)?;
+    listener.set_nonblocking(true)?;
+
+    if let Some(gid) = gid {
+        set_group(socket_path, gid)?;
+    }
+    if let Some(mode) = socket_mode {
+        fs::set_permissions(socket_path, Permissions::from_mode(mode))?;
+    }
+
+    Ok(listener)
+}
+
+/// Create the parent directory of the socket if needed and remove a socket left at its path.
+///
+/// Anything else than a socket at the path is left untouched and an error is returned.
+fn prepare_socket_path(socket_path: &Path, gid: Option<libc::gid_t>) -> Result<()> {
+    if let Some(parent) = socket_path.parent() {
+        if !parent.as_os_str().is_empty() && !parent.exists() {
+            let mode = if gid.is_some() {
+                SOCKET_DIR_GROUP_MODE
+            } else {
+                SOCKET_DIR_MODE
+            };
+            DirBuilder::new()
+                .recursive(true)
+                .mode(mode)
+                .create(parent)?;
+            if let Some(gid) = gid {
+                set_group(parent, gid)?;
+            }
+        }
+    }
+
+    match fs::symlink_metadata(socket_path) {
+        Ok(metadata) => {
+            if metadata.file_type().is_socket() {
+                fs::remove_file(socket_path)
+            } else {
+                error!(
+                    "Refusing to remove {}: it exists and is not a socket.",
+                    socket_path.display()
+                );
+                Err(Error::new(
+                    ErrorKind::AlreadyExists,
+                    "socket path is used by something else than a socket",
+                ))
+            }
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Find the identifier of a group from its name.
+fn group_id(name: &str) -> Result<libc::gid_t> {
+    let c_name = CString::new(name)
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "group name contains a nul byte"))?;
+    // Safe as libc::group is a plain C structure for which all zeroes is a valid value.
+    let mut group: libc::group = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::group = std::ptr::null_mut();
+    let mut buffer: Vec<libc::c_char> = vec![0; 1024];
+
+    loop {
+        // Safe as all the pointers are valid for the duration of the call and the buffer length
+        // given is its real length.
+        let ret = unsafe {
+            libc::getgrnam_r(
+                c_name.as_ptr(),
+                &mut group,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        };
+        if ret == libc::ERANGE {
+            // The buffer is too small to hold the group entry.
+            buffer.resize(buffer.len() * 2, 0);
+        } else if ret != 0 {
+            return Err(Error::from_raw_os_error(ret));
+        } else if result.is_null() {
+            error!("Group \"{}\" does not exist.", name);
+            return Err(Error::new(ErrorKind::NotFound, "socket group not found"));
+        } else {
+            return Ok(group.gr_gid);
+        }
+    }
+}
+
+/// Change the group of a file, keeping its owner.
+fn set_group(path: &Path, gid: libc::gid_t) -> Result<()> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "path contains a nul byte"))?;
+    // Passing -1 as the owner leaves it unchanged.
+    // Safe as the path is a valid nul-terminated string for the duration of the call.
+    let ret = unsafe { libc::chown(c_path.as_ptr(), libc::uid_t::MAX, gid) };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+/// Retrieval of the credentials of the process connected at the other end of a Unix stream.
+///
+/// The credentials are queried from the kernel and can not be forged by the peer.
+mod peer_credentials {
+    use super::ConnectionMetadata;
+    use std::io::{Error, Result};
+    use std::mem;
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::UnixStream;
+
+    /// Get the credentials of the peer with the `SO_PEERCRED` socket option.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn peer_cred(stream: &UnixStream) -> Result<ConnectionMetadata> {
+        let mut ucred = libc::ucred {
+            pid: 0,
+            uid: 0,
+            gid: 0,
+        };
+        let ucred_size = mem::size_of::<libc::ucred>();
+        let mut ucred_len = ucred_size as libc::socklen_t;
+        let ucred_ptr: *mut libc::ucred = &mut ucred;
+
+        // Safe as the pointers given are valid for the duration of the call and the length
+        // passed is the size of the structure pointed to.
+        let ret = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                ucred_ptr as *mut libc::c_void,
+                &mut ucred_len,
+            )
+        };
+
+        if ret == 0 && ucred_len as usize == ucred_size {
+            Ok(ConnectionMetadata::UnixPeerCredentials {
+                uid: ucred.uid,
+                gid: ucred.gid,
+                pid: Some(ucred.pid),
+            })
+        } else {
+            Err(Error::last_os_error())
+        }
+    }
+
+    /// Get the credentials of the peer with the `getpeereid` call.
+    #[cfg(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "openbsd",
+        target_os = "netbsd"
+    ))]
+    pub fn peer_cred(stream: &UnixStream) -> Result<ConnectionMetadata> {
+        let mut uid: libc::uid_t = 0;
+        let mut gid: libc::gid_t = 0;
+
+        // Safe as the pointers given are valid for the duration of the call.
+        let ret = unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) };
+
+        if ret == 0 {
+            Ok(ConnectionMetadata::UnixPeerCredentials {
+                uid,
+                gid,
+                pid: None,
+            })
+        } else {
+            Err(Error::last_os_error())
+        }
+    }
+}
+
 /// Builder for `DomainSocketListener`
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Debug, Default)]
 pub struct DomainSocketListenerBuilder {
     timeout: Option<Duration>,
+    socket_path: Option<PathBuf>,
+    socket_mode: Option<u32>,
+    socket_group: Option<String>,
+    admin_socket_path: Option<PathBuf>,
+    wake_up: Option<UnixStream>,
 }
 
 impl DomainSocketListenerBuilder {
     pub fn new() -> Self {
-        DomainSocketListenerBuilder { timeout: None }
+        DomainSocketListenerBuilder {
+            timeout: None,
+            socket_path: None,
+            socket_mode: None,
+            socket_group: None,
+            admin_socket_path: None,
+            wake_up: None,
+        }
     }
 
     pub fn with_timeout(mut self, timeout: Duration) -> Self {
@@ -128,10 +434,150 @@ impl DomainSocketListenerBuilder {
         self
     }
 
+    pub fn with_socket_path(mut self, socket_path: PathBuf) -> Self {
+        self.socket_path = Some(socket_path);
+        self
+    }
+
+    pub fn with_socket_mode(mut self, socket_mode: u32) -> Self {
+        self.socket_mode = Some(socket_mode);
+        self
+    }
+
+    pub fn with_socket_group(mut self, socket_group: String) -> Self {
+        self.socket_group = Some(socket_group);
+        self
+    }
+
+    /// Path of the socket on which admin requests are received (see `back::admin`).
+    pub fn with_admin_socket_path(mut self, admin_socket_path: PathBuf) -> Self {
+        self.admin_socket_path = Some(admin_socket_path);
+        self
+    }
+
+    /// Stream which, when readable, makes a blocked `accept` return. Writing to the other end of
+    /// this stream, for example from a signal handler, wakes the listener up.
+    pub fn with_wake_up(mut self, wake_up: UnixStream) -> Self {
+        self.wake_up = Some(wake_up);
+        self
+    }
+
     pub fn build(self) -> Result<DomainSocketListener> {
-        DomainSocketListener::new(self.timeout.ok_or_else(|| {
+        let timeout = self.timeout.ok_or_else(|| {
             error!("The listener timeout was not set.");
             Error::new(ErrorKind::InvalidInput, "listener timeout missing")
-        })?)
+        })?;
+        let socket_path = self
+            .socket_path
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_SOCKET_PATH));
+
+        DomainSocketListener::new(
+            timeout,
+            &socket_path,
+            self.socket_mode,
+            self.socket_group.as_deref(),
+            self.admin_socket_path.as_deref(),
+            self.wake_up,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DomainSocketListenerBuilder;
+    use crate::front::listener::Listen;
+    use std::fs;
+    use std::io::{ErrorKind, Write};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixStream;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    #[test]
+    fn accept_woken_up() {
+        let dir = PathBuf::from(env!("OUT_DIR").to_owned() + "/accept_woken_up");
+        let socket_path = dir.join("parsec.sock");
+        let (wake_up_reader, mut wake_up_writer) = UnixStream::pair().unwrap();
+
+        let listener = DomainSocketListenerBuilder::new()
+            .with_timeout(Duration::from_millis(100))
+            .with_socket_path(socket_path.clone())
+            .with_wake_up(wake_up_reader)
+            .build()
+            .unwrap();
+
+        wake_up_writer.write_all(&[0, 0]).unwrap();
+        assert!(listener.accept().is_none());
+
+        let _client = UnixStream::connect(&socket_path).unwrap();
+        assert!(listener.accept().is_some());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn accept_on_admin_socket() {
+        let dir = PathBuf::from(env!("OUT_DIR").to_owned() + "/accept_on_admin_socket");
+        let socket_path = dir.join("parsec.sock");
+        let admin_socket_path = dir.join("admin.sock");
+
+        let listener = DomainSocketListenerBuilder::new()
+            .with_timeout(Duration::from_millis(100))
+            .with_socket_path(socket_path.clone())
+            .with_admin_socket_path(admin_socket_path.clone())
+            .build()
+            .unwrap();
+
+        let _admin_client = UnixStream::connect(&admin_socket_path).unwrap();
+        assert!(listener.accept().unwrap().admin);
+
+        let _client = UnixStream::connect(&socket_path).unwrap();
+        assert!(!listener.accept().unwrap().admin);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn socket_created_with_mode() {
+        let dir = PathBuf::from(env!("OUT_DIR").to_owned() + "/socket_created_with_mode");
+        let socket_path = dir.join("parsec.sock");
+
+        let _listener = DomainSocketListenerBuilder::new()
+            .with_timeout(Duration::from_millis(100))
+            .with_socket_path(socket_path.clone())
+            .with_socket_mode(0o660)
+            .build()
+            .unwrap();
+
+        let mode = fs::metadata(&socket_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+
+        // A socket left at the path is replaced.
+        let _listener = DomainSocketListenerBuilder::new()
+            .with_timeout(Duration::from_millis(100))
+            .with_socket_path(socket_path)
+            .build()
+            .unwrap();
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn file_at_socket_path_not_removed() {
+        let dir = PathBuf::from(env!("OUT_DIR").to_owned() + "/file_at_socket_path_not_removed");
+        let socket_path = dir.join("parsec.sock");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&socket_path, b"precious data").unwrap();
+
+        let err = DomainSocketListenerBuilder::new()
+            .with_timeout(Duration::from_millis(100))
+            .with_socket_path(socket_path.clone())
+            .build()
+            .unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&socket_path).unwrap(), b"precious data");
+
+        fs::remove_dir_all(dir).unwrap();
     }
 }
@@ -4,67 +4,209 @@
 //!
 //! The front end handler accepts streams of data that it can use to read requests,
 //! pass them to the rest of the service and write the responses back.
-use crate::authenticators::Authenticate;
+//!
+//! A client can send several requests, one after the other, on the same connection. The
+//! connection is closed when the client closes it, when it stays idle for too long or when the
+//! maximum number of requests per connection is reached. Connections of the admin socket only
+//! carry a single admin request.
+use crate::authenticators::{ApplicationName, Authenticate};
+use crate::back::admin::{AdminRequest, AdminResponse};
 use crate::back::dispatcher::Dispatcher;
+use crate::front::access_control::AccessPolicy;
+use crate::front::listener::{Connection, ConnectionMetadata, ReadWrite};
 use derivative::Derivative;
 use log::{error, info};
+use parsec_interface::requests::request::RequestAuth;
 use parsec_interface::requests::AuthType;
 use parsec_interface::requests::ResponseStatus;
 use parsec_interface::requests::{Request, Response};
 use std::collections::HashMap;
-use std::io::{Error, ErrorKind, Result};
-use std::io::{Read, Write};
+use std::io::{Error, ErrorKind, Read, Result};
+use std::time::{Duration, Instant};
 
 /// Read and verify request from IPC stream
 ///
 /// Service component that serializes requests and deserializes responses
 /// from/to the stream provided by the listener.
 ///
-/// Requests are passed forward to the `Dispatcher`.
+/// Requests are passed forward to the `Dispatcher` if the `AccessPolicy` allows them.
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct FrontEndHandler {
     dispatcher: Dispatcher,
+    access_policy: AccessPolicy,
     // Send and Sync are required for Arc<FrontEndHandler> to be Send.
     #[derivative(Debug = "ignore")]
     authenticators: HashMap<AuthType, Box<dyn Authenticate + Send + Sync>>,
     /// Value used to limit the size of the request body to be that can be accepted by the service.
     body_len_limit: usize,
+    /// Maximum number of requests served on a single connection.
+    max_requests_per_connection: usize,
+    /// Time a connection can stay idle, waiting for the next request, before being closed.
+    connection_idle_timeout: Duration,
 }
 
 impl FrontEndHandler {
     /// Handle new connections on the underlying IPC mechanism.
     ///
     /// Unmarshalls a request from the stream, passes it to the dispatcher and marshalls
-    /// the response back onto the stream.
+    /// the response back onto the stream. The connection metadata is given to the
+    /// authenticator alongside the request authentication field. This is repeated for all the
+    /// requests sent on the connection until it is closed by the client, it stays idle for
+    /// longer than the idle timeout or the maximum number of requests per connection is
+    /// reached.
     ///
     /// If an error occurs during (un)marshalling, no operation will be performed and the
-    /// method will return.
-    pub fn handle_request<T: Read + Write>(&self, mut stream: T) {
-        // Read bytes from stream
-        // De-Serialise bytes into a request
-        let request = match Request::read_from_stream(&mut stream, self.body_len_limit) {
-            Ok(request) => request,
-            Err(status) => {
-                error!("Failed to read request; status: {}", status);
-
-                let response = Response::from_status(status);
-                if let Err(status) = response.write_to_stream(&mut stream) {
-                    error!("Failed to write response; status: {}", status);
+    /// method will return, closing the connection.
+    pub fn handle_request(&self, mut connection: Connection) {
+        if connection.admin {
+            self.handle_admin_request(connection);
+            return;
+        }
+
+        for served in 0..self.max_requests_per_connection {
+            // Read bytes from stream
+            // De-Serialise bytes into a request
+            let request = if served == 0 {
+                // The first request is expected as soon as the connection is established.
+                Request::read_from_stream(&mut connection.stream, self.body_len_limit)
+            } else {
+                let first_byte = match self.wait_for_next_request(&mut connection.stream) {
+                    Some(first_byte) => first_byte,
+                    None => return,
+                };
+                let mut stream = (&first_byte[..]).chain(&mut connection.stream);
+                Request::read_from_stream(&mut stream, self.body_len_limit)
+            };
+            let request = match request {
+                Ok(request) => request,
+                Err(status) => {
+                    error!("Failed to read request; status: {}", status);
+
+                    let response = Response::from_status(status);
+                    if let Err(status) = response.write_to_stream(&mut connection.stream) {
+                        error!("Failed to write response; status: {}", status);
+                    }
+                    return;
+                }
+            };
+
+            let response = self.authenticate_and_dispatch(request, connection.metadata);
+
+            // Serialise the responso into bytes
+            // Write bytes to stream
+            match response.write_to_stream(&mut connection.stream) {
+                Ok(_) => info!("Request handled successfully"),
+                Err(err) => {
+                    error!("Failed to send response; error: {}", err);
+                    return;
                 }
-                return;
+            }
+        }
+
+        info!(
+            "Maximum number of requests per connection ({}) reached, closing the connection.",
+            self.max_requests_per_connection
+        );
+    }
+
+    /// Handle a connection made on the admin socket: a single admin request is read, authenticated
+    /// with the Unix peer credentials authenticator and passed to the dispatcher. The response is
+    /// written back before the connection is closed.
+    fn handle_admin_request(&self, mut connection: Connection) {
+        let response =
+            match AdminRequest::read_from_stream(&mut connection.stream, self.body_len_limit) {
+                Ok(request) => self.authenticate_and_dispatch_admin(request, connection.metadata),
+                Err(status) => {
+                    error!("Failed to read admin request; status: {}", status);
+                    AdminResponse::from_status(status)
+                }
+            };
+
+        match response.write_to_stream(&mut connection.stream) {
+            Ok(_) => info!("Admin request handled successfully"),
+            Err(status) => error!("Failed to write admin response; status: {}", status),
+        }
+    }
+
+    /// Authenticate an admin request with the peer credentials of the connection and pass it
+    /// forward.
+    fn authenticate_and_dispatch_admin(
+        &self,
+        request: AdminRequest,
+        metadata: Option<ConnectionMetadata>,
+    ) -> AdminResponse {
+        let authenticator = match self.authenticators.get(&AuthType::UnixPeerCredentials) {
+            Some(authenticator) => authenticator,
+            None => {
+                error!("Admin requests need the UnixPeerCredentials authenticator.");
+                return AdminResponse::from_status(ResponseStatus::AuthenticatorNotRegistered);
             }
         };
+
+        match authenticator.authenticate(&RequestAuth::new(request.auth), metadata) {
+            Ok(app_name) => self
+                .dispatcher
+                .dispatch_admin_request(request.operation, app_name),
+            Err(status) => AdminResponse::from_status(status),
+        }
+    }
+
+    /// Answer a connection that can not be handled because the service is busy, without reading
+    /// the request, and close it.
+    pub fn reject_connection(&self, mut connection: Connection) {
+        let response = Response::from_status(ResponseStatus::PsaErrorInsufficientMemory);
+        if let Err(status) = response.write_to_stream(&mut connection.stream) {
+            error!("Failed to write response; status: {}", status);
+        }
+    }
+
+    /// Wait until the client starts sending its next request and return the first byte of it.
+    ///
+    /// Returns `None` if the client closed the connection or if nothing was received during
+    /// the idle timeout.
+    fn wait_for_next_request(&self, stream: &mut Box<dyn ReadWrite + Send>) -> Option<[u8; 1]> {
+        let mut first_byte = [0; 1];
+        let start = Instant::now();
+        loop {
+            match stream.read(&mut first_byte) {
+                Ok(0) => return None,
+                Ok(_) => return Some(first_byte),
+                // The read timeout of the stream can be shorter than the idle timeout.
+                Err(err)
+                    if err.kind() == ErrorKind::WouldBlock
+                        || err.kind() == ErrorKind::TimedOut
+                        || err.kind() == ErrorKind::Interrupted =>
+                {
+                    if start.elapsed() >= self.connection_idle_timeout {
+                        info!("Connection idle for too long, closing it.");
+                        return None;
+                    }
+                }
+                Err(err) => {
+                    error!("Failed to read from the connection; error: {}", err);
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Authenticate the request, using the connection metadata if needed, and pass it forward.
+    fn authenticate_and_dispatch(
+        &self,
+        request: Request,
+        metadata: Option<ConnectionMetadata>,
+    ) -> Response {
         // Check if the request was sent without authentication
-        let response = if AuthType::NoAuth == request.header.auth_type {
-            self.dispatcher.dispatch_request(request, None)
+        if AuthType::NoAuth == request.header.auth_type {
+            self.authorize_and_dispatch(request, None)
         // Otherwise find an authenticator that is capable to authenticate the request
         } else if let Some(authenticator) = self.authenticators.get(&request.header.auth_type) {
             // Authenticate the request
-            match authenticator.authenticate(&request.auth) {
+            match authenticator.authenticate(&request.auth, metadata) {
                 // Send the request to the dispatcher
                 // Get a response back
-                Ok(app_name) => self.dispatcher.dispatch_request(request, Some(app_name)),
+                Ok(app_name) => self.authorize_and_dispatch(request, Some(app_name)),
                 Err(status) => Response::from_request_header(request.header, status),
             }
         } else {
@@ -72,14 +214,25 @@ impl FrontEndHandler {
                 request.header,
                 ResponseStatus::AuthenticatorNotRegistered,
             )
-        };
+        }
+    }
 
-        // Serialise the responso into bytes
-        // Write bytes to stream
-        match response.write_to_stream(&mut stream) {
-            Ok(_) => info!("Request handled successfully"),
-            Err(err) => error!("Failed to send response; error: {}", err),
+    /// Check the request against the access policy and, if it is allowed, pass it to the
+    /// dispatcher.
+    fn authorize_and_dispatch(
+        &self,
+        request: Request,
+        app_name: Option<ApplicationName>,
+    ) -> Response {
+        if let Err(status) = self.access_policy.check(
+            app_name.as_ref(),
+            request.header.provider,
+            request.header.opcode,
+        ) {
+            return Response::from_request_header(request.header, status);
         }
+
+        self.dispatcher.dispatch_request(request, app_name)
     }
 }
 
@@ -88,17 +241,23 @@ impl FrontEndHandler {
 #[derivative(Debug)]
 pub struct FrontEndHandlerBuilder {
     dispatcher: Option<Dispatcher>,
+    access_policy: Option<AccessPolicy>,
     #[derivative(Debug = "ignore")]
     authenticators: Option<HashMap<AuthType, Box<dyn Authenticate + Send + Sync>>>,
     body_len_limit: Option<usize>,
+    max_requests_per_connection: Option<usize>,
+    connection_idle_timeout: Option<Duration>,
 }
 
 impl FrontEndHandlerBuilder {
     pub fn new() -> Self {
         FrontEndHandlerBuilder {
             dispatcher: None,
+            access_policy: None,
             authenticators: None,
             body_len_limit: None,
+            max_requests_per_connection: None,
+            connection_idle_timeout: None,
         }
     }
 
@@ -107,6 +266,11 @@ impl FrontEndHandlerBuilder {
         self
     }
 
+    pub fn with_access_policy(mut self, access_policy: AccessPolicy) -> Self {
+        self.access_policy = Some(access_policy);
+        self
+    }
+
     pub fn with_authenticator(
         mut self,
         auth_type: AuthType,
@@ -131,17 +295,85 @@ impl FrontEndHandlerBuilder {
         self
     }
 
+    pub fn with_max_requests_per_connection(mut self, max_requests_per_connection: usize) -> Self {
+        self.max_requests_per_connection = Some(max_requests_per_connection);
+        self
+    }
+
+    pub fn with_connection_idle_timeout(mut self, connection_idle_timeout: Duration) -> Self {
+        self.connection_idle_timeout = Some(connection_idle_timeout);
+        self
+    }
+
     pub fn build(self) -> Result<FrontEndHandler> {
+        let max_requests_per_connection = self.max_requests_per_connection.ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                "max_requests_per_connection is missing",
+            )
+        })?;
+        // No request would ever be answered.
+        if max_requests_per_connection == 0 {
+            error!("The maximum number of requests per connection can not be 0.");
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "max_requests_per_connection is 0",
+            ));
+        }
+
         Ok(FrontEndHandler {
             dispatcher: self
                 .dispatcher
                 .ok_or_else(|| Error::new(ErrorKind::InvalidData, "dispatcher is missing"))?,
+            access_policy: self
+                .access_policy
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "access_policy is missing"))?,
             authenticators: self
                 .authenticators
                 .ok_or_else(|| Error::new(ErrorKind::InvalidData, "authenticators is missing"))?,
             body_len_limit: self
                 .body_len_limit
                 .ok_or_else(|| Error::new(ErrorKind::InvalidData, "body_len_limit is missing"))?,
+            max_requests_per_connection,
+            connection_idle_timeout: self.connection_idle_timeout.ok_or_else(|| {
+                Error::new(ErrorKind::InvalidData, "connection_idle_timeout is missing")
+            })?,
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::FrontEndHandlerBuilder;
+    use crate::authenticators::direct_authenticator::DirectAuthenticator;
+    use crate::back::dispatcher::DispatcherBuilder;
+    use crate::front::access_control::AccessPolicy;
+    use parsec_interface::requests::AuthType;
+    use std::collections::HashMap;
+    use std::io::ErrorKind;
+    use std::time::Duration;
+
+    fn builder(max_requests_per_connection: usize) -> FrontEndHandlerBuilder {
+        FrontEndHandlerBuilder::new()
+            .with_dispatcher(
+                DispatcherBuilder::new()
+                    .with_backends(HashMap::new())
+                    .build()
+                    .unwrap(),
+            )
+            .with_access_policy(AccessPolicy::allow_all())
+            .with_authenticator(AuthType::Direct, Box::new(DirectAuthenticator))
+            .with_body_len_limit(1024)
+            .with_max_requests_per_connection(max_requests_per_connection)
+            .with_connection_idle_timeout(Duration::from_millis(100))
+    }
+
+    #[test]
+    fn zero_requests_per_connection_rejected() {
+        assert!(builder(1).build().is_ok());
+        assert_eq!(
+            builder(0).build().unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+}
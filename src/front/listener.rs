@@ -5,6 +5,7 @@
 //! The [`Listen`](https://parallaxsecond.github.io/parsec-book/parsec_service/listeners.html)
 //! trait acts as an interface for the operations that must be supported by any implementation
 //! of the IPC mechanism used as a Parsec front.
+use derivative::Derivative;
 use serde::Deserialize;
 use std::time::Duration;
 
@@ -19,10 +20,46 @@ pub enum ListenerType {
     DomainSocket,
 }
 
-#[derive(Copy, Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Debug)]
 pub struct ListenerConfig {
     pub listener_type: ListenerType,
     pub timeout: u64,
+    pub socket_path: Option<String>,
+    pub socket_mode: Option<u32>,
+    pub socket_group: Option<String>,
+    pub admin_socket_path: Option<String>,
+}
+
+/// Specifies metadata associated with a connection, if any.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ConnectionMetadata {
+    /// Credentials of the process at the other end of a Unix domain socket, as reported by the
+    /// operating system at the time the connection was established.
+    UnixPeerCredentials {
+        /// Effective user ID of the peer process.
+        uid: u32,
+        /// Effective group ID of the peer process.
+        gid: u32,
+        /// Process ID of the peer, if the platform provides it.
+        pid: Option<i32>,
+    },
+}
+
+/// Represents a connection to a single client.
+///
+/// Contains a stream, used for communicating with the client, and some metadata associated
+/// with the connection that might be useful elsewhere (e.g. for authentication).
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct Connection {
+    /// Stream used for communication with the client.
+    #[derivative(Debug = "ignore")]
+    pub stream: Box<dyn ReadWrite + Send>,
+    /// Metadata associated with the connection.
+    pub metadata: Option<ConnectionMetadata>,
+    /// The connection was made on the admin socket: it carries an admin request instead of a
+    /// request of the wire protocol.
+    pub admin: bool,
 }
 
 /// IPC front manager interface
@@ -33,17 +70,15 @@ pub trait Listen {
     /// Set the timeout on read and write calls on any stream returned by this listener.
     fn set_timeout(&mut self, duration: Duration);
 
-    /// Non-blocking call that gets the next client connection and returns a stream
-    /// (a Read and Write trait object). Requests are read from the stream and responses are written
+    /// Blocking call that waits for the next client connection and returns it as a `Connection`,
+    /// made of a stream (a Read and Write trait object) and of the metadata the listener could
+    /// gather about the peer. Requests are read from the stream and responses are written
     /// to it. Streams returned by this method should have a timeout period as set by the
     /// `set_timeout` method.
-    /// If no connections are present, return `None`.
-    /// If there are any errors in establishing the connection other than the missing
-    /// initialization, the implementation should log them and return `None`.
+    /// The call returns `None` without a connection if the listener was woken up, for example
+    /// because a signal was received, so that the caller can act on it.
+    /// If there are any errors in establishing the connection, the implementation should log
+    /// them and return `None`.
     /// `Send` is needed because the stream is moved to a thread.
-    ///
-    /// # Panics
-    ///
-    /// If the listener has not been initialised before, with the `init` method.
-    fn accept(&self) -> Option<Box<dyn ReadWrite + Send>>;
+    fn accept(&self) -> Option<Connection>;
 }
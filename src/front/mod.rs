@@ -1,6 +1,8 @@
 // Copyright 2019 Contributors to the Parsec project.
 // SPDX-License-Identifier: Apache-2.0
 //! IPC front handlers
+pub mod access_control;
+pub mod connection_limit;
 pub mod domain_socket;
 pub mod front_end;
 pub mod listener;
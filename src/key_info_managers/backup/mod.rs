@@ -0,0 +1,733 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+//! Backup and restore of the key info mappings
+//!
+//! All the mappings of the key info managers can be exported to a single archive and imported
+//! back, on the same host or on a replacement one. The archive is protected with a backup key of
+//! 32 bytes, read from a file, from which two independent keys are derived. The archive is either
+//! signed, with an HMAC-SHA256 tag computed over all of it, or encrypted and authenticated with
+//! AES-256-GCM. Archives which were modified or which are protected with another key are rejected.
+//! Signed archives are readable by anyone: the key info of some providers contains secrets, like
+//! the authentication values of the TPM keys, so they should only be used when the archive is
+//! stored safely.
+//!
+//! The key info managers are locked for the whole export or import, so that the archive is a
+//! consistent copy of the mappings even while the service is running.
+//!
+//! When importing, a mapping conflicts with the existing ones if its key triple already exists
+//! with a different key info or if another key triple of the same provider already points to the
+//! same key. What happens to the conflicting mappings is decided by the `ConflictPolicy`.
+//!
+//! The archive starts with a magic value, the version of the archive format and the protection
+//! used, followed by the records serialized with bincode, each containing a key triple and its key
+//! info in the current record format.
+use super::encryption::KeyEncryptionKey;
+use super::record_format;
+use super::{KeyInfo, KeyTriple, ManageKeyInfo, KEY_STORING_PROVIDERS};
+use crate::authenticators::ApplicationName;
+use log::{error, warn};
+use parsec_interface::requests::ProviderId;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use zeroize::Zeroizing;
+
+type KeyInfoManager = Arc<RwLock<dyn ManageKeyInfo + Send + Sync>>;
+
+/// Length in bytes of the backup key
+pub const BACKUP_KEY_LEN: usize = 32;
+/// Version of the archives written by this version of the service
+pub const ARCHIVE_VERSION: u32 = 1;
+
+const MAGIC: &[u8; 4] = b"PKIB";
+const HEADER_LEN: usize = 9;
+const SIGNING_KEY_LABEL: &[u8] = b"parsec-backup-signing-key";
+const ENCRYPTION_KEY_LABEL: &[u8] = b"parsec-backup-encryption-key";
+const TAG_LEN: usize = 32;
+
+/// How an archive is protected
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ArchiveProtection {
+    /// The archive is in clear text, followed by an HMAC-SHA256 tag.
+    Signed,
+    /// The archive is encrypted and authenticated with AES-256-GCM.
+    Encrypted,
+}
+
+impl ArchiveProtection {
+    fn to_byte(self) -> u8 {
+        match self {
+            ArchiveProtection::Signed => 1,
+            ArchiveProtection::Encrypted => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<ArchiveProtection> {
+        match byte {
+            1 => Some(ArchiveProtection::Signed),
+            2 => Some(ArchiveProtection::Encrypted),
+            _ => None,
+        }
+    }
+}
+
+/// What to do with the mappings of an archive conflicting with the existing ones
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ConflictPolicy {
+    /// Nothing is imported if any conflict is found.
+    Abort,
+    /// The conflicting mappings are not imported, the other ones are.
+    Skip,
+    /// The existing mappings of the same key triples are replaced. Mappings pointing to a key
+    /// already used by another key triple are never imported.
+    Overwrite,
+}
+
+/// Mapping of an archive conflicting with the existing ones
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Conflict {
+    /// The key triple already exists with a different key info.
+    MappingDiffers(KeyTriple),
+    /// The key is already used by another key triple of the same provider.
+    KeyInUse {
+        /// Key triple from the archive
+        key_triple: KeyTriple,
+        /// Existing key triple pointing to the same key
+        used_by: KeyTriple,
+    },
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Conflict::MappingDiffers(key_triple) => {
+                write!(f, "{} exists with a different key", key_triple)
+            }
+            Conflict::KeyInUse {
+                key_triple,
+                used_by,
+            } => write!(f, "the key of {} is used by {}", key_triple, used_by),
+        }
+    }
+}
+
+/// Result of the import of an archive
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportReport {
+    /// Mappings created or overwritten.
+    pub imported: Vec<KeyTriple>,
+    /// Mappings already existing with the same key info.
+    pub unchanged: Vec<KeyTriple>,
+    /// Mappings conflicting with the existing ones.
+    pub conflicts: Vec<Conflict>,
+    /// Mappings of providers without a key info manager.
+    pub skipped: Vec<KeyTriple>,
+}
+
+/// Key protecting the archives
+pub struct BackupKey {
+    signing_key: hmac::Key,
+    encryption_key: KeyEncryptionKey,
+}
+
+impl fmt::Debug for BackupKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BackupKey").finish()
+    }
+}
+
+impl BackupKey {
+    /// Creates a backup key from its raw bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the key does not have the right length.
+    pub fn new(key: &[u8]) -> std::io::Result<BackupKey> {
+        if key.len() != BACKUP_KEY_LEN {
+            error!(
+                "The backup key should be {} bytes long, not {}.",
+                BACKUP_KEY_LEN,
+                key.len()
+            );
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "invalid backup key length",
+            ));
+        }
+        let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+        let signing_key = Zeroizing::new(hmac::sign(&key, SIGNING_KEY_LABEL).as_ref().to_vec());
+        let encryption_key =
+            Zeroizing::new(hmac::sign(&key, ENCRYPTION_KEY_LABEL).as_ref().to_vec());
+
+        Ok(BackupKey {
+            signing_key: hmac::Key::new(hmac::HMAC_SHA256, &signing_key),
+            encryption_key: KeyEncryptionKey::new(&encryption_key)?,
+        })
+    }
+
+    /// Reads the backup key from a file. A warning is logged if the file can be accessed by other
+    /// users than its owner.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file could not be read or does not contain a valid key.
+    pub fn from_file(path: &Path) -> std::io::Result<BackupKey> {
+        BackupKey::new(&read_key_file(path)?)
+    }
+}
+
+/// Reads the raw bytes of the backup key from a file. A warning is logged if the file can be
+/// accessed by other users than its owner.
+///
+/// # Errors
+///
+/// Returns an error if the file could not be read.
+pub fn read_key_file(path: &Path) -> std::io::Result<Zeroizing<Vec<u8>>> {
+    let metadata = fs::metadata(path)?;
+    if metadata.permissions().mode() & 0o077 != 0 {
+        warn!(
+            "The backup key file {:?} can be accessed by other users than its owner.",
+            path
+        );
+    }
+
+    Ok(Zeroizing::new(fs::read(path)?))
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArchivedRecord {
+    app_name: String,
+    provider_id: u8,
+    key_name: String,
+    key_info: Vec<u8>,
+}
+
+fn header(protection: ArchiveProtection) -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(&ARCHIVE_VERSION.to_le_bytes());
+    header.push(protection.to_byte());
+    header
+}
+
+fn invalid_archive(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn key_info_manager_error(error: String) -> Error {
+    error!("Key info manager error: {}.", error);
+    Error::new(ErrorKind::Other, "key info manager error")
+}
+
+/// Protects the records with the backup key.
+///
+/// # Errors
+///
+/// Returns an error if the records could not be serialized or encrypted.
+pub fn seal_archive(
+    records: &[(KeyTriple, KeyInfo)],
+    key: &BackupKey,
+    protection: ArchiveProtection,
+) -> std::io::Result<Vec<u8>> {
+    let mut archived_records = Vec::new();
+    for (key_triple, key_info) in records {
+        archived_records.push(ArchivedRecord {
+            app_name: key_triple.app_name().get_name().to_string(),
+            provider_id: key_triple.provider_id() as u8,
+            key_name: key_triple.key_name().to_string(),
+            key_info: record_format::serialize_key_info(key_info)
+                .map_err(|e| Error::new(ErrorKind::Other, e))?,
+        });
+    }
+    let body = Zeroizing::new(bincode::serialize(&archived_records).map_err(|e| {
+        error!("Error serializing the archive ({}).", e);
+        Error::new(ErrorKind::Other, "error serializing archive")
+    })?);
+
+    let mut archive = header(protection);
+    match protection {
+        ArchiveProtection::Signed => {
+            archive.extend_from_slice(&body);
+            let tag = hmac::sign(&key.signing_key, &archive);
+            archive.extend_from_slice(tag.as_ref());
+        }
+        ArchiveProtection::Encrypted => {
+            let mut ciphertext = key.encryption_key.encrypt(&archive, &body)?;
+            archive.append(&mut ciphertext);
+        }
+    }
+
+    Ok(archive)
+}
+
+/// Checks the archive with the backup key and returns its records.
+///
+/// # Errors
+///
+/// Returns an `InvalidData` error if the archive is not valid, was modified or is protected with
+/// another key.
+pub fn open_archive(archive: &[u8], key: &BackupKey) -> std::io::Result<Vec<(KeyTriple, KeyInfo)>> {
+    if archive.len() < HEADER_LEN || !archive.starts_with(MAGIC) {
+        error!("The file is not a key info archive.");
+        return Err(invalid_archive("not a key info archive"));
+    }
+    let version = u32::from_le_bytes(
+        archive[MAGIC.len()..HEADER_LEN - 1]
+            .try_into()
+            .expect("The version has the right length."),
+    );
+    if version != ARCHIVE_VERSION {
+        error!("Archive version {} is not supported.", version);
+        return Err(invalid_archive("archive version not supported"));
+    }
+    let (header, payload) = archive.split_at(HEADER_LEN);
+    let body = match ArchiveProtection::from_byte(header[HEADER_LEN - 1]) {
+        Some(ArchiveProtection::Signed) => {
+            if payload.len() < TAG_LEN {
+                return Err(invalid_archive("archive truncated"));
+            }
+            let (signed, tag) = archive.split_at(archive.len() - TAG_LEN);
+            hmac::verify(&key.signing_key, signed, tag).map_err(|_| {
+                error!("The archive was modified or the backup key is wrong.");
+                invalid_archive("archive authentication failed")
+            })?;
+            Zeroizing::new(payload[..payload.len() - TAG_LEN].to_vec())
+        }
+        Some(ArchiveProtection::Encrypted) => match key.encryption_key.decrypt(header, payload) {
+            Ok(body) => body,
+            Err(e) => {
+                error!("The archive was modified or the backup key is wrong.");
+                return Err(e);
+            }
+        },
+        None => return Err(invalid_archive("unknown archive protection")),
+    };
+
+    let archived_records: Vec<ArchivedRecord> = bincode::deserialize(&body).map_err(|e| {
+        error!("Error deserializing the archive ({}).", e);
+        invalid_archive("error deserializing archive")
+    })?;
+    let mut records = Vec::new();
+    for record in archived_records {
+        let provider_id = ProviderId::try_from(record.provider_id)
+            .map_err(|_| invalid_archive("unknown provider in archive"))?;
+        let (key_info, _) = record_format::deserialize_key_info(&record.key_info)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        records.push((
+            KeyTriple::new(
+                ApplicationName::new(record.app_name),
+                provider_id,
+                record.key_name,
+            ),
+            key_info,
+        ));
+    }
+
+    Ok(records)
+}
+
+/// Returns each key info manager once, in a fixed order so that they are always locked in the
+/// same order.
+fn distinct_managers(managers: &HashMap<ProviderId, KeyInfoManager>) -> Vec<&KeyInfoManager> {
+    let mut distinct: Vec<&KeyInfoManager> = Vec::new();
+    for manager in managers.values() {
+        if !distinct.iter().any(|other| Arc::ptr_eq(other, manager)) {
+            distinct.push(manager);
+        }
+    }
+    distinct.sort_by_key(|manager| Arc::as_ptr(manager) as *const u8 as usize);
+    distinct
+}
+
+/// Exports the mappings of all the key info managers to an archive. Each key info manager is
+/// given with a provider using it and all the mappings it contains are exported, whichever
+/// provider they belong to.
+///
+/// # Errors
+///
+/// Returns an error if the mappings could not be read or the archive could not be sealed.
+pub fn export_archive(
+    managers: &HashMap<ProviderId, KeyInfoManager>,
+    key: &BackupKey,
+    protection: ArchiveProtection,
+) -> std::io::Result<Vec<u8>> {
+    let distinct = distinct_managers(managers);
+    let store_handles: Vec<_> = distinct
+        .iter()
+        .map(|manager| manager.read().expect("Key store lock poisoned"))
+        .collect();
+
+    let mut records = Vec::new();
+    for store_handle in &store_handles {
+        for provider_id in KEY_STORING_PROVIDERS.iter() {
+            for key_triple in store_handle
+                .get_all(*provider_id)
+                .map_err(key_info_manager_error)?
+            {
+                match store_handle
+                    .get(&key_triple)
+                    .map_err(key_info_manager_error)?
+                {
+                    Some(key_info) => records.push((key_triple, key_info)),
+                    None => warn!("Key information missing for: {}", key_triple),
+                }
+            }
+        }
+    }
+
+    seal_archive(&records, key, protection)
+}
+
+/// Imports the mappings of an archive in the key info managers of their providers. Conflicts with
+/// the existing mappings are handled following the policy given and reported.
+///
+/// # Errors
+///
+/// Returns an error if the archive is not valid or if a key info manager failed. The mappings
+/// already imported are kept in the latter case.
+pub fn import_archive(
+    managers: &HashMap<ProviderId, KeyInfoManager>,
+    archive: &[u8],
+    key: &BackupKey,
+    policy: ConflictPolicy,
+) -> std::io::Result<ImportReport> {
+    let records = open_archive(archive, key)?;
+
+    let distinct = distinct_managers(managers);
+    let mut store_handles: Vec<_> = distinct
+        .iter()
+        .map(|manager| manager.write().expect("Key store lock poisoned"))
+        .collect();
+
+    // Owner of each key, per key info manager and provider.
+    let mut key_owners: HashMap<(usize, ProviderId, Vec<u8>), KeyTriple> = HashMap::new();
+    for (index, store_handle) in store_handles.iter().enumerate() {
+        for provider_id in KEY_STORING_PROVIDERS.iter() {
+            for key_triple in store_handle
+                .get_all(*provider_id)
+                .map_err(key_info_manager_error)?
+            {
+                if let Some(key_info) = store_handle
+                    .get(&key_triple)
+                    .map_err(key_info_manager_error)?
+                {
+                    let _ = key_owners.insert((index, *provider_id, key_info.id), key_triple);
+                }
+            }
+        }
+    }
+
+    let mut report = ImportReport::default();
+    let mut to_import = Vec::new();
+    for (key_triple, key_info) in records {
+        let provider_id = key_triple.provider_id();
+        let index = match managers.get(&provider_id).and_then(|manager| {
+            distinct
+                .iter()
+                .position(|other| Arc::ptr_eq(other, manager))
+        }) {
+            Some(index) => index,
+            None => {
+                warn!("No key info manager for the provider of {}.", key_triple);
+                report.skipped.push(key_triple);
+                continue;
+            }
+        };
+        let existing = store_handles[index]
+            .get(&key_triple)
+            .map_err(key_info_manager_error)?;
+        if existing.as_ref() == Some(&key_info) {
+            report.unchanged.push(key_triple);
+            continue;
+        }
+        let owner_key = (index, provider_id, key_info.id.clone());
+        match key_owners.get(&owner_key) {
+            Some(used_by) if *used_by != key_triple => {
+                warn!("The key of {} is used by {}.", key_triple, used_by);
+                report.conflicts.push(Conflict::KeyInUse {
+                    key_triple,
+                    used_by: used_by.clone(),
+                });
+                continue;
+            }
+            _ => (),
+        }
+        if existing.is_some() {
+            warn!("{} exists with a different key.", key_triple);
+            report
+                .conflicts
+                .push(Conflict::MappingDiffers(key_triple.clone()));
+            if policy != ConflictPolicy::Overwrite {
+                continue;
+            }
+        }
+        let _ = key_owners.insert(owner_key, key_triple.clone());
+        to_import.push((index, key_triple, key_info));
+    }
+
+    if policy == ConflictPolicy::Abort && !report.conflicts.is_empty() {
+        error!(
+            "{} conflicts found, nothing was imported.",
+            report.conflicts.len()
+        );
+        return Ok(report);
+    }
+
+    for (index, key_triple, key_info) in to_import {
+        let _ = store_handles[index]
+            .insert(key_triple.clone(), key_info)
+            .map_err(key_info_manager_error)?;
+        report.imported.push(key_triple);
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::on_disk_manager::OnDiskKeyInfoManagerBuilder;
+    use super::super::{KeyInfo, KeyTriple};
+    use super::{
+        export_archive, import_archive, seal_archive, ArchiveProtection, BackupKey, Conflict,
+        ConflictPolicy, KeyInfoManager, BACKUP_KEY_LEN,
+    };
+    use crate::authenticators::ApplicationName;
+    use parsec_interface::operations::psa_algorithm::{
+        Algorithm, AsymmetricSignature, Hash, SignHash,
+    };
+    use parsec_interface::operations::psa_key_attributes::{
+        Attributes, Lifetime, Policy, Type, UsageFlags,
+    };
+    use parsec_interface::requests::ProviderId;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::path::PathBuf;
+    use std::sync::{Arc, RwLock};
+
+    fn key_info(id: u8) -> KeyInfo {
+        KeyInfo {
+            id: vec![id; 4],
+            attributes: Attributes {
+                lifetime: Lifetime::Persistent,
+                key_type: Type::RsaKeyPair,
+                bits: 1024,
+                policy: Policy {
+                    usage_flags: {
+                        let mut usage_flags = UsageFlags::default();
+                        let _ = usage_flags.set_sign_hash();
+                        usage_flags
+                    },
+                    permitted_algorithms: Algorithm::AsymmetricSignature(
+                        AsymmetricSignature::RsaPkcs1v15Sign {
+                            hash_alg: SignHash::Specific(Hash::Sha256),
+                        },
+                    ),
+                },
+            },
+        }
+    }
+
+    fn key_triple(provider_id: ProviderId, key_name: &str) -> KeyTriple {
+        KeyTriple::new(
+            ApplicationName::new(String::from("app")),
+            provider_id,
+            key_name.to_string(),
+        )
+    }
+
+    /// Key info managers of the Pkcs11 and TPM providers, sharing the same manager.
+    fn managers(path: PathBuf) -> HashMap<ProviderId, KeyInfoManager> {
+        let manager: KeyInfoManager = Arc::new(RwLock::new(
+            OnDiskKeyInfoManagerBuilder::new()
+                .with_mappings_dir_path(path)
+                .build()
+                .unwrap(),
+        ));
+        let mut managers = HashMap::new();
+        let _ = managers.insert(ProviderId::Pkcs11, manager.clone());
+        let _ = managers.insert(ProviderId::Tpm, manager);
+        managers
+    }
+
+    fn insert(managers: &HashMap<ProviderId, KeyInfoManager>, key_triple: KeyTriple, id: u8) {
+        let _ = managers[&key_triple.provider_id()]
+            .write()
+            .unwrap()
+            .insert(key_triple, key_info(id))
+            .unwrap();
+    }
+
+    fn get(managers: &HashMap<ProviderId, KeyInfoManager>, key_triple: &KeyTriple) -> Option<u8> {
+        managers[&key_triple.provider_id()]
+            .read()
+            .unwrap()
+            .get(key_triple)
+            .unwrap()
+            .map(|key_info| key_info.id[0])
+    }
+
+    #[test]
+    fn export_import_round_trip() {
+        let source_path = PathBuf::from(env!("OUT_DIR").to_owned() + "/backup_round_trip_source");
+        let destination_path =
+            PathBuf::from(env!("OUT_DIR").to_owned() + "/backup_round_trip_destination");
+        let key = BackupKey::new(&[0x42; BACKUP_KEY_LEN]).unwrap();
+        let source = managers(source_path.clone());
+        insert(&source, key_triple(ProviderId::Pkcs11, "key_1"), 1);
+        insert(&source, key_triple(ProviderId::Tpm, "key_2"), 2);
+
+        for protection in &[ArchiveProtection::Signed, ArchiveProtection::Encrypted] {
+            let archive = export_archive(&source, &key, *protection).unwrap();
+
+            let destination = managers(destination_path.clone());
+            let report =
+                import_archive(&destination, &archive, &key, ConflictPolicy::Abort).unwrap();
+            assert_eq!(report.imported.len(), 2);
+            assert!(report.conflicts.is_empty());
+            assert_eq!(
+                get(&destination, &key_triple(ProviderId::Pkcs11, "key_1")),
+                Some(1)
+            );
+            assert_eq!(
+                get(&destination, &key_triple(ProviderId::Tpm, "key_2")),
+                Some(2)
+            );
+
+            // Importing the same archive again changes nothing.
+            let report =
+                import_archive(&destination, &archive, &key, ConflictPolicy::Abort).unwrap();
+            assert!(report.imported.is_empty());
+            assert_eq!(report.unchanged.len(), 2);
+
+            drop(destination);
+            fs::remove_dir_all(&destination_path).unwrap();
+        }
+
+        // Mappings of providers without a key info manager are skipped.
+        let archive = export_archive(&source, &key, ArchiveProtection::Signed).unwrap();
+        let mut destination = managers(destination_path.clone());
+        let _ = destination.remove(&ProviderId::Tpm);
+        let report = import_archive(&destination, &archive, &key, ConflictPolicy::Abort).unwrap();
+        assert_eq!(
+            report.imported,
+            vec![key_triple(ProviderId::Pkcs11, "key_1")]
+        );
+        assert_eq!(report.skipped, vec![key_triple(ProviderId::Tpm, "key_2")]);
+
+        drop(destination);
+        drop(source);
+        fs::remove_dir_all(destination_path).unwrap();
+        fs::remove_dir_all(source_path).unwrap();
+    }
+
+    #[test]
+    fn reject_invalid_archives() {
+        let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/backup_reject_invalid_archives");
+        let key = BackupKey::new(&[0x42; BACKUP_KEY_LEN]).unwrap();
+        let other_key = BackupKey::new(&[0x43; BACKUP_KEY_LEN]).unwrap();
+        let records = vec![(key_triple(ProviderId::Pkcs11, "key_1"), key_info(1))];
+        let destination = managers(path.clone());
+
+        for protection in &[ArchiveProtection::Signed, ArchiveProtection::Encrypted] {
+            let archive = seal_archive(&records, &key, *protection).unwrap();
+
+            let mut tampered = archive.clone();
+            let last = tampered.len() - 1;
+            tampered[last] ^= 0x01;
+            let mut truncated = archive.clone();
+            truncated.truncate(10);
+            for (archive, key) in &[(tampered, &key), (truncated, &key), (archive, &other_key)] {
+                assert_eq!(
+                    import_archive(&destination, archive, key, ConflictPolicy::Abort)
+                        .unwrap_err()
+                        .kind(),
+                    ErrorKind::InvalidData
+                );
+            }
+        }
+        assert!(BackupKey::new(&[0x42; BACKUP_KEY_LEN - 1]).is_err());
+        assert_eq!(
+            get(&destination, &key_triple(ProviderId::Pkcs11, "key_1")),
+            None
+        );
+
+        drop(destination);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn import_conflicts() {
+        let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/backup_import_conflicts");
+        let key = BackupKey::new(&[0x42; BACKUP_KEY_LEN]).unwrap();
+        let archive = seal_archive(
+            &[
+                (key_triple(ProviderId::Pkcs11, "differs"), key_info(1)),
+                (key_triple(ProviderId::Pkcs11, "key_in_use"), key_info(2)),
+                (key_triple(ProviderId::Pkcs11, "new"), key_info(3)),
+                // Same ID, for another provider.
+                (key_triple(ProviderId::Tpm, "new"), key_info(2)),
+            ],
+            &key,
+            ArchiveProtection::Encrypted,
+        )
+        .unwrap();
+        let conflicts = vec![
+            Conflict::MappingDiffers(key_triple(ProviderId::Pkcs11, "differs")),
+            Conflict::KeyInUse {
+                key_triple: key_triple(ProviderId::Pkcs11, "key_in_use"),
+                used_by: key_triple(ProviderId::Pkcs11, "existing"),
+            },
+        ];
+        let destination = managers(path.clone());
+        insert(&destination, key_triple(ProviderId::Pkcs11, "differs"), 4);
+        insert(&destination, key_triple(ProviderId::Pkcs11, "existing"), 2);
+
+        let report = import_archive(&destination, &archive, &key, ConflictPolicy::Abort).unwrap();
+        assert_eq!(report.conflicts, conflicts);
+        assert!(report.imported.is_empty());
+        assert_eq!(
+            get(&destination, &key_triple(ProviderId::Pkcs11, "new")),
+            None
+        );
+
+        let report = import_archive(&destination, &archive, &key, ConflictPolicy::Skip).unwrap();
+        assert_eq!(report.conflicts, conflicts);
+        assert_eq!(
+            report.imported,
+            vec![
+                key_triple(ProviderId::Pkcs11, "new"),
+                key_triple(ProviderId::Tpm, "new")
+            ]
+        );
+        assert_eq!(
+            get(&destination, &key_triple(ProviderId::Pkcs11, "differs")),
+            Some(4)
+        );
+
+        let report =
+            import_archive(&destination, &archive, &key, ConflictPolicy::Overwrite).unwrap();
+        assert_eq!(report.conflicts, conflicts);
+        assert_eq!(
+            report.imported,
+            vec![key_triple(ProviderId::Pkcs11, "differs")]
+        );
+        assert_eq!(
+            get(&destination, &key_triple(ProviderId::Pkcs11, "differs")),
+            Some(1)
+        );
+        assert_eq!(
+            get(&destination, &key_triple(ProviderId::Pkcs11, "key_in_use")),
+            None
+        );
+
+        drop(destination);
+        fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+//! Encryption of the key info records
+//!
+//! A key info manager can be given a key-encryption key (KEK) to encrypt and authenticate the
+//! records it persists. Records are encrypted with AES-256-GCM, under a random nonce stored
+//! alongside the ciphertext. The additional authenticated data given by the manager binds a
+//! record to its location in the store so that records can not be swapped with each other.
+//!
+//! The source of the key-encryption key is configurable. Currently, the key can only be read from
+//! a file containing the 32 raw bytes of the key, which should only be readable by the service.
+//! Keys sealed by one of the providers are not supported: the key info managers are created
+//! before the providers, which need them.
+use derivative::Derivative;
+use log::{error, warn};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Deserialize;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// Length in bytes of the key-encryption key
+pub const KEY_ENCRYPTION_KEY_LEN: usize = 32;
+
+/// Where the key-encryption key is taken from
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "source")]
+pub enum KeyEncryptionKeyConfig {
+    /// The key is the content of a file.
+    File { path: String },
+}
+
+/// Key used to encrypt and authenticate the records of a key info manager
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct KeyEncryptionKey {
+    #[derivative(Debug = "ignore")]
+    key: LessSafeKey,
+    #[derivative(Debug = "ignore")]
+    rng: SystemRandom,
+}
+
+impl KeyEncryptionKey {
+    /// Creates a key-encryption key from its raw bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the key does not have the right length.
+    pub fn new(key: &[u8]) -> std::io::Result<KeyEncryptionKey> {
+        if key.len() != KEY_ENCRYPTION_KEY_LEN {
+            error!(
+                "The key-encryption key should be {} bytes long, not {}.",
+                KEY_ENCRYPTION_KEY_LEN,
+                key.len()
+            );
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "invalid key-encryption key length",
+            ));
+        }
+        let key = UnboundKey::new(&AES_256_GCM, key)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "invalid key-encryption key"))?;
+
+        Ok(KeyEncryptionKey {
+            key: LessSafeKey::new(key),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Gets the key-encryption key from the source given in the configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key could not be read or is not valid.
+    pub fn from_config(config: &KeyEncryptionKeyConfig) -> std::io::Result<KeyEncryptionKey> {
+        match config {
+            KeyEncryptionKeyConfig::File { path } => {
+                KeyEncryptionKey::from_file(&PathBuf::from(path))
+            }
+        }
+    }
+
+    /// Reads the key-encryption key from a file. A warning is logged if the file can be accessed
+    /// by other users than its owner.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file could not be read or does not contain a valid key.
+    pub fn from_file(path: &Path) -> std::io::Result<KeyEncryptionKey> {
+        let metadata = fs::metadata(path)?;
+        if metadata.permissions().mode() & 0o077 != 0 {
+            warn!(
+                "The key-encryption key file {:?} can be accessed by other users than its owner.",
+                path
+            );
+        }
+        let key = Zeroizing::new(fs::read(path)?);
+
+        KeyEncryptionKey::new(&key)
+    }
+
+    /// Encrypts and authenticates `plaintext`, also authenticating `aad`. The nonce is put in
+    /// front of the ciphertext and the tag is appended to it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no random nonce could be generated or if the encryption failed.
+    pub fn encrypt(&self, aad: &[u8], plaintext: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut nonce = [0; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| Error::new(ErrorKind::Other, "failed to generate a nonce"))?;
+
+        let mut in_out = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(aad),
+                &mut in_out,
+            )
+            .map_err(|_| Error::new(ErrorKind::Other, "failed to encrypt record"))?;
+
+        let mut ciphertext = nonce.to_vec();
+        ciphertext.append(&mut in_out);
+        Ok(ciphertext)
+    }
+
+    /// Checks and decrypts data produced by `encrypt` with the same `aad`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the data was modified, was encrypted with another key or
+    /// with other additional authenticated data.
+    pub fn decrypt(&self, aad: &[u8], ciphertext: &[u8]) -> std::io::Result<Zeroizing<Vec<u8>>> {
+        let authentication_failed =
+            || Error::new(ErrorKind::InvalidData, "record authentication failed");
+        if ciphertext.len() < NONCE_LEN {
+            return Err(authentication_failed());
+        }
+        let (nonce, ciphertext) = ciphertext.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| authentication_failed())?;
+
+        let mut in_out = Zeroizing::new(ciphertext.to_vec());
+        let plaintext_len = self
+            .key
+            .open_in_place(nonce, Aad::from(aad), &mut in_out)
+            .map_err(|_| authentication_failed())?
+            .len();
+        in_out.truncate(plaintext_len);
+        Ok(in_out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{KeyEncryptionKey, KEY_ENCRYPTION_KEY_LEN};
+
+    #[test]
+    fn encrypt_decrypt() {
+        let kek = KeyEncryptionKey::new(&[0x42; KEY_ENCRYPTION_KEY_LEN]).unwrap();
+        let ciphertext = kek.encrypt(b"record", b"key info").unwrap();
+
+        assert_eq!(
+            &kek.decrypt(b"record", &ciphertext).unwrap()[..],
+            b"key info"
+        );
+        // The additional authenticated data has to match.
+        let _ = kek.decrypt(b"other record", &ciphertext).unwrap_err();
+        // The ciphertext can not be modified.
+        let mut tampered = ciphertext.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        let _ = kek.decrypt(b"record", &tampered).unwrap_err();
+        // Another key can not decrypt it.
+        let other_kek = KeyEncryptionKey::new(&[0x43; KEY_ENCRYPTION_KEY_LEN]).unwrap();
+        let _ = other_kek.decrypt(b"record", &ciphertext).unwrap_err();
+    }
+
+    #[test]
+    fn wrong_key_length() {
+        let _ = KeyEncryptionKey::new(&[0x42; 16]).unwrap_err();
+    }
+}
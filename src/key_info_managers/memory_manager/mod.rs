@@ -0,0 +1,183 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+//! A key info manager keeping the key triple to key info mappings in memory only
+//!
+//! Nothing is persisted: all the mappings are lost when the manager is dropped, for example when
+//! the service restarts or reloads its configuration. This manager is meant for tests and for
+//! ephemeral deployments where the keys should not outlive the service. The service builder only
+//! allows it for the TPM provider, whose keys are stored in their mappings: the keys of the other
+//! providers would be left in their backends without a mapping.
+use super::{KeyInfo, KeyTriple, ManageKeyInfo};
+use crate::authenticators::ApplicationName;
+use parsec_interface::requests::ProviderId;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Default)]
+pub struct MemoryKeyInfoManager {
+    /// Mappings of the manager.
+    key_store: HashMap<KeyTriple, KeyInfo>,
+    /// Key triples reserved for keys being created, without a mapping yet.
+    reserved: HashSet<KeyTriple>,
+}
+
+impl MemoryKeyInfoManager {
+    /// Creates an empty manager.
+    pub fn new() -> MemoryKeyInfoManager {
+        MemoryKeyInfoManager::default()
+    }
+}
+
+impl ManageKeyInfo for MemoryKeyInfoManager {
+    fn get(&self, key_triple: &KeyTriple) -> Result<Option<KeyInfo>, String> {
+        Ok(self.key_store.get(key_triple).cloned())
+    }
+
+    fn get_all(&self, provider_id: ProviderId) -> Result<Vec<KeyTriple>, String> {
+        Ok(self
+            .key_store
+            .keys()
+            .filter(|key_triple| key_triple.belongs_to_provider(provider_id))
+            .cloned()
+            .collect())
+    }
+
+    fn get_all_by_app(&self, app_name: &ApplicationName) -> Result<Vec<KeyTriple>, String> {
+        Ok(self
+            .key_store
+            .keys()
+            .filter(|key_triple| key_triple.belongs_to_app(app_name))
+            .cloned()
+            .collect())
+    }
+
+    fn insert(
+        &mut self,
+        key_triple: KeyTriple,
+        key_info: KeyInfo,
+    ) -> Result<Option<KeyInfo>, String> {
+        let _ = self.reserved.remove(&key_triple);
+        Ok(self.key_store.insert(key_triple, key_info))
+    }
+
+    fn remove(&mut self, key_triple: &KeyTriple) -> Result<Option<KeyInfo>, String> {
+        Ok(self.key_store.remove(key_triple))
+    }
+
+    fn exists(&self, key_triple: &KeyTriple) -> Result<bool, String> {
+        Ok(self.key_store.contains_key(key_triple) || self.reserved.contains(key_triple))
+    }
+
+    fn reserve(&mut self, key_triple: &KeyTriple) -> Result<bool, String> {
+        if self.key_store.contains_key(key_triple) {
+            Ok(false)
+        } else {
+            Ok(self.reserved.insert(key_triple.clone()))
+        }
+    }
+
+    fn release(&mut self, key_triple: &KeyTriple) {
+        let _ = self.reserved.remove(key_triple);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{KeyInfo, KeyTriple, ManageKeyInfo};
+    use super::MemoryKeyInfoManager;
+    use crate::authenticators::ApplicationName;
+    use parsec_interface::operations::psa_algorithm::{
+        Algorithm, AsymmetricSignature, Hash, SignHash,
+    };
+    use parsec_interface::operations::psa_key_attributes::{
+        Attributes, Lifetime, Policy, Type, UsageFlags,
+    };
+    use parsec_interface::requests::ProviderId;
+
+    fn test_key_info(id: u8) -> KeyInfo {
+        KeyInfo {
+            id: vec![id; 3],
+            attributes: Attributes {
+                lifetime: Lifetime::Persistent,
+                key_type: Type::RsaKeyPair,
+                bits: 1024,
+                policy: Policy {
+                    usage_flags: {
+                        let mut usage_flags = UsageFlags::default();
+                        let _ = usage_flags.set_sign_hash();
+                        usage_flags
+                    },
+                    permitted_algorithms: Algorithm::AsymmetricSignature(
+                        AsymmetricSignature::RsaPkcs1v15Sign {
+                            hash_alg: SignHash::Specific(Hash::Sha256),
+                        },
+                    ),
+                },
+            },
+        }
+    }
+
+    fn new_key_triple(app_name: &str, provider_id: ProviderId, key_name: &str) -> KeyTriple {
+        KeyTriple::new(
+            ApplicationName::new(app_name.to_string()),
+            provider_id,
+            key_name.to_string(),
+        )
+    }
+
+    #[test]
+    fn insert_get_remove_key_info() {
+        let mut manager = MemoryKeyInfoManager::new();
+        let key_triple = new_key_triple("app", ProviderId::MbedCrypto, "key");
+
+        assert!(!manager.exists(&key_triple).unwrap());
+        assert!(manager
+            .insert(key_triple.clone(), test_key_info(1))
+            .unwrap()
+            .is_none());
+        assert_eq!(manager.get(&key_triple).unwrap(), Some(test_key_info(1)));
+        assert_eq!(
+            manager
+                .insert(key_triple.clone(), test_key_info(2))
+                .unwrap(),
+            Some(test_key_info(1))
+        );
+        assert!(manager.exists(&key_triple).unwrap());
+        assert_eq!(manager.remove(&key_triple).unwrap(), Some(test_key_info(2)));
+        assert!(!manager.exists(&key_triple).unwrap());
+        assert_eq!(manager.remove(&key_triple).unwrap(), None);
+    }
+
+    #[test]
+    fn get_all() {
+        let mut manager = MemoryKeyInfoManager::new();
+        let key_triples = [
+            new_key_triple("app_1", ProviderId::MbedCrypto, "key"),
+            new_key_triple("app_1", ProviderId::Pkcs11, "key"),
+            new_key_triple("app_2", ProviderId::Pkcs11, "key"),
+        ];
+        for key_triple in key_triples.iter() {
+            let _ = manager
+                .insert(key_triple.clone(), test_key_info(1))
+                .unwrap();
+        }
+        assert!(manager
+            .reserve(&new_key_triple("app_1", ProviderId::Pkcs11, "reserved"))
+            .unwrap());
+
+        let mut pkcs11_keys = manager.get_all(ProviderId::Pkcs11).unwrap();
+        pkcs11_keys.sort_by_key(|key_triple| key_triple.app_name().get_name().to_string());
+        assert_eq!(
+            pkcs11_keys,
+            vec![key_triples[1].clone(), key_triples[2].clone()]
+        );
+
+        let mut app_1_keys = manager
+            .get_all_by_app(&ApplicationName::new("app_1".to_string()))
+            .unwrap();
+        app_1_keys.sort_by_key(|key_triple| key_triple.provider_id() as u8);
+        assert_eq!(
+            app_1_keys,
+            vec![key_triples[0].clone(), key_triples[1].clone()]
+        );
+    }
+}
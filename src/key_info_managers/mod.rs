@@ -8,17 +8,36 @@
 //! means but it has to be persistent.
 
 use crate::authenticators::ApplicationName;
+use derivative::Derivative;
+use encryption::KeyEncryptionKeyConfig;
 use log::error;
 use parsec_interface::operations::psa_key_attributes::Attributes;
-use parsec_interface::requests::{ProviderID, ResponseStatus};
+use parsec_interface::requests::{ProviderId, ResponseStatus};
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::sync::RwLock;
 
+pub mod backup;
+pub mod encryption;
+pub mod memory_manager;
 pub mod on_disk_manager;
+pub mod record_format;
+pub mod sqlite_manager;
+
+/// Identifiers of all the providers that can store keys in a key info manager.
+pub const KEY_STORING_PROVIDERS: [ProviderId; 5] = [
+    ProviderId::MbedCrypto,
+    ProviderId::Pkcs11,
+    ProviderId::Tpm,
+    ProviderId::TrustedService,
+    ProviderId::CryptoAuthLib,
+];
 
 #[derive(Copy, Clone, Deserialize, Debug)]
 pub enum KeyInfoManagerType {
     OnDisk,
+    Sqlite,
+    Memory,
 }
 
 #[derive(Deserialize, Debug)]
@@ -26,14 +45,16 @@ pub struct KeyInfoManagerConfig {
     pub name: String,
     pub manager_type: KeyInfoManagerType,
     pub store_path: Option<String>,
+    pub encryption_key: Option<KeyEncryptionKeyConfig>,
 }
 
 /// This structure corresponds to a unique identifier of the key. It is used internally by the Key
 /// ID manager to refer to a key.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct KeyTriple {
     app_name: ApplicationName,
-    provider_id: ProviderID,
+    #[serde(with = "provider_id_serde")]
+    provider_id: ProviderId,
     key_name: String,
 }
 
@@ -58,7 +79,7 @@ pub struct KeyInfo {
 
 impl KeyTriple {
     /// Creates a new instance of KeyTriple.
-    pub fn new(app_name: ApplicationName, provider_id: ProviderID, key_name: String) -> KeyTriple {
+    pub fn new(app_name: ApplicationName, provider_id: ProviderId, key_name: String) -> KeyTriple {
         KeyTriple {
             app_name,
             provider_id,
@@ -66,10 +87,75 @@ impl KeyTriple {
         }
     }
 
+    /// Name of the application owning the key.
+    pub fn app_name(&self) -> &ApplicationName {
+        &self.app_name
+    }
+
+    /// Provider in which the key is stored.
+    pub fn provider_id(&self) -> ProviderId {
+        self.provider_id
+    }
+
+    /// Name of the key, as given by the application.
+    pub fn key_name(&self) -> &str {
+        &self.key_name
+    }
+
     /// Checks if this key belongs to a specific provider.
-    pub fn belongs_to_provider(&self, provider_id: ProviderID) -> bool {
+    pub fn belongs_to_provider(&self, provider_id: ProviderId) -> bool {
         self.provider_id == provider_id
     }
+
+    /// Checks if this key belongs to a specific application.
+    pub fn belongs_to_app(&self, app_name: &ApplicationName) -> bool {
+        self.app_name == *app_name
+    }
+}
+
+/// (De)serialization of a `ProviderId` as its numerical value, for the structures sent on the
+/// admin socket.
+pub(crate) mod provider_id_serde {
+    use parsec_interface::requests::ProviderId;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::convert::TryFrom;
+
+    pub fn serialize<S: Serializer>(
+        provider_id: &ProviderId,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(*provider_id as u8)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ProviderId, D::Error> {
+        let provider_id = u8::deserialize(deserializer)?;
+        ProviderId::try_from(provider_id)
+            .map_err(|_| D::Error::custom(format!("invalid provider ID {}", provider_id)))
+    }
+
+    /// (De)serialization of an optional `ProviderId`.
+    pub mod option {
+        use parsec_interface::requests::ProviderId;
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+        #[derive(Serialize, Deserialize)]
+        struct Wrapper(#[serde(with = "super")] ProviderId);
+
+        pub fn serialize<S: Serializer>(
+            provider_id: &Option<ProviderId>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            provider_id.map(Wrapper).serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<ProviderId>, D::Error> {
+            Ok(Option::<Wrapper>::deserialize(deserializer)?
+                .map(|Wrapper(provider_id)| provider_id))
+        }
+    }
 }
 
 /// Converts the error string returned by the ManageKeyInfo methods to
@@ -85,21 +171,28 @@ pub fn to_response_status(error_string: String) -> ResponseStatus {
 /// Management interface for key name to key info mapping
 ///
 /// Interface to be implemented for persistent storage of key name -> key info mappings.
+/// The mappings are returned by value so that the managers do not have to keep them all in memory.
 pub trait ManageKeyInfo {
-    /// Returns a reference to the key info corresponding to this key triple or `None` if it does not
-    /// exist.
+    /// Returns the key info corresponding to this key triple or `None` if it does not exist.
     ///
     /// # Errors
     ///
     /// Returns an error as a String if there was a problem accessing the Key Info Manager.
-    fn get(&self, key_triple: &KeyTriple) -> Result<Option<&KeyInfo>, String>;
+    fn get(&self, key_triple: &KeyTriple) -> Result<Option<KeyInfo>, String>;
 
-    /// Returns a Vec of reference to the key triples corresponding to this provider.
+    /// Returns a Vec of the key triples corresponding to this provider.
     ///
     /// # Errors
     ///
     /// Returns an error as a String if there was a problem accessing the Key Info Manager.
-    fn get_all(&self, provider_id: ProviderID) -> Result<Vec<&KeyTriple>, String>;
+    fn get_all(&self, provider_id: ProviderId) -> Result<Vec<KeyTriple>, String>;
+
+    /// Returns a Vec of the key triples owned by this application, in all providers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error as a String if there was a problem accessing the Key Info Manager.
+    fn get_all_by_app(&self, app_name: &ApplicationName) -> Result<Vec<KeyTriple>, String>;
 
     /// Inserts a new mapping between the key triple and the key info. If the triple already exists,
     /// overwrite the existing mapping and returns the old `KeyInfo`. Otherwise returns `None`.
@@ -121,10 +214,105 @@ pub trait ManageKeyInfo {
     /// Returns an error as a String if there was a problem accessing the Key Info Manager.
     fn remove(&mut self, key_triple: &KeyTriple) -> Result<Option<KeyInfo>, String>;
 
-    /// Check if a key triple mapping exists.
+    /// Check if a key triple mapping exists or if the key triple is reserved.
     ///
     /// # Errors
     ///
     /// Returns an error as a String if there was a problem accessing the Key Info Manager.
     fn exists(&self, key_triple: &KeyTriple) -> Result<bool, String>;
+
+    /// Reserves a key triple for a key being created, so that the Key Info Manager does not have
+    /// to stay locked while the key is created in the backend. A reserved key triple exists but
+    /// has no key info until it is inserted, which ends the reservation. Reservations are not
+    /// persistent. Returns `false` if the key triple already exists or is reserved.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error as a String if there was a problem accessing the Key Info Manager.
+    fn reserve(&mut self, key_triple: &KeyTriple) -> Result<bool, String>;
+
+    /// Ends the reservation of a key triple without inserting a mapping, when the key could not be
+    /// created. Does nothing if the key triple is not reserved.
+    fn release(&mut self, key_triple: &KeyTriple);
+}
+
+/// Reservation of a key triple in a shared Key Info Manager, held while the key is created in the
+/// backend without the Key Info Manager being locked. The reservation ends when the mapping is
+/// committed or when it is dropped.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct KeyTripleReservation<'a> {
+    #[derivative(Debug = "ignore")]
+    store: &'a RwLock<dyn ManageKeyInfo + Send + Sync>,
+    key_triple: KeyTriple,
+    committed: bool,
+}
+
+impl<'a> KeyTripleReservation<'a> {
+    /// Reserves the key triple in the Key Info Manager, which is only locked for the duration of
+    /// this call.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ResponseStatus::PsaErrorAlreadyExists` if the key triple already exists or is
+    /// reserved and `ResponseStatus::KeyInfoManagerError` if the Key Info Manager failed.
+    pub fn new(
+        store: &'a RwLock<dyn ManageKeyInfo + Send + Sync>,
+        key_triple: KeyTriple,
+    ) -> Result<KeyTripleReservation<'a>, ResponseStatus> {
+        let reserved = store
+            .write()
+            .expect("Key store lock poisoned")
+            .reserve(&key_triple)
+            .map_err(to_response_status)?;
+        if !reserved {
+            return Err(ResponseStatus::PsaErrorAlreadyExists);
+        }
+
+        Ok(KeyTripleReservation {
+            store,
+            key_triple,
+            committed: false,
+        })
+    }
+
+    /// The key triple reserved.
+    pub fn key_triple(&self) -> &KeyTriple {
+        &self.key_triple
+    }
+
+    /// Stores the mapping of the key triple once the key has been created in the backend.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ResponseStatus::KeyInfoManagerError` if the mapping could not be stored. The
+    /// reservation is then released.
+    pub fn commit(mut self, key_info: KeyInfo) -> Result<(), ResponseStatus> {
+        let _ = self
+            .store
+            .write()
+            .expect("Key store lock poisoned")
+            .insert(self.key_triple.clone(), key_info)
+            .map_err(to_response_status)?;
+        self.committed = true;
+
+        Ok(())
+    }
+}
+
+impl Drop for KeyTripleReservation<'_> {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+        // Panicking while dropping could abort the service if the thread is already unwinding.
+        if let Ok(mut store) = self.store.write() {
+            store.release(&self.key_triple);
+        } else {
+            error!(
+                "Key store lock poisoned, the reservation of {} could not be released.",
+                self.key_triple
+            );
+        }
+    }
 }
@@ -0,0 +1,287 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+//! Integrity of the encrypted mappings
+//!
+//! When a key-encryption key is given to the on-disk manager, each mapping file contains a
+//! generation number followed by the key info encrypted with the key-encryption key. The
+//! additional authenticated data is the path of the mapping file relative to the mappings
+//! directory and the generation number, so that a mapping file can not be modified, moved or
+//! given another generation number without being detected.
+//!
+//! Every write uses a new generation number. A manifest, also encrypted with the key-encryption
+//! key, records the last generation number used and the generation number of each mapping file.
+//! A mapping file with a valid tag but a generation number different from the one in the manifest
+//! is a previous version of the mapping, or a mapping that was deleted, put back in the store.
+//!
+//! Mapping files are written before the manifest is updated, so a mapping file newer than the
+//! manifest is the result of an interrupted write and is accepted. When a mapping is removed, the
+//! manifest is updated before the mapping file is deleted.
+//!
+//! The manifest is written as soon as the store is created, before any mapping file. A missing
+//! manifest while mapping files sealed with the key-encryption key exist means that it was
+//! deleted, which would make any previous version of a mapping file look newer than the manifest:
+//! the mappings are then refused.
+//!
+//! The manifest does not protect against the whole mappings directory, manifest included, being
+//! replaced by an older copy.
+//!
+//! When the key-encryption key is given to an existing plaintext store, the plaintext mapping
+//! files are encrypted and added to the manifest. Plaintext mapping files are only accepted if the
+//! manifest does not exist yet, or if it records that the store is still being encrypted. The
+//! manifest is written with that record before the first mapping file is encrypted, so that an
+//! interrupted encryption is resumed the next time the store is loaded, and without it once all
+//! the mapping files are encrypted. As for the rest of the store, plaintext mapping files found
+//! before the manifest is created can not be told apart from ones put there by someone with
+//! write access to the mappings directory.
+use super::super::encryption::KeyEncryptionKey;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+/// Name of the manifest file, in the mappings directory
+pub const MANIFEST_FILE_NAME: &str = ".manifest";
+
+const MANIFEST_AAD: &[u8] = b"parsec-key-info-manifest";
+const GENERATION_LEN: usize = 8;
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Manifest {
+    /// Last generation number used
+    generation: u64,
+    /// Generation number of each mapping file, indexed by its path relative to the mappings
+    /// directory
+    records: HashMap<String, u64>,
+    /// Set while the plaintext mapping files of an existing store are being encrypted
+    encrypting_plaintext: bool,
+}
+
+/// Encryption state of the mappings
+#[derive(Debug)]
+pub struct EncryptedMappings {
+    kek: KeyEncryptionKey,
+    manifest: Manifest,
+    /// Set if the manifest in memory is different from the one read from disk
+    manifest_changed: bool,
+    /// Set if the manifest did not exist when it was loaded
+    manifest_missing: bool,
+    /// Set if a mapping file sealed with the key-encryption key was opened while the manifest
+    /// is missing
+    sealed_record_found: bool,
+}
+
+fn record_aad(record_path: &str, generation: u64) -> Vec<u8> {
+    let mut aad = record_path.as_bytes().to_vec();
+    aad.extend_from_slice(&generation.to_le_bytes());
+    aad
+}
+
+impl EncryptedMappings {
+    /// Reads the manifest from the mappings directory. An empty manifest is used if it does not
+    /// exist yet, to be written once `check_manifest` confirmed that the store is new.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the manifest was modified or was encrypted with another
+    /// key-encryption key.
+    pub fn load(kek: KeyEncryptionKey, mappings_dir_path: &Path) -> std::io::Result<Self> {
+        let manifest_path = mappings_dir_path.join(MANIFEST_FILE_NAME);
+        let manifest_missing = !manifest_path.exists();
+        let manifest = if !manifest_missing {
+            let manifest = fs::read(&manifest_path)?;
+            let manifest = match kek.decrypt(MANIFEST_AAD, &manifest) {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    error!(
+                        "The mappings manifest was modified or the key-encryption key is wrong."
+                    );
+                    return Err(e);
+                }
+            };
+            bincode::deserialize(&manifest).map_err(|e| {
+                error!("Error deserializing the mappings manifest ({}).", e);
+                Error::new(ErrorKind::InvalidData, "error deserializing manifest")
+            })?
+        } else {
+            Manifest::default()
+        };
+
+        Ok(EncryptedMappings {
+            kek,
+            manifest,
+            manifest_changed: manifest_missing,
+            manifest_missing,
+            sealed_record_found: false,
+        })
+    }
+
+    /// Checks that the manifest existed if mapping files sealed with the key-encryption key were
+    /// opened. To be called once all the mapping files were opened.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the manifest was deleted from an existing store.
+    pub fn check_manifest(&self) -> std::io::Result<()> {
+        if self.sealed_record_found {
+            error!("The mappings manifest is missing but encrypted mapping files exist.");
+            Err(Error::new(
+                ErrorKind::InvalidData,
+                "mappings manifest deleted",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks and decrypts the content of a mapping file. A mapping file newer than the manifest
+    /// is added to it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the mapping file was modified or if it is not the one
+    /// recorded in the manifest.
+    pub fn open_record(
+        &mut self,
+        record_path: &str,
+        data: &[u8],
+    ) -> std::io::Result<Zeroizing<Vec<u8>>> {
+        if data.len() < GENERATION_LEN {
+            return Err(Error::new(ErrorKind::InvalidData, "mapping file truncated"));
+        }
+        let (generation, ciphertext) = data.split_at(GENERATION_LEN);
+        let generation = u64::from_le_bytes(
+            generation
+                .try_into()
+                .expect("The generation number has the right length."),
+        );
+        let key_info = self
+            .kek
+            .decrypt(&record_aad(record_path, generation), ciphertext)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "mapping file tampered with"))?;
+
+        if self.manifest_missing {
+            self.sealed_record_found = true;
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "mapping file found without a manifest",
+            ));
+        }
+
+        match self.manifest.records.get(record_path) {
+            Some(recorded) if *recorded == generation => Ok(key_info),
+            _ if generation > self.manifest.generation => {
+                warn!(
+                    "Mapping file {} was written but not recorded in the manifest, adding it.",
+                    record_path
+                );
+                self.commit_record(record_path, generation);
+                Ok(key_info)
+            }
+            Some(_) => Err(Error::new(
+                ErrorKind::InvalidData,
+                "mapping file rolled back to a previous version",
+            )),
+            None => Err(Error::new(
+                ErrorKind::InvalidData,
+                "mapping file deleted or never written by the service",
+            )),
+        }
+    }
+
+    /// Checks if plaintext mapping files can be read: only when the manifest did not exist yet or
+    /// while the plaintext mapping files are being encrypted.
+    pub fn accepts_plaintext(&self) -> bool {
+        self.manifest_missing || self.manifest.encrypting_plaintext
+    }
+
+    /// Checks if the plaintext mapping files are being encrypted.
+    pub fn encrypting_plaintext(&self) -> bool {
+        self.manifest.encrypting_plaintext
+    }
+
+    /// Records in the manifest whether the plaintext mapping files are being encrypted.
+    pub fn set_encrypting_plaintext(&mut self, encrypting_plaintext: bool) {
+        if self.manifest.encrypting_plaintext != encrypting_plaintext {
+            self.manifest.encrypting_plaintext = encrypting_plaintext;
+            self.manifest_changed = true;
+        }
+    }
+
+    /// Reports the mappings present in the manifest but whose file was not found.
+    pub fn report_missing_records(&self, found_records: &HashSet<String>) {
+        for record_path in self
+            .manifest
+            .records
+            .keys()
+            .filter(|record_path| !found_records.contains(*record_path))
+        {
+            error!(
+                "Mapping file {} is recorded in the manifest but was not found.",
+                record_path
+            );
+        }
+    }
+
+    /// Encrypts the content of a mapping file with the next generation number. The generation
+    /// number is returned to be given to `commit_record` once the mapping file is written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the encryption failed.
+    pub fn seal_record(
+        &self,
+        record_path: &str,
+        key_info: &[u8],
+    ) -> std::io::Result<(u64, Vec<u8>)> {
+        let generation = self.manifest.generation + 1;
+        let mut data = generation.to_le_bytes().to_vec();
+        data.append(
+            &mut self
+                .kek
+                .encrypt(&record_aad(record_path, generation), key_info)?,
+        );
+
+        Ok((generation, data))
+    }
+
+    /// Records in the manifest that a mapping file was written.
+    pub fn commit_record(&mut self, record_path: &str, generation: u64) {
+        let _ = self
+            .manifest
+            .records
+            .insert(record_path.to_owned(), generation);
+        self.manifest.generation = self.manifest.generation.max(generation);
+        self.manifest_changed = true;
+    }
+
+    /// Removes a mapping file from the manifest.
+    pub fn forget_record(&mut self, record_path: &str) {
+        if self.manifest.records.remove(record_path).is_some() {
+            // Removing a mapping is a modification of the store as well.
+            self.manifest.generation += 1;
+            self.manifest_changed = true;
+        }
+    }
+
+    /// Checks if the manifest was modified since it was read from disk.
+    pub fn manifest_changed(&self) -> bool {
+        self.manifest_changed
+    }
+
+    /// Returns the encrypted manifest, to be written to disk.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the manifest could not be serialized or encrypted.
+    pub fn seal_manifest(&self) -> std::io::Result<Vec<u8>> {
+        let manifest = Zeroizing::new(bincode::serialize(&self.manifest).map_err(|e| {
+            error!("Error serializing the mappings manifest ({}).", e);
+            Error::new(ErrorKind::Other, "error serializing manifest")
+        })?);
+        self.kek.encrypt(MANIFEST_AAD, &manifest)
+    }
+}
@@ -0,0 +1,81 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+//! Exclusive lock on the mappings directory
+//!
+//! The on-disk manager takes an exclusive advisory lock (`flock`) on a lock file in the mappings
+//! directory for as long as it exists. The PID of the process holding the lock is written in the
+//! lock file so that it can be reported if another manager tries to use the same directory. The
+//! lock is released by the kernel when the file is closed, including when the process dies, so a
+//! lock file left behind does not prevent the next manager from starting.
+use log::error;
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+/// Name of the lock file, in the mappings directory
+pub const LOCK_FILE_NAME: &str = ".lock";
+
+/// Exclusive lock on a mappings directory, released when dropped
+#[derive(Debug)]
+pub struct MappingsLock {
+    file: File,
+}
+
+impl MappingsLock {
+    /// Takes the lock on the mappings directory, without waiting.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `WouldBlock` error, naming the process holding the lock, if the lock is already
+    /// held, or any other error encountered while taking the lock.
+    pub fn acquire(mappings_dir_path: &Path) -> std::io::Result<MappingsLock> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            // The PID of the process holding the lock must not be erased before the lock is taken.
+            .truncate(false)
+            .open(mappings_dir_path.join(LOCK_FILE_NAME))?;
+
+        // Safe as the file descriptor is valid for the duration of the call.
+        let ret = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+        if ret != 0 {
+            let err = Error::last_os_error();
+            if err.kind() != ErrorKind::WouldBlock {
+                return Err(err);
+            }
+            let mut owner = String::new();
+            let _ = file.read_to_string(&mut owner);
+            let owner = match owner.trim() {
+                "" => String::from("unknown"),
+                pid => pid.to_owned(),
+            };
+            error!(
+                "The mappings directory {:?} is already used by the process with PID {}. Only one key info manager can use a mappings directory at a time.",
+                mappings_dir_path, owner
+            );
+            return Err(Error::new(
+                ErrorKind::WouldBlock,
+                format!(
+                    "mappings directory already used by the process with PID {}",
+                    owner
+                ),
+            ));
+        }
+
+        file.set_len(0)?;
+        writeln!(file, "{}", std::process::id())?;
+        file.sync_all()?;
+
+        Ok(MappingsLock { file })
+    }
+}
+
+impl Drop for MappingsLock {
+    fn drop(&mut self) {
+        // The lock would be released when the file is closed anyway, but this makes it explicit.
+        // Safe as the file descriptor is valid for the duration of the call.
+        let _ = unsafe { libc::flock(self.file.as_raw_fd(), libc::LOCK_UN) };
+    }
+}
@@ -4,28 +4,95 @@
 //!
 //! The path where the mappings should be stored is configurable. Because of possible data races,
 //! there should not be two instances of this manager pointing to the same mapping folder at a time.
+//! This is enforced with an exclusive lock on a lock file in the mappings folder, held for as long
+//! as the manager exists: creating a second manager on the same folder, from this process or from
+//! another one, fails with an error naming the process holding the lock.
 //! Methods modifying the mapping will also block until the modifications are done on disk to be
 //! ensured to not lose mappings.
-//! Because application and key names can contain any UTF-8 characters, those strings are converted
-//! to base64 strings so that they can be used as filenames. Because of filenames limitations, some
-//! very long UTF-8 names might not be able to be represented as a filename and will fail. For
-//! example, for operating systems having a limit of 255 characters for filenames (Unix systems),
-//! names will be limited to 188 bytes of UTF-8 characters.
+//! Because application and key names can contain any UTF-8 characters, and be of any length, the
+//! mapping files are named after the SHA-256 hashes of those names. The names themselves are stored
+//! inside the mapping files.
+//! Mapping files of the legacy layout, named after the base64 encoding of the names, are still
+//! loaded. They are converted to the hashed layout when they are written again or migrated. The
+//! legacy layout limited the names to 188 bytes of UTF-8 characters on systems having a limit of
+//! 255 characters for filenames.
+//! Mappings are first written to a temporary file which is synced to disk and then atomically
+//! renamed over the mapping file, so that a crash can not leave a mapping half-written. Temporary
+//! files left by a crash, and mapping files that can not be read, are reported and ignored when
+//! the mappings are loaded.
+//! If a key-encryption key is given, the mapping files are encrypted and authenticated with it
+//! and a manifest is kept to detect mapping files rolled back to a previous version. Mapping files
+//! failing those checks are reported and ignored when the mappings are loaded. See the
+//! `integrity` module for details.
 //! For security reasons, only the PARSEC service should have the ability to modify these files.
+use super::encryption::KeyEncryptionKey;
+use super::record_format::{deserialize_key_info, serialize_key_info, CURRENT_VERSION};
 use super::{KeyInfo, KeyTriple, ManageKeyInfo};
 use crate::authenticators::ApplicationName;
-use log::{error, info};
-use parsec_interface::requests::ProviderID;
-use std::collections::HashMap;
+use integrity::{EncryptedMappings, MANIFEST_FILE_NAME};
+use lock::MappingsLock;
+use log::{error, info, warn};
+use parsec_interface::requests::ProviderId;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::ffi::OsStr;
+use std::fmt;
 use std::fs;
 use std::fs::{DirEntry, File};
 use std::io::{Error, ErrorKind, Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+mod integrity;
+mod lock;
 
 pub const DEFAULT_MAPPINGS_PATH: &str = "./mappings";
 
+/// Prefix of the temporary files. Hashed and base64 file names do not contain dots so those files
+/// can not be mistaken for mappings.
+const TEMP_FILE_PREFIX: &str = ".tmp-";
+
+/// Prefix of the hashed file names. It is not part of the base64 alphabet so that hashed names can
+/// not be mistaken for the names of the legacy layout.
+const HASHED_NAME_PREFIX: &str = "~";
+
+/// Format in which a mapping is stored on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MappingFormat {
+    /// Version of the key info record
+    pub record_version: u32,
+    /// Set if the mapping file is named after the base64 encoding of the names
+    pub legacy_layout: bool,
+}
+
+impl MappingFormat {
+    /// Checks if the mapping needs to be converted to the current format.
+    fn is_outdated(&self) -> bool {
+        self.legacy_layout || self.record_version < CURRENT_VERSION
+    }
+}
+
+impl fmt::Display for MappingFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "record version {}", self.record_version)?;
+        if self.legacy_layout {
+            write!(f, ", legacy layout")?;
+        }
+        Ok(())
+    }
+}
+
+/// Content of a mapping file of the hashed layout
+#[derive(Serialize, Deserialize)]
+struct NamedRecord {
+    app_name: String,
+    key_name: String,
+    /// Key info record, in the format of the `record_format` module
+    record: Vec<u8>,
+}
+
 #[derive(Debug)]
 pub struct OnDiskKeyInfoManager {
     /// Internal mapping, used for non-modifying operations.
@@ -33,10 +100,18 @@ pub struct OnDiskKeyInfoManager {
     /// Folder where all the key triple to key info mappings are saved. This folder will be created
     /// if it does already exist.
     mappings_dir_path: PathBuf,
+    /// Encryption state of the mapping files, if they are encrypted.
+    encrypted_mappings: Option<EncryptedMappings>,
+    /// Mappings stored in an older format, with that format.
+    outdated_mappings: HashMap<KeyTriple, MappingFormat>,
+    /// Key triples reserved for keys being created, without a mapping yet.
+    reserved: HashSet<KeyTriple>,
+    /// Lock on the mappings folder, only held to be released when the manager is dropped.
+    _lock: MappingsLock,
 }
 
 /// Encodes a KeyTriple's data into base64 strings that can be used as filenames.
-/// The ProviderID will not be converted as a base64 as it can always be represented as a String
+/// The ProviderId will not be converted as a base64 as it can always be represented as a String
 /// being a number from 0 and 255.
 fn key_triple_to_base64_filenames(key_triple: &KeyTriple) -> (String, String, String) {
     (
@@ -46,6 +121,30 @@ fn key_triple_to_base64_filenames(key_triple: &KeyTriple) -> (String, String, St
     )
 }
 
+/// Hashes an application or key name into a filename of fixed length.
+fn hashed_filename(name: &str) -> String {
+    format!(
+        "{}{}",
+        HASHED_NAME_PREFIX,
+        hex::encode(digest::digest(&digest::SHA256, name.as_bytes()))
+    )
+}
+
+/// Hashes a KeyTriple's names into filenames. The ProviderId is represented as a number, as in the
+/// legacy layout.
+fn key_triple_to_hashed_filenames(key_triple: &KeyTriple) -> (String, String, String) {
+    (
+        hashed_filename(key_triple.app_name.get_name()),
+        (key_triple.provider_id as u8).to_string(),
+        hashed_filename(&key_triple.key_name),
+    )
+}
+
+/// Path of a mapping file relative to the mappings directory, identifying it in the manifest.
+fn record_path(app_name: &str, prov: &str, key_name: &str) -> String {
+    format!("{}/{}/{}", app_name, prov, key_name)
+}
+
 /// Decodes base64 bytes to its original String value.
 ///
 /// # Errors
@@ -69,7 +168,7 @@ fn base64_data_to_string(base64_bytes: &[u8]) -> Result<String, String> {
 /// Returns an error as a string if either the decoding or the bytes conversion to UTF-8 failed.
 fn base64_data_triple_to_key_triple(
     app_name: &[u8],
-    provider_id: ProviderID,
+    provider_id: ProviderId,
     key_name: &[u8],
 ) -> Result<KeyTriple, String> {
     let app_name = ApplicationName::new(base64_data_to_string(app_name)?);
@@ -82,30 +181,96 @@ fn base64_data_triple_to_key_triple(
     })
 }
 
-/// Converts an OsStr reference to a byte array.
+/// Returns the final component of a path as a string.
 ///
 /// # Errors
 ///
 /// Returns a custom std::io error if the conversion failed.
-fn os_str_to_u8_ref(os_str: &OsStr) -> std::io::Result<&[u8]> {
-    match os_str.to_str() {
-        Some(str) => Ok(str.as_bytes()),
-        None => Err(Error::new(
-            ErrorKind::Other,
-            "Conversion from PathBuf to String failed.",
-        )),
+fn file_name_str(path: &Path) -> std::io::Result<&str> {
+    path.file_name()
+        .expect("The mapping paths should contain a final component.")
+        .to_str()
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::Other,
+                "Conversion from PathBuf to String failed.",
+            )
+        })
+}
+
+/// Reads the key triple and key info from the content of a mapping file of the hashed layout and
+/// checks that the names match the path of the file.
+fn read_hashed_mapping(
+    app_name_dir: &str,
+    provider_id: ProviderId,
+    key_name_file: &str,
+    data: &[u8],
+) -> Result<(KeyTriple, KeyInfo, MappingFormat), String> {
+    let named_record: NamedRecord = bincode::deserialize(data)
+        .map_err(|e| format!("mapping file truncated or corrupted ({})", e))?;
+    if hashed_filename(&named_record.app_name) != app_name_dir
+        || hashed_filename(&named_record.key_name) != key_name_file
+    {
+        return Err(String::from(
+            "the names in the mapping file do not match its path",
+        ));
     }
+    let (key_info, record_version) = deserialize_key_info(&named_record.record)?;
+
+    Ok((
+        KeyTriple::new(
+            ApplicationName::new(named_record.app_name),
+            provider_id,
+            named_record.key_name,
+        ),
+        key_info,
+        MappingFormat {
+            record_version,
+            legacy_layout: false,
+        },
+    ))
+}
+
+/// Reads the key triple and key info from the path and content of a mapping file of the legacy
+/// layout.
+fn read_legacy_mapping(
+    app_name_dir: &str,
+    provider_id: ProviderId,
+    key_name_file: &str,
+    data: &[u8],
+) -> Result<(KeyTriple, KeyInfo, MappingFormat), String> {
+    let key_triple = base64_data_triple_to_key_triple(
+        app_name_dir.as_bytes(),
+        provider_id,
+        key_name_file.as_bytes(),
+    )
+    .map_err(|e| {
+        format!(
+            "failed to convert the mapping path to an UTF-8 string ({})",
+            e
+        )
+    })?;
+    let (key_info, record_version) = deserialize_key_info(data)?;
+
+    Ok((
+        key_triple,
+        key_info,
+        MappingFormat {
+            record_version,
+            legacy_layout: true,
+        },
+    ))
 }
 
-/// Converts an OsStr reference to a ProviderID value.
+/// Converts an OsStr reference to a ProviderId value.
 ///
 /// # Errors
 ///
 /// Returns a custom std::io error if the conversion failed.
-fn os_str_to_provider_id(os_str: &OsStr) -> std::io::Result<ProviderID> {
+fn os_str_to_provider_id(os_str: &OsStr) -> std::io::Result<ProviderId> {
     match os_str.to_str() {
         Some(str) => match str.parse::<u8>() {
-            Ok(provider_id_u8) => match ProviderID::try_from(provider_id_u8) {
+            Ok(provider_id_u8) => match ProviderId::try_from(provider_id_u8) {
                 Ok(provider_id) => Ok(provider_id),
                 Err(response_status) => {
                     Err(Error::new(ErrorKind::Other, response_status.to_string()))
@@ -135,6 +300,32 @@ fn list_dirs(path: &PathBuf) -> std::io::Result<Vec<PathBuf>> {
         .collect())
 }
 
+/// Flushes the content of a directory, for example after a file was created, renamed or removed in
+/// it, to disk.
+fn sync_dir(path: &Path) -> std::io::Result<()> {
+    File::open(path)?.sync_all()
+}
+
+/// Writes a file in a directory by writing the data to a temporary file, syncing it and renaming
+/// it over the file. The directory is synced as well.
+fn write_file_atomically(dir_path: &Path, file_path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let temp_file_path = dir_path.join(format!("{}{}", TEMP_FILE_PREFIX, std::process::id()));
+    let mut temp_file = File::create(&temp_file_path)?;
+    temp_file.write_all(data)?;
+    temp_file.sync_all()?;
+    fs::rename(&temp_file_path, file_path)?;
+
+    sync_dir(dir_path)
+}
+
+/// Checks if the file at this path is a temporary file.
+fn is_temp_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(OsStr::to_str)
+        .map(|file_name| file_name.starts_with(TEMP_FILE_PREFIX))
+        .unwrap_or(false)
+}
+
 /// Lists all the file paths in the given directory path.
 fn list_files(path: &PathBuf) -> std::io::Result<Vec<PathBuf>> {
     let dir_entries: std::io::Result<Vec<DirEntry>> = path.read_dir()?.collect();
@@ -170,92 +361,345 @@ impl OnDiskKeyInfoManager {
     /// |---appN/
     ///
     /// where the path of a key name from the mappings directory is the key triple (application,
-    /// provider, key) and the data inside the key name file is the key info serialised in binary
-    /// format.
+    /// provider, key), with the application and key names hashed, and the data inside the key name
+    /// file is the names and the key info serialised in binary format. In the legacy layout, the
+    /// application and key names are encoded in base64 and the file only contains the key info.
     /// Each mapping is contained in its own file to prevent the modification of one mapping
     /// impacting the other ones.
     ///
+    /// Temporary files left by an interrupted write are removed: the mapping file they were going
+    /// to replace, if any, is still complete. Mapping files that can not be deserialized, for
+    /// example because they were truncated, are reported and ignored.
+    ///
+    /// If a key-encryption key is given, the manifest is read as well and mapping files that were
+    /// tampered with, or that do not match the manifest, are reported and ignored. Plaintext
+    /// mapping files found when the key-encryption key is given for the first time are encrypted.
+    ///
     /// # Errors
     ///
-    /// Returns an std::io error if the function failed reading the mapping files.
-    fn new(mappings_dir_path: PathBuf) -> std::io::Result<OnDiskKeyInfoManager> {
+    /// Returns an std::io error if the function failed reading the mapping files, if the
+    /// manifest could not be authenticated with the key-encryption key, if it is missing while
+    /// encrypted mapping files exist or if the mappings folder is already used by another manager.
+    fn new(
+        mappings_dir_path: PathBuf,
+        key_encryption_key: Option<KeyEncryptionKey>,
+    ) -> std::io::Result<OnDiskKeyInfoManager> {
         let mut key_store = HashMap::new();
+        let mut found_records = HashSet::new();
+        let mut plaintext_mappings = HashMap::new();
+        let mut outdated_mappings: HashMap<KeyTriple, MappingFormat> = HashMap::new();
 
         // Will ignore if the mappings directory already exists.
         fs::create_dir_all(&mappings_dir_path)?;
+        let lock = MappingsLock::acquire(&mappings_dir_path)?;
+
+        let mut encrypted_mappings = match key_encryption_key {
+            Some(kek) => Some(EncryptedMappings::load(kek, &mappings_dir_path)?),
+            None => None,
+        };
+
+        for temp_file_path in list_files(&mappings_dir_path)?
+            .iter()
+            .filter(|file_path| is_temp_file(file_path))
+        {
+            warn!(
+                "Removing temporary file left by an interrupted write: {:?}.",
+                temp_file_path
+            );
+            fs::remove_file(temp_file_path)?;
+        }
 
         for app_name_dir_path in list_dirs(&mappings_dir_path)?.iter() {
+            let app_name_dir = file_name_str(app_name_dir_path)?;
             for provider_dir_path in list_dirs(&app_name_dir_path)?.iter() {
+                let prov = file_name_str(provider_dir_path)?;
+                let provider_id = os_str_to_provider_id(OsStr::new(prov))?;
                 for key_name_file_path in list_files(&provider_dir_path)?.iter() {
+                    if is_temp_file(key_name_file_path) {
+                        warn!(
+                            "Removing temporary file left by an interrupted write: {:?}.",
+                            key_name_file_path
+                        );
+                        fs::remove_file(key_name_file_path)?;
+                        continue;
+                    }
                     info!("Found mapping file: {:?}.", key_name_file_path);
-                    let mut key_info = Vec::new();
+                    let key_name_file = file_name_str(key_name_file_path)?;
+                    let record_path = record_path(app_name_dir, prov, key_name_file);
+
+                    let mut data = Vec::new();
                     let mut key_info_file = File::open(&key_name_file_path)?;
-                    let _ = key_info_file.read_to_end(&mut key_info)?;
-                    let key_info = bincode::deserialize(&key_info[..]).or_else(|e| {
-                        error!("Error deserializing key info ({}).", e);
-                        Err(Error::new(ErrorKind::Other, "error deserializing key info"))
-                    })?;
-                    match base64_data_triple_to_key_triple(
-                        os_str_to_u8_ref(app_name_dir_path.file_name().expect(
-                            "The application name directory path should contain a final component.",
-                        ))?,
-                        os_str_to_provider_id(provider_dir_path.file_name().expect(
-                            "The provider directory path should contain a final component.",
-                        ))?,
-                        os_str_to_u8_ref(key_name_file_path.file_name().expect(
-                            "The key name directory path should contain a final component.",
-                        ))?,
-                    ) {
-                        Ok(key_triple) => {
-                            let _ = key_store.insert(key_triple, key_info);
+                    let _ = key_info_file.read_to_end(&mut data)?;
+                    let read_mapping = |data: &[u8]| {
+                        if key_name_file.starts_with(HASHED_NAME_PREFIX) {
+                            read_hashed_mapping(app_name_dir, provider_id, key_name_file, data)
+                        } else {
+                            read_legacy_mapping(app_name_dir, provider_id, key_name_file, data)
                         }
-                        Err(string) => {
-                            error!("Failed to convert the mapping path found to an UTF-8 string (error: {}).", string);
+                    };
+                    let mapping = match encrypted_mappings.as_mut() {
+                        Some(encrypted_mappings) => {
+                            match encrypted_mappings
+                                .open_record(&record_path, &data)
+                                .map_err(|e| e.to_string())
+                                .and_then(|data| read_mapping(&data))
+                            {
+                                Err(_) if encrypted_mappings.accepts_plaintext() => {
+                                    match read_mapping(&data) {
+                                        Ok((key_triple, key_info, format)) => {
+                                            // As for the other mappings, the hashed layout is
+                                            // used if a mapping is found in both.
+                                            if !format.legacy_layout
+                                                || !plaintext_mappings.contains_key(&key_triple)
+                                            {
+                                                let _ =
+                                                    plaintext_mappings.insert(key_triple, key_info);
+                                            }
+                                            continue;
+                                        }
+                                        Err(e) => Err(e),
+                                    }
+                                }
+                                mapping => mapping,
+                            }
                         }
+                        None => read_mapping(&data),
+                    };
+                    let _ = found_records.insert(record_path);
+
+                    let (key_triple, key_info, format) = match mapping {
+                        Ok(mapping) => mapping,
+                        Err(e) => {
+                            error!("Ignoring mapping file {:?}: {}.", key_name_file_path, e);
+                            continue;
+                        }
+                    };
+                    // A mapping can be found in both layouts if the service stopped before
+                    // removing the legacy file, after writing the hashed one. The hashed one is
+                    // used and the mapping is kept as outdated so that the legacy file is removed
+                    // when the mapping is migrated.
+                    let in_both_layouts = if format.legacy_layout {
+                        key_store.contains_key(&key_triple)
+                    } else {
+                        matches!(outdated_mappings.get(&key_triple), Some(format) if format.legacy_layout)
+                    };
+                    if in_both_layouts {
+                        warn!(
+                            "Mapping of {} found in both layouts, using the hashed one.",
+                            key_triple
+                        );
                     }
+                    if format.legacy_layout {
+                        let _ = outdated_mappings.insert(key_triple.clone(), format);
+                        if in_both_layouts {
+                            continue;
+                        }
+                    } else if format.is_outdated() && !in_both_layouts {
+                        let _ = outdated_mappings.insert(key_triple.clone(), format);
+                    }
+                    let _ = key_store.insert(key_triple, key_info);
                 }
             }
         }
 
-        Ok(OnDiskKeyInfoManager {
+        if let Some(encrypted_mappings) = encrypted_mappings.as_mut() {
+            encrypted_mappings.check_manifest()?;
+            encrypted_mappings.report_missing_records(&found_records);
+            if !plaintext_mappings.is_empty() {
+                // Recorded before the first mapping file is encrypted so that an interrupted
+                // encryption is resumed the next time the store is loaded.
+                encrypted_mappings.set_encrypting_plaintext(true);
+            }
+            if encrypted_mappings.manifest_changed() {
+                write_file_atomically(
+                    &mappings_dir_path,
+                    &mappings_dir_path.join(MANIFEST_FILE_NAME),
+                    &encrypted_mappings.seal_manifest()?,
+                )?;
+            }
+        }
+
+        if !outdated_mappings.is_empty() {
+            warn!(
+                "{} mappings are stored in an older format, use the migrate subcommand to convert them.",
+                outdated_mappings.len()
+            );
+        }
+
+        let mut on_disk_manager = OnDiskKeyInfoManager {
             key_store,
             mappings_dir_path,
-        })
+            encrypted_mappings,
+            outdated_mappings,
+            reserved: HashSet::new(),
+            _lock: lock,
+        };
+        on_disk_manager.encrypt_plaintext_mappings(plaintext_mappings)?;
+
+        Ok(on_disk_manager)
+    }
+
+    /// Encrypts the plaintext mappings found in the store when the key-encryption key was given to
+    /// it for the first time. Each mapping file is sealed and added to the manifest, which stops
+    /// accepting plaintext mapping files once they are all encrypted.
+    fn encrypt_plaintext_mappings(
+        &mut self,
+        plaintext_mappings: HashMap<KeyTriple, KeyInfo>,
+    ) -> std::io::Result<()> {
+        match &self.encrypted_mappings {
+            Some(encrypted_mappings) if encrypted_mappings.encrypting_plaintext() => (),
+            _ => return Ok(()),
+        }
+
+        for (key_triple, key_info) in plaintext_mappings {
+            if self.key_store.contains_key(&key_triple) {
+                // The encryption was interrupted after the mapping file of the hashed layout was
+                // written, only the plaintext one of the legacy layout is left.
+                let (app_name, prov, key_name) = key_triple_to_base64_filenames(&key_triple);
+                self.remove_mapping_file(&app_name, &prov, &key_name)?;
+                continue;
+            }
+            warn!("Encrypting the plaintext mapping of {}.", key_triple);
+            self.save_mapping(&key_triple, &key_info)?;
+            let _ = self.key_store.insert(key_triple, key_info);
+        }
+
+        let encrypted_mappings = self
+            .encrypted_mappings
+            .as_mut()
+            .expect("The mappings are encrypted.");
+        encrypted_mappings.set_encrypting_plaintext(false);
+        write_file_atomically(
+            &self.mappings_dir_path,
+            &self.mappings_dir_path.join(MANIFEST_FILE_NAME),
+            &encrypted_mappings.seal_manifest()?,
+        )
     }
 
     /// Saves the key triple to key info mapping in its own file.
-    /// The filename will be `mappings/[APP_NAME]/[PROVIDER_NAME]/[KEY_NAME]` under the same path as the
-    /// on-disk manager. It will contain the Key info data.
-    fn save_mapping(&self, key_triple: &KeyTriple, key_info: &KeyInfo) -> std::io::Result<()> {
-        // Create the directories with base64 names.
+    /// The filename will be `mappings/[HASHED_APP_NAME]/[PROVIDER_NAME]/[HASHED_KEY_NAME]` under
+    /// the same path as the on-disk manager. It will contain the names and the Key info data.
+    /// The data is written to a temporary file in the same directory, synced to disk and renamed
+    /// over the mapping file. The directories modified are synced as well.
+    /// The mapping file of the legacy layout is removed afterwards, if it exists.
+    fn save_mapping(&mut self, key_triple: &KeyTriple, key_info: &KeyInfo) -> std::io::Result<()> {
+        // Create the directories with hashed names.
+        let (app_name, prov, key_name) = key_triple_to_hashed_filenames(key_triple);
+        let app_name_dir_path = self.mappings_dir_path.join(&app_name);
+        let provider_dir_path = app_name_dir_path.join(&prov);
+        let key_name_file_path = provider_dir_path.join(&key_name);
+        if !provider_dir_path.exists() {
+            let app_name_dir_existed = app_name_dir_path.exists();
+            fs::create_dir_all(&provider_dir_path)?;
+            // Make sure the new directory entries are on disk.
+            sync_dir(&app_name_dir_path)?;
+            if !app_name_dir_existed {
+                sync_dir(&self.mappings_dir_path)?;
+            }
+        }
+
+        let named_record = NamedRecord {
+            app_name: key_triple.app_name.get_name().to_owned(),
+            key_name: key_triple.key_name.clone(),
+            record: serialize_key_info(key_info).map_err(|e| Error::new(ErrorKind::Other, e))?,
+        };
+        let data = Zeroizing::new(bincode::serialize(&named_record).map_err(|e| {
+            error!("Error serializing mapping ({}).", e);
+            Error::new(ErrorKind::Other, "error serializing mapping")
+        })?);
+
+        match self.encrypted_mappings.as_mut() {
+            Some(encrypted_mappings) => {
+                let record_path = record_path(&app_name, &prov, &key_name);
+                let (generation, data) = encrypted_mappings.seal_record(&record_path, &data)?;
+                write_file_atomically(&provider_dir_path, &key_name_file_path, &data)?;
+                encrypted_mappings.commit_record(&record_path, generation);
+                write_file_atomically(
+                    &self.mappings_dir_path,
+                    &self.mappings_dir_path.join(MANIFEST_FILE_NAME),
+                    &encrypted_mappings.seal_manifest()?,
+                )?;
+            }
+            None => write_file_atomically(&provider_dir_path, &key_name_file_path, &data)?,
+        }
+
         let (app_name, prov, key_name) = key_triple_to_base64_filenames(key_triple);
-        let provider_dir_path = self.mappings_dir_path.join(app_name).join(prov);
-        let key_name_file_path = provider_dir_path.join(key_name);
-        // Will ignore if they already exist.
-        fs::create_dir_all(&provider_dir_path)?;
+        self.remove_mapping_file(&app_name, &prov, &key_name)
+    }
 
-        if key_name_file_path.exists() {
-            fs::remove_file(&key_name_file_path)?;
+    /// Rewrites the mappings stored in an older format in the current one. Returns the key
+    /// triples of those mappings, with the format they were stored in. If `dry_run` is set, the
+    /// mappings are only returned and not modified.
+    ///
+    /// # Errors
+    ///
+    /// Returns an std::io error if a mapping could not be written. The mappings migrated before
+    /// are kept in the current format.
+    pub fn migrate_mappings(
+        &mut self,
+        dry_run: bool,
+    ) -> std::io::Result<Vec<(KeyTriple, MappingFormat)>> {
+        let mut outdated_mappings: Vec<(KeyTriple, MappingFormat)> = self
+            .outdated_mappings
+            .iter()
+            .map(|(key_triple, format)| (key_triple.clone(), *format))
+            .collect();
+        outdated_mappings.sort_by_key(|(key_triple, _)| key_triple.to_string());
+        if dry_run {
+            return Ok(outdated_mappings);
+        }
+
+        for (key_triple, _) in outdated_mappings.iter() {
+            let key_info = self
+                .key_store
+                .get(key_triple)
+                .expect("Outdated mappings are in the key store.")
+                .clone();
+            self.save_mapping(key_triple, &key_info)?;
+            let _ = self.outdated_mappings.remove(key_triple);
         }
 
-        let mut mapping_file = fs::File::create(&key_name_file_path)?;
-        mapping_file.write_all(&bincode::serialize(key_info).or_else(|e| {
-            error!("Error serializing key info ({}).", e);
-            Err(Error::new(ErrorKind::Other, "error serializing key info"))
-        })?)
+        Ok(outdated_mappings)
     }
 
-    /// Removes the mapping file.
+    /// Removes the mapping file, in both layouts.
     /// Will do nothing if the mapping file does not exist.
-    fn delete_mapping(&self, key_triple: &KeyTriple) -> std::io::Result<()> {
+    fn delete_mapping(&mut self, key_triple: &KeyTriple) -> std::io::Result<()> {
+        let (app_name, prov, key_name) = key_triple_to_hashed_filenames(key_triple);
+        self.remove_mapping_file(&app_name, &prov, &key_name)?;
         let (app_name, prov, key_name) = key_triple_to_base64_filenames(key_triple);
+        self.remove_mapping_file(&app_name, &prov, &key_name)
+    }
+
+    /// Removes a mapping file given the names of its path components.
+    /// Will do nothing if the mapping file does not exist.
+    /// If the mapping files are encrypted, the mapping is removed from the manifest first.
+    fn remove_mapping_file(
+        &mut self,
+        app_name: &str,
+        prov: &str,
+        key_name: &str,
+    ) -> std::io::Result<()> {
         let key_name_file_path = self
             .mappings_dir_path
             .join(app_name)
             .join(prov)
             .join(key_name);
         if key_name_file_path.exists() {
-            fs::remove_file(key_name_file_path)
+            if let Some(encrypted_mappings) = self.encrypted_mappings.as_mut() {
+                encrypted_mappings.forget_record(&record_path(app_name, prov, key_name));
+                write_file_atomically(
+                    &self.mappings_dir_path,
+                    &self.mappings_dir_path.join(MANIFEST_FILE_NAME),
+                    &encrypted_mappings.seal_manifest()?,
+                )?;
+            }
+            fs::remove_file(&key_name_file_path)?;
+            sync_dir(
+                key_name_file_path
+                    .parent()
+                    .expect("The key name file path should be inside the provider directory."),
+            )
         } else {
             Ok(())
         }
@@ -263,21 +707,25 @@ impl OnDiskKeyInfoManager {
 }
 
 impl ManageKeyInfo for OnDiskKeyInfoManager {
-    fn get(&self, key_triple: &KeyTriple) -> Result<Option<&KeyInfo>, String> {
-        // An Option<&Vec<u8>> can not automatically coerce to an Option<&[u8]>, it needs to be
-        // done by hand.
-        if let Some(key_info) = self.key_store.get(key_triple) {
-            Ok(Some(key_info))
-        } else {
-            Ok(None)
-        }
+    fn get(&self, key_triple: &KeyTriple) -> Result<Option<KeyInfo>, String> {
+        Ok(self.key_store.get(key_triple).cloned())
     }
 
-    fn get_all(&self, provider_id: ProviderID) -> Result<Vec<&KeyTriple>, String> {
+    fn get_all(&self, provider_id: ProviderId) -> Result<Vec<KeyTriple>, String> {
         Ok(self
             .key_store
             .keys()
             .filter(|key_triple| key_triple.belongs_to_provider(provider_id))
+            .cloned()
+            .collect())
+    }
+
+    fn get_all_by_app(&self, app_name: &ApplicationName) -> Result<Vec<KeyTriple>, String> {
+        Ok(self
+            .key_store
+            .keys()
+            .filter(|key_triple| key_triple.belongs_to_app(app_name))
+            .cloned()
             .collect())
     }
 
@@ -289,6 +737,8 @@ impl ManageKeyInfo for OnDiskKeyInfoManager {
         if let Err(err) = self.save_mapping(&key_triple, &key_info) {
             Err(err.to_string())
         } else {
+            let _ = self.outdated_mappings.remove(&key_triple);
+            let _ = self.reserved.remove(&key_triple);
             Ok(self.key_store.insert(key_triple, key_info))
         }
     }
@@ -297,6 +747,7 @@ impl ManageKeyInfo for OnDiskKeyInfoManager {
         if let Err(err) = self.delete_mapping(key_triple) {
             Err(err.to_string())
         } else if let Some(key_info) = self.key_store.remove(key_triple) {
+            let _ = self.outdated_mappings.remove(key_triple);
             Ok(Some(key_info))
         } else {
             Ok(None)
@@ -304,19 +755,33 @@ impl ManageKeyInfo for OnDiskKeyInfoManager {
     }
 
     fn exists(&self, key_triple: &KeyTriple) -> Result<bool, String> {
-        Ok(self.key_store.contains_key(key_triple))
+        Ok(self.key_store.contains_key(key_triple) || self.reserved.contains(key_triple))
+    }
+
+    fn reserve(&mut self, key_triple: &KeyTriple) -> Result<bool, String> {
+        if self.key_store.contains_key(key_triple) {
+            Ok(false)
+        } else {
+            Ok(self.reserved.insert(key_triple.clone()))
+        }
+    }
+
+    fn release(&mut self, key_triple: &KeyTriple) {
+        let _ = self.reserved.remove(key_triple);
     }
 }
 
 #[derive(Debug, Default)]
 pub struct OnDiskKeyInfoManagerBuilder {
     mappings_dir_path: Option<PathBuf>,
+    key_encryption_key: Option<KeyEncryptionKey>,
 }
 
 impl OnDiskKeyInfoManagerBuilder {
     pub fn new() -> OnDiskKeyInfoManagerBuilder {
         OnDiskKeyInfoManagerBuilder {
             mappings_dir_path: None,
+            key_encryption_key: None,
         }
     }
 
@@ -326,18 +791,32 @@ impl OnDiskKeyInfoManagerBuilder {
         self
     }
 
+    pub fn with_key_encryption_key(
+        mut self,
+        key_encryption_key: KeyEncryptionKey,
+    ) -> OnDiskKeyInfoManagerBuilder {
+        self.key_encryption_key = Some(key_encryption_key);
+
+        self
+    }
+
     pub fn build(self) -> std::io::Result<OnDiskKeyInfoManager> {
-        OnDiskKeyInfoManager::new(self.mappings_dir_path.ok_or_else(|| {
-            error!("Mappings directory path is missing");
-            Error::new(ErrorKind::InvalidData, "mappings directory path is missing")
-        })?)
+        OnDiskKeyInfoManager::new(
+            self.mappings_dir_path.ok_or_else(|| {
+                error!("Mappings directory path is missing");
+                Error::new(ErrorKind::InvalidData, "mappings directory path is missing")
+            })?,
+            self.key_encryption_key,
+        )
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::super::{KeyInfo, KeyTriple, ManageKeyInfo};
-    use super::OnDiskKeyInfoManager;
+    use super::super::encryption::{KeyEncryptionKey, KEY_ENCRYPTION_KEY_LEN};
+    use super::super::record_format::LEGACY_VERSION;
+    use super::super::{KeyInfo, KeyTriple, KeyTripleReservation, ManageKeyInfo};
+    use super::{MappingFormat, OnDiskKeyInfoManager, MANIFEST_FILE_NAME};
     use crate::authenticators::ApplicationName;
     use parsec_interface::operations::psa_algorithm::{
         Algorithm, AsymmetricSignature, Hash, SignHash,
@@ -345,9 +824,11 @@ mod test {
     use parsec_interface::operations::psa_key_attributes::{
         Attributes, Lifetime, Policy, Type, UsageFlags,
     };
-    use parsec_interface::requests::ProviderID;
+    use parsec_interface::requests::{ProviderId, ResponseStatus};
     use std::fs;
-    use std::path::PathBuf;
+    use std::io::ErrorKind;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, RwLock};
 
     fn test_key_attributes() -> Attributes {
         Attributes {
@@ -355,17 +836,10 @@ mod test {
             key_type: Type::Derive,
             bits: 1024,
             policy: Policy {
-                usage_flags: UsageFlags {
-                    sign_hash: true,
-                    verify_hash: false,
-                    sign_message: false,
-                    verify_message: false,
-                    export: false,
-                    encrypt: false,
-                    decrypt: false,
-                    cache: false,
-                    copy: false,
-                    derive: false,
+                usage_flags: {
+                    let mut usage_flags = UsageFlags::default();
+                    let _ = usage_flags.set_sign_hash();
+                    usage_flags
                 },
                 permitted_algorithms: Algorithm::AsymmetricSignature(
                     AsymmetricSignature::RsaPkcs1v15Sign {
@@ -386,7 +860,7 @@ mod test {
     #[test]
     fn insert_get_key_info() {
         let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/insert_get_key_info_mappings");
-        let mut manager = OnDiskKeyInfoManager::new(path.clone()).unwrap();
+        let mut manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
 
         let key_triple = new_key_triple("insert_get_key_info".to_string());
         let key_info = test_key_info();
@@ -412,7 +886,7 @@ mod test {
     #[test]
     fn insert_remove_key() {
         let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/insert_remove_key_mappings");
-        let mut manager = OnDiskKeyInfoManager::new(path.clone()).unwrap();
+        let mut manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
 
         let key_triple = new_key_triple("insert_remove_key".to_string());
         let key_info = test_key_info();
@@ -426,7 +900,7 @@ mod test {
     #[test]
     fn remove_unexisting_key() {
         let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/remove_unexisting_key_mappings");
-        let mut manager = OnDiskKeyInfoManager::new(path.clone()).unwrap();
+        let mut manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
 
         let key_triple = new_key_triple("remove_unexisting_key".to_string());
         assert_eq!(manager.remove(&key_triple).unwrap(), None);
@@ -436,7 +910,7 @@ mod test {
     #[test]
     fn exists() {
         let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/exists_mappings");
-        let mut manager = OnDiskKeyInfoManager::new(path.clone()).unwrap();
+        let mut manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
 
         let key_triple = new_key_triple("exists".to_string());
         let key_info = test_key_info();
@@ -451,10 +925,98 @@ mod test {
         fs::remove_dir_all(path).unwrap();
     }
 
+    #[test]
+    fn reserve_key_triple() {
+        let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/reserve_key_triple_mappings");
+        let mut manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
+
+        let key_triple = new_key_triple("reserve_key_triple".to_string());
+        let key_info = test_key_info();
+
+        assert!(manager.reserve(&key_triple).unwrap());
+        assert!(!manager.reserve(&key_triple).unwrap());
+        assert!(manager.exists(&key_triple).unwrap());
+        assert!(manager.get(&key_triple).unwrap().is_none());
+        assert!(manager.get_all(ProviderId::MbedCrypto).unwrap().is_empty());
+
+        manager.release(&key_triple);
+        assert!(!manager.exists(&key_triple).unwrap());
+
+        // Inserting the mapping ends the reservation.
+        assert!(manager.reserve(&key_triple).unwrap());
+        let _ = manager.insert(key_triple.clone(), key_info).unwrap();
+        assert!(!manager.reserve(&key_triple).unwrap());
+        let _ = manager.remove(&key_triple).unwrap();
+        assert!(!manager.exists(&key_triple).unwrap());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn key_triple_reservation() {
+        let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/key_triple_reservation_mappings");
+        let store: Arc<RwLock<dyn ManageKeyInfo + Send + Sync>> = Arc::new(RwLock::new(
+            OnDiskKeyInfoManager::new(path.clone(), None).unwrap(),
+        ));
+
+        let key_triple = new_key_triple("key_triple_reservation".to_string());
+
+        let reservation = KeyTripleReservation::new(&store, key_triple.clone()).unwrap();
+        assert_eq!(
+            KeyTripleReservation::new(&store, key_triple.clone()).unwrap_err(),
+            ResponseStatus::PsaErrorAlreadyExists
+        );
+        // The store is not locked while the key triple is reserved.
+        assert!(store.try_read().is_ok());
+        drop(reservation);
+        assert!(!store.read().unwrap().exists(&key_triple).unwrap());
+
+        let reservation = KeyTripleReservation::new(&store, key_triple.clone()).unwrap();
+        reservation.commit(test_key_info()).unwrap();
+        assert_eq!(
+            store.read().unwrap().get(&key_triple).unwrap(),
+            Some(test_key_info())
+        );
+        assert_eq!(
+            KeyTripleReservation::new(&store, key_triple.clone()).unwrap_err(),
+            ResponseStatus::PsaErrorAlreadyExists
+        );
+
+        let _ = store.write().unwrap().remove(&key_triple).unwrap();
+        drop(store);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn reservation_dropped_with_poisoned_lock() {
+        let path = PathBuf::from(
+            env!("OUT_DIR").to_owned() + "/reservation_dropped_with_poisoned_lock_mappings",
+        );
+        let store: Arc<RwLock<dyn ManageKeyInfo + Send + Sync>> = Arc::new(RwLock::new(
+            OnDiskKeyInfoManager::new(path.clone(), None).unwrap(),
+        ));
+
+        let key_triple = new_key_triple("reservation_dropped_with_poisoned_lock".to_string());
+        let reservation = KeyTripleReservation::new(&store, key_triple).unwrap();
+
+        let poisoning_store = store.clone();
+        assert!(std::thread::spawn(move || {
+            let _guard = poisoning_store.write().unwrap();
+            panic!("Poisoning the key store lock");
+        })
+        .join()
+        .is_err());
+        assert!(store.is_poisoned());
+
+        // The reservation can not be released but dropping it does not panic.
+        drop(reservation);
+        drop(store);
+        fs::remove_dir_all(path).unwrap();
+    }
+
     #[test]
     fn insert_overwrites() {
         let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/insert_overwrites_mappings");
-        let mut manager = OnDiskKeyInfoManager::new(path.clone()).unwrap();
+        let mut manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
 
         let key_triple = new_key_triple("insert_overwrites".to_string());
         let key_info_1 = test_key_info();
@@ -482,12 +1044,12 @@ mod test {
     #[test]
     fn big_names_ascii() {
         let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/big_names_ascii_mappings");
-        let mut manager = OnDiskKeyInfoManager::new(path.clone()).unwrap();
+        let mut manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
 
         let big_app_name_ascii = ApplicationName::new("  Lorem ipsum dolor sit amet, ei suas viris sea, deleniti repudiare te qui. Natum paulo decore ut nec, ne propriae offendit adipisci has. Eius clita legere mel at, ei vis minimum tincidunt.".to_string());
         let big_key_name_ascii = "  Lorem ipsum dolor sit amet, ei suas viris sea, deleniti repudiare te qui. Natum paulo decore ut nec, ne propriae offendit adipisci has. Eius clita legere mel at, ei vis minimum tincidunt.".to_string();
 
-        let key_triple = KeyTriple::new(big_app_name_ascii, ProviderID::Core, big_key_name_ascii);
+        let key_triple = KeyTriple::new(big_app_name_ascii, ProviderId::Core, big_key_name_ascii);
         let key_info = test_key_info();
 
         let _ = manager
@@ -500,14 +1062,14 @@ mod test {
     #[test]
     fn big_names_emoticons() {
         let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/big_names_emoticons_mappings");
-        let mut manager = OnDiskKeyInfoManager::new(path.clone()).unwrap();
+        let mut manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
 
         let big_app_name_emoticons = ApplicationName::new("😀😁😂😃😄😅😆😇😈😉😊😋😌😍😎😏😐😑😒😓😔😕😖😗😘😙😚😛😜😝😞😟😠😡😢😣😤😥😦😧😨😩😪😫😬😭😮".to_string());
         let big_key_name_emoticons = "😀😁😂😃😄😅😆😇😈😉😊😋😌😍😎😏😐😑😒😓😔😕😖😗😘😙😚😛😜😝😞😟😠😡😢😣😤😥😦😧😨😩😪😫😬😭😮".to_string();
 
         let key_triple = KeyTriple::new(
             big_app_name_emoticons,
-            ProviderID::MbedCrypto,
+            ProviderId::MbedCrypto,
             big_key_name_emoticons,
         );
         let key_info = test_key_info();
@@ -525,12 +1087,12 @@ mod test {
 
         let app_name1 = ApplicationName::new("😀 Application One 😀".to_string());
         let key_name1 = "😀 Key One 😀".to_string();
-        let key_triple1 = KeyTriple::new(app_name1, ProviderID::Core, key_name1);
+        let key_triple1 = KeyTriple::new(app_name1, ProviderId::Core, key_name1);
         let key_info1 = test_key_info();
 
         let app_name2 = ApplicationName::new("😇 Application Two 😇".to_string());
         let key_name2 = "😇 Key Two 😇".to_string();
-        let key_triple2 = KeyTriple::new(app_name2, ProviderID::MbedCrypto, key_name2);
+        let key_triple2 = KeyTriple::new(app_name2, ProviderId::MbedCrypto, key_name2);
         let key_info2 = KeyInfo {
             id: vec![0x12, 0x22, 0x32],
             attributes: test_key_attributes(),
@@ -538,13 +1100,13 @@ mod test {
 
         let app_name3 = ApplicationName::new("😈 Application Three 😈".to_string());
         let key_name3 = "😈 Key Three 😈".to_string();
-        let key_triple3 = KeyTriple::new(app_name3, ProviderID::Core, key_name3);
+        let key_triple3 = KeyTriple::new(app_name3, ProviderId::Core, key_name3);
         let key_info3 = KeyInfo {
             id: vec![0x13, 0x23, 0x33],
             attributes: test_key_attributes(),
         };
         {
-            let mut manager = OnDiskKeyInfoManager::new(path.clone()).unwrap();
+            let mut manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
 
             let _ = manager
                 .insert(key_triple1.clone(), key_info1.clone())
@@ -558,7 +1120,7 @@ mod test {
         }
         // The local hashmap is dropped when leaving the inner scope.
         {
-            let mut manager = OnDiskKeyInfoManager::new(path.clone()).unwrap();
+            let mut manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
 
             assert_eq!(manager.remove(&key_triple1).unwrap().unwrap(), key_info1);
             assert_eq!(manager.remove(&key_triple2).unwrap().unwrap(), key_info2);
@@ -568,10 +1130,314 @@ mod test {
         fs::remove_dir_all(path).unwrap();
     }
 
+    #[test]
+    fn interrupted_writes_ignored() {
+        let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/interrupted_writes_mappings");
+        let key_triple = new_key_triple("interrupted_writes".to_string());
+        let key_info = test_key_info();
+        let truncated_key_triple = new_key_triple("interrupted_writes_truncated".to_string());
+        {
+            let mut manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
+            let _ = manager
+                .insert(key_triple.clone(), key_info.clone())
+                .unwrap();
+            let _ = manager
+                .insert(truncated_key_triple.clone(), key_info.clone())
+                .unwrap();
+        }
+
+        // Simulate a crash while writing a mapping and another one while writing a temporary file.
+        let truncated_file_path = mapping_file_path(&path, &truncated_key_triple);
+        let provider_dir_path = truncated_file_path.parent().unwrap().to_path_buf();
+        let truncated_len = fs::read(&truncated_file_path).unwrap().len() / 2;
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&truncated_file_path)
+            .unwrap()
+            .set_len(truncated_len as u64)
+            .unwrap();
+        let temp_file_path = provider_dir_path.join(".tmp-1234");
+        fs::write(&temp_file_path, [0x11, 0x22]).unwrap();
+
+        let manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
+        assert_eq!(manager.get(&key_triple).unwrap().unwrap(), key_info);
+        assert!(!manager.exists(&truncated_key_triple).unwrap());
+        assert!(!temp_file_path.exists());
+        // The corrupted mapping is kept for investigation.
+        assert!(truncated_file_path.exists());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn get_all_by_app() {
+        let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/get_all_by_app_mappings");
+        let mut manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
+
+        let key_triple1 = new_key_triple("get_all_by_app_1".to_string());
+        let key_triple2 = KeyTriple::new(
+            ApplicationName::new("Testing Application 😎".to_string()),
+            ProviderId::Pkcs11,
+            "get_all_by_app_2".to_string(),
+        );
+        let other_app_key_triple = KeyTriple::new(
+            ApplicationName::new("Other Application".to_string()),
+            ProviderId::MbedCrypto,
+            "get_all_by_app_1".to_string(),
+        );
+        for key_triple in &[&key_triple1, &key_triple2, &other_app_key_triple] {
+            let _ = manager
+                .insert((*key_triple).clone(), test_key_info())
+                .unwrap();
+        }
+
+        let mut key_triples = manager
+            .get_all_by_app(&ApplicationName::new("Testing Application 😎".to_string()))
+            .unwrap();
+        key_triples.sort_by_key(|key_triple| key_triple.key_name().to_string());
+        assert_eq!(key_triples, vec![key_triple1, key_triple2]);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    fn test_kek() -> KeyEncryptionKey {
+        KeyEncryptionKey::new(&[0x42; KEY_ENCRYPTION_KEY_LEN]).unwrap()
+    }
+
+    fn mapping_file_path(path: &Path, key_triple: &KeyTriple) -> PathBuf {
+        let (app_name, prov, key_name) = super::key_triple_to_hashed_filenames(key_triple);
+        path.join(app_name).join(prov).join(key_name)
+    }
+
+    fn legacy_mapping_file_path(path: &Path, key_triple: &KeyTriple) -> PathBuf {
+        let (app_name, prov, key_name) = super::key_triple_to_base64_filenames(key_triple);
+        path.join(app_name).join(prov).join(key_name)
+    }
+
+    #[test]
+    fn encrypted_mappings() {
+        let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/encrypted_mappings");
+        let key_triple = new_key_triple("encrypted_mappings".to_string());
+        let key_info = test_key_info();
+        {
+            let mut manager = OnDiskKeyInfoManager::new(path.clone(), Some(test_kek())).unwrap();
+            let _ = manager
+                .insert(key_triple.clone(), key_info.clone())
+                .unwrap();
+        }
+
+        let mapping = fs::read(mapping_file_path(&path, &key_triple)).unwrap();
+        let plaintext = super::serialize_key_info(&key_info).unwrap();
+        assert!(!mapping
+            .windows(plaintext.len())
+            .any(|window| window == &plaintext[..]));
+
+        let manager = OnDiskKeyInfoManager::new(path.clone(), Some(test_kek())).unwrap();
+        assert_eq!(manager.get(&key_triple).unwrap().unwrap(), key_info);
+        drop(manager);
+
+        // The manifest can not be read with another key-encryption key.
+        let other_kek = KeyEncryptionKey::new(&[0x43; KEY_ENCRYPTION_KEY_LEN]).unwrap();
+        let _ = OnDiskKeyInfoManager::new(path.clone(), Some(other_kek)).unwrap_err();
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn tampered_mappings_ignored() {
+        let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/tampered_mappings");
+        let untouched = new_key_triple("untouched".to_string());
+        let tampered = new_key_triple("tampered".to_string());
+        let rolled_back = new_key_triple("rolled_back".to_string());
+        let deleted = new_key_triple("deleted".to_string());
+        let key_info = test_key_info();
+        let mut new_key_info = test_key_info();
+        new_key_info.id = vec![0x44, 0x55, 0x66];
+        {
+            let mut manager = OnDiskKeyInfoManager::new(path.clone(), Some(test_kek())).unwrap();
+            for key_triple in &[&untouched, &tampered, &rolled_back, &deleted] {
+                let _ = manager
+                    .insert((*key_triple).clone(), key_info.clone())
+                    .unwrap();
+            }
+
+            let old_mapping = fs::read(mapping_file_path(&path, &rolled_back)).unwrap();
+            let _ = manager.insert(rolled_back.clone(), new_key_info).unwrap();
+            fs::write(mapping_file_path(&path, &rolled_back), old_mapping).unwrap();
+
+            let old_mapping = fs::read(mapping_file_path(&path, &deleted)).unwrap();
+            let _ = manager.remove(&deleted).unwrap();
+            fs::write(mapping_file_path(&path, &deleted), old_mapping).unwrap();
+        }
+        let mut mapping = fs::read(mapping_file_path(&path, &tampered)).unwrap();
+        *mapping.last_mut().unwrap() ^= 1;
+        fs::write(mapping_file_path(&path, &tampered), mapping).unwrap();
+
+        let manager = OnDiskKeyInfoManager::new(path.clone(), Some(test_kek())).unwrap();
+        assert_eq!(manager.get(&untouched).unwrap().unwrap(), key_info);
+        assert!(!manager.exists(&tampered).unwrap());
+        assert!(!manager.exists(&rolled_back).unwrap());
+        assert!(!manager.exists(&deleted).unwrap());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn deleted_manifest_detected() {
+        let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/deleted_manifest");
+        let key_triple = new_key_triple("rolled_back".to_string());
+        let key_info = test_key_info();
+        let mut new_key_info = test_key_info();
+        new_key_info.id = vec![0x44, 0x55, 0x66];
+        {
+            let mut manager = OnDiskKeyInfoManager::new(path.clone(), Some(test_kek())).unwrap();
+            let _ = manager
+                .insert(key_triple.clone(), key_info.clone())
+                .unwrap();
+            let old_mapping = fs::read(mapping_file_path(&path, &key_triple)).unwrap();
+            let _ = manager.insert(key_triple.clone(), new_key_info).unwrap();
+            fs::write(mapping_file_path(&path, &key_triple), old_mapping).unwrap();
+        }
+        fs::remove_file(path.join(MANIFEST_FILE_NAME)).unwrap();
+
+        // The older mapping file is not accepted as newer than an empty manifest.
+        let err = OnDiskKeyInfoManager::new(path.clone(), Some(test_kek())).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(!path.join(MANIFEST_FILE_NAME).exists());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn manifest_written_when_store_created() {
+        let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/manifest_written");
+        let _ = fs::remove_dir_all(&path);
+        let manager = OnDiskKeyInfoManager::new(path.clone(), Some(test_kek())).unwrap();
+        drop(manager);
+        assert!(path.join(MANIFEST_FILE_NAME).exists());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn plaintext_mappings_encrypted() {
+        let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/plaintext_mappings_encrypted");
+        let _ = fs::remove_dir_all(&path);
+        let key_triple1 = new_key_triple("plaintext1".to_string());
+        let key_triple2 = new_key_triple("plaintext2".to_string());
+        let planted = new_key_triple("planted".to_string());
+        let key_info = test_key_info();
+        {
+            let mut manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
+            let _ = manager
+                .insert(key_triple1.clone(), key_info.clone())
+                .unwrap();
+            let _ = manager
+                .insert(key_triple2.clone(), key_info.clone())
+                .unwrap();
+        }
+
+        let manager = OnDiskKeyInfoManager::new(path.clone(), Some(test_kek())).unwrap();
+        assert_eq!(manager.get(&key_triple1).unwrap().unwrap(), key_info);
+        assert_eq!(manager.get(&key_triple2).unwrap().unwrap(), key_info);
+        drop(manager);
+
+        // The mapping files are now encrypted.
+        let manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
+        assert!(!manager.exists(&key_triple1).unwrap());
+        drop(manager);
+
+        // Plaintext mapping files are not accepted any more.
+        {
+            let mut manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
+            let _ = manager.insert(planted.clone(), key_info.clone()).unwrap();
+        }
+        let manager = OnDiskKeyInfoManager::new(path.clone(), Some(test_kek())).unwrap();
+        assert_eq!(manager.get(&key_triple1).unwrap().unwrap(), key_info);
+        assert_eq!(manager.get(&key_triple2).unwrap().unwrap(), key_info);
+        assert!(!manager.exists(&planted).unwrap());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn legacy_mappings_migrated() {
+        let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/legacy_mappings");
+        let key_triple = new_key_triple("legacy".to_string());
+        let key_info = test_key_info();
+        let legacy_mapping_path = legacy_mapping_file_path(&path, &key_triple);
+        let legacy_mapping = bincode::serialize(&key_info).unwrap();
+        fs::create_dir_all(legacy_mapping_path.parent().unwrap()).unwrap();
+        fs::write(&legacy_mapping_path, &legacy_mapping).unwrap();
+        let legacy_format = MappingFormat {
+            record_version: LEGACY_VERSION,
+            legacy_layout: true,
+        };
+
+        let mut manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
+        assert_eq!(manager.get(&key_triple).unwrap().unwrap(), key_info);
+
+        let migrated = manager.migrate_mappings(true).unwrap();
+        assert_eq!(migrated, vec![(key_triple.clone(), legacy_format)]);
+        assert_eq!(fs::read(&legacy_mapping_path).unwrap(), legacy_mapping);
+
+        let migrated = manager.migrate_mappings(false).unwrap();
+        assert_eq!(migrated, vec![(key_triple.clone(), legacy_format)]);
+        assert!(!legacy_mapping_path.exists());
+        assert!(mapping_file_path(&path, &key_triple).exists());
+        drop(manager);
+
+        let mut manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
+        assert_eq!(manager.get(&key_triple).unwrap().unwrap(), key_info);
+        assert!(manager.migrate_mappings(false).unwrap().is_empty());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn long_names() {
+        let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/long_names_mappings");
+        let key_triple = KeyTriple::new(
+            ApplicationName::new("application 😎".repeat(100)),
+            ProviderId::MbedCrypto,
+            "key 😎".repeat(100),
+        );
+        let key_info = test_key_info();
+        {
+            let mut manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
+            let _ = manager
+                .insert(key_triple.clone(), key_info.clone())
+                .unwrap();
+        }
+
+        let mut manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
+        assert_eq!(manager.get(&key_triple).unwrap().unwrap(), key_info);
+        let _ = manager.remove(&key_triple).unwrap().unwrap();
+        assert!(!mapping_file_path(&path, &key_triple).exists());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn mappings_dir_locked() {
+        let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/locked_mappings");
+        let manager = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
+
+        let err = OnDiskKeyInfoManager::new(path.clone(), None).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        assert!(err.to_string().contains(&std::process::id().to_string()));
+
+        // The lock is released with the manager.
+        drop(manager);
+        let _ = OnDiskKeyInfoManager::new(path.clone(), None).unwrap();
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
     fn new_key_triple(key_name: String) -> KeyTriple {
         KeyTriple::new(
             ApplicationName::new("Testing Application 😎".to_string()),
-            ProviderID::MbedCrypto,
+            ProviderId::MbedCrypto,
             key_name,
         )
     }
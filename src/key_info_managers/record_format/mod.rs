@@ -0,0 +1,133 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+//! Versioned format of the key info records
+//!
+//! Key info records start with a header made of a magic value and of the version of the format
+//! used, followed by the record itself serialized with bincode. Records written before the format
+//! was versioned are the bincode serialization of `KeyInfo` alone and are considered to be of
+//! version 1. They can not be mistaken for versioned records: they start with the length of the
+//! key ID as a 64-bits little-endian integer, which would need to be over 1 GB for the first bytes
+//! to match the magic value.
+//!
+//! Records of an older version are converted to the current `KeyInfo` when deserialized. When the
+//! layout of `KeyInfo`, including the `Attributes` defined in the interface, or the format of the
+//! key IDs of a provider changes, the format version has to be increased and a copy of the
+//! previous layout kept to deserialize and convert the older records.
+use super::KeyInfo;
+use log::error;
+use std::convert::TryInto;
+
+/// Version of the records written before the format was versioned
+pub const LEGACY_VERSION: u32 = 1;
+/// Version of the records written by this version of the service
+pub const CURRENT_VERSION: u32 = 2;
+
+const MAGIC: &[u8; 4] = b"PKIR";
+const HEADER_LEN: usize = 8;
+
+/// Serializes the key info in the current record format.
+///
+/// # Errors
+///
+/// Returns an error as a String if the serialization failed.
+pub fn serialize_key_info(key_info: &KeyInfo) -> Result<Vec<u8>, String> {
+    let mut record = MAGIC.to_vec();
+    record.extend_from_slice(&CURRENT_VERSION.to_le_bytes());
+    let mut key_info = bincode::serialize(key_info).map_err(|e| {
+        error!("Error serializing key info ({}).", e);
+        String::from("error serializing key info")
+    })?;
+    record.append(&mut key_info);
+
+    Ok(record)
+}
+
+/// Deserializes a key info record of any supported version. The version of the record is
+/// returned alongside the key info.
+///
+/// # Errors
+///
+/// Returns an error as a String if the record is corrupted or has a version newer than the
+/// current one.
+pub fn deserialize_key_info(record: &[u8]) -> Result<(KeyInfo, u32), String> {
+    let (version, data) = if record.len() >= HEADER_LEN && record.starts_with(MAGIC) {
+        let version = u32::from_le_bytes(
+            record[MAGIC.len()..HEADER_LEN]
+                .try_into()
+                .expect("The version has the right length."),
+        );
+        (version, &record[HEADER_LEN..])
+    } else {
+        (LEGACY_VERSION, record)
+    };
+
+    let key_info = match version {
+        // The layout of KeyInfo has not changed since the legacy records.
+        LEGACY_VERSION | CURRENT_VERSION => bincode::deserialize(data)
+            .map_err(|e| format!("record truncated or corrupted ({})", e))?,
+        _ => {
+            return Err(format!(
+                "record version {} not supported, it was written by a newer version of the service",
+                version
+            ))
+        }
+    };
+
+    Ok((key_info, version))
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::KeyInfo;
+    use super::{deserialize_key_info, serialize_key_info, CURRENT_VERSION, LEGACY_VERSION, MAGIC};
+    use parsec_interface::operations::psa_algorithm::{Algorithm, Hash};
+    use parsec_interface::operations::psa_key_attributes::{
+        Attributes, Lifetime, Policy, Type, UsageFlags,
+    };
+
+    fn test_key_info() -> KeyInfo {
+        KeyInfo {
+            id: vec![0x11, 0x22, 0x33],
+            attributes: Attributes {
+                lifetime: Lifetime::Persistent,
+                key_type: Type::RawData,
+                bits: 256,
+                policy: Policy {
+                    usage_flags: UsageFlags::default(),
+                    permitted_algorithms: Algorithm::Hash(Hash::Sha256),
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn current_record() {
+        let key_info = test_key_info();
+        let record = serialize_key_info(&key_info).unwrap();
+
+        assert_eq!(
+            deserialize_key_info(&record).unwrap(),
+            (key_info, CURRENT_VERSION)
+        );
+    }
+
+    #[test]
+    fn legacy_record() {
+        let key_info = test_key_info();
+        let record = bincode::serialize(&key_info).unwrap();
+
+        assert_eq!(
+            deserialize_key_info(&record).unwrap(),
+            (key_info, LEGACY_VERSION)
+        );
+    }
+
+    #[test]
+    fn newer_record() {
+        let mut record = MAGIC.to_vec();
+        record.extend_from_slice(&(CURRENT_VERSION + 1).to_le_bytes());
+        record.append(&mut bincode::serialize(&test_key_info()).unwrap());
+
+        let _ = deserialize_key_info(&record).unwrap_err();
+    }
+}
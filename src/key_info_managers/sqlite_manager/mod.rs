@@ -0,0 +1,463 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+//! A key info manager storing key triple to key info mapping in a SQLite database
+//!
+//! All the mappings are stored in a single table of the database, with the key triple as primary
+//! key. The table is indexed by application name and by provider so that the mappings of an
+//! application or of a provider are found without reading the whole table.
+//! Every modification of the mappings is done inside a transaction and is committed to the
+//! database before the method returns.
+//! The mappings are not kept in memory: all the operations query the database.
+//! There should not be two instances of this manager using the same database at a time.
+//! For security reasons, only the PARSEC service should have the ability to modify the database.
+use super::{KeyInfo, KeyTriple, ManageKeyInfo};
+use crate::authenticators::ApplicationName;
+use log::error;
+use parsec_interface::requests::ProviderId;
+use rusqlite::{params, Connection, OptionalExtension, ToSql};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+pub const DEFAULT_DB_PATH: &str = "./mappings.sqlite3";
+
+/// Creation of the table and of its indexes, done if they do not exist yet.
+const CREATE_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS key_info (
+        app_name TEXT NOT NULL,
+        provider_id INTEGER NOT NULL,
+        key_name TEXT NOT NULL,
+        key_id BLOB NOT NULL,
+        attributes BLOB NOT NULL,
+        PRIMARY KEY (app_name, provider_id, key_name)
+    );
+    CREATE INDEX IF NOT EXISTS key_info_app_name ON key_info (app_name);
+    CREATE INDEX IF NOT EXISTS key_info_provider_id ON key_info (provider_id);
+";
+
+#[derive(Debug)]
+pub struct SqliteKeyInfoManager {
+    /// Connection to the database, guarded by a Mutex as it can not be shared between threads.
+    connection: Mutex<Connection>,
+    /// Key triples reserved for keys being created, without a mapping yet.
+    reserved: HashSet<KeyTriple>,
+}
+
+fn sqlite_error_to_io(err: rusqlite::Error) -> Error {
+    error!("SQLite error ({}).", err);
+    Error::new(ErrorKind::Other, "SQLite error")
+}
+
+/// Reads the key info of the key triple from the database, using the primary key.
+fn read_mapping(
+    connection: &Connection,
+    key_triple: &KeyTriple,
+) -> Result<Option<KeyInfo>, String> {
+    let row = connection
+        .query_row(
+            "SELECT key_id, attributes FROM key_info
+             WHERE app_name = ?1 AND provider_id = ?2 AND key_name = ?3",
+            params![
+                key_triple.app_name().get_name(),
+                key_triple.provider_id() as u8,
+                key_triple.key_name()
+            ],
+            |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    match row {
+        Some((id, attributes)) => {
+            let attributes = bincode::deserialize(&attributes).map_err(|e| {
+                error!("Error deserializing key attributes ({}).", e);
+                String::from("error deserializing key attributes")
+            })?;
+            Ok(Some(KeyInfo { id, attributes }))
+        }
+        None => Ok(None),
+    }
+}
+
+impl SqliteKeyInfoManager {
+    /// Creates an instance of the SQLite manager from the database at `db_path`. The database,
+    /// and its parent directory, are created if they do not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an std::io error if the database can not be opened or created.
+    fn new(db_path: PathBuf) -> std::io::Result<SqliteKeyInfoManager> {
+        if let Some(parent) = db_path.parent() {
+            // Will ignore if the directory already exists.
+            fs::create_dir_all(parent)?;
+        }
+
+        let connection = Connection::open(&db_path).map_err(sqlite_error_to_io)?;
+        connection
+            .execute_batch(CREATE_SCHEMA)
+            .map_err(sqlite_error_to_io)?;
+
+        Ok(SqliteKeyInfoManager {
+            connection: Mutex::new(connection),
+            reserved: HashSet::new(),
+        })
+    }
+
+    /// Returns the key triples of the mappings selected by the query, which has one parameter and
+    /// returns the app_name, provider_id and key_name columns.
+    /// Mappings with an invalid provider ID are ignored.
+    fn read_key_triples(&self, query: &str, param: &dyn ToSql) -> Result<Vec<KeyTriple>, String> {
+        let connection = self.connection.lock().expect("Connection lock poisoned");
+        let mut statement = connection.prepare(query).map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map(params![param], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+
+        let mut key_triples = Vec::new();
+        for row in rows {
+            let (app_name, provider_id, key_name) = row.map_err(|e| e.to_string())?;
+            let provider_id = match u8::try_from(provider_id)
+                .ok()
+                .and_then(|provider_id| ProviderId::try_from(provider_id).ok())
+            {
+                Some(provider_id) => provider_id,
+                None => {
+                    error!(
+                        "Ignoring the mapping of key \"{}\" of \"{}\": provider ID {} is invalid.",
+                        key_name, app_name, provider_id
+                    );
+                    continue;
+                }
+            };
+            key_triples.push(KeyTriple::new(
+                ApplicationName::new(app_name),
+                provider_id,
+                key_name,
+            ));
+        }
+
+        Ok(key_triples)
+    }
+
+    /// Checks if the database contains a mapping for the key triple.
+    fn mapping_exists(&self, key_triple: &KeyTriple) -> Result<bool, String> {
+        let connection = self.connection.lock().expect("Connection lock poisoned");
+        let row = connection
+            .query_row(
+                "SELECT 1 FROM key_info WHERE app_name = ?1 AND provider_id = ?2 AND key_name = ?3",
+                params![
+                    key_triple.app_name().get_name(),
+                    key_triple.provider_id() as u8,
+                    key_triple.key_name()
+                ],
+                |_| Ok(()),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+
+        Ok(row.is_some())
+    }
+
+    /// Saves the key triple to key info mapping in the database, replacing an existing one.
+    /// Returns the key info replaced, if any.
+    fn save_mapping(
+        &self,
+        key_triple: &KeyTriple,
+        key_info: &KeyInfo,
+    ) -> Result<Option<KeyInfo>, String> {
+        let attributes = bincode::serialize(&key_info.attributes).map_err(|e| {
+            error!("Error serializing key attributes ({}).", e);
+            String::from("error serializing key attributes")
+        })?;
+        let mut connection = self.connection.lock().expect("Connection lock poisoned");
+        let transaction = connection.transaction().map_err(|e| e.to_string())?;
+        let previous = read_mapping(&transaction, key_triple)?;
+        let _ = transaction
+            .execute(
+                "INSERT OR REPLACE INTO key_info (app_name, provider_id, key_name, key_id, attributes)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    key_triple.app_name().get_name(),
+                    key_triple.provider_id() as u8,
+                    key_triple.key_name(),
+                    key_info.id,
+                    attributes
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        transaction.commit().map_err(|e| e.to_string())?;
+
+        Ok(previous)
+    }
+
+    /// Removes the mapping from the database and returns its key info.
+    /// Will do nothing if the mapping does not exist.
+    fn delete_mapping(&self, key_triple: &KeyTriple) -> Result<Option<KeyInfo>, String> {
+        let mut connection = self.connection.lock().expect("Connection lock poisoned");
+        let transaction = connection.transaction().map_err(|e| e.to_string())?;
+        let previous = read_mapping(&transaction, key_triple)?;
+        let _ = transaction
+            .execute(
+                "DELETE FROM key_info WHERE app_name = ?1 AND provider_id = ?2 AND key_name = ?3",
+                params![
+                    key_triple.app_name().get_name(),
+                    key_triple.provider_id() as u8,
+                    key_triple.key_name()
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        transaction.commit().map_err(|e| e.to_string())?;
+
+        Ok(previous)
+    }
+}
+
+impl ManageKeyInfo for SqliteKeyInfoManager {
+    fn get(&self, key_triple: &KeyTriple) -> Result<Option<KeyInfo>, String> {
+        let connection = self.connection.lock().expect("Connection lock poisoned");
+        read_mapping(&connection, key_triple)
+    }
+
+    fn get_all(&self, provider_id: ProviderId) -> Result<Vec<KeyTriple>, String> {
+        self.read_key_triples(
+            "SELECT app_name, provider_id, key_name FROM key_info WHERE provider_id = ?1",
+            &(provider_id as u8),
+        )
+    }
+
+    fn get_all_by_app(&self, app_name: &ApplicationName) -> Result<Vec<KeyTriple>, String> {
+        self.read_key_triples(
+            "SELECT app_name, provider_id, key_name FROM key_info WHERE app_name = ?1",
+            &app_name.get_name(),
+        )
+    }
+
+    fn insert(
+        &mut self,
+        key_triple: KeyTriple,
+        key_info: KeyInfo,
+    ) -> Result<Option<KeyInfo>, String> {
+        let previous_key_info = self.save_mapping(&key_triple, &key_info)?;
+        let _ = self.reserved.remove(&key_triple);
+        Ok(previous_key_info)
+    }
+
+    fn remove(&mut self, key_triple: &KeyTriple) -> Result<Option<KeyInfo>, String> {
+        self.delete_mapping(key_triple)
+    }
+
+    fn exists(&self, key_triple: &KeyTriple) -> Result<bool, String> {
+        Ok(self.reserved.contains(key_triple) || self.mapping_exists(key_triple)?)
+    }
+
+    fn reserve(&mut self, key_triple: &KeyTriple) -> Result<bool, String> {
+        if self.mapping_exists(key_triple)? {
+            Ok(false)
+        } else {
+            Ok(self.reserved.insert(key_triple.clone()))
+        }
+    }
+
+    fn release(&mut self, key_triple: &KeyTriple) {
+        let _ = self.reserved.remove(key_triple);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SqliteKeyInfoManagerBuilder {
+    db_path: Option<PathBuf>,
+}
+
+impl SqliteKeyInfoManagerBuilder {
+    pub fn new() -> SqliteKeyInfoManagerBuilder {
+        SqliteKeyInfoManagerBuilder { db_path: None }
+    }
+
+    pub fn with_db_path(mut self, path: PathBuf) -> SqliteKeyInfoManagerBuilder {
+        self.db_path = Some(path);
+
+        self
+    }
+
+    pub fn build(self) -> std::io::Result<SqliteKeyInfoManager> {
+        SqliteKeyInfoManager::new(self.db_path.ok_or_else(|| {
+            error!("Database path is missing");
+            Error::new(ErrorKind::InvalidData, "database path is missing")
+        })?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{KeyInfo, KeyTriple, ManageKeyInfo};
+    use super::SqliteKeyInfoManager;
+    use crate::authenticators::ApplicationName;
+    use parsec_interface::operations::psa_algorithm::{
+        Algorithm, AsymmetricSignature, Hash, SignHash,
+    };
+    use parsec_interface::operations::psa_key_attributes::{
+        Attributes, Lifetime, Policy, Type, UsageFlags,
+    };
+    use parsec_interface::requests::ProviderId;
+    use rusqlite::NO_PARAMS;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn test_key_attributes() -> Attributes {
+        Attributes {
+            lifetime: Lifetime::Persistent,
+            key_type: Type::Derive,
+            bits: 1024,
+            policy: Policy {
+                usage_flags: {
+                    let mut usage_flags = UsageFlags::default();
+                    let _ = usage_flags.set_sign_hash();
+                    usage_flags
+                },
+                permitted_algorithms: Algorithm::AsymmetricSignature(
+                    AsymmetricSignature::RsaPkcs1v15Sign {
+                        hash_alg: SignHash::Specific(Hash::Sha256),
+                    },
+                ),
+            },
+        }
+    }
+
+    fn test_key_info() -> KeyInfo {
+        KeyInfo {
+            id: vec![0x11, 0x22, 0x33],
+            attributes: test_key_attributes(),
+        }
+    }
+
+    fn new_key_triple(key_name: String) -> KeyTriple {
+        KeyTriple::new(
+            ApplicationName::new("Testing Application 😎".to_string()),
+            ProviderId::MbedCrypto,
+            key_name,
+        )
+    }
+
+    #[test]
+    fn insert_get_remove_key_info() {
+        let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/insert_get_remove_key_info.db");
+        let _ = fs::remove_file(&path);
+        let mut manager = SqliteKeyInfoManager::new(path.clone()).unwrap();
+
+        let key_triple = new_key_triple("insert_get_remove_key_info".to_string());
+        let key_info = test_key_info();
+
+        assert!(manager.get(&key_triple).unwrap().is_none());
+        assert!(manager
+            .insert(key_triple.clone(), key_info.clone())
+            .unwrap()
+            .is_none());
+        assert_eq!(manager.get(&key_triple).unwrap(), Some(key_info.clone()));
+        assert!(manager.exists(&key_triple).unwrap());
+
+        assert_eq!(manager.remove(&key_triple).unwrap().unwrap(), key_info);
+        assert!(!manager.exists(&key_triple).unwrap());
+        assert_eq!(manager.remove(&key_triple).unwrap(), None);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn create_and_load() {
+        let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/sqlite_create_and_load.db");
+        let _ = fs::remove_file(&path);
+
+        let key_triple1 = new_key_triple("😀 Monday".to_string());
+        let key_triple2 = KeyTriple::new(
+            ApplicationName::new("Other application".to_string()),
+            ProviderId::Pkcs11,
+            "😀 Tuesday".to_string(),
+        );
+        let key_triple3 = new_key_triple("😀 Wednesday".to_string());
+        let key_info1 = test_key_info();
+        let mut key_info2 = test_key_info();
+        key_info2.id = vec![0x44, 0x55];
+        let mut key_info3 = test_key_info();
+        key_info3.id = vec![0x66];
+
+        {
+            let mut manager = SqliteKeyInfoManager::new(path.clone()).unwrap();
+            let _ = manager
+                .insert(key_triple1.clone(), key_info1.clone())
+                .unwrap();
+            let _ = manager
+                .insert(key_triple2.clone(), key_info2.clone())
+                .unwrap();
+            let _ = manager
+                .insert(key_triple3.clone(), test_key_info())
+                .unwrap();
+            // Overwrite the third mapping.
+            assert_eq!(
+                manager
+                    .insert(key_triple3.clone(), key_info3.clone())
+                    .unwrap(),
+                Some(test_key_info())
+            );
+        }
+
+        {
+            let manager = SqliteKeyInfoManager::new(path.clone()).unwrap();
+            assert_eq!(manager.get(&key_triple1).unwrap().unwrap(), key_info1);
+            assert_eq!(manager.get(&key_triple2).unwrap().unwrap(), key_info2);
+            assert_eq!(manager.get(&key_triple3).unwrap().unwrap(), key_info3);
+
+            assert_eq!(
+                manager.get_all(ProviderId::Pkcs11).unwrap(),
+                vec![key_triple2]
+            );
+            let mut key_triples = manager
+                .get_all_by_app(&ApplicationName::new("Testing Application 😎".to_string()))
+                .unwrap();
+            key_triples.sort_by_key(|key_triple| key_triple.key_name().to_string());
+            assert_eq!(key_triples, vec![key_triple1, key_triple3]);
+        }
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn queries_use_indexes() {
+        let path = PathBuf::from(env!("OUT_DIR").to_owned() + "/sqlite_queries_use_indexes.db");
+        let _ = fs::remove_file(&path);
+        let manager = SqliteKeyInfoManager::new(path.clone()).unwrap();
+        let connection = manager.connection.lock().unwrap();
+
+        for query in &[
+            "SELECT app_name, provider_id, key_name FROM key_info WHERE provider_id = 1",
+            "SELECT app_name, provider_id, key_name FROM key_info WHERE app_name = 'app'",
+            "SELECT key_id, attributes FROM key_info
+             WHERE app_name = 'app' AND provider_id = 1 AND key_name = 'key'",
+        ] {
+            let mut statement = connection
+                .prepare(&format!("EXPLAIN QUERY PLAN {}", query))
+                .unwrap();
+            let plan: Vec<String> = statement
+                .query_map(NO_PARAMS, |row| row.get(3))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            // A full scan of the table would be reported as "SCAN".
+            assert!(
+                plan.iter().all(|step| step.starts_with("SEARCH")),
+                "{:?}",
+                plan
+            );
+        }
+        drop(connection);
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -5,30 +5,287 @@
 //! The core provider acts as a source of information for the Parsec service,
 //! aiding clients in discovering the capabilities offered by their underlying
 //! platform.
+//!
+//! It also implements the administrative operations of the service, which act on the keys of
+//! all the applications. Those operations are only allowed for the applications listed as
+//! admins in the configuration. The ones without an opcode are received on the admin socket (see
+//! `back::admin`).
+use super::reconciliation::{self, ReconciliationMode, ReconciliationReport};
 use super::Provide;
-use log::error;
+use crate::authenticators::{AdminList, ApplicationName};
+use crate::back::admin::{AdminOperation, AdminResult};
+use crate::key_info_managers::backup::{
+    self, ArchiveProtection, BackupKey, ConflictPolicy, ImportReport,
+};
+use crate::key_info_managers::{self, KeyTriple, ManageKeyInfo, KEY_STORING_PROVIDERS};
+use derivative::Derivative;
+use log::{error, warn};
+use parsec_interface::operations::list_authenticators::AuthenticatorInfo;
 use parsec_interface::operations::list_providers::ProviderInfo;
-use parsec_interface::operations::{list_opcodes, list_providers, ping};
-use parsec_interface::requests::{Opcode, ProviderID, ResponseStatus, Result};
-use std::collections::{HashMap, HashSet};
+use parsec_interface::operations::{
+    delete_client, list_authenticators, list_clients, list_keys, list_opcodes, list_providers,
+    ping, psa_destroy_key,
+};
+use parsec_interface::requests::{Opcode, ProviderId, ResponseStatus, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
 use std::io::{Error, ErrorKind};
 use std::str::FromStr;
+use std::sync::{Arc, RwLock};
 use uuid::Uuid;
 use version::{version, Version};
+use zeroize::Zeroizing;
+
+const SUPPORTED_OPCODES: [Opcode; 7] = [
+    Opcode::ListProviders,
+    Opcode::ListOpcodes,
+    Opcode::ListAuthenticators,
+    Opcode::ListKeys,
+    Opcode::ListClients,
+    Opcode::DeleteClient,
+    Opcode::Ping,
+];
+
+type KeyInfoManager = Arc<RwLock<dyn ManageKeyInfo + Send + Sync>>;
+type Provider = Arc<dyn Provide + Send + Sync>;
+
+/// Result of a consistency check of the key info managers
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConsistencyReport {
+    /// Comparison of the mappings of each provider with the keys stored in its backend, for the
+    /// providers supporting it.
+    pub backends: Vec<ReconciliationReport>,
+    /// Key triples stored for a provider which is not running in the service.
+    pub orphaned: Vec<KeyTriple>,
+}
+
+impl ConsistencyReport {
+    /// Returns `true` if no inconsistency was found.
+    pub fn is_consistent(&self) -> bool {
+        self.orphaned.is_empty() && self.backends.iter().all(|report| report.is_consistent())
+    }
+}
+
+impl fmt::Display for ConsistencyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for report in &self.backends {
+            write!(f, "{}", report)?;
+        }
+        writeln!(
+            f,
+            "{} mappings of providers not running",
+            self.orphaned.len()
+        )?;
+        for key_triple in &self.orphaned {
+            writeln!(f, "  orphaned mapping: {}", key_triple)?;
+        }
 
-const SUPPORTED_OPCODES: [Opcode; 3] = [Opcode::ListProviders, Opcode::ListOpcodes, Opcode::Ping];
+        Ok(())
+    }
+}
 
 /// Service information provider
 ///
 /// The core provider is a non-cryptographic provider tasked with offering
 /// structured information about the status of the service and the providers
 /// available.
-#[derive(Debug)]
+#[derive(Derivative)]
+#[derivative(Debug)]
 pub struct CoreProvider {
     wire_protocol_version_min: u8,
     wire_protocol_version_maj: u8,
     provider_info: Vec<ProviderInfo>,
-    provider_opcodes: HashMap<ProviderID, HashSet<Opcode>>,
+    provider_opcodes: HashMap<ProviderId, HashSet<Opcode>>,
+    authenticator_info: Vec<AuthenticatorInfo>,
+    #[derivative(Debug = "ignore")]
+    providers: HashMap<ProviderId, Provider>,
+    #[derivative(Debug = "ignore")]
+    key_info_managers: HashMap<ProviderId, KeyInfoManager>,
+    admin_list: AdminList,
+}
+
+impl CoreProvider {
+    /// List the key triples of all the applications, in all the providers.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ResponseStatus::AdminOperation` if the application is not an admin.
+    pub fn list_all_keys(&self, app_name: &ApplicationName) -> Result<Vec<KeyTriple>> {
+        self.check_admin(app_name)?;
+        self.all_key_triples()
+    }
+
+    /// Check that the key info managers are consistent with the providers running in the service.
+    ///
+    /// The mappings of each provider are compared with the keys stored in its backend, without
+    /// repairing anything, and the mappings stored for a provider which is not running are
+    /// reported as orphaned. The inconsistencies found are logged and returned in the report.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ResponseStatus::AdminOperation` if the application is not an admin or the error of
+    /// the first provider failing.
+    pub fn check_key_info_consistency(
+        &self,
+        app_name: &ApplicationName,
+    ) -> Result<ConsistencyReport> {
+        self.check_admin(app_name)?;
+
+        let mut report = ConsistencyReport {
+            backends: reconciliation::reconcile_providers(
+                &self.providers,
+                None,
+                &ReconciliationMode::DryRun,
+            )?,
+            orphaned: Vec::new(),
+        };
+        let mut checked_managers: Vec<&KeyInfoManager> = Vec::new();
+        for key_info_manager in self.key_info_managers.values() {
+            // Several providers can share the same key info manager.
+            if checked_managers
+                .iter()
+                .any(|checked| Arc::ptr_eq(checked, key_info_manager))
+            {
+                continue;
+            }
+            checked_managers.push(key_info_manager);
+
+            let store_handle = key_info_manager.read().expect("Key store lock poisoned");
+            for provider_id in KEY_STORING_PROVIDERS.iter() {
+                if self.providers.contains_key(provider_id) {
+                    continue;
+                }
+                for key_triple in store_handle
+                    .get_all(*provider_id)
+                    .map_err(key_info_managers::to_response_status)?
+                {
+                    warn!("Key mapping for an absent provider: {}", key_triple);
+                    report.orphaned.push(key_triple);
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Reconcile the key mappings of a provider, or of all the providers running if none is given,
+    /// with the keys stored in their backends. Depending on the mode, the inconsistencies found are
+    /// only reported or are repaired.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ResponseStatus::AdminOperation` if the application is not an admin,
+    /// `ResponseStatus::ProviderNotRegistered` if the provider given is not running or the error of
+    /// the first provider failing.
+    pub fn reconcile_keys(
+        &self,
+        app_name: &ApplicationName,
+        provider_id: Option<ProviderId>,
+        mode: &ReconciliationMode,
+    ) -> Result<Vec<ReconciliationReport>> {
+        self.check_admin(app_name)?;
+        reconciliation::reconcile_providers(&self.providers, provider_id, mode)
+    }
+
+    /// Export the mappings of all the key info managers to an archive protected with the backup
+    /// key. The key info managers are locked while the archive is written.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ResponseStatus::AdminOperation` if the application is not an admin or
+    /// `ResponseStatus::PsaErrorStorageFailure` if the mappings could not be exported.
+    pub fn export_key_info(
+        &self,
+        app_name: &ApplicationName,
+        key: &BackupKey,
+        protection: ArchiveProtection,
+    ) -> Result<Vec<u8>> {
+        self.check_admin(app_name)?;
+        backup::export_archive(&self.key_info_managers, key, protection).map_err(backup_error)
+    }
+
+    /// Import the mappings of an archive protected with the backup key, handling the conflicts
+    /// with the existing mappings following the policy given. The key info managers are locked
+    /// while the archive is imported. The providers which received new mappings are then
+    /// reconciled, without repairing anything, to refresh their view of the keys used.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ResponseStatus::AdminOperation` if the application is not an admin,
+    /// `ResponseStatus::InvalidEncoding` if the archive is not valid, was modified or is protected
+    /// with another key and `ResponseStatus::PsaErrorStorageFailure` if the mappings could not be
+    /// imported.
+    pub fn import_key_info(
+        &self,
+        app_name: &ApplicationName,
+        archive: &[u8],
+        key: &BackupKey,
+        policy: ConflictPolicy,
+    ) -> Result<ImportReport> {
+        self.check_admin(app_name)?;
+        let report = backup::import_archive(&self.key_info_managers, archive, key, policy)
+            .map_err(backup_error)?;
+
+        let updated_providers: HashSet<ProviderId> = report
+            .imported
+            .iter()
+            .map(|key_triple| key_triple.provider_id())
+            .collect();
+        for provider_id in updated_providers {
+            if let Some(provider) = self.providers.get(&provider_id) {
+                match provider.reconcile(&ReconciliationMode::DryRun) {
+                    Ok(_) | Err(ResponseStatus::PsaErrorNotSupported) => (),
+                    Err(e) => warn!(
+                        "Provider {} could not be reconciled after the import ({}).",
+                        provider_id, e
+                    ),
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn check_admin(&self, app_name: &ApplicationName) -> Result<()> {
+        if self.admin_list.is_admin(app_name) {
+            Ok(())
+        } else {
+            warn!(
+                "Application \"{}\" is not allowed to perform admin operations.",
+                app_name
+            );
+            Err(ResponseStatus::AdminOperation)
+        }
+    }
+
+    fn all_key_triples(&self) -> Result<Vec<KeyTriple>> {
+        let mut key_triples = Vec::new();
+        for (provider_id, key_info_manager) in &self.key_info_managers {
+            let store_handle = key_info_manager.read().expect("Key store lock poisoned");
+            key_triples.extend(
+                store_handle
+                    .get_all(*provider_id)
+                    .map_err(key_info_managers::to_response_status)?,
+            );
+        }
+
+        Ok(key_triples)
+    }
+}
+
+/// Creates the backup key sent in an admin request, erasing its raw bytes.
+fn admin_backup_key(key: Vec<u8>) -> Result<BackupKey> {
+    let key = Zeroizing::new(key);
+    BackupKey::new(&key).map_err(|_| ResponseStatus::PsaErrorInvalidArgument)
+}
+
+fn backup_error(error: Error) -> ResponseStatus {
+    if error.kind() == ErrorKind::InvalidData {
+        ResponseStatus::InvalidEncoding
+    } else {
+        ResponseStatus::PsaErrorStorageFailure
+    }
 }
 
 impl Provide for CoreProvider {
@@ -48,6 +305,129 @@ impl Provide for CoreProvider {
         })
     }
 
+    fn list_authenticators(
+        &self,
+        _op: list_authenticators::Operation,
+    ) -> Result<list_authenticators::Result> {
+        Ok(list_authenticators::Result {
+            authenticators: self.authenticator_info.clone(),
+        })
+    }
+
+    fn list_keys(
+        &self,
+        app_name: ApplicationName,
+        _op: list_keys::Operation,
+    ) -> Result<list_keys::Result> {
+        let mut keys = Vec::new();
+        for (provider_id, key_info_manager) in &self.key_info_managers {
+            let store_handle = key_info_manager.read().expect("Key store lock poisoned");
+            let key_triples = store_handle
+                .get_all_by_app(&app_name)
+                .map_err(key_info_managers::to_response_status)?;
+            // A key info manager can be shared between providers: only the keys of the provider
+            // it was registered for are taken.
+            for key_triple in key_triples {
+                if !key_triple.belongs_to_provider(*provider_id) {
+                    continue;
+                }
+                let key_info = store_handle
+                    .get(&key_triple)
+                    .map_err(key_info_managers::to_response_status)?
+                    .ok_or_else(|| {
+                        error!("Key information missing for: {}", key_triple);
+                        ResponseStatus::KeyInfoManagerError
+                    })?;
+                keys.push(list_keys::KeyInfo {
+                    provider_id: *provider_id,
+                    name: key_triple.key_name().to_string(),
+                    attributes: key_info.attributes,
+                });
+            }
+        }
+        keys.sort_by(|a, b| (a.provider_id as u8, &a.name).cmp(&(b.provider_id as u8, &b.name)));
+
+        Ok(list_keys::Result { keys })
+    }
+
+    fn list_clients(&self, _op: list_clients::Operation) -> Result<list_clients::Result> {
+        let clients: BTreeSet<String> = self
+            .all_key_triples()?
+            .iter()
+            .map(|key_triple| key_triple.app_name().get_name().to_string())
+            .collect();
+
+        Ok(list_clients::Result {
+            clients: clients.into_iter().collect(),
+        })
+    }
+
+    fn delete_client(&self, op: delete_client::Operation) -> Result<delete_client::Result> {
+        let client = ApplicationName::new(op.client);
+
+        // The key triples are collected first as the providers need to lock the key info
+        // manager to destroy the keys.
+        let key_triples: Vec<KeyTriple> = self
+            .all_key_triples()?
+            .into_iter()
+            .filter(|key_triple| *key_triple.app_name() == client)
+            .collect();
+
+        for key_triple in key_triples {
+            let provider = self
+                .providers
+                .get(&key_triple.provider_id())
+                .ok_or(ResponseStatus::ProviderNotRegistered)?;
+            if let Err(status) = provider.psa_destroy_key(
+                client.clone(),
+                psa_destroy_key::Operation {
+                    key_name: key_triple.key_name().to_string(),
+                },
+            ) {
+                error!("Failed to destroy key ({}): {}.", key_triple, status);
+                return Err(status);
+            }
+        }
+
+        Ok(delete_client::Result {})
+    }
+
+    fn admin_operation(
+        &self,
+        app_name: ApplicationName,
+        op: AdminOperation,
+    ) -> Result<AdminResult> {
+        match op {
+            AdminOperation::ListAllKeys => {
+                Ok(AdminResult::ListAllKeys(self.list_all_keys(&app_name)?))
+            }
+            AdminOperation::CheckConsistency => Ok(AdminResult::CheckConsistency(
+                self.check_key_info_consistency(&app_name)?,
+            )),
+            AdminOperation::Reconcile { provider_id, mode } => Ok(AdminResult::Reconcile(
+                self.reconcile_keys(&app_name, provider_id, &mode)?,
+            )),
+            AdminOperation::ExportMappings {
+                backup_key,
+                protection,
+            } => Ok(AdminResult::ExportMappings(self.export_key_info(
+                &app_name,
+                &admin_backup_key(backup_key)?,
+                protection,
+            )?)),
+            AdminOperation::ImportMappings {
+                archive,
+                backup_key,
+                policy,
+            } => Ok(AdminResult::ImportMappings(self.import_key_info(
+                &app_name,
+                &archive,
+                &admin_backup_key(backup_key)?,
+                policy,
+            )?)),
+        }
+    }
+
     fn ping(&self, _op: ping::Operation) -> Result<ping::Result> {
         let result = ping::Result {
             wire_protocol_version_maj: self.wire_protocol_version_maj,
@@ -59,12 +439,19 @@ impl Provide for CoreProvider {
 }
 
 /// Builder for CoreProvider
-#[derive(Debug, Default)]
+#[derive(Derivative, Default)]
+#[derivative(Debug)]
 pub struct CoreProviderBuilder {
     version_maj: Option<u8>,
     version_min: Option<u8>,
     provider_info: Vec<ProviderInfo>,
-    provider_opcodes: HashMap<ProviderID, HashSet<Opcode>>,
+    provider_opcodes: HashMap<ProviderId, HashSet<Opcode>>,
+    authenticator_info: Vec<AuthenticatorInfo>,
+    #[derivative(Debug = "ignore")]
+    providers: HashMap<ProviderId, Provider>,
+    #[derivative(Debug = "ignore")]
+    key_info_managers: HashMap<ProviderId, KeyInfoManager>,
+    admin_list: AdminList,
 }
 
 impl CoreProviderBuilder {
@@ -88,12 +475,12 @@ impl CoreProviderBuilder {
             version_maj: crate_version.major,
             version_min: crate_version.minor,
             version_rev: crate_version.patch,
-            id: ProviderID::Core,
+            id: ProviderId::Core,
         }];
 
         let mut provider_opcodes = HashMap::new();
         let _ = provider_opcodes.insert(
-            ProviderID::Core,
+            ProviderId::Core,
             SUPPORTED_OPCODES.iter().copied().collect(),
         );
 
@@ -102,6 +489,10 @@ impl CoreProviderBuilder {
             version_min: None,
             provider_info,
             provider_opcodes,
+            authenticator_info: Vec::new(),
+            providers: HashMap::new(),
+            key_info_managers: HashMap::new(),
+            admin_list: AdminList::default(),
         })
     }
 
@@ -123,6 +514,32 @@ impl CoreProviderBuilder {
         self
     }
 
+    pub fn with_authenticator_info(mut self, authenticator_info: AuthenticatorInfo) -> Self {
+        self.authenticator_info.push(authenticator_info);
+
+        self
+    }
+
+    /// Give access to a provider and to the key info manager it uses, for the operations acting
+    /// on the keys of all the applications.
+    pub fn with_provider(
+        mut self,
+        provider_id: ProviderId,
+        provider: Provider,
+        key_info_manager: KeyInfoManager,
+    ) -> Self {
+        let _ = self.providers.insert(provider_id, provider);
+        let _ = self.key_info_managers.insert(provider_id, key_info_manager);
+
+        self
+    }
+
+    pub fn with_admin_list(mut self, admin_list: AdminList) -> Self {
+        self.admin_list = admin_list;
+
+        self
+    }
+
     pub fn build(self) -> std::io::Result<CoreProvider> {
         let core_provider = CoreProvider {
             wire_protocol_version_maj: self
@@ -133,6 +550,10 @@ impl CoreProviderBuilder {
                 .ok_or_else(|| Error::new(ErrorKind::InvalidData, "version min is missing"))?,
             provider_opcodes: self.provider_opcodes,
             provider_info: self.provider_info,
+            authenticator_info: self.authenticator_info,
+            providers: self.providers,
+            key_info_managers: self.key_info_managers,
+            admin_list: self.admin_list,
         };
 
         Ok(core_provider)
@@ -150,6 +571,10 @@ mod tests {
             wire_protocol_version_maj: 10,
             provider_info: Vec::new(),
             provider_opcodes: HashMap::new(),
+            authenticator_info: Vec::new(),
+            providers: HashMap::new(),
+            key_info_managers: HashMap::new(),
+            admin_list: AdminList::default(),
         };
         let op = ping::Operation {};
         let result = provider.ping(op).unwrap();
@@ -162,4 +587,426 @@ mod tests {
             provider.wire_protocol_version_min
         );
     }
+
+    #[test]
+    fn test_list_authenticators() {
+        use parsec_interface::requests::AuthType;
+
+        let authenticator_info = AuthenticatorInfo {
+            description: String::from("Test authenticator"),
+            version_maj: 0,
+            version_min: 1,
+            version_rev: 0,
+            id: AuthType::UnixPeerCredentials,
+        };
+        let provider = CoreProvider {
+            wire_protocol_version_min: 8,
+            wire_protocol_version_maj: 10,
+            provider_info: Vec::new(),
+            provider_opcodes: HashMap::new(),
+            authenticator_info: vec![authenticator_info.clone()],
+            providers: HashMap::new(),
+            key_info_managers: HashMap::new(),
+            admin_list: AdminList::default(),
+        };
+        let op = list_authenticators::Operation {};
+        let result = provider.list_authenticators(op).unwrap();
+        assert_eq!(result.authenticators, vec![authenticator_info]);
+    }
+
+    mod key_management {
+        use super::super::*;
+        use crate::authenticators::Admin;
+        use crate::key_info_managers::backup::BACKUP_KEY_LEN;
+        use crate::key_info_managers::memory_manager::MemoryKeyInfoManager;
+        use crate::key_info_managers::KeyInfo;
+        use crate::providers::reconciliation::{BackendObject, ManageBackendObjects};
+        use parsec_interface::operations::psa_algorithm::{
+            Algorithm, AsymmetricSignature, Hash, SignHash,
+        };
+        use parsec_interface::operations::psa_key_attributes::{
+            Attributes, Lifetime, Policy, Type, UsageFlags,
+        };
+
+        /// Provider only removing the key mappings when destroying a key. Its backend is the list
+        /// of the identifiers of the keys it stores.
+        struct MockProvider {
+            key_info_store: KeyInfoManager,
+            backend_ids: Vec<Vec<u8>>,
+        }
+
+        impl ManageBackendObjects for MockProvider {
+            fn object_exists(&self, key_info: &KeyInfo) -> Result<bool> {
+                Ok(self.backend_ids.contains(&key_info.id))
+            }
+
+            fn list_objects(&self) -> Result<Vec<BackendObject>> {
+                Ok(self
+                    .backend_ids
+                    .iter()
+                    .map(|id| BackendObject {
+                        id: id.clone(),
+                        attributes: None,
+                    })
+                    .collect())
+            }
+
+            fn destroy_object(&self, _id: &[u8]) -> Result<()> {
+                Err(ResponseStatus::PsaErrorNotPermitted)
+            }
+        }
+
+        impl Provide for MockProvider {
+            fn reconcile(&self, mode: &ReconciliationMode) -> Result<ReconciliationReport> {
+                let mut store_handle = self.key_info_store.write().unwrap();
+                reconciliation::reconcile(self, ProviderId::Pkcs11, &mut *store_handle, mode)
+            }
+
+            fn psa_destroy_key(
+                &self,
+                app_name: ApplicationName,
+                op: psa_destroy_key::Operation,
+            ) -> Result<psa_destroy_key::Result> {
+                let key_triple = KeyTriple::new(app_name, ProviderId::Pkcs11, op.key_name);
+                let _ = self
+                    .key_info_store
+                    .write()
+                    .unwrap()
+                    .remove(&key_triple)
+                    .unwrap()
+                    .ok_or(ResponseStatus::PsaErrorDoesNotExist)?;
+                Ok(psa_destroy_key::Result {})
+            }
+        }
+
+        fn key_info(id: u8) -> KeyInfo {
+            KeyInfo {
+                id: vec![id],
+                attributes: Attributes {
+                    lifetime: Lifetime::Persistent,
+                    key_type: Type::RsaKeyPair,
+                    bits: 1024,
+                    policy: Policy {
+                        usage_flags: {
+                            let mut usage_flags = UsageFlags::default();
+                            let _ = usage_flags.set_sign_hash();
+                            usage_flags
+                        },
+                        permitted_algorithms: Algorithm::AsymmetricSignature(
+                            AsymmetricSignature::RsaPkcs1v15Sign {
+                                hash_alg: SignHash::Specific(Hash::Sha256),
+                            },
+                        ),
+                    },
+                },
+            }
+        }
+
+        /// Core provider giving access to a mock provider whose backend stores the keys given,
+        /// with the identifier of each key being its index.
+        fn core_provider(keys: &[(&str, ProviderId, &str)]) -> CoreProvider {
+            let backend_ids = (0..keys.len()).map(|id| vec![id as u8]).collect();
+            core_provider_with_backend(keys, backend_ids)
+        }
+
+        /// Core provider giving access to a mock provider whose backend stores the keys of the
+        /// identifiers given. The identifier of each key mapping is its index.
+        fn core_provider_with_backend(
+            keys: &[(&str, ProviderId, &str)],
+            backend_ids: Vec<Vec<u8>>,
+        ) -> CoreProvider {
+            let key_info_store: KeyInfoManager = Arc::new(RwLock::new(MemoryKeyInfoManager::new()));
+            for (id, (app_name, provider_id, key_name)) in keys.iter().enumerate() {
+                let key_triple = KeyTriple::new(
+                    ApplicationName::new(app_name.to_string()),
+                    *provider_id,
+                    key_name.to_string(),
+                );
+                let _ = key_info_store
+                    .write()
+                    .unwrap()
+                    .insert(key_triple, key_info(id as u8))
+                    .unwrap();
+            }
+
+            CoreProviderBuilder::new()
+                .unwrap()
+                .with_wire_protocol_version(0, 1)
+                .with_provider(
+                    ProviderId::Pkcs11,
+                    Arc::new(MockProvider {
+                        key_info_store: key_info_store.clone(),
+                        backend_ids,
+                    }),
+                    key_info_store,
+                )
+                .with_admin_list(AdminList::from(vec![Admin::new(String::from("admin"))]))
+                .build()
+                .unwrap()
+        }
+
+        #[test]
+        fn list_keys() {
+            let provider = core_provider(&[
+                ("app_1", ProviderId::Pkcs11, "key_2"),
+                ("app_1", ProviderId::Pkcs11, "key_1"),
+                ("app_1", ProviderId::Tpm, "key_3"),
+                ("app_2", ProviderId::Pkcs11, "key_4"),
+            ]);
+
+            let result = provider
+                .list_keys(
+                    ApplicationName::new(String::from("app_1")),
+                    list_keys::Operation {},
+                )
+                .unwrap();
+            // The TPM provider is not running so its key is not listed.
+            assert_eq!(
+                result.keys,
+                vec![
+                    list_keys::KeyInfo {
+                        provider_id: ProviderId::Pkcs11,
+                        name: String::from("key_1"),
+                        attributes: key_info(0).attributes,
+                    },
+                    list_keys::KeyInfo {
+                        provider_id: ProviderId::Pkcs11,
+                        name: String::from("key_2"),
+                        attributes: key_info(0).attributes,
+                    },
+                ]
+            );
+
+            let result = provider
+                .list_keys(
+                    ApplicationName::new(String::from("app_3")),
+                    list_keys::Operation {},
+                )
+                .unwrap();
+            assert!(result.keys.is_empty());
+        }
+
+        #[test]
+        fn list_and_delete_clients() {
+            let provider = core_provider(&[
+                ("app_1", ProviderId::Pkcs11, "key_1"),
+                ("app_1", ProviderId::Pkcs11, "key_2"),
+                ("app_2", ProviderId::Pkcs11, "key_1"),
+            ]);
+
+            let clients = provider.list_clients(list_clients::Operation {}).unwrap();
+            assert_eq!(clients.clients, vec!["app_1", "app_2"]);
+
+            let _ = provider
+                .delete_client(delete_client::Operation {
+                    client: String::from("app_1"),
+                })
+                .unwrap();
+
+            let clients = provider.list_clients(list_clients::Operation {}).unwrap();
+            assert_eq!(clients.clients, vec!["app_2"]);
+        }
+
+        #[test]
+        fn admin_only_methods() {
+            let provider = core_provider_with_backend(
+                &[
+                    ("app_1", ProviderId::Pkcs11, "key_1"),
+                    ("app_2", ProviderId::Pkcs11, "key_1"),
+                    ("app_2", ProviderId::Tpm, "key_1"),
+                ],
+                vec![vec![0], vec![1]],
+            );
+            let admin = ApplicationName::new(String::from("admin"));
+            let not_admin = ApplicationName::new(String::from("app_1"));
+
+            assert_eq!(
+                provider.list_all_keys(&not_admin).unwrap_err(),
+                ResponseStatus::AdminOperation
+            );
+            assert_eq!(
+                provider.check_key_info_consistency(&not_admin).unwrap_err(),
+                ResponseStatus::AdminOperation
+            );
+            assert_eq!(
+                provider
+                    .reconcile_keys(&not_admin, None, &ReconciliationMode::DryRun)
+                    .unwrap_err(),
+                ResponseStatus::AdminOperation
+            );
+
+            assert_eq!(provider.list_all_keys(&admin).unwrap().len(), 2);
+
+            // The TPM provider is not running in the service.
+            let report = provider.check_key_info_consistency(&admin).unwrap();
+            assert!(!report.is_consistent());
+            assert_eq!(report.backends.len(), 1);
+            assert_eq!(report.backends[0].checked, 2);
+            assert!(report.backends[0].is_consistent());
+            assert_eq!(
+                report.orphaned,
+                vec![KeyTriple::new(
+                    ApplicationName::new(String::from("app_2")),
+                    ProviderId::Tpm,
+                    String::from("key_1")
+                )]
+            );
+
+            assert_eq!(
+                provider
+                    .reconcile_keys(&admin, None, &ReconciliationMode::DryRun)
+                    .unwrap(),
+                report.backends
+            );
+            assert_eq!(
+                provider
+                    .reconcile_keys(&admin, Some(ProviderId::Tpm), &ReconciliationMode::DryRun)
+                    .unwrap_err(),
+                ResponseStatus::ProviderNotRegistered
+            );
+
+            let key = BackupKey::new(&[0x42; BACKUP_KEY_LEN]).unwrap();
+            assert_eq!(
+                provider
+                    .export_key_info(&not_admin, &key, ArchiveProtection::Encrypted)
+                    .unwrap_err(),
+                ResponseStatus::AdminOperation
+            );
+            // All the mappings of the key info manager are exported, even the ones of the TPM
+            // provider, but those can not be imported back.
+            let archive = provider
+                .export_key_info(&admin, &key, ArchiveProtection::Encrypted)
+                .unwrap();
+            assert_eq!(
+                provider
+                    .import_key_info(&not_admin, &archive, &key, ConflictPolicy::Abort)
+                    .unwrap_err(),
+                ResponseStatus::AdminOperation
+            );
+            let report = provider
+                .import_key_info(&admin, &archive, &key, ConflictPolicy::Abort)
+                .unwrap();
+            assert!(report.imported.is_empty());
+            assert_eq!(report.unchanged.len(), 2);
+            assert_eq!(report.skipped.len(), 1);
+        }
+
+        #[test]
+        fn consistency_checked_against_backend() {
+            // The first key is missing from the backend, which also stores a key without mapping.
+            let provider = core_provider_with_backend(
+                &[
+                    ("app_1", ProviderId::Pkcs11, "key_1"),
+                    ("app_1", ProviderId::Pkcs11, "key_2"),
+                ],
+                vec![vec![1], vec![7]],
+            );
+            let admin = ApplicationName::new(String::from("admin"));
+
+            let report = provider.check_key_info_consistency(&admin).unwrap();
+            assert!(!report.is_consistent());
+            assert!(report.orphaned.is_empty());
+            assert_eq!(report.backends.len(), 1);
+            assert_eq!(report.backends[0].checked, 2);
+            assert_eq!(
+                report.backends[0].dangling_mappings,
+                vec![KeyTriple::new(
+                    ApplicationName::new(String::from("app_1")),
+                    ProviderId::Pkcs11,
+                    String::from("key_1")
+                )]
+            );
+            assert_eq!(report.backends[0].orphaned_objects, vec![vec![7]]);
+            // Nothing is repaired.
+            assert!(report.backends[0].mappings_removed.is_empty());
+            assert_eq!(provider.list_all_keys(&admin).unwrap().len(), 2);
+        }
+
+        #[test]
+        fn admin_operations() {
+            let provider = core_provider(&[
+                ("app_1", ProviderId::Pkcs11, "key_1"),
+                ("app_2", ProviderId::Pkcs11, "key_1"),
+            ]);
+            let admin = ApplicationName::new(String::from("admin"));
+            let not_admin = ApplicationName::new(String::from("app_1"));
+
+            assert_eq!(
+                provider
+                    .admin_operation(not_admin, AdminOperation::ListAllKeys)
+                    .unwrap_err(),
+                ResponseStatus::AdminOperation
+            );
+            match provider
+                .admin_operation(admin.clone(), AdminOperation::ListAllKeys)
+                .unwrap()
+            {
+                AdminResult::ListAllKeys(key_triples) => assert_eq!(key_triples.len(), 2),
+                result => panic!("Unexpected result: {:?}", result),
+            }
+            match provider
+                .admin_operation(admin.clone(), AdminOperation::CheckConsistency)
+                .unwrap()
+            {
+                AdminResult::CheckConsistency(report) => assert!(report.is_consistent()),
+                result => panic!("Unexpected result: {:?}", result),
+            }
+            match provider
+                .admin_operation(
+                    admin.clone(),
+                    AdminOperation::Reconcile {
+                        provider_id: Some(ProviderId::Pkcs11),
+                        mode: ReconciliationMode::DryRun,
+                    },
+                )
+                .unwrap()
+            {
+                AdminResult::Reconcile(reports) => {
+                    assert_eq!(reports.len(), 1);
+                    assert_eq!(reports[0].checked, 2);
+                }
+                result => panic!("Unexpected result: {:?}", result),
+            }
+
+            assert_eq!(
+                provider
+                    .admin_operation(
+                        admin.clone(),
+                        AdminOperation::ExportMappings {
+                            backup_key: vec![0x42; BACKUP_KEY_LEN - 1],
+                            protection: ArchiveProtection::Signed,
+                        },
+                    )
+                    .unwrap_err(),
+                ResponseStatus::PsaErrorInvalidArgument
+            );
+            let archive = match provider
+                .admin_operation(
+                    admin.clone(),
+                    AdminOperation::ExportMappings {
+                        backup_key: vec![0x42; BACKUP_KEY_LEN],
+                        protection: ArchiveProtection::Signed,
+                    },
+                )
+                .unwrap()
+            {
+                AdminResult::ExportMappings(archive) => archive,
+                result => panic!("Unexpected result: {:?}", result),
+            };
+            match provider
+                .admin_operation(
+                    admin,
+                    AdminOperation::ImportMappings {
+                        archive,
+                        backup_key: vec![0x42; BACKUP_KEY_LEN],
+                        policy: ConflictPolicy::Abort,
+                    },
+                )
+                .unwrap()
+            {
+                AdminResult::ImportMappings(report) => assert_eq!(report.unchanged.len(), 2),
+                result => panic!("Unexpected result: {:?}", result),
+            }
+        }
+    }
 }
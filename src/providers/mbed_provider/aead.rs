@@ -0,0 +1,140 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+use super::constants::PSA_SUCCESS;
+use super::utils::{self, KeyHandle};
+use super::{key_management, psa_crypto_binding, MbedProvider};
+use crate::authenticators::ApplicationName;
+use crate::key_info_managers::KeyTriple;
+use log::{error, info};
+use parsec_interface::operations::{psa_aead_decrypt, psa_aead_encrypt};
+use parsec_interface::requests::{ProviderId, ResponseStatus, Result};
+
+impl MbedProvider {
+    pub(super) fn psa_aead_encrypt_internal(
+        &self,
+        app_name: ApplicationName,
+        op: psa_aead_encrypt::Operation,
+    ) -> Result<psa_aead_encrypt::Result> {
+        info!("Mbed Provider - AEAD Encrypt");
+        let _semaphore_guard = self.key_slot_semaphore.access();
+        let key_triple = KeyTriple::new(app_name, ProviderId::MbedCrypto, op.key_name.clone());
+        let (key_id, key_attributes) = key_management::get_key_id_and_attributes(
+            &key_triple,
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+        )?;
+        op.validate(key_attributes)?;
+        let alg = utils::convert_aead_algorithm(op.alg)?;
+
+        // The ciphertext is the encrypted plaintext followed by the tag.
+        let buffer_size = op
+            .plaintext
+            .len()
+            .checked_add(utils::psa_aead_tag_length(alg))
+            .ok_or(ResponseStatus::PsaErrorInvalidArgument)?;
+        let mut ciphertext = vec![0u8; buffer_size];
+        let mut ciphertext_size = 0;
+
+        let _guard = self
+            .key_handle_mutex
+            .lock()
+            .expect("Grabbing key handle mutex failed");
+
+        let mut key_handle;
+        let encrypt_status;
+        // Safety:
+        //   * at this point the provider has been instantiated so Mbed Crypto has been initialized
+        //   * self.key_handle_mutex prevents concurrent accesses
+        //   * self.key_slot_semaphore prevents overflowing key slots
+        unsafe {
+            key_handle = KeyHandle::open(key_id)?;
+            encrypt_status = psa_crypto_binding::psa_aead_encrypt(
+                key_handle.raw(),
+                alg,
+                op.nonce.as_ptr(),
+                op.nonce.len() as u64,
+                op.additional_data.as_ptr(),
+                op.additional_data.len() as u64,
+                op.plaintext.as_ptr(),
+                op.plaintext.len() as u64,
+                ciphertext.as_mut_ptr(),
+                buffer_size as u64,
+                &mut ciphertext_size,
+            );
+            key_handle.close()?;
+        }
+
+        if encrypt_status == PSA_SUCCESS {
+            ciphertext.resize(ciphertext_size as usize, 0);
+            Ok(psa_aead_encrypt::Result {
+                ciphertext: ciphertext.into(),
+            })
+        } else {
+            error!("AEAD encrypt status: {}", encrypt_status);
+            Err(utils::convert_status(encrypt_status))
+        }
+    }
+
+    pub(super) fn psa_aead_decrypt_internal(
+        &self,
+        app_name: ApplicationName,
+        op: psa_aead_decrypt::Operation,
+    ) -> Result<psa_aead_decrypt::Result> {
+        info!("Mbed Provider - AEAD Decrypt");
+        let _semaphore_guard = self.key_slot_semaphore.access();
+        let key_triple = KeyTriple::new(app_name, ProviderId::MbedCrypto, op.key_name.clone());
+        let (key_id, key_attributes) = key_management::get_key_id_and_attributes(
+            &key_triple,
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+        )?;
+        op.validate(key_attributes)?;
+        let alg = utils::convert_aead_algorithm(op.alg)?;
+
+        // The ciphertext is the encrypted plaintext followed by the tag.
+        let buffer_size = op
+            .ciphertext
+            .len()
+            .checked_sub(utils::psa_aead_tag_length(alg))
+            .ok_or(ResponseStatus::PsaErrorInvalidArgument)?;
+        let mut plaintext = vec![0u8; buffer_size];
+        let mut plaintext_size = 0;
+
+        let _guard = self
+            .key_handle_mutex
+            .lock()
+            .expect("Grabbing key handle mutex failed");
+
+        let mut key_handle;
+        let decrypt_status;
+        // Safety:
+        //   * at this point the provider has been instantiated so Mbed Crypto has been initialized
+        //   * self.key_handle_mutex prevents concurrent accesses
+        //   * self.key_slot_semaphore prevents overflowing key slots
+        unsafe {
+            key_handle = KeyHandle::open(key_id)?;
+            decrypt_status = psa_crypto_binding::psa_aead_decrypt(
+                key_handle.raw(),
+                alg,
+                op.nonce.as_ptr(),
+                op.nonce.len() as u64,
+                op.additional_data.as_ptr(),
+                op.additional_data.len() as u64,
+                op.ciphertext.as_ptr(),
+                op.ciphertext.len() as u64,
+                plaintext.as_mut_ptr(),
+                buffer_size as u64,
+                &mut plaintext_size,
+            );
+            key_handle.close()?;
+        }
+
+        if decrypt_status == PSA_SUCCESS {
+            plaintext.resize(plaintext_size as usize, 0);
+            Ok(psa_aead_decrypt::Result {
+                plaintext: plaintext.into(),
+            })
+        } else {
+            error!("AEAD decrypt status: {}", decrypt_status);
+            Err(utils::convert_status(decrypt_status))
+        }
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+use super::constants::PSA_SUCCESS;
+use super::utils::{self, KeyHandle};
+use super::{key_management, psa_crypto_binding, MbedProvider};
+use crate::authenticators::ApplicationName;
+use crate::key_info_managers::KeyTriple;
+use log::{error, info};
+use parsec_interface::operations::{psa_asymmetric_decrypt, psa_asymmetric_encrypt};
+use parsec_interface::requests::{ProviderId, Result};
+
+impl MbedProvider {
+    pub(super) fn psa_asymmetric_encrypt_internal(
+        &self,
+        app_name: ApplicationName,
+        op: psa_asymmetric_encrypt::Operation,
+    ) -> Result<psa_asymmetric_encrypt::Result> {
+        info!("Mbed Provider - Asym Encrypt");
+        let _semaphore_guard = self.key_slot_semaphore.access();
+        let key_triple = KeyTriple::new(app_name, ProviderId::MbedCrypto, op.key_name.clone());
+        let (key_id, key_attributes) = key_management::get_key_id_and_attributes(
+            &key_triple,
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+        )?;
+        op.validate(key_attributes)?;
+        let alg = utils::convert_algorithm(&op.alg.into())?;
+        let salt = op.salt.as_ref().map_or(&[][..], |salt| salt.as_slice());
+
+        let _guard = self
+            .key_handle_mutex
+            .lock()
+            .expect("Grabbing key handle mutex failed");
+
+        let mut key_handle;
+        let mut key_attrs;
+        // Safety:
+        //   * at this point the provider has been instantiated so Mbed Crypto has been initialized
+        //   * self.key_handle_mutex prevents concurrent accesses
+        //   * self.key_slot_semaphore prevents overflowing key slots
+        unsafe {
+            key_handle = KeyHandle::open(key_id)?;
+            key_attrs = key_handle.attributes()?;
+        }
+
+        let buffer_size = match utils::psa_asymmetric_encrypt_output_size(key_attrs.as_ref()) {
+            Ok(buffer_size) => buffer_size,
+            Err(e) => {
+                // Safety: same conditions than above.
+                unsafe {
+                    key_attrs.reset();
+                    key_handle.close()?;
+                }
+                return Err(e);
+            }
+        };
+        let mut ciphertext = vec![0u8; buffer_size];
+        let mut ciphertext_size = 0;
+
+        let encrypt_status;
+        // Safety: same conditions than above.
+        unsafe {
+            encrypt_status = psa_crypto_binding::psa_asymmetric_encrypt(
+                key_handle.raw(),
+                alg,
+                op.plaintext.as_ptr(),
+                op.plaintext.len() as u64,
+                salt.as_ptr(),
+                salt.len() as u64,
+                ciphertext.as_mut_ptr(),
+                buffer_size as u64,
+                &mut ciphertext_size,
+            );
+            key_attrs.reset();
+            key_handle.close()?;
+        }
+
+        if encrypt_status == PSA_SUCCESS {
+            ciphertext.resize(ciphertext_size as usize, 0);
+            Ok(psa_asymmetric_encrypt::Result {
+                ciphertext: ciphertext.into(),
+            })
+        } else {
+            error!("Encrypt status: {}", encrypt_status);
+            Err(utils::convert_status(encrypt_status))
+        }
+    }
+
+    pub(super) fn psa_asymmetric_decrypt_internal(
+        &self,
+        app_name: ApplicationName,
+        op: psa_asymmetric_decrypt::Operation,
+    ) -> Result<psa_asymmetric_decrypt::Result> {
+        info!("Mbed Provider - Asym Decrypt");
+        let _semaphore_guard = self.key_slot_semaphore.access();
+        let key_triple = KeyTriple::new(app_name, ProviderId::MbedCrypto, op.key_name.clone());
+        let (key_id, key_attributes) = key_management::get_key_id_and_attributes(
+            &key_triple,
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+        )?;
+        op.validate(key_attributes)?;
+        let alg = utils::convert_algorithm(&op.alg.into())?;
+        let salt = op.salt.as_ref().map_or(&[][..], |salt| salt.as_slice());
+
+        let _guard = self
+            .key_handle_mutex
+            .lock()
+            .expect("Grabbing key handle mutex failed");
+
+        let mut key_handle;
+        let mut key_attrs;
+        // Safety:
+        //   * at this point the provider has been instantiated so Mbed Crypto has been initialized
+        //   * self.key_handle_mutex prevents concurrent accesses
+        //   * self.key_slot_semaphore prevents overflowing key slots
+        unsafe {
+            key_handle = KeyHandle::open(key_id)?;
+            key_attrs = key_handle.attributes()?;
+        }
+
+        // The plaintext is never longer than the ciphertext.
+        let buffer_size = match utils::psa_asymmetric_encrypt_output_size(key_attrs.as_ref()) {
+            Ok(buffer_size) => buffer_size,
+            Err(e) => {
+                // Safety: same conditions than above.
+                unsafe {
+                    key_attrs.reset();
+                    key_handle.close()?;
+                }
+                return Err(e);
+            }
+        };
+        let mut plaintext = vec![0u8; buffer_size];
+        let mut plaintext_size = 0;
+
+        let decrypt_status;
+        // Safety: same conditions than above.
+        unsafe {
+            decrypt_status = psa_crypto_binding::psa_asymmetric_decrypt(
+                key_handle.raw(),
+                alg,
+                op.ciphertext.as_ptr(),
+                op.ciphertext.len() as u64,
+                salt.as_ptr(),
+                salt.len() as u64,
+                plaintext.as_mut_ptr(),
+                buffer_size as u64,
+                &mut plaintext_size,
+            );
+            key_attrs.reset();
+            key_handle.close()?;
+        }
+
+        if decrypt_status == PSA_SUCCESS {
+            plaintext.resize(plaintext_size as usize, 0);
+            Ok(psa_asymmetric_decrypt::Result {
+                plaintext: plaintext.into(),
+            })
+        } else {
+            error!("Decrypt status: {}", decrypt_status);
+            Err(utils::convert_status(decrypt_status))
+        }
+    }
+}
@@ -7,7 +7,7 @@ use crate::authenticators::ApplicationName;
 use crate::key_info_managers::KeyTriple;
 use log::{error, info};
 use parsec_interface::operations::{psa_sign_hash, psa_verify_hash};
-use parsec_interface::requests::{ProviderID, Result};
+use parsec_interface::requests::{ProviderId, Result};
 
 impl MbedProvider {
     pub(super) fn psa_sign_hash_internal(
@@ -20,9 +20,11 @@ impl MbedProvider {
         let key_name = op.key_name;
         let hash = op.hash;
         let alg = op.alg;
-        let key_triple = KeyTriple::new(app_name, ProviderID::MbedCrypto, key_name);
-        let store_handle = self.key_info_store.read().expect("Key store lock poisoned");
-        let key_id = key_management::get_key_id(&key_triple, &*store_handle)?;
+        let key_triple = KeyTriple::new(app_name, ProviderId::MbedCrypto, key_name);
+        let key_id = key_management::get_key_id(
+            &key_triple,
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+        )?;
 
         let _guard = self
             .key_handle_mutex
@@ -62,7 +64,7 @@ impl MbedProvider {
 
         if sign_status == PSA_SUCCESS {
             let mut res = psa_sign_hash::Result {
-                signature: Vec::new(),
+                signature: Vec::new().into(),
             };
             res.signature.resize(signature_size as usize, 0);
             res.signature
@@ -86,9 +88,11 @@ impl MbedProvider {
         let hash = op.hash;
         let alg = op.alg;
         let signature = op.signature;
-        let key_triple = KeyTriple::new(app_name, ProviderID::MbedCrypto, key_name);
-        let store_handle = self.key_info_store.read().expect("Key store lock poisoned");
-        let key_id = key_management::get_key_id(&key_triple, &*store_handle)?;
+        let key_triple = KeyTriple::new(app_name, ProviderId::MbedCrypto, key_name);
+        let key_id = key_management::get_key_id(
+            &key_triple,
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+        )?;
 
         let _guard = self
             .key_handle_mutex
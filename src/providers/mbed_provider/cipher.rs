@@ -0,0 +1,205 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+use super::constants::{PSA_BLOCK_CIPHER_BLOCK_SIZE, PSA_SUCCESS};
+use super::psa_crypto_binding::{self, psa_cipher_operation_t, psa_status_t};
+use super::utils::{self, KeyHandle};
+use super::{key_management, MbedProvider};
+use crate::authenticators::ApplicationName;
+use crate::key_info_managers::KeyTriple;
+use log::{error, info};
+use parsec_interface::operations::{psa_cipher_decrypt, psa_cipher_encrypt};
+use parsec_interface::requests::{ProviderId, ResponseStatus, Result};
+
+/// Feeds the whole input to a cipher operation which has been set up and whose IV has been set,
+/// then finishes it. Returns the status of the first step which failed, or of the last one, and
+/// the length of the output written.
+///
+/// Mbed Crypto 2.0.0 does not implement the single-part cipher functions, the multi-part ones are
+/// used instead.
+///
+/// # Safety
+///
+/// The operation must have been set up with a key handle which is still open.
+unsafe fn cipher_update_finish(
+    operation: &mut psa_cipher_operation_t,
+    input: &[u8],
+    output: &mut [u8],
+) -> (psa_status_t, usize) {
+    let mut update_length = 0;
+    let status = psa_crypto_binding::psa_cipher_update(
+        operation,
+        input.as_ptr(),
+        input.len() as u64,
+        output.as_mut_ptr(),
+        output.len() as u64,
+        &mut update_length,
+    );
+    if status != PSA_SUCCESS {
+        return (status, 0);
+    }
+
+    let update_length = update_length as usize;
+    let mut finish_length = 0;
+    let status = psa_crypto_binding::psa_cipher_finish(
+        operation,
+        output[update_length..].as_mut_ptr(),
+        (output.len() - update_length) as u64,
+        &mut finish_length,
+    );
+    (status, update_length + finish_length as usize)
+}
+
+impl MbedProvider {
+    pub(super) fn psa_cipher_encrypt_internal(
+        &self,
+        app_name: ApplicationName,
+        op: psa_cipher_encrypt::Operation,
+    ) -> Result<psa_cipher_encrypt::Result> {
+        info!("Mbed Provider - Cipher Encrypt");
+        let _semaphore_guard = self.key_slot_semaphore.access();
+        let key_triple = KeyTriple::new(app_name, ProviderId::MbedCrypto, op.key_name.clone());
+        let (key_id, key_attributes) = key_management::get_key_id_and_attributes(
+            &key_triple,
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+        )?;
+        op.validate(key_attributes)?;
+        let alg = utils::convert_cipher_algorithm(op.alg)?;
+
+        // The ciphertext is the generated IV followed by the encrypted plaintext, which is padded
+        // to at most one more block.
+        let iv_length = PSA_BLOCK_CIPHER_BLOCK_SIZE;
+        let buffer_size = op
+            .plaintext
+            .len()
+            .checked_add(iv_length + PSA_BLOCK_CIPHER_BLOCK_SIZE)
+            .ok_or(ResponseStatus::PsaErrorInvalidArgument)?;
+        let mut ciphertext = vec![0u8; buffer_size];
+        let mut output_length = 0;
+
+        let _guard = self
+            .key_handle_mutex
+            .lock()
+            .expect("Grabbing key handle mutex failed");
+
+        let mut key_handle;
+        let mut encrypt_status;
+        // Safety:
+        //   * at this point the provider has been instantiated so Mbed Crypto has been initialized
+        //   * self.key_handle_mutex prevents concurrent accesses
+        //   * self.key_slot_semaphore prevents overflowing key slots
+        //   * an all-zero cipher operation is the PSA_CIPHER_OPERATION_INIT initial value and
+        //     aborting is possible in any state of the operation
+        unsafe {
+            let mut operation: psa_cipher_operation_t = std::mem::zeroed();
+            key_handle = KeyHandle::open(key_id)?;
+            encrypt_status =
+                psa_crypto_binding::psa_cipher_encrypt_setup(&mut operation, key_handle.raw(), alg);
+            if encrypt_status == PSA_SUCCESS {
+                let mut generated_iv_length = 0;
+                encrypt_status = psa_crypto_binding::psa_cipher_generate_iv(
+                    &mut operation,
+                    ciphertext.as_mut_ptr(),
+                    iv_length as u64,
+                    &mut generated_iv_length,
+                );
+            }
+            if encrypt_status == PSA_SUCCESS {
+                let (status, length) = cipher_update_finish(
+                    &mut operation,
+                    &op.plaintext,
+                    &mut ciphertext[iv_length..],
+                );
+                encrypt_status = status;
+                output_length = iv_length + length;
+            }
+            if encrypt_status != PSA_SUCCESS {
+                let _ = psa_crypto_binding::psa_cipher_abort(&mut operation);
+            }
+            key_handle.close()?;
+        }
+
+        if encrypt_status == PSA_SUCCESS {
+            ciphertext.resize(output_length, 0);
+            Ok(psa_cipher_encrypt::Result {
+                ciphertext: ciphertext.into(),
+            })
+        } else {
+            error!("Cipher encrypt status: {}", encrypt_status);
+            Err(utils::convert_status(encrypt_status))
+        }
+    }
+
+    pub(super) fn psa_cipher_decrypt_internal(
+        &self,
+        app_name: ApplicationName,
+        op: psa_cipher_decrypt::Operation,
+    ) -> Result<psa_cipher_decrypt::Result> {
+        info!("Mbed Provider - Cipher Decrypt");
+        let _semaphore_guard = self.key_slot_semaphore.access();
+        let key_triple = KeyTriple::new(app_name, ProviderId::MbedCrypto, op.key_name.clone());
+        let (key_id, key_attributes) = key_management::get_key_id_and_attributes(
+            &key_triple,
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+        )?;
+        op.validate(key_attributes)?;
+        let alg = utils::convert_cipher_algorithm(op.alg)?;
+
+        // The ciphertext is the IV followed by the encrypted message.
+        let iv_length = PSA_BLOCK_CIPHER_BLOCK_SIZE;
+        if op.ciphertext.len() < iv_length {
+            error!("The ciphertext is shorter than the IV.");
+            return Err(ResponseStatus::PsaErrorInvalidArgument);
+        }
+        let (iv, encrypted_message) = op.ciphertext.split_at(iv_length);
+        let buffer_size = encrypted_message.len() + PSA_BLOCK_CIPHER_BLOCK_SIZE;
+        let mut plaintext = vec![0u8; buffer_size];
+        let mut plaintext_length = 0;
+
+        let _guard = self
+            .key_handle_mutex
+            .lock()
+            .expect("Grabbing key handle mutex failed");
+
+        let mut key_handle;
+        let mut decrypt_status;
+        // Safety:
+        //   * at this point the provider has been instantiated so Mbed Crypto has been initialized
+        //   * self.key_handle_mutex prevents concurrent accesses
+        //   * self.key_slot_semaphore prevents overflowing key slots
+        //   * an all-zero cipher operation is the PSA_CIPHER_OPERATION_INIT initial value and
+        //     aborting is possible in any state of the operation
+        unsafe {
+            let mut operation: psa_cipher_operation_t = std::mem::zeroed();
+            key_handle = KeyHandle::open(key_id)?;
+            decrypt_status =
+                psa_crypto_binding::psa_cipher_decrypt_setup(&mut operation, key_handle.raw(), alg);
+            if decrypt_status == PSA_SUCCESS {
+                decrypt_status = psa_crypto_binding::psa_cipher_set_iv(
+                    &mut operation,
+                    iv.as_ptr(),
+                    iv.len() as u64,
+                );
+            }
+            if decrypt_status == PSA_SUCCESS {
+                let (status, length) =
+                    cipher_update_finish(&mut operation, encrypted_message, &mut plaintext);
+                decrypt_status = status;
+                plaintext_length = length;
+            }
+            if decrypt_status != PSA_SUCCESS {
+                let _ = psa_crypto_binding::psa_cipher_abort(&mut operation);
+            }
+            key_handle.close()?;
+        }
+
+        if decrypt_status == PSA_SUCCESS {
+            plaintext.resize(plaintext_length, 0);
+            Ok(psa_cipher_decrypt::Result {
+                plaintext: plaintext.into(),
+            })
+        } else {
+            error!("Cipher decrypt status: {}", decrypt_status);
+            Err(utils::convert_status(decrypt_status))
+        }
+    }
+}
@@ -30,6 +30,9 @@ pub const PSA_MAX_KEY_BITS: usize = 0xfff8;
 pub const PSA_KEY_BITS_TOO_LARGE: psa_key_bits_t = 0xffff;
 pub const PSA_MAX_PERSISTENT_KEY_IDENTIFIER: psa_key_id_t = 0x3fff_ffff;
 pub const PSA_KEY_SLOT_COUNT: isize = 32;
+pub const PSA_HASH_MAX_SIZE: usize = 64;
+// Block size of AES, the only block cipher supported by the provider.
+pub const PSA_BLOCK_CIPHER_BLOCK_SIZE: usize = 16;
 pub const EMPTY_KEY_HANDLE: psa_key_handle_t = 0;
 pub const PSA_KEY_TYPE_NONE: psa_key_type_t = 0x0000_0000;
 pub const PSA_KEY_TYPE_VENDOR_FLAG: psa_key_type_t = 0x8000_0000;
@@ -115,6 +118,7 @@ pub const PSA_ALG_ANY_HASH: psa_algorithm_t = 0x0100_00ff;
 pub const PSA_ALG_MAC_SUBCATEGORY_MASK: psa_algorithm_t = 0x00c0_0000;
 pub const PSA_ALG_HMAC_BASE: psa_algorithm_t = 0x0280_0000;
 pub const PSA_ALG_MAC_TRUNCATION_MASK: psa_algorithm_t = 0x0000_3f00;
+pub const PSA_MAC_TRUNCATION_OFFSET: psa_algorithm_t = 8;
 pub const PSA_ALG_CIPHER_MAC_BASE: psa_algorithm_t = 0x02c0_0000;
 pub const PSA_ALG_CBC_MAC: psa_algorithm_t = 0x02c0_0001;
 pub const PSA_ALG_CMAC: psa_algorithm_t = 0x02c0_0002;
@@ -131,6 +135,7 @@ pub const PSA_ALG_CBC_PKCS7: psa_algorithm_t = 0x0460_0101;
 pub const PSA_ALG_CCM: psa_algorithm_t = 0x0600_1001;
 pub const PSA_ALG_GCM: psa_algorithm_t = 0x0600_1002;
 pub const PSA_ALG_AEAD_TAG_LENGTH_MASK: psa_algorithm_t = 0x0000_3f00;
+pub const PSA_AEAD_TAG_LENGTH_OFFSET: psa_algorithm_t = 8;
 pub const PSA_ALG_RSA_PKCS1V15_SIGN_BASE: psa_algorithm_t = 0x1002_0000;
 pub const PSA_ALG_RSA_PSS_BASE: psa_algorithm_t = 0x1003_0000;
 pub const PSA_ALG_DSA_BASE: psa_algorithm_t = 0x1004_0000;
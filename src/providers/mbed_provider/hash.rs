@@ -0,0 +1,98 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+use super::constants::{PSA_HASH_MAX_SIZE, PSA_SUCCESS};
+use super::psa_crypto_binding::{self, psa_hash_operation_t};
+use super::{utils, MbedProvider};
+use log::{error, info};
+use parsec_interface::operations::psa_algorithm::{Hash, SignHash};
+use parsec_interface::operations::{psa_hash_compare, psa_hash_compute};
+use parsec_interface::requests::Result;
+
+/// Sets up a hash operation and feeds it the whole input. The operation is aborted if one of the
+/// steps fails.
+///
+/// Mbed Crypto 2.0.0 does not implement the single-part hash functions, the multi-part ones are
+/// used instead.
+fn hash_setup_update(alg: Hash, input: &[u8]) -> Result<psa_hash_operation_t> {
+    let alg = utils::convert_hash_algorithm(SignHash::Specific(alg))?;
+    // Safety: an all-zero hash operation is the PSA_HASH_OPERATION_INIT initial value.
+    let mut operation: psa_hash_operation_t = unsafe { std::mem::zeroed() };
+
+    // Safety: the operation is valid and the input buffer outlives the calls. Hash operations do
+    // not use key slots.
+    let mut status = unsafe { psa_crypto_binding::psa_hash_setup(&mut operation, alg) };
+    if status == PSA_SUCCESS {
+        status = unsafe {
+            psa_crypto_binding::psa_hash_update(&mut operation, input.as_ptr(), input.len() as u64)
+        };
+    }
+
+    if status == PSA_SUCCESS {
+        Ok(operation)
+    } else {
+        error!("Hash setup or update status: {}", status);
+        // Safety: aborting is possible in any state of the operation.
+        let _ = unsafe { psa_crypto_binding::psa_hash_abort(&mut operation) };
+        Err(utils::convert_status(status))
+    }
+}
+
+impl MbedProvider {
+    pub(super) fn psa_hash_compute_internal(
+        &self,
+        op: psa_hash_compute::Operation,
+    ) -> Result<psa_hash_compute::Result> {
+        info!("Mbed Provider - Hash Compute");
+        let mut operation = hash_setup_update(op.alg, &op.input)?;
+
+        let mut hash = vec![0u8; PSA_HASH_MAX_SIZE];
+        let mut hash_length = 0;
+        // Safety: the operation has been set up and the hash buffer outlives the call. The
+        // operation is terminated by the call, whatever its result.
+        let finish_status = unsafe {
+            psa_crypto_binding::psa_hash_finish(
+                &mut operation,
+                hash.as_mut_ptr(),
+                PSA_HASH_MAX_SIZE as u64,
+                &mut hash_length,
+            )
+        };
+
+        if finish_status == PSA_SUCCESS {
+            hash.resize(hash_length as usize, 0);
+            Ok(psa_hash_compute::Result { hash: hash.into() })
+        } else {
+            error!("Hash finish status: {}", finish_status);
+            // Safety: aborting is possible in any state of the operation.
+            let _ = unsafe { psa_crypto_binding::psa_hash_abort(&mut operation) };
+            Err(utils::convert_status(finish_status))
+        }
+    }
+
+    pub(super) fn psa_hash_compare_internal(
+        &self,
+        op: psa_hash_compare::Operation,
+    ) -> Result<psa_hash_compare::Result> {
+        info!("Mbed Provider - Hash Compare");
+        let mut operation = hash_setup_update(op.alg, &op.input)?;
+
+        // Safety: the operation has been set up and the hash buffer outlives the call.
+        let verify_status = unsafe {
+            psa_crypto_binding::psa_hash_verify(
+                &mut operation,
+                op.hash.as_ptr(),
+                op.hash.len() as u64,
+            )
+        };
+
+        if verify_status == PSA_SUCCESS {
+            Ok(psa_hash_compare::Result {})
+        } else {
+            // The hashes do not match if the status is PSA_ERROR_INVALID_SIGNATURE.
+            error!("Hash verify status: {}", verify_status);
+            // Safety: aborting is possible in any state of the operation.
+            let _ = unsafe { psa_crypto_binding::psa_hash_abort(&mut operation) };
+            Err(utils::convert_status(verify_status))
+        }
+    }
+}
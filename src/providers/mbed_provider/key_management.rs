@@ -6,13 +6,15 @@ use super::utils::{self, KeyHandle};
 use super::{LocalIdStore, MbedProvider};
 use crate::authenticators::ApplicationName;
 use crate::key_info_managers;
-use crate::key_info_managers::{KeyInfo, KeyTriple, ManageKeyInfo};
-use log::{error, info, warn};
+use crate::key_info_managers::{KeyInfo, KeyTriple, KeyTripleReservation, ManageKeyInfo};
+use log::{error, info};
 use parsec_interface::operations::psa_key_attributes::Attributes;
 use parsec_interface::operations::{
     psa_destroy_key, psa_export_public_key, psa_generate_key, psa_import_key,
 };
-use parsec_interface::requests::{ProviderID, ResponseStatus, Result};
+use parsec_interface::requests::{ProviderId, ResponseStatus, Result};
+use parsec_interface::secrecy::ExposeSecret;
+use std::sync::RwLock;
 
 /// Gets a PSA Key ID from the Key Info Manager.
 /// Wrapper around the get method of the Key Info Manager to convert the key ID to the psa_key_id_t
@@ -21,12 +23,20 @@ pub fn get_key_id(
     key_triple: &KeyTriple,
     store_handle: &dyn ManageKeyInfo,
 ) -> Result<psa_key_id_t> {
+    Ok(get_key_id_and_attributes(key_triple, store_handle)?.0)
+}
+
+/// Gets a PSA Key ID and the Parsec attributes of a key from the Key Info Manager.
+pub fn get_key_id_and_attributes(
+    key_triple: &KeyTriple,
+    store_handle: &dyn ManageKeyInfo,
+) -> Result<(psa_key_id_t, Attributes)> {
     match store_handle.get(key_triple) {
         Ok(Some(key_info)) => {
             if key_info.id.len() == 4 {
                 let mut dst = [0; 4];
                 dst.copy_from_slice(&key_info.id);
-                Ok(u32::from_ne_bytes(dst))
+                Ok((u32::from_ne_bytes(dst), key_info.attributes))
             } else {
                 error!("Stored Key ID is not valid.");
                 Err(ResponseStatus::KeyInfoManagerError)
@@ -37,13 +47,9 @@ pub fn get_key_id(
     }
 }
 
-/// Creates a new PSA Key ID and stores it in the Key Info Manager.
-fn create_key_id(
-    key_triple: KeyTriple,
-    key_attributes: Attributes,
-    store_handle: &mut dyn ManageKeyInfo,
-    local_ids_handle: &mut LocalIdStore,
-) -> Result<psa_key_id_t> {
+/// Picks a PSA Key ID which is not used by the provider and marks it as used.
+fn create_key_id(local_ids: &RwLock<LocalIdStore>) -> psa_key_id_t {
+    let mut local_ids_handle = local_ids.write().expect("Local ID lock poisoned");
     let mut key_id = rand::random::<psa_key_id_t>();
     while local_ids_handle.contains(&key_id)
         || key_id == 0
@@ -51,42 +57,80 @@ fn create_key_id(
     {
         key_id = rand::random::<psa_key_id_t>();
     }
+    let _ = local_ids_handle.insert(key_id);
+
+    key_id
+}
+
+/// Marks a PSA Key ID as unused, when the key could not be created.
+fn release_key_id(key_id: psa_key_id_t, local_ids: &RwLock<LocalIdStore>) {
+    let _ = local_ids
+        .write()
+        .expect("Local ID lock poisoned")
+        .remove(&key_id);
+}
+
+/// Destroys a key which was created but whose handle could not be closed, and marks its key ID as
+/// unused. If the key can not be destroyed, it is left in the Mbed Crypto storage and its key ID
+/// stays used.
+///
+/// # Safety
+///
+/// Calling this function is only safe under the same conditions as `KeyHandle::close`.
+unsafe fn destroy_created_key(
+    key_handle: &KeyHandle,
+    key_id: psa_key_id_t,
+    local_ids: &RwLock<LocalIdStore>,
+) {
+    let destroy_key_status = psa_crypto_binding::psa_destroy_key(key_handle.raw());
+    if destroy_key_status == PSA_SUCCESS {
+        release_key_id(key_id, local_ids);
+    } else {
+        error!(
+            "The key created could not be destroyed (status: {}), it can be removed with the reconcile command.",
+            destroy_key_status
+        );
+    }
+}
+
+/// Stores the mapping of a key created by Mbed Crypto. If that fails, the key is left in the
+/// Mbed Crypto storage without a mapping and its key ID stays used.
+fn commit_key_id(
+    reservation: KeyTripleReservation,
+    key_id: psa_key_id_t,
+    key_attributes: Attributes,
+) -> Result<()> {
+    let key_triple = reservation.key_triple().clone();
     let key_info = KeyInfo {
         id: key_id.to_ne_bytes().to_vec(),
         attributes: key_attributes,
     };
-    match store_handle.insert(key_triple.clone(), key_info) {
-        Ok(insert_option) => {
-            if insert_option.is_some() {
-                warn!("Overwriting Key triple mapping ({})", key_triple);
-            }
-            let _ = local_ids_handle.insert(key_id);
-
-            Ok(key_id)
-        }
-        Err(string) => Err(key_info_managers::to_response_status(string)),
+    if let Err(e) = reservation.commit(key_info) {
+        error!(
+            "The mapping of the key created could not be stored ({}), the key can be removed with the reconcile command.",
+            key_triple
+        );
+        return Err(e);
     }
+
+    Ok(())
 }
 
+/// Removes the mapping of a destroyed key and marks its key ID as unused.
 fn remove_key_id(
     key_triple: &KeyTriple,
     key_id: psa_key_id_t,
-    store_handle: &mut dyn ManageKeyInfo,
-    local_ids_handle: &mut LocalIdStore,
+    key_info_store: &RwLock<dyn ManageKeyInfo + Send + Sync>,
+    local_ids: &RwLock<LocalIdStore>,
 ) -> Result<()> {
-    match store_handle.remove(key_triple) {
-        Ok(_) => {
-            let _ = local_ids_handle.remove(&key_id);
-            Ok(())
-        }
-        Err(string) => Err(key_info_managers::to_response_status(string)),
-    }
-}
+    let _ = key_info_store
+        .write()
+        .expect("Key store lock poisoned")
+        .remove(key_triple)
+        .map_err(key_info_managers::to_response_status)?;
+    release_key_id(key_id, local_ids);
 
-pub fn key_info_exists(key_triple: &KeyTriple, store_handle: &dyn ManageKeyInfo) -> Result<bool> {
-    store_handle
-        .exists(key_triple)
-        .or_else(|e| Err(key_info_managers::to_response_status(e)))
+    Ok(())
 }
 
 impl MbedProvider {
@@ -99,32 +143,19 @@ impl MbedProvider {
         let _semaphore_guard = self.key_slot_semaphore.access();
         let key_name = op.key_name;
         let key_attributes = op.attributes;
-        let key_triple = KeyTriple::new(app_name, ProviderID::MbedCrypto, key_name);
-        let mut store_handle = self
-            .key_info_store
-            .write()
-            .expect("Key store lock poisoned");
-        let mut local_ids_handle = self.local_ids.write().expect("Local ID lock poisoned");
-        if key_info_exists(&key_triple, &*store_handle)? {
-            return Err(ResponseStatus::PsaErrorAlreadyExists);
-        }
-        let key_id = create_key_id(
-            key_triple.clone(),
-            key_attributes,
-            &mut *store_handle,
-            &mut local_ids_handle,
-        )?;
+        let key_triple = KeyTriple::new(app_name, ProviderId::MbedCrypto, key_name);
+        // The key info manager is not locked while the key is created, which can take a while.
+        let reservation = KeyTripleReservation::new(&self.key_info_store, key_triple)?;
+        let key_id = create_key_id(&self.local_ids);
 
-        let key_attrs = utils::convert_key_attributes(&key_attributes, key_id).or_else(|e| {
-            remove_key_id(
-                &key_triple,
-                key_id,
-                &mut *store_handle,
-                &mut local_ids_handle,
-            )?;
-            error!("Failed converting key attributes.");
-            Err(e)
-        })?;
+        let key_attrs = match utils::convert_key_attributes(&key_attributes, key_id) {
+            Ok(key_attrs) => key_attrs,
+            Err(e) => {
+                release_key_id(key_id, &self.local_ids);
+                error!("Failed converting key attributes.");
+                return Err(e);
+            }
+        };
 
         let _guard = self
             .key_handle_mutex
@@ -135,21 +166,22 @@ impl MbedProvider {
         //   * at this point the provider has been instantiated so Mbed Crypto has been initialized
         //   * self.key_handle_mutex prevents concurrent accesses
         //   * self.key_slot_semaphore prevents overflowing key slots
-        let mut key_handle = unsafe { KeyHandle::generate(&key_attrs) }.or_else(|e| {
-            remove_key_id(
-                &key_triple,
-                key_id,
-                &mut *store_handle,
-                &mut local_ids_handle,
-            )?;
-            error!("Generate key status: {}", e);
-            Err(e)
-        })?;
+        let mut key_handle = match unsafe { KeyHandle::generate(&key_attrs) } {
+            Ok(key_handle) => key_handle,
+            Err(e) => {
+                release_key_id(key_id, &self.local_ids);
+                error!("Generate key status: {}", e);
+                return Err(e);
+            }
+        };
 
         // Safety: same conditions than above.
-        unsafe {
-            key_handle.close()?;
+        if let Err(e) = unsafe { key_handle.close() } {
+            // Safety: same conditions than above.
+            unsafe { destroy_created_key(&key_handle, key_id, &self.local_ids) };
+            return Err(e);
         }
+        commit_key_id(reservation, key_id, key_attributes)?;
 
         Ok(psa_generate_key::Result {})
     }
@@ -164,32 +196,19 @@ impl MbedProvider {
         let key_name = op.key_name;
         let key_attributes = op.attributes;
         let key_data = op.data;
-        let key_triple = KeyTriple::new(app_name, ProviderID::MbedCrypto, key_name);
-        let mut store_handle = self
-            .key_info_store
-            .write()
-            .expect("Key store lock poisoned");
-        let mut local_ids_handle = self.local_ids.write().expect("Local ID lock poisoned");
-        if key_info_exists(&key_triple, &*store_handle)? {
-            return Err(ResponseStatus::PsaErrorAlreadyExists);
-        }
-        let key_id = create_key_id(
-            key_triple.clone(),
-            key_attributes,
-            &mut *store_handle,
-            &mut local_ids_handle,
-        )?;
+        let key_triple = KeyTriple::new(app_name, ProviderId::MbedCrypto, key_name);
+        // The key info manager is not locked while the key is created, which can take a while.
+        let reservation = KeyTripleReservation::new(&self.key_info_store, key_triple)?;
+        let key_id = create_key_id(&self.local_ids);
 
-        let key_attrs = utils::convert_key_attributes(&key_attributes, key_id).or_else(|e| {
-            remove_key_id(
-                &key_triple,
-                key_id,
-                &mut *store_handle,
-                &mut local_ids_handle,
-            )?;
-            error!("Failed converting key attributes.");
-            Err(e)
-        })?;
+        let key_attrs = match utils::convert_key_attributes(&key_attributes, key_id) {
+            Ok(key_attrs) => key_attrs,
+            Err(e) => {
+                release_key_id(key_id, &self.local_ids);
+                error!("Failed converting key attributes.");
+                return Err(e);
+            }
+        };
 
         let _guard = self
             .key_handle_mutex
@@ -200,21 +219,23 @@ impl MbedProvider {
         //   * at this point the provider has been instantiated so Mbed Crypto has been initialized
         //   * self.key_handle_mutex prevents concurrent accesses
         //   * self.key_slot_semaphore prevents overflowing key slots
-        let mut key_handle = unsafe { KeyHandle::import(&key_attrs, key_data) }.or_else(|e| {
-            remove_key_id(
-                &key_triple,
-                key_id,
-                &mut *store_handle,
-                &mut local_ids_handle,
-            )?;
-            error!("Import key status: {}", e);
-            Err(e)
-        })?;
+        let mut key_handle =
+            match unsafe { KeyHandle::import(&key_attrs, key_data.expose_secret()) } {
+                Ok(key_handle) => key_handle,
+                Err(e) => {
+                    release_key_id(key_id, &self.local_ids);
+                    error!("Import key status: {}", e);
+                    return Err(e);
+                }
+            };
 
         // Safety: same conditions than above.
-        unsafe {
-            key_handle.close()?;
+        if let Err(e) = unsafe { key_handle.close() } {
+            // Safety: same conditions than above.
+            unsafe { destroy_created_key(&key_handle, key_id, &self.local_ids) };
+            return Err(e);
         }
+        commit_key_id(reservation, key_id, key_attributes)?;
 
         Ok(psa_import_key::Result {})
     }
@@ -227,9 +248,11 @@ impl MbedProvider {
         info!("Mbed Provider - Export Public Key");
         let _semaphore_guard = self.key_slot_semaphore.access();
         let key_name = op.key_name;
-        let key_triple = KeyTriple::new(app_name, ProviderID::MbedCrypto, key_name);
-        let store_handle = self.key_info_store.read().expect("Key store lock poisoned");
-        let key_id = get_key_id(&key_triple, &*store_handle)?;
+        let key_triple = KeyTriple::new(app_name, ProviderId::MbedCrypto, key_name);
+        let key_id = get_key_id(
+            &key_triple,
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+        )?;
 
         let _guard = self
             .key_handle_mutex
@@ -271,7 +294,9 @@ impl MbedProvider {
         }
 
         buffer.resize(actual_size as usize, 0);
-        Ok(psa_export_public_key::Result { data: buffer })
+        Ok(psa_export_public_key::Result {
+            data: buffer.into(),
+        })
     }
 
     pub(super) fn psa_destroy_key_internal(
@@ -282,13 +307,11 @@ impl MbedProvider {
         info!("Mbed Provider - Destroy Key");
         let _semaphore_guard = self.key_slot_semaphore.access();
         let key_name = op.key_name;
-        let key_triple = KeyTriple::new(app_name, ProviderID::MbedCrypto, key_name);
-        let mut store_handle = self
-            .key_info_store
-            .write()
-            .expect("Key store lock poisoned");
-        let mut local_ids_handle = self.local_ids.write().expect("Local ID lock poisoned");
-        let key_id = get_key_id(&key_triple, &*store_handle)?;
+        let key_triple = KeyTriple::new(app_name, ProviderId::MbedCrypto, key_name);
+        let key_id = get_key_id(
+            &key_triple,
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+        )?;
 
         let _guard = self
             .key_handle_mutex
@@ -308,12 +331,7 @@ impl MbedProvider {
         }
 
         if destroy_key_status == PSA_SUCCESS {
-            remove_key_id(
-                &key_triple,
-                key_id,
-                &mut *store_handle,
-                &mut local_ids_handle,
-            )?;
+            remove_key_id(&key_triple, key_id, &self.key_info_store, &self.local_ids)?;
             Ok(psa_destroy_key::Result {})
         } else {
             error!("Destroy key status: {}", destroy_key_status);
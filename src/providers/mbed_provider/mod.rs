@@ -1,17 +1,20 @@
 // Copyright 2019 Contributors to the Parsec project.
 // SPDX-License-Identifier: Apache-2.0
+use super::reconciliation::{ReconciliationMode, ReconciliationReport};
 use super::Provide;
 use crate::authenticators::ApplicationName;
-use crate::key_info_managers::{KeyTriple, ManageKeyInfo};
+use crate::key_info_managers::ManageKeyInfo;
 use constants::PSA_SUCCESS;
 use derivative::Derivative;
-use log::error;
+use log::{error, warn};
 use parsec_interface::operations::list_providers::ProviderInfo;
 use parsec_interface::operations::{
-    psa_destroy_key, psa_export_public_key, psa_generate_key, psa_import_key, psa_sign_hash,
+    psa_aead_decrypt, psa_aead_encrypt, psa_asymmetric_decrypt, psa_asymmetric_encrypt,
+    psa_cipher_decrypt, psa_cipher_encrypt, psa_destroy_key, psa_export_public_key,
+    psa_generate_key, psa_hash_compare, psa_hash_compute, psa_import_key, psa_sign_hash,
     psa_verify_hash,
 };
-use parsec_interface::requests::{Opcode, ProviderID, ResponseStatus, Result};
+use parsec_interface::requests::{Opcode, ProviderId, ResponseStatus, Result};
 use psa_crypto_binding::psa_key_id_t;
 use std::collections::HashSet;
 use std::io::{Error, ErrorKind};
@@ -32,21 +35,37 @@ mod psa_crypto_binding {
     include!(concat!(env!("OUT_DIR"), "/psa_crypto_bindings.rs"));
 }
 
+mod aead;
+mod asym_encryption;
 mod asym_sign;
+mod cipher;
 #[allow(dead_code)]
 mod constants;
+mod hash;
 mod key_management;
+mod reconciliation;
 mod utils;
 
 type LocalIdStore = HashSet<psa_key_id_t>;
 
-const SUPPORTED_OPCODES: [Opcode; 6] = [
+// TODO: add the MAC compute and verify operations, with `psa_mac_sign_setup` and
+// `psa_mac_verify_setup`, once parsec-interface defines them. Until then HMAC keys and MAC
+// policies can be created but not used.
+const SUPPORTED_OPCODES: [Opcode; 14] = [
     Opcode::PsaGenerateKey,
     Opcode::PsaDestroyKey,
     Opcode::PsaSignHash,
     Opcode::PsaVerifyHash,
     Opcode::PsaImportKey,
     Opcode::PsaExportPublicKey,
+    Opcode::PsaAsymmetricEncrypt,
+    Opcode::PsaAsymmetricDecrypt,
+    Opcode::PsaAeadEncrypt,
+    Opcode::PsaAeadDecrypt,
+    Opcode::PsaCipherEncrypt,
+    Opcode::PsaCipherDecrypt,
+    Opcode::PsaHashCompute,
+    Opcode::PsaHashCompare,
 ];
 
 #[derive(Derivative)]
@@ -74,8 +93,9 @@ pub struct MbedProvider {
 
 impl MbedProvider {
     /// Creates and initialise a new instance of MbedProvider.
-    /// Checks if there are not more keys stored in the Key Info Manager than in the MbedProvider and
-    /// if there, delete them. Adds Key IDs currently in use in the local IDs store.
+    /// Adds Key IDs currently in use in the local IDs store. Mappings pointing to keys that are not
+    /// in Mbed Crypto are only reported: they are deleted by an explicit reconciliation in the
+    /// `Delete` mode. Keys stored in Mbed Crypto without a mapping are only found by `reconcile`.
     /// Returns `None` if the initialisation failed.
     fn new(key_info_store: Arc<RwLock<dyn ManageKeyInfo + Send + Sync>>) -> Option<MbedProvider> {
         // Safety: this function should be called before any of the other Mbed Crypto functions
@@ -93,26 +113,24 @@ impl MbedProvider {
         {
             // The local scope allows to drop store_handle and local_ids_handle in order to return
             // the mbed_provider.
-            let mut store_handle = mbed_provider
+            let store_handle = mbed_provider
                 .key_info_store
-                .write()
+                .read()
                 .expect("Key store lock poisoned");
             let mut local_ids_handle = mbed_provider
                 .local_ids
                 .write()
                 .expect("Local ID lock poisoned");
-            let mut to_remove: Vec<KeyTriple> = Vec::new();
             // Go through all MbedProvider key triple to key info mappings and check if they are still
             // present.
-            // Delete those who are not present and add to the local_store the ones present.
-            match store_handle.get_all(ProviderID::MbedCrypto) {
+            // Report those who are not present and add to the local_store the ones present.
+            match store_handle.get_all(ProviderId::MbedCrypto) {
                 Ok(key_triples) => {
-                    for key_triple in key_triples.iter().cloned() {
+                    for key_triple in key_triples.iter() {
                         let key_id = match key_management::get_key_id(key_triple, &*store_handle) {
                             Ok(key_id) => key_id,
                             Err(response_status) => {
                                 error!("Error getting the Key ID for triple:\n{}\n(error: {}), continuing...", key_triple, response_status);
-                                to_remove.push(key_triple.clone());
                                 continue;
                             }
                         };
@@ -125,7 +143,7 @@ impl MbedProvider {
                                 let _ = local_ids_handle.insert(key_id);
                             }
                             Err(ResponseStatus::PsaErrorDoesNotExist) => {
-                                to_remove.push(key_triple.clone())
+                                warn!("Key {} not found in Mbed Crypto, the mapping can be deleted with \"parsec reconcile --delete\".", key_triple);
                             }
                             Err(e) => {
                                 error!("Error {} when opening a persistent Mbed Crypto key.", e);
@@ -139,12 +157,6 @@ impl MbedProvider {
                     return None;
                 }
             };
-            for key_triple in to_remove.iter() {
-                if let Err(string) = store_handle.remove(key_triple) {
-                    error!("Key Info Manager error: {}", string);
-                    return None;
-                }
-            }
         }
 
         Some(mbed_provider)
@@ -161,7 +173,7 @@ impl Provide for MbedProvider {
             version_maj: 0,
             version_min: 1,
             version_rev: 0,
-            id: ProviderID::MbedCrypto,
+            id: ProviderId::MbedCrypto,
         }, SUPPORTED_OPCODES.iter().copied().collect()))
     }
 
@@ -212,6 +224,72 @@ impl Provide for MbedProvider {
     ) -> Result<psa_verify_hash::Result> {
         self.psa_verify_hash_internal(app_name, op)
     }
+
+    fn psa_asymmetric_encrypt(
+        &self,
+        app_name: ApplicationName,
+        op: psa_asymmetric_encrypt::Operation,
+    ) -> Result<psa_asymmetric_encrypt::Result> {
+        self.psa_asymmetric_encrypt_internal(app_name, op)
+    }
+
+    fn psa_asymmetric_decrypt(
+        &self,
+        app_name: ApplicationName,
+        op: psa_asymmetric_decrypt::Operation,
+    ) -> Result<psa_asymmetric_decrypt::Result> {
+        self.psa_asymmetric_decrypt_internal(app_name, op)
+    }
+
+    fn psa_aead_encrypt(
+        &self,
+        app_name: ApplicationName,
+        op: psa_aead_encrypt::Operation,
+    ) -> Result<psa_aead_encrypt::Result> {
+        self.psa_aead_encrypt_internal(app_name, op)
+    }
+
+    fn psa_aead_decrypt(
+        &self,
+        app_name: ApplicationName,
+        op: psa_aead_decrypt::Operation,
+    ) -> Result<psa_aead_decrypt::Result> {
+        self.psa_aead_decrypt_internal(app_name, op)
+    }
+
+    fn psa_cipher_encrypt(
+        &self,
+        app_name: ApplicationName,
+        op: psa_cipher_encrypt::Operation,
+    ) -> Result<psa_cipher_encrypt::Result> {
+        self.psa_cipher_encrypt_internal(app_name, op)
+    }
+
+    fn psa_cipher_decrypt(
+        &self,
+        app_name: ApplicationName,
+        op: psa_cipher_decrypt::Operation,
+    ) -> Result<psa_cipher_decrypt::Result> {
+        self.psa_cipher_decrypt_internal(app_name, op)
+    }
+
+    fn psa_hash_compute(
+        &self,
+        op: psa_hash_compute::Operation,
+    ) -> Result<psa_hash_compute::Result> {
+        self.psa_hash_compute_internal(op)
+    }
+
+    fn psa_hash_compare(
+        &self,
+        op: psa_hash_compare::Operation,
+    ) -> Result<psa_hash_compare::Result> {
+        self.psa_hash_compare_internal(op)
+    }
+
+    fn reconcile(&self, mode: &ReconciliationMode) -> Result<ReconciliationReport> {
+        self.reconcile_internal(mode)
+    }
 }
 
 impl Drop for MbedProvider {
@@ -0,0 +1,396 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+use super::constants::{
+    PSA_ALG_AEAD_TAG_LENGTH_MASK, PSA_ALG_CBC_NO_PADDING, PSA_ALG_CBC_PKCS7, PSA_ALG_CCM,
+    PSA_ALG_CMAC, PSA_ALG_CTR, PSA_ALG_DETERMINISTIC_ECDSA_BASE, PSA_ALG_ECDSA_BASE, PSA_ALG_GCM,
+    PSA_ALG_HASH_MASK, PSA_ALG_HMAC_BASE, PSA_ALG_MAC_TRUNCATION_MASK, PSA_ALG_RSA_OAEP_BASE,
+    PSA_ALG_RSA_PKCS1V15_CRYPT, PSA_ALG_RSA_PKCS1V15_SIGN_BASE, PSA_ALG_RSA_PSS_BASE,
+    PSA_KEY_TYPE_AES, PSA_KEY_TYPE_ECC_CURVE_MASK, PSA_KEY_TYPE_ECC_KEYPAIR_BASE,
+    PSA_KEY_TYPE_ECC_PUBLIC_KEY_BASE, PSA_KEY_TYPE_HMAC, PSA_KEY_TYPE_RSA_KEYPAIR,
+    PSA_KEY_TYPE_RSA_PUBLIC_KEY, PSA_KEY_USAGE_DECRYPT, PSA_KEY_USAGE_DERIVE,
+    PSA_KEY_USAGE_ENCRYPT, PSA_KEY_USAGE_EXPORT, PSA_KEY_USAGE_SIGN, PSA_KEY_USAGE_VERIFY,
+    PSA_MAC_TRUNCATION_OFFSET, PSA_MAX_PERSISTENT_KEY_IDENTIFIER, PSA_SUCCESS,
+};
+use super::psa_crypto_binding::{
+    self, psa_algorithm_t, psa_ecc_curve_t, psa_key_attributes_t, psa_key_id_t,
+};
+use super::utils::{self, KeyHandle};
+use super::MbedProvider;
+use crate::key_info_managers::{self, KeyInfo, ManageKeyInfo};
+use crate::providers::reconciliation::{self, BackendObject, ManageBackendObjects};
+use crate::providers::reconciliation::{ReconciliationMode, ReconciliationReport};
+use log::{error, info};
+use parsec_interface::operations::psa_algorithm::{
+    Aead, AeadWithDefaultLengthTag, Algorithm, AsymmetricEncryption, AsymmetricSignature, Cipher,
+    FullLengthMac, Hash, Mac, SignHash,
+};
+use parsec_interface::operations::psa_key_attributes::{
+    Attributes, Lifetime, Policy, Type, UsageFlags,
+};
+use parsec_interface::requests::{ProviderId, ResponseStatus, Result};
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::fs;
+
+/// Mbed Crypto stores each persistent key in a file of the working directory named after the
+/// 64-bit hexadecimal representation of its key ID, followed by this suffix.
+const ITS_FILE_SUFFIX: &str = ".psa_its";
+const ITS_FILE_ID_LEN: usize = 16;
+
+/// Hash algorithms which can be used with the RSA PKCS#1 v1.5, RSA PSS and ECDSA signatures and
+/// the RSA OAEP encryption
+const SIGN_HASHES: [Hash; 11] = [
+    Hash::Ripemd160,
+    Hash::Sha224,
+    Hash::Sha256,
+    Hash::Sha384,
+    Hash::Sha512,
+    Hash::Sha512_224,
+    Hash::Sha512_256,
+    Hash::Sha3_224,
+    Hash::Sha3_256,
+    Hash::Sha3_384,
+    Hash::Sha3_512,
+];
+
+fn key_id_from_info(key_info: &KeyInfo) -> Option<psa_key_id_t> {
+    let key_id: [u8; 4] = key_info.id.as_slice().try_into().ok()?;
+    Some(u32::from_ne_bytes(key_id))
+}
+
+/// Parses the key ID out of the name of a key file of the Mbed Crypto storage.
+fn key_id_from_file_name(file_name: &str) -> Option<psa_key_id_t> {
+    let uid = file_name.strip_suffix(ITS_FILE_SUFFIX)?;
+    if uid.len() != ITS_FILE_ID_LEN {
+        return None;
+    }
+    let uid = u64::from_str_radix(uid, 16).ok()?;
+    // Other files, like the random seed, use identifiers outside of the persistent key range.
+    match uid.try_into() {
+        Ok(key_id) if key_id != 0 && key_id <= PSA_MAX_PERSISTENT_KEY_IDENTIFIER => Some(key_id),
+        _ => None,
+    }
+}
+
+/// Finds the hash algorithm encoded in an Mbed Crypto algorithm value.
+fn convert_hash_algorithm_back(alg: psa_algorithm_t) -> Option<Hash> {
+    SIGN_HASHES.iter().copied().find(|hash| {
+        matches!(
+            utils::convert_hash_algorithm(SignHash::Specific(*hash)),
+            Ok(psa_hash) if psa_hash & PSA_ALG_HASH_MASK == alg & PSA_ALG_HASH_MASK
+        )
+    })
+}
+
+/// Converts an Mbed Crypto HMAC or CMAC algorithm value back to a Parsec MAC algorithm.
+fn convert_mac_algorithm_back(alg: psa_algorithm_t) -> Option<Mac> {
+    let full_length_alg = alg & !PSA_ALG_MAC_TRUNCATION_MASK;
+    let mac_alg = if full_length_alg == PSA_ALG_CMAC {
+        FullLengthMac::Cmac
+    } else if full_length_alg & !PSA_ALG_HASH_MASK == PSA_ALG_HMAC_BASE {
+        FullLengthMac::Hmac {
+            hash_alg: convert_hash_algorithm_back(full_length_alg)?,
+        }
+    } else {
+        return None;
+    };
+    let mac_length = (alg & PSA_ALG_MAC_TRUNCATION_MASK) >> PSA_MAC_TRUNCATION_OFFSET;
+    if mac_length == 0 {
+        Some(Mac::FullLength(mac_alg))
+    } else {
+        Some(Mac::Truncated {
+            mac_alg,
+            mac_length: mac_length as usize,
+        })
+    }
+}
+
+/// Converts an Mbed Crypto algorithm value used with AES keys back to a Parsec algorithm.
+fn convert_aes_algorithm_back(alg: psa_algorithm_t) -> Option<Algorithm> {
+    if alg & !PSA_ALG_MAC_TRUNCATION_MASK == PSA_ALG_CMAC {
+        return convert_mac_algorithm_back(alg).map(Algorithm::Mac);
+    }
+    match alg {
+        PSA_ALG_CTR => return Some(Algorithm::Cipher(Cipher::Ctr)),
+        PSA_ALG_CBC_NO_PADDING => return Some(Algorithm::Cipher(Cipher::CbcNoPadding)),
+        PSA_ALG_CBC_PKCS7 => return Some(Algorithm::Cipher(Cipher::CbcPkcs7)),
+        _ => (),
+    }
+    let (aead_alg, default_alg) = match alg & !PSA_ALG_AEAD_TAG_LENGTH_MASK {
+        base if base == PSA_ALG_CCM & !PSA_ALG_AEAD_TAG_LENGTH_MASK => {
+            (AeadWithDefaultLengthTag::Ccm, PSA_ALG_CCM)
+        }
+        base if base == PSA_ALG_GCM & !PSA_ALG_AEAD_TAG_LENGTH_MASK => {
+            (AeadWithDefaultLengthTag::Gcm, PSA_ALG_GCM)
+        }
+        _ => return None,
+    };
+    let aead = if alg == default_alg {
+        Aead::AeadWithDefaultLengthTag(aead_alg)
+    } else {
+        Aead::AeadWithShortenedTag {
+            aead_alg,
+            tag_length: utils::psa_aead_tag_length(alg),
+        }
+    };
+    Some(Algorithm::Aead(aead))
+}
+
+/// Converts the attributes of a key created by the provider back to Parsec attributes: an RSA
+/// key pair, or public key, used for RSA PKCS#1 v1.5 or RSA PSS signatures or for RSA PKCS#1 v1.5
+/// or RSA OAEP encryption, an ECC key pair, or public key, used for ECDSA signatures, or an AES
+/// key used for CCM or GCM authenticated encryption, for the CTR or CBC cipher modes or for CMAC,
+/// or an HMAC key, the only algorithms supported. Returns `None` for other keys.
+fn convert_key_attributes_back(attrs: &psa_key_attributes_t) -> Option<Attributes> {
+    let key_type = attrs.core.type_;
+    let alg = attrs.core.policy.alg;
+    let (key_type, permitted_algorithms) = match key_type {
+        PSA_KEY_TYPE_RSA_KEYPAIR | PSA_KEY_TYPE_RSA_PUBLIC_KEY => {
+            let permitted_algorithm = if alg == PSA_ALG_RSA_PKCS1V15_CRYPT {
+                Algorithm::AsymmetricEncryption(AsymmetricEncryption::RsaPkcs1v15Crypt)
+            } else {
+                let hash_alg = convert_hash_algorithm_back(alg)?;
+                match alg & !PSA_ALG_HASH_MASK {
+                    PSA_ALG_RSA_PKCS1V15_SIGN_BASE => {
+                        Algorithm::AsymmetricSignature(AsymmetricSignature::RsaPkcs1v15Sign {
+                            hash_alg: hash_alg.into(),
+                        })
+                    }
+                    PSA_ALG_RSA_PSS_BASE => {
+                        Algorithm::AsymmetricSignature(AsymmetricSignature::RsaPss {
+                            hash_alg: hash_alg.into(),
+                        })
+                    }
+                    PSA_ALG_RSA_OAEP_BASE => {
+                        Algorithm::AsymmetricEncryption(AsymmetricEncryption::RsaOaep { hash_alg })
+                    }
+                    _ => return None,
+                }
+            };
+            let key_type = if key_type == PSA_KEY_TYPE_RSA_KEYPAIR {
+                Type::RsaKeyPair
+            } else {
+                Type::RsaPublicKey
+            };
+            (key_type, permitted_algorithm)
+        }
+        _ if utils::is_ecc_key_type(key_type) => {
+            let curve_family = utils::convert_ecc_curve_back(
+                (key_type & PSA_KEY_TYPE_ECC_CURVE_MASK) as psa_ecc_curve_t,
+            )?;
+            let key_type = match key_type & !PSA_KEY_TYPE_ECC_CURVE_MASK {
+                PSA_KEY_TYPE_ECC_KEYPAIR_BASE => Type::EccKeyPair { curve_family },
+                PSA_KEY_TYPE_ECC_PUBLIC_KEY_BASE => Type::EccPublicKey { curve_family },
+                _ => return None,
+            };
+            let hash_alg = convert_hash_algorithm_back(alg)?.into();
+            let sign_alg = match alg & !PSA_ALG_HASH_MASK {
+                PSA_ALG_ECDSA_BASE => AsymmetricSignature::Ecdsa { hash_alg },
+                PSA_ALG_DETERMINISTIC_ECDSA_BASE => {
+                    AsymmetricSignature::DeterministicEcdsa { hash_alg }
+                }
+                _ => return None,
+            };
+            (key_type, Algorithm::AsymmetricSignature(sign_alg))
+        }
+        PSA_KEY_TYPE_AES => (Type::Aes, convert_aes_algorithm_back(alg)?),
+        PSA_KEY_TYPE_HMAC => (Type::Hmac, Algorithm::Mac(convert_mac_algorithm_back(alg)?)),
+        _ => return None,
+    };
+    let usage = attrs.core.policy.usage;
+    let mut usage_flags = UsageFlags::default();
+    if usage & PSA_KEY_USAGE_SIGN != 0 {
+        let _ = usage_flags.set_sign_hash();
+    }
+    if usage & PSA_KEY_USAGE_VERIFY != 0 {
+        let _ = usage_flags.set_verify_hash();
+    }
+    if usage & PSA_KEY_USAGE_EXPORT != 0 {
+        let _ = usage_flags.set_export();
+    }
+    if usage & PSA_KEY_USAGE_ENCRYPT != 0 {
+        let _ = usage_flags.set_encrypt();
+    }
+    if usage & PSA_KEY_USAGE_DECRYPT != 0 {
+        let _ = usage_flags.set_decrypt();
+    }
+    if usage & PSA_KEY_USAGE_DERIVE != 0 {
+        let _ = usage_flags.set_derive();
+    }
+
+    Some(Attributes {
+        lifetime: Lifetime::Persistent,
+        key_type,
+        bits: attrs.core.bits as usize,
+        policy: Policy {
+            usage_flags,
+            permitted_algorithms,
+        },
+    })
+}
+
+/// Keys of the Mbed Crypto storage. The key handle mutex and a key slot have to be held while it
+/// is used.
+struct MbedObjects {
+    /// IDs of the keys being created, whose mappings are not stored yet.
+    pending_ids: HashSet<psa_key_id_t>,
+}
+
+/// Returns the key IDs of the mappings of the provider.
+fn mapped_key_ids(store_handle: &dyn ManageKeyInfo) -> Result<HashSet<psa_key_id_t>> {
+    let mut key_ids = HashSet::new();
+    for key_triple in store_handle
+        .get_all(ProviderId::MbedCrypto)
+        .map_err(key_info_managers::to_response_status)?
+    {
+        if let Ok(Some(key_info)) = store_handle.get(&key_triple) {
+            if let Some(key_id) = key_id_from_info(&key_info) {
+                let _ = key_ids.insert(key_id);
+            }
+        }
+    }
+
+    Ok(key_ids)
+}
+
+impl ManageBackendObjects for MbedObjects {
+    fn object_exists(&self, key_info: &KeyInfo) -> Result<bool> {
+        let key_id = match key_id_from_info(key_info) {
+            Some(key_id) => key_id,
+            None => {
+                error!("Stored Key ID is not valid.");
+                return Ok(false);
+            }
+        };
+        // Safety: the Mbed Crypto library has been initialized and the caller holds the key handle
+        // mutex and a key slot.
+        unsafe {
+            match KeyHandle::open(key_id) {
+                Ok(mut key_handle) => {
+                    key_handle.close()?;
+                    Ok(true)
+                }
+                Err(ResponseStatus::PsaErrorDoesNotExist) => Ok(false),
+                Err(e) => Err(e),
+            }
+        }
+    }
+
+    fn list_objects(&self) -> Result<Vec<BackendObject>> {
+        let mut key_ids = Vec::new();
+        for entry in fs::read_dir(".")? {
+            let file_name = entry?.file_name();
+            if let Some(key_id) = file_name.to_str().and_then(key_id_from_file_name) {
+                if !self.pending_ids.contains(&key_id) {
+                    key_ids.push(key_id);
+                }
+            }
+        }
+        key_ids.sort_unstable();
+
+        let mut objects = Vec::new();
+        for key_id in key_ids {
+            // Safety: same conditions than above.
+            let attributes = unsafe {
+                let mut key_handle = KeyHandle::open(key_id)?;
+                let mut key_attrs = key_handle.attributes()?;
+                let attributes = convert_key_attributes_back(key_attrs.as_ref());
+                key_attrs.reset();
+                key_handle.close()?;
+                attributes
+            };
+            objects.push(BackendObject {
+                id: key_id.to_ne_bytes().to_vec(),
+                attributes,
+            });
+        }
+
+        Ok(objects)
+    }
+
+    fn destroy_object(&self, id: &[u8]) -> Result<()> {
+        let key_id = u32::from_ne_bytes(id.try_into().or(Err(ResponseStatus::InvalidEncoding))?);
+        // Safety: same conditions than above.
+        let destroy_key_status = unsafe {
+            let key_handle = KeyHandle::open(key_id)?;
+            psa_crypto_binding::psa_destroy_key(key_handle.raw())
+        };
+        if destroy_key_status == PSA_SUCCESS {
+            Ok(())
+        } else {
+            error!("Destroy key status: {}", destroy_key_status);
+            Err(utils::convert_status(destroy_key_status))
+        }
+    }
+}
+
+impl MbedProvider {
+    pub(super) fn reconcile_internal(
+        &self,
+        mode: &ReconciliationMode,
+    ) -> Result<ReconciliationReport> {
+        info!("Mbed Provider - Reconcile");
+        let _semaphore_guard = self.key_slot_semaphore.access();
+        let mut store_handle = self
+            .key_info_store
+            .write()
+            .expect("Key store lock poisoned");
+        let mut local_ids_handle = self.local_ids.write().expect("Local ID lock poisoned");
+        let _guard = self
+            .key_handle_mutex
+            .lock()
+            .expect("Grabbing key handle mutex failed");
+
+        // The keys being created have a key ID but no mapping yet, they are not orphaned.
+        let mapped_ids = mapped_key_ids(&*store_handle)?;
+        let objects = MbedObjects {
+            pending_ids: local_ids_handle.difference(&mapped_ids).copied().collect(),
+        };
+
+        let report =
+            reconciliation::reconcile(&objects, ProviderId::MbedCrypto, &mut *store_handle, mode);
+
+        // The local IDs are rebuilt from the mappings left, even if the reconciliation failed
+        // half-way.
+        *local_ids_handle = mapped_key_ids(&*store_handle)?;
+        local_ids_handle.extend(objects.pending_ids.iter());
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mac_algorithms_round_trip() {
+        let macs = [
+            Mac::FullLength(FullLengthMac::Hmac {
+                hash_alg: Hash::Sha384,
+            }),
+            Mac::FullLength(FullLengthMac::Cmac),
+            Mac::Truncated {
+                mac_alg: FullLengthMac::Hmac {
+                    hash_alg: Hash::Sha256,
+                },
+                mac_length: 16,
+            },
+            Mac::Truncated {
+                mac_alg: FullLengthMac::Cmac,
+                mac_length: 8,
+            },
+        ];
+        for mac in macs.iter() {
+            let alg = utils::convert_mac_algorithm(*mac).unwrap();
+            assert_eq!(convert_mac_algorithm_back(alg), Some(*mac));
+        }
+
+        // A CMAC policy is found back for AES keys.
+        assert_eq!(
+            convert_aes_algorithm_back(PSA_ALG_CMAC),
+            Some(Algorithm::Mac(Mac::FullLength(FullLengthMac::Cmac)))
+        );
+        assert_eq!(convert_mac_algorithm_back(PSA_ALG_CTR), None);
+    }
+}
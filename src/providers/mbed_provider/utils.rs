@@ -2,14 +2,17 @@
 // SPDX-License-Identifier: Apache-2.0
 use super::constants::*;
 use super::psa_crypto_binding::{
-    self, psa_algorithm_t, psa_core_key_attributes_t, psa_key_attributes_t, psa_key_bits_t,
-    psa_key_handle_t, psa_key_id_t, psa_key_policy_s, psa_key_type_t, psa_key_usage_t,
-    psa_status_t,
+    self, psa_algorithm_t, psa_core_key_attributes_t, psa_ecc_curve_t, psa_key_attributes_t,
+    psa_key_bits_t, psa_key_handle_t, psa_key_id_t, psa_key_policy_s, psa_key_type_t,
+    psa_key_usage_t, psa_status_t,
 };
 use log::error;
-use parsec_interface::operations::psa_algorithm::{Algorithm, AsymmetricSignature, Hash, SignHash};
+use parsec_interface::operations::psa_algorithm::{
+    Aead, AeadWithDefaultLengthTag, Algorithm, AsymmetricEncryption, AsymmetricSignature, Cipher,
+    FullLengthMac, Hash, Mac, SignHash,
+};
 use parsec_interface::operations::psa_key_attributes;
-use parsec_interface::operations::psa_key_attributes::Type;
+use parsec_interface::operations::psa_key_attributes::{EccFamily, Type};
 use parsec_interface::requests::{ResponseStatus, Result};
 use std::convert::TryFrom;
 use std::convert::TryInto;
@@ -27,7 +30,7 @@ pub fn convert_key_attributes(
 ) -> Result<psa_key_attributes_t> {
     Ok(psa_key_attributes_t {
         core: psa_core_key_attributes_t {
-            type_: convert_key_type(attrs.key_type)?,
+            type_: convert_key_type(attrs.key_type, attrs.bits)?,
             lifetime: PSA_KEY_LIFETIME_PERSISTENT,
             id: key_id,
             policy: psa_key_policy_s {
@@ -69,20 +72,56 @@ pub fn convert_key_bits(key_size: u32) -> psa_key_bits_t {
     psa_key_bits_t::try_from(key_size).unwrap_or(PSA_KEY_BITS_TOO_LARGE)
 }
 
-/// Converts between native and Mbed Crypto type values.
+/// Converts between native and Mbed Crypto type values. The size of the key, in bits, is needed
+/// to find the curve of ECC keys.
 ///
 /// # Errors
 ///
-/// Only `Type::RsaKeypair` and `Type::RsaPublicKey` are supported. Returns
-/// ResponseStatus::PsaErrorNotSupported otherwise.
-pub fn convert_key_type(key_type: Type) -> Result<psa_key_type_t> {
+/// Only RSA keys, ECC keys on the secp256r1 and secp384r1 curves, AES keys and HMAC keys are
+/// supported. Returns ResponseStatus::PsaErrorNotSupported otherwise.
+pub fn convert_key_type(key_type: Type, bits: usize) -> Result<psa_key_type_t> {
     match key_type {
         Type::RsaKeyPair => Ok(PSA_KEY_TYPE_RSA_KEYPAIR),
         Type::RsaPublicKey => Ok(PSA_KEY_TYPE_RSA_PUBLIC_KEY),
+        Type::EccKeyPair { curve_family } => Ok(PSA_KEY_TYPE_ECC_KEYPAIR_BASE
+            | psa_key_type_t::from(convert_ecc_curve(curve_family, bits)?)),
+        Type::EccPublicKey { curve_family } => Ok(PSA_KEY_TYPE_ECC_PUBLIC_KEY_BASE
+            | psa_key_type_t::from(convert_ecc_curve(curve_family, bits)?)),
+        Type::Aes => Ok(PSA_KEY_TYPE_AES),
+        Type::Hmac => Ok(PSA_KEY_TYPE_HMAC),
+        _ => Err(ResponseStatus::PsaErrorNotSupported),
+    }
+}
+
+/// Converts a native curve family and key size to the Mbed Crypto curve value.
+///
+/// # Errors
+///
+/// Only the secp256r1 and secp384r1 curves are supported. Returns
+/// ResponseStatus::PsaErrorNotSupported otherwise.
+pub fn convert_ecc_curve(curve_family: EccFamily, bits: usize) -> Result<psa_ecc_curve_t> {
+    match (curve_family, bits) {
+        (EccFamily::SecpR1, 256) => Ok(PSA_ECC_CURVE_SECP256R1),
+        (EccFamily::SecpR1, 384) => Ok(PSA_ECC_CURVE_SECP384R1),
         _ => Err(ResponseStatus::PsaErrorNotSupported),
     }
 }
 
+/// Converts an Mbed Crypto curve value back to the native curve family. Returns `None` for the
+/// curves which are not supported.
+pub fn convert_ecc_curve_back(curve: psa_ecc_curve_t) -> Option<EccFamily> {
+    match curve {
+        PSA_ECC_CURVE_SECP256R1 | PSA_ECC_CURVE_SECP384R1 => Some(EccFamily::SecpR1),
+        _ => None,
+    }
+}
+
+/// Returns true if the Mbed Crypto key type is an ECC key pair or public key.
+pub fn is_ecc_key_type(key_type: psa_key_type_t) -> bool {
+    let base = key_type & !PSA_KEY_TYPE_ECC_CURVE_MASK;
+    base == PSA_KEY_TYPE_ECC_KEYPAIR_BASE || base == PSA_KEY_TYPE_ECC_PUBLIC_KEY_BASE
+}
+
 /// Converts between native and Mbed Crypto key usage values.
 pub fn convert_key_usage(operation: &psa_key_attributes::UsageFlags) -> psa_key_usage_t {
     let mut usage: psa_key_usage_t = 0;
@@ -90,27 +129,27 @@ pub fn convert_key_usage(operation: &psa_key_attributes::UsageFlags) -> psa_key_
     // Build up the individual usage flags in the OpKeyCreateBase, and use them to bitwise-combine the equivalent flags
     // in the PSA definition.
 
-    if operation.decrypt {
+    if operation.decrypt() {
         usage |= PSA_KEY_USAGE_DECRYPT;
     }
 
-    if operation.encrypt {
+    if operation.encrypt() {
         usage |= PSA_KEY_USAGE_ENCRYPT;
     }
 
-    if operation.export {
+    if operation.export() {
         usage |= PSA_KEY_USAGE_EXPORT;
     }
 
-    if operation.sign_message && operation.sign_hash {
+    if operation.sign_message() && operation.sign_hash() {
         usage |= PSA_KEY_USAGE_SIGN;
     }
 
-    if operation.verify_message && operation.verify_hash {
+    if operation.verify_message() && operation.verify_hash() {
         usage |= PSA_KEY_USAGE_VERIFY;
     }
 
-    if operation.derive {
+    if operation.derive() {
         usage |= PSA_KEY_USAGE_DERIVE;
     }
 
@@ -121,9 +160,12 @@ pub fn convert_key_usage(operation: &psa_key_attributes::UsageFlags) -> psa_key_
 ///
 /// # Errors
 ///
-/// Only `AlgorithmInner::Sign` is supported as algorithm with only the
-/// `SignAlgorithm::RsaPkcs1v15Sign` signing algorithm. Will return
-/// ResponseStatus::PsaErrorNotSupported otherwise.
+/// Only asymmetric signature algorithms, `AsymmetricSignature::RsaPkcs1v15Sign`,
+/// `AsymmetricSignature::RsaPss`, `AsymmetricSignature::Ecdsa` and
+/// `AsymmetricSignature::DeterministicEcdsa`, and asymmetric encryption algorithms,
+/// `AsymmetricEncryption::RsaPkcs1v15Crypt` and `AsymmetricEncryption::RsaOaep`, the CCM and GCM
+/// AEAD algorithms, the CTR and CBC cipher modes and the HMAC and CMAC algorithms are supported.
+/// Will return ResponseStatus::PsaErrorNotSupported otherwise.
 pub fn convert_algorithm(alg: &Algorithm) -> Result<psa_algorithm_t> {
     let mut algo_val: psa_algorithm_t;
     match alg {
@@ -133,9 +175,112 @@ pub fn convert_algorithm(alg: &Algorithm) -> Result<psa_algorithm_t> {
                 algo_val |= convert_hash_algorithm(*hash_alg)? & PSA_ALG_HASH_MASK;
                 Ok(algo_val)
             }
+            AsymmetricSignature::RsaPss { hash_alg } => {
+                algo_val = PSA_ALG_RSA_PSS_BASE;
+                algo_val |= convert_hash_algorithm(*hash_alg)? & PSA_ALG_HASH_MASK;
+                Ok(algo_val)
+            }
+            AsymmetricSignature::Ecdsa { hash_alg } => {
+                algo_val = PSA_ALG_ECDSA_BASE;
+                algo_val |= convert_hash_algorithm(*hash_alg)? & PSA_ALG_HASH_MASK;
+                Ok(algo_val)
+            }
+            AsymmetricSignature::DeterministicEcdsa { hash_alg } => {
+                algo_val = PSA_ALG_DETERMINISTIC_ECDSA_BASE;
+                algo_val |= convert_hash_algorithm(*hash_alg)? & PSA_ALG_HASH_MASK;
+                Ok(algo_val)
+            }
             _ => Err(ResponseStatus::PsaErrorNotSupported),
         },
+        Algorithm::AsymmetricEncryption(asym_encryption) => match asym_encryption {
+            AsymmetricEncryption::RsaPkcs1v15Crypt => Ok(PSA_ALG_RSA_PKCS1V15_CRYPT),
+            AsymmetricEncryption::RsaOaep { hash_alg } => {
+                algo_val = PSA_ALG_RSA_OAEP_BASE;
+                algo_val |=
+                    convert_hash_algorithm(SignHash::Specific(*hash_alg))? & PSA_ALG_HASH_MASK;
+                Ok(algo_val)
+            }
+        },
+        Algorithm::Aead(aead) => convert_aead_algorithm(*aead),
+        Algorithm::Cipher(cipher) => convert_cipher_algorithm(*cipher),
+        Algorithm::Mac(mac) => convert_mac_algorithm(*mac),
+        _ => Err(ResponseStatus::PsaErrorNotSupported),
+    }
+}
+
+/// Converts between native and Mbed Crypto cipher algorithm values.
+///
+/// # Errors
+///
+/// Only the CTR and CBC modes, which use an IV of one block, are supported. Will return
+/// ResponseStatus::PsaErrorNotSupported otherwise.
+pub fn convert_cipher_algorithm(cipher: Cipher) -> Result<psa_algorithm_t> {
+    match cipher {
+        Cipher::Ctr => Ok(PSA_ALG_CTR),
+        Cipher::CbcNoPadding => Ok(PSA_ALG_CBC_NO_PADDING),
+        Cipher::CbcPkcs7 => Ok(PSA_ALG_CBC_PKCS7),
+        _ => Err(ResponseStatus::PsaErrorNotSupported),
+    }
+}
+
+/// Converts between native and Mbed Crypto MAC algorithm values. The length of a truncated MAC is
+/// encoded in the algorithm value.
+///
+/// # Errors
+///
+/// Only the HMAC and CMAC algorithms are supported. Will return
+/// ResponseStatus::PsaErrorNotSupported otherwise.
+pub fn convert_mac_algorithm(mac: Mac) -> Result<psa_algorithm_t> {
+    let convert_full_length = |mac_alg| match mac_alg {
+        FullLengthMac::Hmac { hash_alg } => Ok(PSA_ALG_HMAC_BASE
+            | (convert_hash_algorithm(SignHash::Specific(hash_alg))? & PSA_ALG_HASH_MASK)),
+        FullLengthMac::Cmac => Ok(PSA_ALG_CMAC),
         _ => Err(ResponseStatus::PsaErrorNotSupported),
+    };
+    match mac {
+        Mac::FullLength(mac_alg) => convert_full_length(mac_alg),
+        Mac::Truncated {
+            mac_alg,
+            mac_length,
+        } => {
+            let mac_length = psa_algorithm_t::try_from(mac_length)
+                .or(Err(ResponseStatus::PsaErrorInvalidArgument))?;
+            let mac_length = mac_length << PSA_MAC_TRUNCATION_OFFSET;
+            if mac_length & !PSA_ALG_MAC_TRUNCATION_MASK != 0 {
+                return Err(ResponseStatus::PsaErrorInvalidArgument);
+            }
+            Ok((convert_full_length(mac_alg)? & !PSA_ALG_MAC_TRUNCATION_MASK) | mac_length)
+        }
+    }
+}
+
+/// Converts between native and Mbed Crypto AEAD algorithm values. A shortened tag length is
+/// encoded in the algorithm value.
+///
+/// # Errors
+///
+/// Only the CCM and GCM algorithms are supported. Will return
+/// ResponseStatus::PsaErrorNotSupported otherwise.
+pub fn convert_aead_algorithm(aead: Aead) -> Result<psa_algorithm_t> {
+    let convert_default = |aead_alg| match aead_alg {
+        AeadWithDefaultLengthTag::Ccm => Ok(PSA_ALG_CCM),
+        AeadWithDefaultLengthTag::Gcm => Ok(PSA_ALG_GCM),
+        _ => Err(ResponseStatus::PsaErrorNotSupported),
+    };
+    match aead {
+        Aead::AeadWithDefaultLengthTag(aead_alg) => convert_default(aead_alg),
+        Aead::AeadWithShortenedTag {
+            aead_alg,
+            tag_length,
+        } => {
+            let tag_length = psa_algorithm_t::try_from(tag_length)
+                .or(Err(ResponseStatus::PsaErrorInvalidArgument))?;
+            let tag_length = tag_length << PSA_AEAD_TAG_LENGTH_OFFSET;
+            if tag_length & !PSA_ALG_AEAD_TAG_LENGTH_MASK != 0 {
+                return Err(ResponseStatus::PsaErrorInvalidArgument);
+            }
+            Ok((convert_default(aead_alg)? & !PSA_ALG_AEAD_TAG_LENGTH_MASK) | tag_length)
+        }
     }
 }
 
@@ -201,13 +346,34 @@ macro_rules! bits_to_bytes {
 pub fn psa_asymmetric_sign_output_size(key_attrs: &psa_key_attributes_t) -> Result<usize> {
     match key_attrs.core.type_ {
         PSA_KEY_TYPE_RSA_KEYPAIR => Ok(usize::from(bits_to_bytes!(key_attrs.core.bits))),
-        PSA_KEY_TYPE_ECC_KEYPAIR_BASE => Ok(usize::from(bits_to_bytes!(key_attrs.core.bits) * 2)),
+        type_ if type_ & !PSA_KEY_TYPE_ECC_CURVE_MASK == PSA_KEY_TYPE_ECC_KEYPAIR_BASE => {
+            Ok(usize::from(bits_to_bytes!(key_attrs.core.bits) * 2))
+        }
         _ => Err(ResponseStatus::PsaErrorNotSupported),
     }
 }
 
+/// Compute the size of the ciphertext of an asymmetric encryption, given the key attributes of
+/// the encryption key. It is also the maximum size of the plaintext of an asymmetric decryption.
+/// Implementing `PSA_ASYMMETRIC_ENCRYPT_OUTPUT_SIZE` as defined in `crypto_sizes.h` (Mbed Crypto).
+pub fn psa_asymmetric_encrypt_output_size(key_attrs: &psa_key_attributes_t) -> Result<usize> {
+    match key_attrs.core.type_ {
+        PSA_KEY_TYPE_RSA_PUBLIC_KEY | PSA_KEY_TYPE_RSA_KEYPAIR => {
+            Ok(usize::from(bits_to_bytes!(key_attrs.core.bits)))
+        }
+        _ => Err(ResponseStatus::PsaErrorNotSupported),
+    }
+}
+
+/// Returns the length, in bytes, of the tag of an Mbed Crypto AEAD algorithm value.
+/// Implementing `PSA_AEAD_TAG_LENGTH` as defined in `crypto_sizes.h` (Mbed Crypto).
+pub fn psa_aead_tag_length(alg: psa_algorithm_t) -> usize {
+    ((alg & PSA_ALG_AEAD_TAG_LENGTH_MASK) >> PSA_AEAD_TAG_LENGTH_OFFSET) as usize
+}
+
 /// Compute the size of the public key material to be exported, given the attributes of the key.
 /// Implementing `PSA_KEY_EXPORT_MAX_SIZE` for public keys only, as defined in `crypto_sizes.h` (Mbed Crypto).
+/// For key pairs, this is the size of their public part.
 pub fn psa_export_public_key_size(key_attrs: &psa_key_attributes_t) -> Result<usize> {
     macro_rules! export_asn1_int_max_size {
         ($size:expr) => {
@@ -219,6 +385,10 @@ pub fn psa_export_public_key_size(key_attrs: &psa_key_attributes_t) -> Result<us
         PSA_KEY_TYPE_RSA_PUBLIC_KEY | PSA_KEY_TYPE_RSA_KEYPAIR => Ok(usize::from(
             export_asn1_int_max_size!(key_attrs.core.bits) + 11,
         )),
+        // The public key is exported as an uncompressed point: 0x04 followed by the coordinates.
+        type_ if is_ecc_key_type(type_) => {
+            Ok(usize::from(bits_to_bytes!(key_attrs.core.bits) * 2 + 1))
+        }
         _ => Err(ResponseStatus::PsaErrorNotSupported),
     }
 }
@@ -307,7 +477,7 @@ impl KeyHandle {
     /// * the Mbed Crypto library has already been initialized
     /// * calls to open, generate, import and close are protected by the same mutex
     /// * only PSA_KEY_SLOT_COUNT slots are used at any given time
-    pub unsafe fn import(attributes: &psa_key_attributes_t, key_data: Vec<u8>) -> Result<Self> {
+    pub unsafe fn import(attributes: &psa_key_attributes_t, key_data: &[u8]) -> Result<Self> {
         let mut key_handle: psa_key_handle_t = Default::default();
         let status = psa_crypto_binding::psa_import_key(
             attributes,
@@ -379,3 +549,303 @@ impl AsMut<psa_key_handle_t> for KeyHandle {
         &mut self.0
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key_attributes(type_: psa_key_type_t, bits: psa_key_bits_t) -> psa_key_attributes_t {
+        let mut attributes = get_empty_key_attributes();
+        attributes.core.type_ = type_;
+        attributes.core.bits = bits;
+        attributes
+    }
+
+    #[test]
+    fn ecc_key_types() {
+        let key_pair = Type::EccKeyPair {
+            curve_family: EccFamily::SecpR1,
+        };
+        let public_key = Type::EccPublicKey {
+            curve_family: EccFamily::SecpR1,
+        };
+
+        let secp256r1_key_pair = convert_key_type(key_pair, 256).unwrap();
+        assert_eq!(
+            secp256r1_key_pair,
+            PSA_KEY_TYPE_ECC_KEYPAIR_BASE | psa_key_type_t::from(PSA_ECC_CURVE_SECP256R1)
+        );
+        assert_eq!(
+            convert_key_type(public_key, 384).unwrap(),
+            PSA_KEY_TYPE_ECC_PUBLIC_KEY_BASE | psa_key_type_t::from(PSA_ECC_CURVE_SECP384R1)
+        );
+        assert!(is_ecc_key_type(secp256r1_key_pair));
+        assert!(!is_ecc_key_type(PSA_KEY_TYPE_RSA_KEYPAIR));
+
+        assert_eq!(
+            convert_key_type(key_pair, 521).unwrap_err(),
+            ResponseStatus::PsaErrorNotSupported
+        );
+        assert_eq!(
+            convert_key_type(
+                Type::EccKeyPair {
+                    curve_family: EccFamily::SecpK1,
+                },
+                256
+            )
+            .unwrap_err(),
+            ResponseStatus::PsaErrorNotSupported
+        );
+    }
+
+    #[test]
+    fn ecc_curves_round_trip() {
+        for bits in [256, 384].iter() {
+            let curve = convert_ecc_curve(EccFamily::SecpR1, *bits).unwrap();
+            assert_eq!(convert_ecc_curve_back(curve), Some(EccFamily::SecpR1));
+        }
+        assert_eq!(convert_ecc_curve_back(0), None);
+    }
+
+    #[test]
+    fn ecdsa_algorithms() {
+        let hash_alg = SignHash::Specific(Hash::Sha256);
+        assert_eq!(
+            convert_algorithm(&Algorithm::AsymmetricSignature(
+                AsymmetricSignature::Ecdsa { hash_alg }
+            ))
+            .unwrap(),
+            PSA_ALG_ECDSA_BASE | (PSA_ALG_SHA_256 & PSA_ALG_HASH_MASK)
+        );
+        assert_eq!(
+            convert_algorithm(&Algorithm::AsymmetricSignature(
+                AsymmetricSignature::DeterministicEcdsa { hash_alg }
+            ))
+            .unwrap(),
+            PSA_ALG_DETERMINISTIC_ECDSA_BASE | (PSA_ALG_SHA_256 & PSA_ALG_HASH_MASK)
+        );
+    }
+
+    #[test]
+    fn rsa_signature_algorithms() {
+        let hash_alg = SignHash::Specific(Hash::Sha384);
+        assert_eq!(
+            convert_algorithm(&Algorithm::AsymmetricSignature(
+                AsymmetricSignature::RsaPss { hash_alg }
+            ))
+            .unwrap(),
+            PSA_ALG_RSA_PSS_BASE | (PSA_ALG_SHA_384 & PSA_ALG_HASH_MASK)
+        );
+        assert_eq!(
+            convert_algorithm(&Algorithm::AsymmetricSignature(
+                AsymmetricSignature::RsaPkcs1v15Sign { hash_alg }
+            ))
+            .unwrap(),
+            PSA_ALG_RSA_PKCS1V15_SIGN_BASE | (PSA_ALG_SHA_384 & PSA_ALG_HASH_MASK)
+        );
+        // The wildcard hash is not supported.
+        assert_eq!(
+            convert_algorithm(&Algorithm::AsymmetricSignature(
+                AsymmetricSignature::RsaPss {
+                    hash_alg: SignHash::Any
+                }
+            ))
+            .unwrap_err(),
+            ResponseStatus::PsaErrorNotSupported
+        );
+    }
+
+    #[test]
+    fn rsa_encryption_algorithms() {
+        assert_eq!(
+            convert_algorithm(&Algorithm::AsymmetricEncryption(
+                AsymmetricEncryption::RsaPkcs1v15Crypt
+            ))
+            .unwrap(),
+            PSA_ALG_RSA_PKCS1V15_CRYPT
+        );
+        assert_eq!(
+            convert_algorithm(&Algorithm::AsymmetricEncryption(
+                AsymmetricEncryption::RsaOaep {
+                    hash_alg: Hash::Sha256
+                }
+            ))
+            .unwrap(),
+            PSA_ALG_RSA_OAEP_BASE | (PSA_ALG_SHA_256 & PSA_ALG_HASH_MASK)
+        );
+
+        // The ciphertext is as long as the modulus.
+        for type_ in [PSA_KEY_TYPE_RSA_KEYPAIR, PSA_KEY_TYPE_RSA_PUBLIC_KEY].iter() {
+            assert_eq!(
+                psa_asymmetric_encrypt_output_size(&key_attributes(*type_, 2048)).unwrap(),
+                256
+            );
+        }
+        let ecc_key_pair =
+            PSA_KEY_TYPE_ECC_KEYPAIR_BASE | psa_key_type_t::from(PSA_ECC_CURVE_SECP256R1);
+        assert_eq!(
+            psa_asymmetric_encrypt_output_size(&key_attributes(ecc_key_pair, 256)).unwrap_err(),
+            ResponseStatus::PsaErrorNotSupported
+        );
+    }
+
+    #[test]
+    fn symmetric_key_types() {
+        assert_eq!(convert_key_type(Type::Aes, 256).unwrap(), PSA_KEY_TYPE_AES);
+        assert_eq!(
+            convert_key_type(Type::Hmac, 256).unwrap(),
+            PSA_KEY_TYPE_HMAC
+        );
+        assert_eq!(
+            convert_key_type(Type::Camellia, 256).unwrap_err(),
+            ResponseStatus::PsaErrorNotSupported
+        );
+    }
+
+    #[test]
+    fn aead_algorithms() {
+        let gcm = convert_aead_algorithm(Aead::AeadWithDefaultLengthTag(
+            AeadWithDefaultLengthTag::Gcm,
+        ))
+        .unwrap();
+        assert_eq!(gcm, PSA_ALG_GCM);
+        assert_eq!(psa_aead_tag_length(gcm), 16);
+        assert_eq!(
+            convert_algorithm(&Algorithm::Aead(Aead::AeadWithDefaultLengthTag(
+                AeadWithDefaultLengthTag::Ccm
+            )))
+            .unwrap(),
+            PSA_ALG_CCM
+        );
+
+        // A shortened tag length is encoded in the algorithm value.
+        let ccm_8 = convert_aead_algorithm(Aead::AeadWithShortenedTag {
+            aead_alg: AeadWithDefaultLengthTag::Ccm,
+            tag_length: 8,
+        })
+        .unwrap();
+        assert_eq!(
+            ccm_8 & !PSA_ALG_AEAD_TAG_LENGTH_MASK,
+            PSA_ALG_CCM & !PSA_ALG_AEAD_TAG_LENGTH_MASK
+        );
+        assert_eq!(psa_aead_tag_length(ccm_8), 8);
+        assert_eq!(
+            convert_aead_algorithm(Aead::AeadWithShortenedTag {
+                aead_alg: AeadWithDefaultLengthTag::Gcm,
+                tag_length: 64,
+            })
+            .unwrap_err(),
+            ResponseStatus::PsaErrorInvalidArgument
+        );
+
+        assert_eq!(
+            convert_aead_algorithm(Aead::AeadWithDefaultLengthTag(
+                AeadWithDefaultLengthTag::Chacha20Poly1305
+            ))
+            .unwrap_err(),
+            ResponseStatus::PsaErrorNotSupported
+        );
+    }
+
+    #[test]
+    fn cipher_algorithms() {
+        assert_eq!(convert_cipher_algorithm(Cipher::Ctr).unwrap(), PSA_ALG_CTR);
+        assert_eq!(
+            convert_cipher_algorithm(Cipher::CbcNoPadding).unwrap(),
+            PSA_ALG_CBC_NO_PADDING
+        );
+        assert_eq!(
+            convert_algorithm(&Algorithm::Cipher(Cipher::CbcPkcs7)).unwrap(),
+            PSA_ALG_CBC_PKCS7
+        );
+
+        // Only the modes using an IV of one block are supported.
+        for cipher in [Cipher::StreamCipher, Cipher::EcbNoPadding, Cipher::Xts].iter() {
+            assert_eq!(
+                convert_cipher_algorithm(*cipher).unwrap_err(),
+                ResponseStatus::PsaErrorNotSupported
+            );
+        }
+    }
+
+    #[test]
+    fn hash_algorithms() {
+        assert_eq!(
+            convert_hash_algorithm(SignHash::Specific(Hash::Sha256)).unwrap(),
+            PSA_ALG_SHA_256
+        );
+        assert_eq!(
+            convert_hash_algorithm(SignHash::Specific(Hash::Sha3_512)).unwrap(),
+            PSA_ALG_SHA3_512
+        );
+        // Only a specific hash algorithm can be computed.
+        assert_eq!(
+            convert_hash_algorithm(SignHash::Any).unwrap_err(),
+            ResponseStatus::PsaErrorNotSupported
+        );
+    }
+
+    #[test]
+    fn mac_algorithms() {
+        let hmac_sha256 = FullLengthMac::Hmac {
+            hash_alg: Hash::Sha256,
+        };
+        assert_eq!(
+            convert_mac_algorithm(Mac::FullLength(hmac_sha256)).unwrap(),
+            0x0280_0009
+        );
+        assert_eq!(
+            convert_algorithm(&Algorithm::Mac(Mac::FullLength(FullLengthMac::Cmac))).unwrap(),
+            PSA_ALG_CMAC
+        );
+
+        // The length of a truncated MAC is encoded in the algorithm value.
+        assert_eq!(
+            convert_mac_algorithm(Mac::Truncated {
+                mac_alg: hmac_sha256,
+                mac_length: 16,
+            })
+            .unwrap(),
+            0x0280_1009
+        );
+        assert_eq!(
+            convert_mac_algorithm(Mac::Truncated {
+                mac_alg: FullLengthMac::Cmac,
+                mac_length: 64,
+            })
+            .unwrap_err(),
+            ResponseStatus::PsaErrorInvalidArgument
+        );
+
+        assert_eq!(
+            convert_mac_algorithm(Mac::FullLength(FullLengthMac::CbcMac)).unwrap_err(),
+            ResponseStatus::PsaErrorNotSupported
+        );
+    }
+
+    #[test]
+    fn ecc_output_sizes() {
+        let key_pair_type =
+            PSA_KEY_TYPE_ECC_KEYPAIR_BASE | psa_key_type_t::from(PSA_ECC_CURVE_SECP384R1);
+        let public_key_type =
+            PSA_KEY_TYPE_ECC_PUBLIC_KEY_BASE | psa_key_type_t::from(PSA_ECC_CURVE_SECP384R1);
+
+        // A signature is made of r and s, an uncompressed point of 0x04 and the coordinates.
+        assert_eq!(
+            psa_asymmetric_sign_output_size(&key_attributes(key_pair_type, 384)).unwrap(),
+            96
+        );
+        assert_eq!(
+            psa_export_public_key_size(&key_attributes(key_pair_type, 384)).unwrap(),
+            97
+        );
+        assert_eq!(
+            psa_export_public_key_size(&key_attributes(public_key_type, 384)).unwrap(),
+            97
+        );
+        assert_eq!(
+            psa_asymmetric_sign_output_size(&key_attributes(public_key_type, 384)).unwrap_err(),
+            ResponseStatus::PsaErrorNotSupported
+        );
+    }
+}
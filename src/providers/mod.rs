@@ -6,11 +6,12 @@
 //! are the real implementors of the operations that Parsec claims to support. They map to
 //! functionality in the underlying hardware which allows the PSA Crypto operations to be
 //! backed by a hardware root of trust.
-use parsec_interface::requests::{Opcode, ProviderID};
+use parsec_interface::requests::{Opcode, ProviderId};
 use serde::Deserialize;
 use std::collections::HashSet;
 
 pub mod core_provider;
+pub mod reconciliation;
 
 #[cfg(feature = "pkcs11-provider")]
 pub mod pkcs11_provider;
@@ -63,19 +64,24 @@ impl ProviderConfig {
             } => key_info_manager,
         }
     }
-    pub fn provider_id(&self) -> ProviderID {
+    pub fn provider_id(&self) -> ProviderId {
         match *self {
-            MbedCrypto { .. } => ProviderID::MbedCrypto,
-            Pkcs11 { .. } => ProviderID::Pkcs11,
-            Tpm { .. } => ProviderID::Tpm,
+            MbedCrypto { .. } => ProviderId::MbedCrypto,
+            Pkcs11 { .. } => ProviderId::Pkcs11,
+            Tpm { .. } => ProviderId::Tpm,
         }
     }
 }
 
+use self::reconciliation::{ReconciliationMode, ReconciliationReport};
 use crate::authenticators::ApplicationName;
+use crate::back::admin::{AdminOperation, AdminResult};
 use parsec_interface::operations::{
-    list_opcodes, list_providers, ping, psa_destroy_key, psa_export_public_key, psa_generate_key,
-    psa_import_key, psa_sign_hash, psa_verify_hash,
+    delete_client, list_authenticators, list_clients, list_keys, list_opcodes, list_providers,
+    ping, psa_aead_decrypt, psa_aead_encrypt, psa_asymmetric_decrypt, psa_asymmetric_encrypt,
+    psa_cipher_decrypt, psa_cipher_encrypt, psa_destroy_key, psa_export_key, psa_export_public_key,
+    psa_generate_key, psa_generate_random, psa_hash_compare, psa_hash_compute, psa_import_key,
+    psa_raw_key_agreement, psa_sign_hash, psa_verify_hash,
 };
 use parsec_interface::requests::{ResponseStatus, Result};
 
@@ -101,6 +107,48 @@ pub trait Provide {
         Err(ResponseStatus::PsaErrorNotSupported)
     }
 
+    /// List the authenticators supported by the service.
+    fn list_authenticators(
+        &self,
+        _op: list_authenticators::Operation,
+    ) -> Result<list_authenticators::Result> {
+        Err(ResponseStatus::PsaErrorNotSupported)
+    }
+
+    /// List the keys belonging to an application.
+    fn list_keys(
+        &self,
+        _app_name: ApplicationName,
+        _op: list_keys::Operation,
+    ) -> Result<list_keys::Result> {
+        Err(ResponseStatus::PsaErrorNotSupported)
+    }
+
+    /// List all the applications owning keys in the service. This is an admin operation.
+    fn list_clients(&self, _op: list_clients::Operation) -> Result<list_clients::Result> {
+        Err(ResponseStatus::PsaErrorNotSupported)
+    }
+
+    /// Delete all the keys of an application. This is an admin operation.
+    fn delete_client(&self, _op: delete_client::Operation) -> Result<delete_client::Result> {
+        Err(ResponseStatus::PsaErrorNotSupported)
+    }
+
+    /// Compare the key mappings of the provider with the keys stored in its backend and repair the
+    /// inconsistencies found, depending on the mode. This is an admin operation.
+    fn reconcile(&self, _mode: &ReconciliationMode) -> Result<ReconciliationReport> {
+        Err(ResponseStatus::PsaErrorNotSupported)
+    }
+
+    /// Execute an operation received on the admin socket. This is an admin operation.
+    fn admin_operation(
+        &self,
+        _app_name: ApplicationName,
+        _op: AdminOperation,
+    ) -> Result<AdminResult> {
+        Err(ResponseStatus::PsaErrorNotSupported)
+    }
+
     /// Execute a Ping operation to get the wire protocol version major and minor information.
     ///
     /// # Errors
@@ -138,6 +186,15 @@ pub trait Provide {
         Err(ResponseStatus::PsaErrorNotSupported)
     }
 
+    /// Execute a ExportKey operation.
+    fn psa_export_key(
+        &self,
+        _app_name: ApplicationName,
+        _op: psa_export_key::Operation,
+    ) -> Result<psa_export_key::Result> {
+        Err(ResponseStatus::PsaErrorNotSupported)
+    }
+
     /// Execute a DestroyKey operation.
     fn psa_destroy_key(
         &self,
@@ -165,4 +222,91 @@ pub trait Provide {
     ) -> Result<psa_verify_hash::Result> {
         Err(ResponseStatus::PsaErrorNotSupported)
     }
+
+    /// Execute a HashCompute operation.
+    fn psa_hash_compute(
+        &self,
+        _op: psa_hash_compute::Operation,
+    ) -> Result<psa_hash_compute::Result> {
+        Err(ResponseStatus::PsaErrorNotSupported)
+    }
+
+    /// Execute a HashCompare operation.
+    fn psa_hash_compare(
+        &self,
+        _op: psa_hash_compare::Operation,
+    ) -> Result<psa_hash_compare::Result> {
+        Err(ResponseStatus::PsaErrorNotSupported)
+    }
+
+    /// Execute an AsymmetricEncrypt operation.
+    fn psa_asymmetric_encrypt(
+        &self,
+        _app_name: ApplicationName,
+        _op: psa_asymmetric_encrypt::Operation,
+    ) -> Result<psa_asymmetric_encrypt::Result> {
+        Err(ResponseStatus::PsaErrorNotSupported)
+    }
+
+    /// Execute an AsymmetricDecrypt operation.
+    fn psa_asymmetric_decrypt(
+        &self,
+        _app_name: ApplicationName,
+        _op: psa_asymmetric_decrypt::Operation,
+    ) -> Result<psa_asymmetric_decrypt::Result> {
+        Err(ResponseStatus::PsaErrorNotSupported)
+    }
+
+    /// Execute an AeadEncrypt operation.
+    fn psa_aead_encrypt(
+        &self,
+        _app_name: ApplicationName,
+        _op: psa_aead_encrypt::Operation,
+    ) -> Result<psa_aead_encrypt::Result> {
+        Err(ResponseStatus::PsaErrorNotSupported)
+    }
+
+    /// Execute an AeadDecrypt operation.
+    fn psa_aead_decrypt(
+        &self,
+        _app_name: ApplicationName,
+        _op: psa_aead_decrypt::Operation,
+    ) -> Result<psa_aead_decrypt::Result> {
+        Err(ResponseStatus::PsaErrorNotSupported)
+    }
+
+    /// Execute a CipherEncrypt operation.
+    fn psa_cipher_encrypt(
+        &self,
+        _app_name: ApplicationName,
+        _op: psa_cipher_encrypt::Operation,
+    ) -> Result<psa_cipher_encrypt::Result> {
+        Err(ResponseStatus::PsaErrorNotSupported)
+    }
+
+    /// Execute a CipherDecrypt operation.
+    fn psa_cipher_decrypt(
+        &self,
+        _app_name: ApplicationName,
+        _op: psa_cipher_decrypt::Operation,
+    ) -> Result<psa_cipher_decrypt::Result> {
+        Err(ResponseStatus::PsaErrorNotSupported)
+    }
+
+    /// Execute a GenerateRandom operation.
+    fn psa_generate_random(
+        &self,
+        _op: psa_generate_random::Operation,
+    ) -> Result<psa_generate_random::Result> {
+        Err(ResponseStatus::PsaErrorNotSupported)
+    }
+
+    /// Execute a RawKeyAgreement operation.
+    fn psa_raw_key_agreement(
+        &self,
+        _app_name: ApplicationName,
+        _op: psa_raw_key_agreement::Operation,
+    ) -> Result<psa_raw_key_agreement::Result> {
+        Err(ResponseStatus::PsaErrorNotSupported)
+    }
 }
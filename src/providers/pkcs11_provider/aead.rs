@@ -0,0 +1,171 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+use super::Pkcs11Provider;
+use super::{key_management::get_key_info, utils, KeyPairType, ReadWriteSession, Session};
+use crate::authenticators::ApplicationName;
+use crate::key_info_managers::KeyTriple;
+use log::{error, info};
+use parsec_interface::operations::psa_algorithm::{Aead, AeadWithDefaultLengthTag};
+use parsec_interface::operations::{psa_aead_decrypt, psa_aead_encrypt};
+use parsec_interface::requests::{ProviderId, ResponseStatus, Result};
+use pkcs11::types::{CK_BYTE_PTR, CK_GCM_PARAMS, CK_MECHANISM, CK_VOID_PTR};
+use std::mem;
+
+// Length in bytes of the tag of the AEAD algorithms when it is not shortened.
+const DEFAULT_TAG_LENGTH: usize = 16;
+
+/// Mechanism used to encrypt, or decrypt, with an AEAD algorithm.
+struct AeadMechanism {
+    gcm_params: CK_GCM_PARAMS,
+}
+
+impl AeadMechanism {
+    /// Only AES-GCM is supported. The nonce and the additional data have to outlive the
+    /// mechanism.
+    fn new(alg: Aead, nonce: &[u8], additional_data: &[u8]) -> Result<AeadMechanism> {
+        let tag_length = match alg {
+            Aead::AeadWithDefaultLengthTag(AeadWithDefaultLengthTag::Gcm) => DEFAULT_TAG_LENGTH,
+            Aead::AeadWithShortenedTag {
+                aead_alg: AeadWithDefaultLengthTag::Gcm,
+                tag_length,
+            } => tag_length,
+            _ => {
+                error!("The PKCS 11 provider only supports the AES-GCM AEAD algorithm.");
+                return Err(ResponseStatus::PsaErrorNotSupported);
+            }
+        };
+
+        Ok(AeadMechanism {
+            gcm_params: CK_GCM_PARAMS {
+                pIv: nonce.as_ptr() as CK_BYTE_PTR,
+                ulIvLen: nonce.len(),
+                ulIvBits: nonce.len() * 8,
+                pAAD: additional_data.as_ptr() as CK_BYTE_PTR,
+                ulAADLen: additional_data.len(),
+                ulTagBits: tag_length * 8,
+            },
+        })
+    }
+
+    /// The returned mechanism points to the parameters stored in this structure.
+    fn as_ck_mechanism(&mut self) -> CK_MECHANISM {
+        let gcm_params: *mut CK_GCM_PARAMS = &mut self.gcm_params;
+        CK_MECHANISM {
+            mechanism: pkcs11::types::CKM_AES_GCM,
+            pParameter: gcm_params as CK_VOID_PTR,
+            ulParameterLen: mem::size_of::<CK_GCM_PARAMS>(),
+        }
+    }
+}
+
+impl Pkcs11Provider {
+    pub(super) fn psa_aead_encrypt_internal(
+        &self,
+        app_name: ApplicationName,
+        op: psa_aead_encrypt::Operation,
+    ) -> Result<psa_aead_encrypt::Result> {
+        info!("Pkcs11 Provider - AEAD Encrypt");
+
+        let key_triple = KeyTriple::new(app_name, ProviderId::Pkcs11, op.key_name.clone());
+        let (key_id, key_attributes) = get_key_info(
+            &key_triple,
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+        )?;
+
+        op.validate(key_attributes)?;
+
+        let mut mechanism = AeadMechanism::new(op.alg, &op.nonce, &op.additional_data)?;
+        let mech = mechanism.as_ck_mechanism();
+
+        let session = Session::new(self, ReadWriteSession::ReadWrite)?;
+        info!(
+            "AEAD encrypt in session {} with {:?}",
+            session.session_handle(),
+            op.alg
+        );
+
+        let key = self.find_key(session.session_handle(), key_id, KeyPairType::SecretKey)?;
+        info!("Located encrypting key.");
+
+        match self
+            .backend
+            .encrypt_init(session.session_handle(), &mech, key)
+        {
+            Ok(_) => {
+                info!("Encrypt operation initialized.");
+                // The ciphertext returned by the token is followed by the tag.
+                match self
+                    .backend
+                    .encrypt(session.session_handle(), &op.plaintext)
+                {
+                    Ok(ciphertext) => Ok(psa_aead_encrypt::Result {
+                        ciphertext: ciphertext.into(),
+                    }),
+                    Err(e) => {
+                        error!("Failed to execute encrypting operation. Error: {}", e);
+                        Err(utils::to_response_status(e))
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Failed to initialize encrypting operation. Error: {}", e);
+                Err(utils::to_response_status(e))
+            }
+        }
+    }
+
+    pub(super) fn psa_aead_decrypt_internal(
+        &self,
+        app_name: ApplicationName,
+        op: psa_aead_decrypt::Operation,
+    ) -> Result<psa_aead_decrypt::Result> {
+        info!("Pkcs11 Provider - AEAD Decrypt");
+
+        let key_triple = KeyTriple::new(app_name, ProviderId::Pkcs11, op.key_name.clone());
+        let (key_id, key_attributes) = get_key_info(
+            &key_triple,
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+        )?;
+
+        op.validate(key_attributes)?;
+
+        let mut mechanism = AeadMechanism::new(op.alg, &op.nonce, &op.additional_data)?;
+        let mech = mechanism.as_ck_mechanism();
+
+        let session = Session::new(self, ReadWriteSession::ReadWrite)?;
+        info!(
+            "AEAD decrypt in session {} with {:?}",
+            session.session_handle(),
+            op.alg
+        );
+
+        let key = self.find_key(session.session_handle(), key_id, KeyPairType::SecretKey)?;
+        info!("Located decrypting key.");
+
+        match self
+            .backend
+            .decrypt_init(session.session_handle(), &mech, key)
+        {
+            Ok(_) => {
+                info!("Decrypt operation initialized.");
+                // The token expects the ciphertext to be followed by the tag.
+                match self
+                    .backend
+                    .decrypt(session.session_handle(), &op.ciphertext)
+                {
+                    Ok(plaintext) => Ok(psa_aead_decrypt::Result {
+                        plaintext: plaintext.into(),
+                    }),
+                    Err(e) => {
+                        error!("Failed to execute decrypting operation. Error: {}", e);
+                        Err(utils::to_response_status(e))
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Failed to initialize decrypting operation. Error: {}", e);
+                Err(utils::to_response_status(e))
+            }
+        }
+    }
+}
@@ -0,0 +1,222 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+use super::Pkcs11Provider;
+use super::{key_management::get_key_info, utils, KeyPairType, ReadWriteSession, Session};
+use crate::authenticators::ApplicationName;
+use crate::key_info_managers::KeyTriple;
+use log::{error, info};
+use parsec_interface::operations::psa_algorithm::AsymmetricEncryption;
+use parsec_interface::operations::{psa_asymmetric_decrypt, psa_asymmetric_encrypt};
+use parsec_interface::requests::{ProviderId, ResponseStatus, Result};
+use pkcs11::types::{CK_MECHANISM, CK_RSA_PKCS_OAEP_PARAMS, CK_VOID_PTR};
+use std::mem;
+
+/// Mechanism used to encrypt, or decrypt, with one of the supported algorithms.
+struct EncryptionMechanism {
+    alg: AsymmetricEncryption,
+    oaep_params: Option<CK_RSA_PKCS_OAEP_PARAMS>,
+}
+
+impl EncryptionMechanism {
+    /// Supported algorithms are RSA PKCS#1 v1.5 encryption and RSA OAEP encryption with SHA-224,
+    /// SHA-256, SHA-384 or SHA-512. The salt is used as the OAEP label: it has to outlive the
+    /// mechanism. PKCS#1 v1.5 encryption does not take a salt.
+    fn new(alg: AsymmetricEncryption, salt: Option<&[u8]>) -> Result<EncryptionMechanism> {
+        let oaep_params = match alg {
+            AsymmetricEncryption::RsaPkcs1v15Crypt => match salt {
+                Some(salt) if !salt.is_empty() => {
+                    error!("A salt can not be used with the RSA PKCS#1 v1.5 encryption.");
+                    return Err(ResponseStatus::PsaErrorInvalidArgument);
+                }
+                _ => None,
+            },
+            AsymmetricEncryption::RsaOaep { hash_alg } => {
+                let (hash_mech, mgf) = utils::convert_hash_to_mechanisms(hash_alg)?;
+                let (source_data, source_data_len) = match salt {
+                    Some(salt) if !salt.is_empty() => (salt.as_ptr() as CK_VOID_PTR, salt.len()),
+                    _ => (std::ptr::null_mut(), 0),
+                };
+                Some(CK_RSA_PKCS_OAEP_PARAMS {
+                    hashAlg: hash_mech,
+                    mgf,
+                    source: pkcs11::types::CKZ_DATA_SPECIFIED,
+                    pSourceData: source_data,
+                    ulSourceDataLen: source_data_len,
+                })
+            }
+        };
+
+        Ok(EncryptionMechanism { alg, oaep_params })
+    }
+
+    /// The returned mechanism points to the parameters stored in this structure.
+    fn as_ck_mechanism(&mut self) -> CK_MECHANISM {
+        match self.oaep_params {
+            Some(ref mut oaep_params) => {
+                let oaep_params: *mut CK_RSA_PKCS_OAEP_PARAMS = oaep_params;
+                CK_MECHANISM {
+                    mechanism: pkcs11::types::CKM_RSA_PKCS_OAEP,
+                    pParameter: oaep_params as CK_VOID_PTR,
+                    ulParameterLen: mem::size_of::<CK_RSA_PKCS_OAEP_PARAMS>(),
+                }
+            }
+            None => CK_MECHANISM {
+                mechanism: pkcs11::types::CKM_RSA_PKCS,
+                pParameter: std::ptr::null_mut(),
+                ulParameterLen: 0,
+            },
+        }
+    }
+}
+
+impl Pkcs11Provider {
+    pub(super) fn psa_asymmetric_encrypt_internal(
+        &self,
+        app_name: ApplicationName,
+        op: psa_asymmetric_encrypt::Operation,
+    ) -> Result<psa_asymmetric_encrypt::Result> {
+        info!("Pkcs11 Provider - Asym Encrypt");
+
+        let key_triple = KeyTriple::new(app_name, ProviderId::Pkcs11, op.key_name.clone());
+        let (key_id, key_attributes) = get_key_info(
+            &key_triple,
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+        )?;
+
+        op.validate(key_attributes)?;
+
+        let salt = op.salt.as_ref().map(|salt| salt.as_slice());
+        let mut mechanism = EncryptionMechanism::new(op.alg, salt)?;
+        let mech = mechanism.as_ck_mechanism();
+
+        let session = Session::new(self, ReadWriteSession::ReadWrite)?;
+        info!(
+            "Asymmetric encrypt in session {} with {:?}",
+            session.session_handle(),
+            mechanism.alg
+        );
+
+        let key = self.find_key(session.session_handle(), key_id, KeyPairType::PublicKey)?;
+        info!("Located encrypting key.");
+
+        match self
+            .backend
+            .encrypt_init(session.session_handle(), &mech, key)
+        {
+            Ok(_) => {
+                info!("Encrypt operation initialized.");
+                match self
+                    .backend
+                    .encrypt(session.session_handle(), &op.plaintext)
+                {
+                    Ok(ciphertext) => Ok(psa_asymmetric_encrypt::Result {
+                        ciphertext: ciphertext.into(),
+                    }),
+                    Err(e) => {
+                        error!("Failed to execute encrypting operation. Error: {}", e);
+                        Err(utils::to_response_status(e))
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Failed to initialize encrypting operation. Error: {}", e);
+                Err(utils::to_response_status(e))
+            }
+        }
+    }
+
+    pub(super) fn psa_asymmetric_decrypt_internal(
+        &self,
+        app_name: ApplicationName,
+        op: psa_asymmetric_decrypt::Operation,
+    ) -> Result<psa_asymmetric_decrypt::Result> {
+        info!("Pkcs11 Provider - Asym Decrypt");
+
+        let key_triple = KeyTriple::new(app_name, ProviderId::Pkcs11, op.key_name.clone());
+        let (key_id, key_attributes) = get_key_info(
+            &key_triple,
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+        )?;
+
+        op.validate(key_attributes)?;
+
+        let salt = op.salt.as_ref().map(|salt| salt.as_slice());
+        let mut mechanism = EncryptionMechanism::new(op.alg, salt)?;
+        let mech = mechanism.as_ck_mechanism();
+
+        let session = Session::new(self, ReadWriteSession::ReadWrite)?;
+        info!(
+            "Asymmetric decrypt in session {} with {:?}",
+            session.session_handle(),
+            mechanism.alg
+        );
+
+        let key = self.find_key(session.session_handle(), key_id, KeyPairType::PrivateKey)?;
+        info!("Located decrypting key.");
+
+        match self
+            .backend
+            .decrypt_init(session.session_handle(), &mech, key)
+        {
+            Ok(_) => {
+                info!("Decrypt operation initialized.");
+                match self
+                    .backend
+                    .decrypt(session.session_handle(), &op.ciphertext)
+                {
+                    Ok(plaintext) => Ok(psa_asymmetric_decrypt::Result {
+                        plaintext: plaintext.into(),
+                    }),
+                    Err(e) => {
+                        error!("Failed to execute decrypting operation. Error: {}", e);
+                        Err(utils::to_response_status(e))
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Failed to initialize decrypting operation. Error: {}", e);
+                Err(utils::to_response_status(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parsec_interface::operations::psa_algorithm::Hash;
+
+    #[test]
+    fn salt_only_used_with_oaep() {
+        let salt = [0x5a; 16];
+        assert_eq!(
+            EncryptionMechanism::new(AsymmetricEncryption::RsaPkcs1v15Crypt, Some(&salt))
+                .err()
+                .unwrap(),
+            ResponseStatus::PsaErrorInvalidArgument
+        );
+
+        let mut mechanism =
+            EncryptionMechanism::new(AsymmetricEncryption::RsaPkcs1v15Crypt, Some(&[])).unwrap();
+        assert_eq!(
+            mechanism.as_ck_mechanism().mechanism,
+            pkcs11::types::CKM_RSA_PKCS
+        );
+
+        let mut mechanism = EncryptionMechanism::new(
+            AsymmetricEncryption::RsaOaep {
+                hash_alg: Hash::Sha256,
+            },
+            Some(&salt),
+        )
+        .unwrap();
+        assert_eq!(
+            mechanism.oaep_params.as_ref().unwrap().ulSourceDataLen,
+            salt.len()
+        );
+        assert_eq!(
+            mechanism.as_ck_mechanism().mechanism,
+            pkcs11::types::CKM_RSA_PKCS_OAEP
+        );
+    }
+}
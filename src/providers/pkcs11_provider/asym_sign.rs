@@ -7,11 +7,12 @@ use crate::key_info_managers::KeyTriple;
 use log::{error, info};
 use parsec_interface::operations::psa_algorithm::*;
 use parsec_interface::operations::{psa_sign_hash, psa_verify_hash};
-use parsec_interface::requests::{ProviderID, ResponseStatus, Result};
+use parsec_interface::requests::{ProviderId, ResponseStatus, Result};
 use picky::{algorithm_identifier::SHAVariant, AlgorithmIdentifier};
 use picky_asn1::wrapper::OctetStringAsn1;
-use pkcs11::types::CK_MECHANISM;
+use pkcs11::types::{CK_MECHANISM, CK_MECHANISM_TYPE, CK_RSA_PKCS_PSS_PARAMS, CK_VOID_PTR};
 use serde::{Deserialize, Serialize};
+use std::mem;
 
 #[derive(Serialize, Deserialize)]
 struct DigestInfo {
@@ -19,6 +20,94 @@ struct DigestInfo {
     digest: OctetStringAsn1,
 }
 
+/// Mechanism used to sign, or verify, a hash with one of the supported algorithms and the data
+/// to give to the token.
+struct SignatureMechanism {
+    mechanism: CK_MECHANISM_TYPE,
+    pss_params: Option<CK_RSA_PKCS_PSS_PARAMS>,
+    data: Vec<u8>,
+}
+
+impl SignatureMechanism {
+    /// Supported algorithms are RSA PKCS#1 v1.5 signatures with SHA-256 and RSA PSS signatures
+    /// with SHA-224, SHA-256, SHA-384 or SHA-512.
+    fn new(alg: AsymmetricSignature, hash: &[u8]) -> Result<SignatureMechanism> {
+        match alg {
+            AsymmetricSignature::RsaPkcs1v15Sign {
+                hash_alg: SignHash::Specific(Hash::Sha256),
+            } => {
+                check_hash_length(Hash::Sha256, hash)?;
+                let digest_info = DigestInfo {
+                    oid: AlgorithmIdentifier::new_sha(SHAVariant::SHA2_256),
+                    digest: hash.to_vec().into(),
+                };
+                let digest_info = picky_asn1_der::to_vec(&digest_info)
+                    // should not fail - if it does, there's some error in our stack
+                    .or(Err(ResponseStatus::PsaErrorGenericError))?;
+                Ok(SignatureMechanism {
+                    // Sign, or verify, without hashing.
+                    mechanism: pkcs11::types::CKM_RSA_PKCS,
+                    pss_params: None,
+                    data: digest_info,
+                })
+            }
+            AsymmetricSignature::RsaPss {
+                hash_alg: SignHash::Specific(hash_alg),
+            } => {
+                let (hash_mech, mgf) = utils::convert_hash_to_mechanisms(hash_alg)?;
+                check_hash_length(hash_alg, hash)?;
+                Ok(SignatureMechanism {
+                    mechanism: pkcs11::types::CKM_RSA_PKCS_PSS,
+                    // The salt is as long as the hash, as required by PSA.
+                    pss_params: Some(CK_RSA_PKCS_PSS_PARAMS {
+                        hashAlg: hash_mech,
+                        mgf,
+                        sLen: hash.len(),
+                    }),
+                    data: hash.to_vec(),
+                })
+            }
+            _ => {
+                error!(
+                    "The PKCS 11 provider currently only supports \"RSA PKCS#1 v1.5 signature with hashing\" algorithm with SHA-256 and \"RSA PSS\" algorithm with SHA-224, SHA-256, SHA-384 or SHA-512 as hashing algorithm.");
+                Err(ResponseStatus::PsaErrorNotSupported)
+            }
+        }
+    }
+
+    /// The returned mechanism points to the parameters stored in this structure.
+    fn as_ck_mechanism(&mut self) -> CK_MECHANISM {
+        match self.pss_params {
+            Some(ref mut pss_params) => {
+                let pss_params: *mut CK_RSA_PKCS_PSS_PARAMS = pss_params;
+                CK_MECHANISM {
+                    mechanism: self.mechanism,
+                    pParameter: pss_params as CK_VOID_PTR,
+                    ulParameterLen: mem::size_of::<CK_RSA_PKCS_PSS_PARAMS>(),
+                }
+            }
+            None => CK_MECHANISM {
+                mechanism: self.mechanism,
+                pParameter: std::ptr::null_mut(),
+                ulParameterLen: 0,
+            },
+        }
+    }
+}
+
+fn check_hash_length(hash_alg: Hash, hash: &[u8]) -> Result<()> {
+    if hash.len() != hash_alg.hash_length() {
+        error!(
+            "The {:?} hash must be {} bytes long.",
+            hash_alg,
+            hash_alg.hash_length()
+        );
+        return Err(ResponseStatus::PsaErrorInvalidArgument);
+    }
+
+    Ok(())
+}
+
 impl Pkcs11Provider {
     pub(super) fn psa_sign_hash_internal(
         &self,
@@ -30,45 +119,18 @@ impl Pkcs11Provider {
         let key_name = op.key_name;
         let hash = op.hash;
         let alg = op.alg;
-        let key_triple = KeyTriple::new(app_name, ProviderID::Pkcs11, key_name);
-        let store_handle = self.key_info_store.read().expect("Key store lock poisoned");
-        let (key_id, key_attributes) = get_key_info(&key_triple, &*store_handle)?;
+        let key_triple = KeyTriple::new(app_name, ProviderId::Pkcs11, key_name);
+        let (key_id, key_attributes) = get_key_info(
+            &key_triple,
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+        )?;
 
         key_attributes.can_sign_hash()?;
         key_attributes.permits_alg(alg.into())?;
         key_attributes.compatible_with_alg(alg.into())?;
 
-        match alg {
-            AsymmetricSignature::RsaPkcs1v15Sign {
-                hash_alg: SignHash::Specific(Hash::Sha256),
-            } => (),
-            _ => {
-                error!(
-                    "The PKCS 11 provider currently only supports \"RSA PKCS#1 v1.5 signature with hashing\" algorithm with SHA-256 as hashing algorithm for the PsaSignHash operation.");
-                return Err(ResponseStatus::PsaErrorNotSupported);
-            }
-        }
-
-        if alg
-            != (AsymmetricSignature::RsaPkcs1v15Sign {
-                hash_alg: SignHash::Specific(Hash::Sha256),
-            })
-        {
-            error!(
-                "The PKCS 11 provider currently only supports signature algorithm to be RSA PKCS#1 v1.5 and the text hashed with SHA-256.");
-            return Err(ResponseStatus::PsaErrorNotSupported);
-        }
-
-        if hash.len() != 32 {
-            error!("The SHA-256 hash must be 32 bytes long.");
-            return Err(ResponseStatus::PsaErrorInvalidArgument);
-        }
-
-        let mech = CK_MECHANISM {
-            mechanism: pkcs11::types::CKM_RSA_PKCS,
-            pParameter: std::ptr::null_mut(),
-            ulParameterLen: 0,
-        };
+        let mut mechanism = SignatureMechanism::new(alg, &hash)?;
+        let mech = mechanism.as_ck_mechanism();
 
         let session = Session::new(self, ReadWriteSession::ReadWrite)?;
         info!("Asymmetric sign in session {}", session.session_handle());
@@ -79,16 +141,10 @@ impl Pkcs11Provider {
         match self.backend.sign_init(session.session_handle(), &mech, key) {
             Ok(_) => {
                 info!("Signing operation initialized.");
-                let digest_info = DigestInfo {
-                    oid: AlgorithmIdentifier::new_sha(SHAVariant::SHA2_256),
-                    digest: hash.into(),
-                };
-                let digest_info = picky_asn1_der::to_vec(&digest_info)
-                    // should not fail - if it does, there's some error in our stack
-                    .or(Err(ResponseStatus::PsaErrorGenericError))?;
-
-                match self.backend.sign(session.session_handle(), &digest_info) {
-                    Ok(signature) => Ok(psa_sign_hash::Result { signature }),
+                match self.backend.sign(session.session_handle(), &mechanism.data) {
+                    Ok(signature) => Ok(psa_sign_hash::Result {
+                        signature: signature.into(),
+                    }),
                     Err(e) => {
                         error!("Failed to execute signing operation. Error: {}", e);
                         Err(utils::to_response_status(e))
@@ -113,46 +169,18 @@ impl Pkcs11Provider {
         let hash = op.hash;
         let signature = op.signature;
         let alg = op.alg;
-        let key_triple = KeyTriple::new(app_name, ProviderID::Pkcs11, key_name);
-        let store_handle = self.key_info_store.read().expect("Key store lock poisoned");
-        let (key_id, key_attributes) = get_key_info(&key_triple, &*store_handle)?;
+        let key_triple = KeyTriple::new(app_name, ProviderId::Pkcs11, key_name);
+        let (key_id, key_attributes) = get_key_info(
+            &key_triple,
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+        )?;
 
         key_attributes.can_verify_hash()?;
         key_attributes.permits_alg(alg.into())?;
         key_attributes.compatible_with_alg(alg.into())?;
 
-        match alg {
-            AsymmetricSignature::RsaPkcs1v15Sign {
-                hash_alg: SignHash::Specific(Hash::Sha256),
-            } => (),
-            _ => {
-                error!(
-                    "The PKCS 11 provider currently only supports \"RSA PKCS#1 v1.5 signature with hashing\" algorithm with SHA-256 as hashing algorithm for the PsaVerifyHash operation.");
-                return Err(ResponseStatus::PsaErrorNotSupported);
-            }
-        }
-
-        if alg
-            != (AsymmetricSignature::RsaPkcs1v15Sign {
-                hash_alg: SignHash::Specific(Hash::Sha256),
-            })
-        {
-            error!(
-                "The PKCS 11 provider currently only supports signature algorithm to be RSA PKCS#1 v1.5 and the text hashed with SHA-256.");
-            return Err(ResponseStatus::PsaErrorNotSupported);
-        }
-
-        if hash.len() != 32 {
-            error!("The SHA-256 hash must be 32 bytes long.");
-            return Err(ResponseStatus::PsaErrorInvalidArgument);
-        }
-
-        let mech = CK_MECHANISM {
-            // Verify without hashing.
-            mechanism: pkcs11::types::CKM_RSA_PKCS,
-            pParameter: std::ptr::null_mut(),
-            ulParameterLen: 0,
-        };
+        let mut mechanism = SignatureMechanism::new(alg, &hash)?;
+        let mech = mechanism.as_ck_mechanism();
 
         let session = Session::new(self, ReadWriteSession::ReadWrite)?;
         info!("Asymmetric verify in session {}", session.session_handle());
@@ -166,17 +194,9 @@ impl Pkcs11Provider {
         {
             Ok(_) => {
                 info!("Verify operation initialized.");
-                let digest_info = DigestInfo {
-                    oid: AlgorithmIdentifier::new_sha(SHAVariant::SHA2_256),
-                    digest: hash.into(),
-                };
-                let digest_info = picky_asn1_der::to_vec(&digest_info)
-                    // should not fail - if it does, there's some error in our stack
-                    .or(Err(ResponseStatus::PsaErrorGenericError))?;
-
                 match self
                     .backend
-                    .verify(session.session_handle(), &digest_info, &signature)
+                    .verify(session.session_handle(), &mechanism.data, &signature)
                 {
                     Ok(_) => Ok(psa_verify_hash::Result {}),
                     Err(e) => Err(utils::to_response_status(e)),
@@ -0,0 +1,70 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+use super::Pkcs11Provider;
+use super::{utils, ReadWriteSession, Session};
+use log::{error, info};
+use parsec_interface::operations::psa_algorithm::Hash;
+use parsec_interface::operations::{psa_hash_compare, psa_hash_compute};
+use parsec_interface::requests::{ResponseStatus, Result};
+use pkcs11::types::CK_MECHANISM;
+
+impl Pkcs11Provider {
+    /// Computes the digest of the input in the token. Only the SHA-2 hash algorithms are
+    /// supported.
+    fn digest(&self, alg: Hash, input: &[u8]) -> Result<Vec<u8>> {
+        let (hash_mech, _) = utils::convert_hash_to_mechanisms(alg)?;
+        let mech = CK_MECHANISM {
+            mechanism: hash_mech,
+            pParameter: std::ptr::null_mut(),
+            ulParameterLen: 0,
+        };
+
+        let session = Session::new(self, ReadWriteSession::ReadOnly)?;
+        info!(
+            "Computing {:?} digest in session {}",
+            alg,
+            session.session_handle()
+        );
+
+        match self.backend.digest_init(session.session_handle(), &mech) {
+            Ok(_) => {
+                info!("Digest operation initialized.");
+                self.backend
+                    .digest(session.session_handle(), input)
+                    .map_err(|e| {
+                        error!("Failed to execute digest operation. Error: {}", e);
+                        utils::to_response_status(e)
+                    })
+            }
+            Err(e) => {
+                error!("Failed to initialize digest operation. Error: {}", e);
+                Err(utils::to_response_status(e))
+            }
+        }
+    }
+
+    pub(super) fn psa_hash_compute_internal(
+        &self,
+        op: psa_hash_compute::Operation,
+    ) -> Result<psa_hash_compute::Result> {
+        info!("Pkcs11 Provider - Hash Compute");
+
+        let hash = self.digest(op.alg, &op.input)?;
+        Ok(psa_hash_compute::Result { hash: hash.into() })
+    }
+
+    pub(super) fn psa_hash_compare_internal(
+        &self,
+        op: psa_hash_compare::Operation,
+    ) -> Result<psa_hash_compare::Result> {
+        info!("Pkcs11 Provider - Hash Compare");
+
+        let hash = self.digest(op.alg, &op.input)?;
+        if hash[..] == op.hash[..] {
+            Ok(psa_hash_compare::Result {})
+        } else {
+            error!("The computed hash does not match the reference one.");
+            Err(ResponseStatus::PsaErrorInvalidSignature)
+        }
+    }
+}
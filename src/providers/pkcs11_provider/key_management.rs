@@ -5,21 +5,36 @@ use super::{
     Session,
 };
 use crate::authenticators::ApplicationName;
-use crate::key_info_managers::KeyTriple;
-use crate::key_info_managers::{self, ManageKeyInfo};
-use log::{error, info, warn};
+use crate::key_info_managers::{self, KeyTriple, KeyTripleReservation, ManageKeyInfo};
+use log::{error, info};
 use parsec_interface::operations::psa_key_attributes::*;
 use parsec_interface::operations::{
     psa_destroy_key, psa_export_public_key, psa_generate_key, psa_import_key,
 };
-use parsec_interface::requests::{ProviderID, ResponseStatus, Result};
+use parsec_interface::requests::{ProviderId, ResponseStatus, Result};
+use parsec_interface::secrecy::ExposeSecret;
 use picky_asn1::wrapper::IntegerAsn1;
-use pkcs11::types::{CKR_OK, CK_ATTRIBUTE, CK_MECHANISM, CK_OBJECT_HANDLE, CK_SESSION_HANDLE};
+use pkcs11::types::{
+    CKR_OK, CK_ATTRIBUTE, CK_BBOOL, CK_MECHANISM, CK_OBJECT_HANDLE, CK_SESSION_HANDLE,
+};
 use std::mem;
+use std::sync::RwLock;
 
 // Public exponent value for all RSA keys.
 const PUBLIC_EXPONENT: [u8; 3] = [0x01, 0x00, 0x01];
 
+/// Gets the length in bytes of an AES key of the given size in bits. Only 128, 192 and 256 bits
+/// AES keys exist.
+fn aes_key_length(bits: usize) -> Result<usize> {
+    match bits {
+        128 | 192 | 256 => Ok(bits / 8),
+        _ => {
+            error!("AES keys of {} bits are not supported.", bits);
+            Err(ResponseStatus::PsaErrorNotSupported)
+        }
+    }
+}
+
 /// Gets a key identifier and key attributes from the Key Info Manager.
 pub fn get_key_info(
     key_triple: &KeyTriple,
@@ -41,58 +56,86 @@ pub fn get_key_info(
     }
 }
 
-pub fn create_key_id(
-    key_triple: KeyTriple,
-    key_attributes: Attributes,
-    store_handle: &mut dyn ManageKeyInfo,
-    local_ids_handle: &mut LocalIdStore,
-) -> Result<[u8; 4]> {
+/// Converts the encrypt and decrypt usage flags of the key to the values of the `CKA_ENCRYPT` and
+/// `CKA_DECRYPT` attributes.
+fn usage_flags_to_bools(key_attributes: &Attributes) -> (CK_BBOOL, CK_BBOOL) {
+    let to_bool = |flag: bool| {
+        if flag {
+            pkcs11::types::CK_TRUE
+        } else {
+            pkcs11::types::CK_FALSE
+        }
+    };
+    let usage_flags = &key_attributes.policy.usage_flags;
+    (
+        to_bool(usage_flags.encrypt()),
+        to_bool(usage_flags.decrypt()),
+    )
+}
+
+/// Picks a key ID which is not used by the provider and marks it as used.
+pub fn create_key_id(local_ids: &RwLock<LocalIdStore>) -> [u8; 4] {
+    let mut local_ids_handle = local_ids.write().expect("Local ID lock poisoned");
     let mut key_id = rand::random::<[u8; 4]>();
     while local_ids_handle.contains(&key_id) {
         key_id = rand::random::<[u8; 4]>();
     }
+    let _ = local_ids_handle.insert(key_id);
+
+    key_id
+}
+
+/// Marks a key ID as unused, when the key could not be created.
+pub fn release_key_id(key_id: [u8; 4], local_ids: &RwLock<LocalIdStore>) {
+    let _ = local_ids
+        .write()
+        .expect("Local ID lock poisoned")
+        .remove(&key_id);
+}
+
+/// Stores the mapping of a key created in the token. If that fails, the key is left in the token
+/// without a mapping and its key ID stays used.
+pub fn commit_key_id(
+    reservation: KeyTripleReservation,
+    key_id: [u8; 4],
+    key_attributes: Attributes,
+) -> Result<()> {
+    let key_triple = reservation.key_triple().clone();
     let key_info = KeyInfo {
         id: key_id.to_vec(),
         attributes: key_attributes,
     };
-    match store_handle.insert(key_triple.clone(), key_info) {
-        Ok(insert_option) => {
-            if insert_option.is_some() {
-                warn!("Overwriting Key triple mapping ({})", key_triple);
-            }
-            let _ = local_ids_handle.insert(key_id);
-
-            Ok(key_id)
-        }
-        Err(string) => Err(key_info_managers::to_response_status(string)),
+    if let Err(e) = reservation.commit(key_info) {
+        error!(
+            "The mapping of the key created could not be stored ({}), the key can be removed with the reconcile command.",
+            key_triple
+        );
+        return Err(e);
     }
+
+    Ok(())
 }
 
+/// Removes the mapping of a destroyed key and marks its key ID as unused.
 pub fn remove_key_id(
     key_triple: &KeyTriple,
     key_id: [u8; 4],
-    store_handle: &mut dyn ManageKeyInfo,
-    local_ids_handle: &mut LocalIdStore,
+    key_info_store: &RwLock<dyn ManageKeyInfo + Send + Sync>,
+    local_ids: &RwLock<LocalIdStore>,
 ) -> Result<()> {
-    match store_handle.remove(key_triple) {
-        Ok(_) => {
-            let _ = local_ids_handle.remove(&key_id);
-            Ok(())
-        }
-        Err(string) => Err(key_info_managers::to_response_status(string)),
-    }
-}
-
-pub fn key_info_exists(key_triple: &KeyTriple, store_handle: &dyn ManageKeyInfo) -> Result<bool> {
-    match store_handle.exists(key_triple) {
-        Ok(val) => Ok(val),
-        Err(string) => Err(key_info_managers::to_response_status(string)),
-    }
+    let _ = key_info_store
+        .write()
+        .expect("Key store lock poisoned")
+        .remove(key_triple)
+        .map_err(key_info_managers::to_response_status)?;
+    release_key_id(key_id, local_ids);
+
+    Ok(())
 }
 
 impl Pkcs11Provider {
-    /// Find the PKCS 11 object handle corresponding to the key ID and the key type (public or
-    /// private key) given as parameters for the current session.
+    /// Find the PKCS 11 object handle corresponding to the key ID and the key type (public,
+    /// private or secret key) given as parameters for the current session.
     pub(super) fn find_key(
         &self,
         session: CK_SESSION_HANDLE,
@@ -109,6 +152,10 @@ impl Pkcs11Provider {
                 CK_ATTRIBUTE::new(pkcs11::types::CKA_CLASS)
                     .with_ck_ulong(&pkcs11::types::CKO_PRIVATE_KEY),
             ),
+            KeyPairType::SecretKey => template.push(
+                CK_ATTRIBUTE::new(pkcs11::types::CKA_CLASS)
+                    .with_ck_ulong(&pkcs11::types::CKO_SECRET_KEY),
+            ),
             KeyPairType::Any => (),
         }
 
@@ -141,9 +188,15 @@ impl Pkcs11Provider {
     ) -> Result<psa_generate_key::Result> {
         info!("Pkcs11 Provider - Create Key");
 
-        if op.attributes.key_type != Type::RsaKeyPair {
-            error!("The PKCS11 provider currently only supports creating RSA key pairs.");
-            return Err(ResponseStatus::PsaErrorNotSupported);
+        match op.attributes.key_type {
+            Type::RsaKeyPair => (),
+            Type::Aes => return self.generate_aes_key(app_name, op),
+            _ => {
+                error!(
+                    "The PKCS11 provider currently only supports creating RSA key pairs and AES keys."
+                );
+                return Err(ResponseStatus::PsaErrorNotSupported);
+            }
         }
 
         let key_name = op.key_name;
@@ -151,21 +204,10 @@ impl Pkcs11Provider {
         // This should never panic on 32 bits or more machines.
         let key_size = std::convert::TryFrom::try_from(op.attributes.bits).unwrap();
 
-        let key_triple = KeyTriple::new(app_name, ProviderID::Pkcs11, key_name);
-        let mut store_handle = self
-            .key_info_store
-            .write()
-            .expect("Key store lock poisoned");
-        let mut local_ids_handle = self.local_ids.write().expect("Local ID lock poisoned");
-        if key_info_exists(&key_triple, &*store_handle)? {
-            return Err(ResponseStatus::PsaErrorAlreadyExists);
-        }
-        let key_id = create_key_id(
-            key_triple.clone(),
-            key_attributes,
-            &mut *store_handle,
-            &mut local_ids_handle,
-        )?;
+        let key_triple = KeyTriple::new(app_name, ProviderId::Pkcs11, key_name);
+        // The key info manager is not locked while the key is generated, which can take a while.
+        let reservation = KeyTripleReservation::new(&self.key_info_store, key_triple)?;
+        let key_id = create_key_id(&self.local_ids);
 
         let mech = CK_MECHANISM {
             mechanism: pkcs11::types::CKM_RSA_PKCS_KEY_PAIR_GEN,
@@ -173,6 +215,8 @@ impl Pkcs11Provider {
             ulParameterLen: 0,
         };
 
+        // The token enforces the encrypt and decrypt usage flags of the key.
+        let (encrypt, decrypt) = usage_flags_to_bools(&key_attributes);
         let mut priv_template: Vec<CK_ATTRIBUTE> = Vec::new();
         let mut pub_template: Vec<CK_ATTRIBUTE> = Vec::new();
 
@@ -181,6 +225,7 @@ impl Pkcs11Provider {
         priv_template.push(CK_ATTRIBUTE::new(pkcs11::types::CKA_ID).with_bytes(&key_id));
         priv_template
             .push(CK_ATTRIBUTE::new(pkcs11::types::CKA_TOKEN).with_bool(&pkcs11::types::CK_TRUE));
+        priv_template.push(CK_ATTRIBUTE::new(pkcs11::types::CKA_DECRYPT).with_bool(&decrypt));
 
         pub_template
             .push(CK_ATTRIBUTE::new(pkcs11::types::CKA_VERIFY).with_bool(&pkcs11::types::CK_TRUE));
@@ -195,19 +240,16 @@ impl Pkcs11Provider {
         pub_template.push(
             CK_ATTRIBUTE::new(pkcs11::types::CKA_PRIVATE).with_bool(&pkcs11::types::CK_FALSE),
         );
-        pub_template
-            .push(CK_ATTRIBUTE::new(pkcs11::types::CKA_ENCRYPT).with_bool(&pkcs11::types::CK_TRUE));
-
-        let session = Session::new(self, ReadWriteSession::ReadWrite).or_else(|err| {
-            error!("Error creating a new session: {}.", err);
-            remove_key_id(
-                &key_triple,
-                key_id,
-                &mut *store_handle,
-                &mut local_ids_handle,
-            )?;
-            Err(err)
-        })?;
+        pub_template.push(CK_ATTRIBUTE::new(pkcs11::types::CKA_ENCRYPT).with_bool(&encrypt));
+
+        let session = match Session::new(self, ReadWriteSession::ReadWrite) {
+            Ok(session) => session,
+            Err(err) => {
+                error!("Error creating a new session: {}.", err);
+                release_key_id(key_id, &self.local_ids);
+                return Err(err);
+            }
+        };
 
         info!(
             "Generating RSA key pair in session {}",
@@ -220,15 +262,13 @@ impl Pkcs11Provider {
             &pub_template,
             &priv_template,
         ) {
-            Ok(_key) => Ok(psa_generate_key::Result {}),
+            Ok(_key) => {
+                commit_key_id(reservation, key_id, key_attributes)?;
+                Ok(psa_generate_key::Result {})
+            }
             Err(e) => {
                 error!("Generate Key Pair operation failed with {}", e);
-                remove_key_id(
-                    &key_triple,
-                    key_id,
-                    &mut *store_handle,
-                    &mut local_ids_handle,
-                )?;
+                release_key_id(key_id, &self.local_ids);
                 Err(utils::to_response_status(e))
             }
         }
@@ -241,35 +281,29 @@ impl Pkcs11Provider {
     ) -> Result<psa_import_key::Result> {
         info!("Pkcs11 Provider - Import Key");
 
-        if op.attributes.key_type != Type::RsaPublicKey {
-            error!("The PKCS 11 provider currently only supports importing RSA public key.");
-            return Err(ResponseStatus::PsaErrorNotSupported);
+        match op.attributes.key_type {
+            Type::RsaPublicKey => (),
+            Type::Aes => return self.import_aes_key(app_name, op),
+            _ => {
+                error!(
+                    "The PKCS 11 provider currently only supports importing RSA public keys and AES keys."
+                );
+                return Err(ResponseStatus::PsaErrorNotSupported);
+            }
         }
 
         let key_name = op.key_name;
         let key_attributes = op.attributes;
-        let key_triple = KeyTriple::new(app_name, ProviderID::Pkcs11, key_name);
-        let mut store_handle = self
-            .key_info_store
-            .write()
-            .expect("Key store lock poisoned");
-        let mut local_ids_handle = self.local_ids.write().expect("Local ID lock poisoned");
-        if key_info_exists(&key_triple, &*store_handle)? {
-            return Err(ResponseStatus::PsaErrorAlreadyExists);
-        }
-        let key_id = create_key_id(
-            key_triple.clone(),
-            key_attributes,
-            &mut *store_handle,
-            &mut local_ids_handle,
-        )?;
+        let key_triple = KeyTriple::new(app_name, ProviderId::Pkcs11, key_name);
+        let reservation = KeyTripleReservation::new(&self.key_info_store, key_triple)?;
 
         let mut template: Vec<CK_ATTRIBUTE> = Vec::new();
 
-        let public_key: RsaPublicKey = picky_asn1_der::from_bytes(&op.data).or_else(|e| {
-            error!("Failed to parse RsaPublicKey data ({}).", e);
-            Err(ResponseStatus::PsaErrorInvalidArgument)
-        })?;
+        let public_key: RsaPublicKey = picky_asn1_der::from_bytes(op.data.expose_secret())
+            .or_else(|e| {
+                error!("Failed to parse RsaPublicKey data ({}).", e);
+                Err(ResponseStatus::PsaErrorInvalidArgument)
+            })?;
 
         if public_key.modulus.is_negative() || public_key.public_exponent.is_negative() {
             error!("Only positive modulus and public exponent are supported.");
@@ -284,6 +318,9 @@ impl Pkcs11Provider {
             return Err(ResponseStatus::PsaErrorInvalidArgument);
         }
 
+        let (encrypt, _) = usage_flags_to_bools(&key_attributes);
+        let key_id = create_key_id(&self.local_ids);
+
         template.push(
             CK_ATTRIBUTE::new(pkcs11::types::CKA_CLASS)
                 .with_ck_ulong(&pkcs11::types::CKO_PUBLIC_KEY),
@@ -299,15 +336,18 @@ impl Pkcs11Provider {
         );
         template
             .push(CK_ATTRIBUTE::new(pkcs11::types::CKA_VERIFY).with_bool(&pkcs11::types::CK_TRUE));
-        template
-            .push(CK_ATTRIBUTE::new(pkcs11::types::CKA_ENCRYPT).with_bool(&pkcs11::types::CK_TRUE));
+        template.push(CK_ATTRIBUTE::new(pkcs11::types::CKA_ENCRYPT).with_bool(&encrypt));
         template.push(CK_ATTRIBUTE::new(pkcs11::types::CKA_ID).with_bytes(&key_id));
         template.push(
             CK_ATTRIBUTE::new(pkcs11::types::CKA_PRIVATE).with_bool(&pkcs11::types::CK_FALSE),
         );
 
         // Restrict to RSA.
-        let allowed_mechanisms = [pkcs11::types::CKM_RSA_PKCS];
+        let allowed_mechanisms = [
+            pkcs11::types::CKM_RSA_PKCS,
+            pkcs11::types::CKM_RSA_PKCS_PSS,
+            pkcs11::types::CKM_RSA_PKCS_OAEP,
+        ];
         // The attribute contains a pointer to the allowed_mechanism array and its size as
         // ulValueLen.
         let mut allowed_mechanisms_attribute =
@@ -318,16 +358,14 @@ impl Pkcs11Provider {
             as pkcs11::types::CK_VOID_PTR;
         template.push(allowed_mechanisms_attribute);
 
-        let session = Session::new(self, ReadWriteSession::ReadWrite).or_else(|err| {
-            error!("Error creating a new session: {}.", err);
-            remove_key_id(
-                &key_triple,
-                key_id,
-                &mut *store_handle,
-                &mut local_ids_handle,
-            )?;
-            Err(err)
-        })?;
+        let session = match Session::new(self, ReadWriteSession::ReadWrite) {
+            Ok(session) => session,
+            Err(err) => {
+                error!("Error creating a new session: {}.", err);
+                release_key_id(key_id, &self.local_ids);
+                return Err(err);
+            }
+        };
 
         info!(
             "Importing RSA public key in session {}",
@@ -338,15 +376,133 @@ impl Pkcs11Provider {
             .backend
             .create_object(session.session_handle(), &template)
         {
-            Ok(_key) => Ok(psa_import_key::Result {}),
+            Ok(_key) => {
+                commit_key_id(reservation, key_id, key_attributes)?;
+                Ok(psa_import_key::Result {})
+            }
             Err(e) => {
                 error!("Import operation failed with {}", e);
-                remove_key_id(
-                    &key_triple,
-                    key_id,
-                    &mut *store_handle,
-                    &mut local_ids_handle,
-                )?;
+                release_key_id(key_id, &self.local_ids);
+                Err(utils::to_response_status(e))
+            }
+        }
+    }
+
+    /// Generates an AES secret key in the token. The key value can not be extracted.
+    fn generate_aes_key(
+        &self,
+        app_name: ApplicationName,
+        op: psa_generate_key::Operation,
+    ) -> Result<psa_generate_key::Result> {
+        let key_attributes = op.attributes;
+        let key_length = aes_key_length(key_attributes.bits)?;
+
+        let key_triple = KeyTriple::new(app_name, ProviderId::Pkcs11, op.key_name);
+        let reservation = KeyTripleReservation::new(&self.key_info_store, key_triple)?;
+        let key_id = create_key_id(&self.local_ids);
+
+        let mech = CK_MECHANISM {
+            mechanism: pkcs11::types::CKM_AES_KEY_GEN,
+            pParameter: std::ptr::null_mut(),
+            ulParameterLen: 0,
+        };
+
+        let (encrypt, decrypt) = usage_flags_to_bools(&key_attributes);
+        let template = vec![
+            CK_ATTRIBUTE::new(pkcs11::types::CKA_CLASS)
+                .with_ck_ulong(&pkcs11::types::CKO_SECRET_KEY),
+            CK_ATTRIBUTE::new(pkcs11::types::CKA_KEY_TYPE).with_ck_ulong(&pkcs11::types::CKK_AES),
+            CK_ATTRIBUTE::new(pkcs11::types::CKA_VALUE_LEN).with_ck_ulong(&key_length),
+            CK_ATTRIBUTE::new(pkcs11::types::CKA_ID).with_bytes(&key_id),
+            CK_ATTRIBUTE::new(pkcs11::types::CKA_TOKEN).with_bool(&pkcs11::types::CK_TRUE),
+            CK_ATTRIBUTE::new(pkcs11::types::CKA_SENSITIVE).with_bool(&pkcs11::types::CK_TRUE),
+            CK_ATTRIBUTE::new(pkcs11::types::CKA_EXTRACTABLE).with_bool(&pkcs11::types::CK_FALSE),
+            CK_ATTRIBUTE::new(pkcs11::types::CKA_ENCRYPT).with_bool(&encrypt),
+            CK_ATTRIBUTE::new(pkcs11::types::CKA_DECRYPT).with_bool(&decrypt),
+        ];
+
+        let session = match Session::new(self, ReadWriteSession::ReadWrite) {
+            Ok(session) => session,
+            Err(err) => {
+                error!("Error creating a new session: {}.", err);
+                release_key_id(key_id, &self.local_ids);
+                return Err(err);
+            }
+        };
+
+        info!("Generating AES key in session {}", session.session_handle());
+
+        match self
+            .backend
+            .generate_key(session.session_handle(), &mech, &template)
+        {
+            Ok(_key) => {
+                commit_key_id(reservation, key_id, key_attributes)?;
+                Ok(psa_generate_key::Result {})
+            }
+            Err(e) => {
+                error!("Generate Key operation failed with {}", e);
+                release_key_id(key_id, &self.local_ids);
+                Err(utils::to_response_status(e))
+            }
+        }
+    }
+
+    /// Imports an AES secret key in the token. The key value can not be extracted afterwards.
+    fn import_aes_key(
+        &self,
+        app_name: ApplicationName,
+        op: psa_import_key::Operation,
+    ) -> Result<psa_import_key::Result> {
+        let key_attributes = op.attributes;
+        let key_value = op.data.expose_secret();
+        let _ = aes_key_length(key_value.len() * 8)?;
+        let bits = key_attributes.bits;
+        if bits != 0 && key_value.len() * 8 != bits {
+            error!("If the bits field is non-zero (value is {}) it must be equal to the size of the key in data.", bits);
+            return Err(ResponseStatus::PsaErrorInvalidArgument);
+        }
+
+        let key_triple = KeyTriple::new(app_name, ProviderId::Pkcs11, op.key_name);
+        let reservation = KeyTripleReservation::new(&self.key_info_store, key_triple)?;
+        let key_id = create_key_id(&self.local_ids);
+
+        let (encrypt, decrypt) = usage_flags_to_bools(&key_attributes);
+        let template = vec![
+            CK_ATTRIBUTE::new(pkcs11::types::CKA_CLASS)
+                .with_ck_ulong(&pkcs11::types::CKO_SECRET_KEY),
+            CK_ATTRIBUTE::new(pkcs11::types::CKA_KEY_TYPE).with_ck_ulong(&pkcs11::types::CKK_AES),
+            CK_ATTRIBUTE::new(pkcs11::types::CKA_VALUE).with_bytes(key_value),
+            CK_ATTRIBUTE::new(pkcs11::types::CKA_ID).with_bytes(&key_id),
+            CK_ATTRIBUTE::new(pkcs11::types::CKA_TOKEN).with_bool(&pkcs11::types::CK_TRUE),
+            CK_ATTRIBUTE::new(pkcs11::types::CKA_SENSITIVE).with_bool(&pkcs11::types::CK_TRUE),
+            CK_ATTRIBUTE::new(pkcs11::types::CKA_EXTRACTABLE).with_bool(&pkcs11::types::CK_FALSE),
+            CK_ATTRIBUTE::new(pkcs11::types::CKA_ENCRYPT).with_bool(&encrypt),
+            CK_ATTRIBUTE::new(pkcs11::types::CKA_DECRYPT).with_bool(&decrypt),
+        ];
+
+        let session = match Session::new(self, ReadWriteSession::ReadWrite) {
+            Ok(session) => session,
+            Err(err) => {
+                error!("Error creating a new session: {}.", err);
+                release_key_id(key_id, &self.local_ids);
+                return Err(err);
+            }
+        };
+
+        info!("Importing AES key in session {}", session.session_handle());
+
+        match self
+            .backend
+            .create_object(session.session_handle(), &template)
+        {
+            Ok(_key) => {
+                commit_key_id(reservation, key_id, key_attributes)?;
+                Ok(psa_import_key::Result {})
+            }
+            Err(e) => {
+                error!("Import operation failed with {}", e);
+                release_key_id(key_id, &self.local_ids);
                 Err(utils::to_response_status(e))
             }
         }
@@ -360,9 +516,11 @@ impl Pkcs11Provider {
         info!("Pkcs11 Provider - Export Public Key");
 
         let key_name = op.key_name;
-        let key_triple = KeyTriple::new(app_name, ProviderID::Pkcs11, key_name);
-        let store_handle = self.key_info_store.read().expect("Key store lock poisoned");
-        let (key_id, _key_attributes) = get_key_info(&key_triple, &*store_handle)?;
+        let key_triple = KeyTriple::new(app_name, ProviderId::Pkcs11, key_name);
+        let (key_id, _key_attributes) = get_key_info(
+            &key_triple,
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+        )?;
 
         let session = Session::new(self, ReadWriteSession::ReadOnly)?;
         info!(
@@ -436,7 +594,7 @@ impl Pkcs11Provider {
                         error!("Could not serialise key elements: {}.", err);
                         Err(ResponseStatus::PsaErrorCommunicationFailure)
                     })?;
-                    Ok(psa_export_public_key::Result { data })
+                    Ok(psa_export_public_key::Result { data: data.into() })
                 }
             }
             Err(e) => {
@@ -454,13 +612,11 @@ impl Pkcs11Provider {
         info!("Pkcs11 Provider - Destroy Key");
 
         let key_name = op.key_name;
-        let key_triple = KeyTriple::new(app_name, ProviderID::Pkcs11, key_name);
-        let mut store_handle = self
-            .key_info_store
-            .write()
-            .expect("Key store lock poisoned");
-        let mut local_ids_handle = self.local_ids.write().expect("Local ID lock poisoned");
-        let (key_id, _) = get_key_info(&key_triple, &*store_handle)?;
+        let key_triple = KeyTriple::new(app_name, ProviderId::Pkcs11, key_name);
+        let (key_id, _) = get_key_info(
+            &key_triple,
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+        )?;
 
         let session = Session::new(self, ReadWriteSession::ReadWrite)?;
         info!(
@@ -503,12 +659,7 @@ impl Pkcs11Provider {
             }
         };
 
-        remove_key_id(
-            &key_triple,
-            key_id,
-            &mut *store_handle,
-            &mut local_ids_handle,
-        )?;
+        remove_key_id(&key_triple, key_id, &self.key_info_store, &self.local_ids)?;
 
         Ok(psa_destroy_key::Result {})
     }
@@ -4,17 +4,19 @@
 //!
 //! This provider allows clients to access any PKCS 11 compliant device
 //! through the Parsec interface.
+use super::reconciliation::{ReconciliationMode, ReconciliationReport};
 use super::Provide;
 use crate::authenticators::ApplicationName;
-use crate::key_info_managers::{KeyInfo, KeyTriple, ManageKeyInfo};
+use crate::key_info_managers::{KeyInfo, ManageKeyInfo};
 use derivative::Derivative;
 use log::{error, info, warn};
 use parsec_interface::operations::list_providers::ProviderInfo;
 use parsec_interface::operations::{
-    psa_destroy_key, psa_export_public_key, psa_generate_key, psa_import_key, psa_sign_hash,
-    psa_verify_hash,
+    psa_aead_decrypt, psa_aead_encrypt, psa_asymmetric_decrypt, psa_asymmetric_encrypt,
+    psa_destroy_key, psa_export_public_key, psa_generate_key, psa_hash_compare, psa_hash_compute,
+    psa_import_key, psa_sign_hash, psa_verify_hash,
 };
-use parsec_interface::requests::{Opcode, ProviderID, ResponseStatus, Result};
+use parsec_interface::requests::{Opcode, ProviderId, ResponseStatus, Result};
 use pkcs11::types::{CKF_OS_LOCKING_OK, CK_C_INITIALIZE_ARGS, CK_SLOT_ID};
 use pkcs11::Ctx;
 use std::collections::HashSet;
@@ -25,17 +27,27 @@ use uuid::Uuid;
 
 type LocalIdStore = HashSet<[u8; 4]>;
 
+mod aead;
+mod asym_encryption;
 mod asym_sign;
+mod hash;
 mod key_management;
+mod reconciliation;
 mod utils;
 
-const SUPPORTED_OPCODES: [Opcode; 6] = [
+const SUPPORTED_OPCODES: [Opcode; 12] = [
     Opcode::PsaGenerateKey,
     Opcode::PsaDestroyKey,
     Opcode::PsaSignHash,
     Opcode::PsaVerifyHash,
     Opcode::PsaImportKey,
     Opcode::PsaExportPublicKey,
+    Opcode::PsaAsymmetricEncrypt,
+    Opcode::PsaAsymmetricDecrypt,
+    Opcode::PsaAeadEncrypt,
+    Opcode::PsaAeadDecrypt,
+    Opcode::PsaHashCompute,
+    Opcode::PsaHashCompare,
 ];
 
 /// Provider for Public Key Cryptography Standard #11
@@ -68,8 +80,10 @@ pub struct Pkcs11Provider {
 
 impl Pkcs11Provider {
     /// Creates and initialise a new instance of Pkcs11Provider.
-    /// Checks if there are not more keys stored in the Key Info Manager than in the PKCS 11 library
-    /// and if there are, delete them. Adds Key IDs currently in use in the local IDs store.
+    /// Adds Key IDs currently in use in the local IDs store. Mappings pointing to keys that are not
+    /// in the PKCS 11 library are only reported: they are deleted by an explicit reconciliation in
+    /// the `Delete` mode. Keys stored in the PKCS 11 token without a mapping are only found by
+    /// `reconcile`.
     /// Returns `None` if the initialisation failed.
     fn new(
         key_info_store: Arc<RwLock<dyn ManageKeyInfo + Send + Sync>>,
@@ -89,24 +103,23 @@ impl Pkcs11Provider {
         {
             // The local scope allows to drop store_handle and local_ids_handle in order to return
             // the pkcs11_provider.
-            let mut store_handle = pkcs11_provider
+            let store_handle = pkcs11_provider
                 .key_info_store
-                .write()
+                .read()
                 .expect("Key store lock poisoned");
             let mut local_ids_handle = pkcs11_provider
                 .local_ids
                 .write()
                 .expect("Local ID lock poisoned");
-            let mut to_remove: Vec<KeyTriple> = Vec::new();
             // Go through all PKCS 11 key triple to key info mappings and check if they are still
             // present.
-            // Delete those who are not present and add to the local_store the ones present.
-            match store_handle.get_all(ProviderID::Pkcs11) {
+            // Report those who are not present and add to the local_store the ones present.
+            match store_handle.get_all(ProviderId::Pkcs11) {
                 Ok(key_triples) => {
                     let session =
                         Session::new(&pkcs11_provider, ReadWriteSession::ReadOnly).ok()?;
 
-                    for key_triple in key_triples.iter().cloned() {
+                    for key_triple in key_triples.iter() {
                         let (key_id, _) = match key_management::get_key_info(
                             key_triple,
                             &*store_handle,
@@ -131,10 +144,9 @@ impl Pkcs11Provider {
                             }
                             Err(ResponseStatus::PsaErrorDoesNotExist) => {
                                 warn!(
-                                    "Key {} not found in the PKCS 11 library, deleting it.",
+                                    "Key {} not found in the PKCS 11 library, the mapping can be deleted with \"parsec reconcile --delete\".",
                                     key_triple
                                 );
-                                to_remove.push(key_triple.clone());
                             }
                             Err(e) => {
                                 error!("Error finding key objects: {}.", e);
@@ -148,12 +160,6 @@ impl Pkcs11Provider {
                     return None;
                 }
             };
-            for key_triple in to_remove.iter() {
-                if let Err(string) = store_handle.remove(key_triple) {
-                    error!("Key Info Manager error: {}", string);
-                    return None;
-                }
-            }
         }
 
         Some(pkcs11_provider)
@@ -174,7 +180,7 @@ impl Provide for Pkcs11Provider {
                 version_maj: 0,
                 version_min: 1,
                 version_rev: 0,
-                id: ProviderID::Pkcs11,
+                id: ProviderId::Pkcs11,
             },
             SUPPORTED_OPCODES.iter().copied().collect(),
         ))
@@ -227,6 +233,56 @@ impl Provide for Pkcs11Provider {
     ) -> Result<psa_verify_hash::Result> {
         self.psa_verify_hash_internal(app_name, op)
     }
+
+    fn psa_asymmetric_encrypt(
+        &self,
+        app_name: ApplicationName,
+        op: psa_asymmetric_encrypt::Operation,
+    ) -> Result<psa_asymmetric_encrypt::Result> {
+        self.psa_asymmetric_encrypt_internal(app_name, op)
+    }
+
+    fn psa_asymmetric_decrypt(
+        &self,
+        app_name: ApplicationName,
+        op: psa_asymmetric_decrypt::Operation,
+    ) -> Result<psa_asymmetric_decrypt::Result> {
+        self.psa_asymmetric_decrypt_internal(app_name, op)
+    }
+
+    fn psa_aead_encrypt(
+        &self,
+        app_name: ApplicationName,
+        op: psa_aead_encrypt::Operation,
+    ) -> Result<psa_aead_encrypt::Result> {
+        self.psa_aead_encrypt_internal(app_name, op)
+    }
+
+    fn psa_aead_decrypt(
+        &self,
+        app_name: ApplicationName,
+        op: psa_aead_decrypt::Operation,
+    ) -> Result<psa_aead_decrypt::Result> {
+        self.psa_aead_decrypt_internal(app_name, op)
+    }
+
+    fn psa_hash_compute(
+        &self,
+        op: psa_hash_compute::Operation,
+    ) -> Result<psa_hash_compute::Result> {
+        self.psa_hash_compute_internal(op)
+    }
+
+    fn psa_hash_compare(
+        &self,
+        op: psa_hash_compare::Operation,
+    ) -> Result<psa_hash_compare::Result> {
+        self.psa_hash_compare_internal(op)
+    }
+
+    fn reconcile(&self, mode: &ReconciliationMode) -> Result<ReconciliationReport> {
+        self.reconcile_internal(mode)
+    }
 }
 
 impl Drop for Pkcs11Provider {
@@ -0,0 +1,272 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+use super::{utils, KeyInfo, KeyPairType, Pkcs11Provider, ReadWriteSession, Session};
+use crate::key_info_managers::{self, ManageKeyInfo};
+use crate::providers::reconciliation::{self, BackendObject, ManageBackendObjects};
+use crate::providers::reconciliation::{ReconciliationMode, ReconciliationReport};
+use log::{error, info};
+use parsec_interface::operations::psa_algorithm::{Algorithm, AsymmetricSignature, Hash, SignHash};
+use parsec_interface::operations::psa_key_attributes::{
+    Attributes, Lifetime, Policy, Type, UsageFlags,
+};
+use parsec_interface::requests::{ProviderId, ResponseStatus, Result};
+use pkcs11::types::{CKR_OK, CK_ATTRIBUTE, CK_OBJECT_HANDLE, CK_ULONG};
+use std::collections::{BTreeSet, HashSet};
+
+/// Number of object handles fetched at once when listing the objects of the token.
+const FIND_OBJECTS_BATCH: CK_ULONG = 32;
+
+/// Keys of the PKCS 11 token, accessed through one session for the whole reconciliation
+struct Pkcs11Objects<'a> {
+    provider: &'a Pkcs11Provider,
+    session: Session<'a>,
+    /// IDs of the keys being created, whose mappings are not stored yet.
+    pending_ids: HashSet<[u8; 4]>,
+}
+
+/// Returns the key IDs of the mappings of the provider.
+fn mapped_key_ids(store_handle: &dyn ManageKeyInfo) -> Result<HashSet<[u8; 4]>> {
+    let mut key_ids = HashSet::new();
+    for key_triple in store_handle
+        .get_all(ProviderId::Pkcs11)
+        .map_err(key_info_managers::to_response_status)?
+    {
+        if let Ok(Some(key_info)) = store_handle.get(&key_triple) {
+            if key_info.id.len() == 4 {
+                let mut key_id = [0; 4];
+                key_id.copy_from_slice(&key_info.id);
+                let _ = key_ids.insert(key_id);
+            }
+        }
+    }
+
+    Ok(key_ids)
+}
+
+impl Pkcs11Objects<'_> {
+    /// Lists the key IDs of the token objects with an ID of the format used by the provider.
+    fn list_key_ids(&self) -> Result<BTreeSet<[u8; 4]>> {
+        let session = self.session.session_handle();
+        let template =
+            vec![CK_ATTRIBUTE::new(pkcs11::types::CKA_TOKEN).with_bool(&pkcs11::types::CK_TRUE)];
+        if let Err(e) = self.provider.backend.find_objects_init(session, &template) {
+            error!("Object enumeration init failed with {}", e);
+            return Err(utils::to_response_status(e));
+        }
+        let mut objects = Vec::new();
+        loop {
+            match self
+                .provider
+                .backend
+                .find_objects(session, FIND_OBJECTS_BATCH)
+            {
+                Ok(batch) if batch.is_empty() => break,
+                Ok(mut batch) => objects.append(&mut batch),
+                Err(e) => {
+                    error!("Finding objects failed with {}", e);
+                    let _ = self.provider.backend.find_objects_final(session);
+                    return Err(utils::to_response_status(e));
+                }
+            }
+        }
+        if let Err(e) = self.provider.backend.find_objects_final(session) {
+            error!("Object enumeration final failed with {}", e);
+            return Err(utils::to_response_status(e));
+        }
+
+        let mut key_ids = BTreeSet::new();
+        for object in objects {
+            if let Some(key_id) = self.key_id(object)? {
+                let _ = key_ids.insert(key_id);
+            }
+        }
+
+        Ok(key_ids)
+    }
+
+    /// Reads the ID of an object, if it has the format used by the provider.
+    fn key_id(&self, object: CK_OBJECT_HANDLE) -> Result<Option<[u8; 4]>> {
+        let mut key_id: Vec<pkcs11::types::CK_BYTE> = vec![0; 4];
+        let mut template =
+            vec![CK_ATTRIBUTE::new(pkcs11::types::CKA_ID).with_bytes(key_id.as_mut_slice())];
+        match self.provider.backend.get_attribute_value(
+            self.session.session_handle(),
+            object,
+            &mut template,
+        ) {
+            Ok((CKR_OK, attrs)) if attrs[0].ulValueLen == 4 => {
+                let mut key_id = [0; 4];
+                key_id.copy_from_slice(&attrs[0].get_bytes());
+                Ok(Some(key_id))
+            }
+            // The object has no ID or an ID of another length.
+            Ok(_) => Ok(None),
+            Err(e) => {
+                error!("Failed to read the ID of an object. Error: {}", e);
+                Err(utils::to_response_status(e))
+            }
+        }
+    }
+
+    /// Builds the attributes of a key created by the provider: an RSA key pair, or public key,
+    /// used for RSA PKCS#1 v1.5 signatures with SHA-256, the only algorithm supported. Returns
+    /// `None` if the key is not of this kind.
+    fn key_attributes(&self, key_id: [u8; 4]) -> Result<Option<Attributes>> {
+        let session = self.session.session_handle();
+        let public_key = match self
+            .provider
+            .find_key(session, key_id, KeyPairType::PublicKey)
+        {
+            Ok(public_key) => public_key,
+            Err(ResponseStatus::PsaErrorDoesNotExist) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let has_private_key = match self
+            .provider
+            .find_key(session, key_id, KeyPairType::PrivateKey)
+        {
+            Ok(_) => true,
+            Err(ResponseStatus::PsaErrorDoesNotExist) => false,
+            Err(e) => return Err(e),
+        };
+
+        let mut template = vec![CK_ATTRIBUTE::new(pkcs11::types::CKA_MODULUS)];
+        let modulus_len =
+            match self
+                .provider
+                .backend
+                .get_attribute_value(session, public_key, &mut template)
+            {
+                Ok((CKR_OK, attrs)) => attrs[0].ulValueLen,
+                // Not an RSA public key.
+                Ok(_) => return Ok(None),
+                Err(e) => {
+                    error!("Failed to read attributes from public key. Error: {}", e);
+                    return Err(utils::to_response_status(e));
+                }
+            };
+
+        Ok(Some(Attributes {
+            lifetime: Lifetime::Persistent,
+            key_type: if has_private_key {
+                Type::RsaKeyPair
+            } else {
+                Type::RsaPublicKey
+            },
+            bits: modulus_len * 8,
+            policy: Policy {
+                usage_flags: {
+                    let mut usage_flags = UsageFlags::default();
+                    if has_private_key {
+                        let _ = usage_flags.set_sign_hash();
+                    }
+                    let _ = usage_flags.set_verify_hash();
+                    usage_flags
+                },
+                permitted_algorithms: Algorithm::AsymmetricSignature(
+                    AsymmetricSignature::RsaPkcs1v15Sign {
+                        hash_alg: SignHash::Specific(Hash::Sha256),
+                    },
+                ),
+            },
+        }))
+    }
+}
+
+impl ManageBackendObjects for Pkcs11Objects<'_> {
+    fn object_exists(&self, key_info: &KeyInfo) -> Result<bool> {
+        if key_info.id.len() != 4 {
+            error!("Stored Key ID is not valid.");
+            return Ok(false);
+        }
+        let mut key_id = [0; 4];
+        key_id.copy_from_slice(&key_info.id);
+        match self
+            .provider
+            .find_key(self.session.session_handle(), key_id, KeyPairType::Any)
+        {
+            Ok(_) => Ok(true),
+            Err(ResponseStatus::PsaErrorDoesNotExist) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn list_objects(&self) -> Result<Vec<BackendObject>> {
+        let mut objects = Vec::new();
+        for key_id in self.list_key_ids()? {
+            if self.pending_ids.contains(&key_id) {
+                continue;
+            }
+            objects.push(BackendObject {
+                id: key_id.to_vec(),
+                attributes: self.key_attributes(key_id)?,
+            });
+        }
+
+        Ok(objects)
+    }
+
+    fn destroy_object(&self, id: &[u8]) -> Result<()> {
+        let mut key_id = [0; 4];
+        key_id.copy_from_slice(id);
+        // Both parts of a key pair have the same ID.
+        loop {
+            let key = match self.provider.find_key(
+                self.session.session_handle(),
+                key_id,
+                KeyPairType::Any,
+            ) {
+                Ok(key) => key,
+                Err(ResponseStatus::PsaErrorDoesNotExist) => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            if let Err(e) = self
+                .provider
+                .backend
+                .destroy_object(self.session.session_handle(), key)
+            {
+                error!("Failed to destroy key object. Error: {}", e);
+                return Err(utils::to_response_status(e));
+            }
+        }
+    }
+}
+
+impl Pkcs11Provider {
+    pub(super) fn reconcile_internal(
+        &self,
+        mode: &ReconciliationMode,
+    ) -> Result<ReconciliationReport> {
+        info!("Pkcs11 Provider - Reconcile");
+
+        let mut store_handle = self
+            .key_info_store
+            .write()
+            .expect("Key store lock poisoned");
+        let mut local_ids_handle = self.local_ids.write().expect("Local ID lock poisoned");
+        let read_write = if *mode == ReconciliationMode::DryRun {
+            ReadWriteSession::ReadOnly
+        } else {
+            ReadWriteSession::ReadWrite
+        };
+        // The keys being created have a key ID but no mapping yet, they are not orphaned.
+        let mapped_ids = mapped_key_ids(&*store_handle)?;
+        let pending_ids: HashSet<[u8; 4]> =
+            local_ids_handle.difference(&mapped_ids).copied().collect();
+        let objects = Pkcs11Objects {
+            provider: self,
+            session: Session::new(self, read_write)?,
+            pending_ids,
+        };
+
+        let report =
+            reconciliation::reconcile(&objects, ProviderId::Pkcs11, &mut *store_handle, mode);
+
+        // The local IDs are rebuilt from the mappings left, even if the reconciliation failed
+        // half-way.
+        *local_ids_handle = mapped_key_ids(&*store_handle)?;
+        local_ids_handle.extend(objects.pending_ids.iter());
+
+        report
+    }
+}
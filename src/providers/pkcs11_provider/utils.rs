@@ -4,6 +4,7 @@
 use super::Pkcs11Provider;
 use log::error;
 use log::{info, warn};
+use parsec_interface::operations::psa_algorithm::Hash;
 use parsec_interface::requests::ResponseStatus;
 use parsec_interface::requests::Result;
 use picky_asn1::wrapper::IntegerAsn1;
@@ -58,6 +59,26 @@ pub fn rv_to_response_status(rv: CK_RV) -> ResponseStatus {
     }
 }
 
+/// Returns the PKCS 11 hash mechanism, also used to compute digests, and the matching mask
+/// generation function, as used by the RSA PSS and RSA OAEP mechanisms.
+pub fn convert_hash_to_mechanisms(
+    hash_alg: Hash,
+) -> Result<(CK_MECHANISM_TYPE, CK_RSA_PKCS_MGF_TYPE)> {
+    match hash_alg {
+        Hash::Sha224 => Ok((CKM_SHA224, CKG_MGF1_SHA224)),
+        Hash::Sha256 => Ok((CKM_SHA256, CKG_MGF1_SHA256)),
+        Hash::Sha384 => Ok((CKM_SHA384, CKG_MGF1_SHA384)),
+        Hash::Sha512 => Ok((CKM_SHA512, CKG_MGF1_SHA512)),
+        _ => {
+            error!(
+                "The PKCS 11 provider does not support the {:?} hash algorithm.",
+                hash_alg
+            );
+            Err(ResponseStatus::PsaErrorNotSupported)
+        }
+    }
+}
+
 // The RSA Public Key data are DER encoded with the following representation:
 // RSAPublicKey ::= SEQUENCE {
 //     modulus            INTEGER,  -- n
@@ -70,10 +91,11 @@ pub struct RsaPublicKey {
 }
 
 // For PKCS 11, a key pair consists of two independant public and private keys. Both will share the
-// same key ID.
+// same key ID. A symmetric key is a single secret key object.
 pub enum KeyPairType {
     PublicKey,
     PrivateKey,
+    SecretKey,
     Any,
 }
 
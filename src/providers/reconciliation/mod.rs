@@ -0,0 +1,497 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+//! Reconciliation of the key info mappings with the provider backends
+//!
+//! The mappings stored in a key info manager and the keys stored by the backend of a provider can
+//! drift apart: a key can be deleted from the backend while its mapping is kept (a dangling
+//! mapping) or a key can be left in the backend without any mapping pointing to it (an orphaned
+//! object), for example after a crash between the two operations or after the mappings were
+//! restored from an older copy.
+//!
+//! Providers implementing `ManageBackendObjects` can compare both sides with `reconcile`, which
+//! reports the inconsistencies found and, depending on the mode, repairs them. Backend objects
+//! are only considered if their identifier has the format used by the provider for the keys it
+//! creates, but the backend should not be shared with other applications when orphaned objects
+//! are deleted.
+use super::Provide;
+use crate::authenticators::ApplicationName;
+use crate::key_info_managers::{self, KeyInfo, KeyTriple, ManageKeyInfo};
+use log::{error, info, warn};
+use parsec_interface::operations::psa_key_attributes::Attributes;
+use parsec_interface::requests::{ProviderId, ResponseStatus, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+/// Prefix of the name given to the adopted keys, followed by the hexadecimal identifier of the
+/// backend object
+pub const ADOPTED_KEY_NAME_PREFIX: &str = "adopted-";
+
+/// What to do with the inconsistencies found
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ReconciliationMode {
+    /// Only report the inconsistencies.
+    DryRun,
+    /// Remove the dangling mappings and destroy the orphaned objects.
+    Delete,
+    /// Remove the dangling mappings and create mappings for the orphaned objects, owned by the
+    /// given application.
+    Adopt(ApplicationName),
+}
+
+/// Key stored in the backend of a provider
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackendObject {
+    /// Identifier of the key, in the format stored in the key info
+    pub id: Vec<u8>,
+    /// Attributes of the key, if the backend can describe them. Keys without attributes can not
+    /// be adopted.
+    pub attributes: Option<Attributes>,
+}
+
+/// Result of the reconciliation of a provider
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReconciliationReport {
+    /// Provider reconciled.
+    #[serde(with = "key_info_managers::provider_id_serde")]
+    pub provider_id: ProviderId,
+    /// Number of key mappings checked.
+    pub checked: usize,
+    /// Key mappings pointing to a key which does not exist in the backend.
+    pub dangling_mappings: Vec<KeyTriple>,
+    /// Identifiers of the keys of the backend which no mapping points to.
+    pub orphaned_objects: Vec<Vec<u8>>,
+    /// Dangling mappings removed.
+    pub mappings_removed: Vec<KeyTriple>,
+    /// Identifiers of the orphaned objects destroyed.
+    pub objects_destroyed: Vec<Vec<u8>>,
+    /// Mappings created for the orphaned objects.
+    pub keys_adopted: Vec<KeyTriple>,
+}
+
+impl ReconciliationReport {
+    /// Creates an empty report for a provider.
+    pub fn new(provider_id: ProviderId) -> ReconciliationReport {
+        ReconciliationReport {
+            provider_id,
+            checked: 0,
+            dangling_mappings: Vec::new(),
+            orphaned_objects: Vec::new(),
+            mappings_removed: Vec::new(),
+            objects_destroyed: Vec::new(),
+            keys_adopted: Vec::new(),
+        }
+    }
+
+    /// Returns `true` if no inconsistency was found.
+    pub fn is_consistent(&self) -> bool {
+        self.dangling_mappings.is_empty() && self.orphaned_objects.is_empty()
+    }
+}
+
+impl fmt::Display for ReconciliationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Provider {}: {} mappings checked, {} dangling, {} orphaned objects",
+            self.provider_id,
+            self.checked,
+            self.dangling_mappings.len(),
+            self.orphaned_objects.len()
+        )?;
+        for key_triple in &self.dangling_mappings {
+            let status = if self.mappings_removed.contains(key_triple) {
+                "removed"
+            } else {
+                "kept"
+            };
+            writeln!(f, "  dangling mapping: {} ({})", key_triple, status)?;
+        }
+        for id in &self.orphaned_objects {
+            let status = if self.objects_destroyed.contains(id) {
+                String::from("destroyed")
+            } else if let Some(key_triple) = self
+                .keys_adopted
+                .iter()
+                .find(|key_triple| key_triple.key_name() == adopted_key_name(id))
+            {
+                format!("adopted as {}", key_triple)
+            } else {
+                String::from("kept")
+            };
+            writeln!(f, "  orphaned object: {} ({})", hex::encode(id), status)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Access to the keys stored in the backend of a provider, needed for the reconciliation
+pub trait ManageBackendObjects {
+    /// Checks if the key pointed to by the key info exists in the backend.
+    fn object_exists(&self, key_info: &KeyInfo) -> Result<bool>;
+
+    /// Lists the keys stored in the backend. Keys whose identifier does not have the format used
+    /// by the provider must not be listed.
+    fn list_objects(&self) -> Result<Vec<BackendObject>>;
+
+    /// Destroys a key of the backend.
+    fn destroy_object(&self, id: &[u8]) -> Result<()>;
+}
+
+/// Name of the key created when adopting the backend object with the given identifier.
+pub fn adopted_key_name(id: &[u8]) -> String {
+    format!("{}{}", ADOPTED_KEY_NAME_PREFIX, hex::encode(id))
+}
+
+/// Compares the mappings of a provider with the keys of its backend and, depending on the mode,
+/// repairs the inconsistencies found. The inconsistencies are logged and returned in the report.
+///
+/// The store handle must be held for writing for the whole reconciliation so that keys can not be
+/// created or destroyed in the meantime.
+///
+/// # Errors
+///
+/// Returns an error if the key info manager or the backend failed. The repairs already done are
+/// kept.
+pub fn reconcile(
+    backend: &dyn ManageBackendObjects,
+    provider_id: ProviderId,
+    store_handle: &mut dyn ManageKeyInfo,
+    mode: &ReconciliationMode,
+) -> Result<ReconciliationReport> {
+    let mut report = ReconciliationReport::new(provider_id);
+
+    let key_triples = store_handle
+        .get_all(provider_id)
+        .map_err(key_info_managers::to_response_status)?;
+    let mut mapped_ids = HashSet::new();
+    for key_triple in key_triples {
+        report.checked += 1;
+        let exists = match store_handle
+            .get(&key_triple)
+            .map_err(key_info_managers::to_response_status)?
+        {
+            Some(key_info) => {
+                let _ = mapped_ids.insert(key_info.id.clone());
+                backend.object_exists(&key_info)?
+            }
+            None => false,
+        };
+        if !exists {
+            warn!("Key {} not found in the backend.", key_triple);
+            report.dangling_mappings.push(key_triple);
+        }
+    }
+
+    let orphaned_objects: Vec<BackendObject> = backend
+        .list_objects()?
+        .into_iter()
+        .filter(|object| !mapped_ids.contains(&object.id))
+        .collect();
+    for object in &orphaned_objects {
+        warn!(
+            "Key {} of provider {} is not mapped to any key name.",
+            hex::encode(&object.id),
+            provider_id
+        );
+        report.orphaned_objects.push(object.id.clone());
+    }
+
+    if *mode == ReconciliationMode::DryRun {
+        return Ok(report);
+    }
+
+    for key_triple in report.dangling_mappings.clone() {
+        let _ = store_handle
+            .remove(&key_triple)
+            .map_err(key_info_managers::to_response_status)?;
+        info!("Dangling mapping {} removed.", key_triple);
+        report.mappings_removed.push(key_triple);
+    }
+
+    for object in orphaned_objects {
+        match mode {
+            ReconciliationMode::Delete => {
+                if let Err(e) = backend.destroy_object(&object.id) {
+                    error!(
+                        "Failed to destroy the orphaned key {} ({}).",
+                        hex::encode(&object.id),
+                        e
+                    );
+                    return Err(e);
+                }
+                info!("Orphaned key {} destroyed.", hex::encode(&object.id));
+                report.objects_destroyed.push(object.id);
+            }
+            ReconciliationMode::Adopt(app_name) => {
+                let attributes = match object.attributes {
+                    Some(attributes) => attributes,
+                    None => {
+                        warn!(
+                            "The attributes of the orphaned key {} are unknown, it can not be adopted.",
+                            hex::encode(&object.id)
+                        );
+                        continue;
+                    }
+                };
+                let key_triple =
+                    KeyTriple::new(app_name.clone(), provider_id, adopted_key_name(&object.id));
+                if store_handle
+                    .exists(&key_triple)
+                    .map_err(key_info_managers::to_response_status)?
+                {
+                    warn!(
+                        "A key named {} already exists, the orphaned key can not be adopted.",
+                        key_triple
+                    );
+                    continue;
+                }
+                let _ = store_handle
+                    .insert(
+                        key_triple.clone(),
+                        KeyInfo {
+                            id: object.id,
+                            attributes,
+                        },
+                    )
+                    .map_err(key_info_managers::to_response_status)?;
+                info!("Orphaned key adopted as {}.", key_triple);
+                report.keys_adopted.push(key_triple);
+            }
+            ReconciliationMode::DryRun => unreachable!(),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Reconciles the given provider, or all the providers if none is given. Providers not
+/// supporting the reconciliation are skipped when reconciling all of them. The reports are
+/// returned in the order of the provider IDs.
+///
+/// # Errors
+///
+/// Returns `ResponseStatus::ProviderNotRegistered` if the given provider is not in the list, or
+/// the error of the first provider failing.
+pub fn reconcile_providers(
+    providers: &HashMap<ProviderId, Arc<dyn Provide + Send + Sync>>,
+    provider_id: Option<ProviderId>,
+    mode: &ReconciliationMode,
+) -> Result<Vec<ReconciliationReport>> {
+    if let Some(provider_id) = provider_id {
+        let provider = providers
+            .get(&provider_id)
+            .ok_or(ResponseStatus::ProviderNotRegistered)?;
+        return Ok(vec![provider.reconcile(mode)?]);
+    }
+
+    let mut provider_ids: Vec<&ProviderId> = providers.keys().collect();
+    provider_ids.sort_by_key(|provider_id| **provider_id as u8);
+    let mut reports = Vec::new();
+    for provider_id in provider_ids {
+        match providers[provider_id].reconcile(mode) {
+            Ok(report) => reports.push(report),
+            Err(ResponseStatus::PsaErrorNotSupported) => {
+                info!("Provider {} does not support reconciliation.", provider_id)
+            }
+            Err(e) => {
+                error!("Reconciliation of provider {} failed ({}).", provider_id, e);
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        adopted_key_name, reconcile, BackendObject, ManageBackendObjects, ReconciliationMode,
+    };
+    use crate::authenticators::ApplicationName;
+    use crate::key_info_managers::memory_manager::MemoryKeyInfoManager;
+    use crate::key_info_managers::{KeyInfo, KeyTriple, ManageKeyInfo};
+    use parsec_interface::operations::psa_algorithm::{Algorithm, Hash};
+    use parsec_interface::operations::psa_key_attributes::{
+        Attributes, Lifetime, Policy, Type, UsageFlags,
+    };
+    use parsec_interface::requests::{ProviderId, Result};
+    use std::cell::RefCell;
+
+    /// Backend storing the identifiers of its keys
+    struct MockBackend {
+        ids: RefCell<Vec<Vec<u8>>>,
+    }
+
+    impl ManageBackendObjects for MockBackend {
+        fn object_exists(&self, key_info: &KeyInfo) -> Result<bool> {
+            Ok(self.ids.borrow().contains(&key_info.id))
+        }
+
+        fn list_objects(&self) -> Result<Vec<BackendObject>> {
+            Ok(self
+                .ids
+                .borrow()
+                .iter()
+                .map(|id| BackendObject {
+                    id: id.clone(),
+                    // Only the keys with a 1-byte ID can be described.
+                    attributes: if id.len() == 1 {
+                        Some(attributes())
+                    } else {
+                        None
+                    },
+                })
+                .collect())
+        }
+
+        fn destroy_object(&self, id: &[u8]) -> Result<()> {
+            self.ids.borrow_mut().retain(|stored| stored != id);
+            Ok(())
+        }
+    }
+
+    fn attributes() -> Attributes {
+        Attributes {
+            lifetime: Lifetime::Persistent,
+            key_type: Type::RawData,
+            bits: 256,
+            policy: Policy {
+                usage_flags: UsageFlags::default(),
+                permitted_algorithms: Algorithm::Hash(Hash::Sha256),
+            },
+        }
+    }
+
+    fn key_triple(app_name: &str, key_name: &str) -> KeyTriple {
+        KeyTriple::new(
+            ApplicationName::new(app_name.to_string()),
+            ProviderId::Pkcs11,
+            key_name.to_string(),
+        )
+    }
+
+    /// Sets up a store with the mappings "mapped" (ID 1) and "dangling" (ID 2) and a backend with
+    /// the keys 1, 3 and [4, 0] (which can not be described).
+    fn set_up() -> (impl ManageKeyInfo, MockBackend) {
+        let mut manager = MemoryKeyInfoManager::new();
+        for (key_name, id) in &[("mapped", vec![1]), ("dangling", vec![2])] {
+            let _ = manager
+                .insert(
+                    key_triple("app", key_name),
+                    KeyInfo {
+                        id: id.clone(),
+                        attributes: attributes(),
+                    },
+                )
+                .unwrap();
+        }
+        let backend = MockBackend {
+            ids: RefCell::new(vec![vec![1], vec![3], vec![4, 0]]),
+        };
+
+        (manager, backend)
+    }
+
+    #[test]
+    fn dry_run() {
+        let (mut manager, backend) = set_up();
+
+        let report = reconcile(
+            &backend,
+            ProviderId::Pkcs11,
+            &mut manager,
+            &ReconciliationMode::DryRun,
+        )
+        .unwrap();
+        assert!(!report.is_consistent());
+        assert_eq!(report.checked, 2);
+        assert_eq!(
+            report.dangling_mappings,
+            vec![key_triple("app", "dangling")]
+        );
+        assert_eq!(report.orphaned_objects, vec![vec![3], vec![4, 0]]);
+        assert!(report.mappings_removed.is_empty());
+        assert!(report.objects_destroyed.is_empty());
+        assert!(report.keys_adopted.is_empty());
+
+        // Nothing was changed.
+        assert!(manager.exists(&key_triple("app", "dangling")).unwrap());
+        assert_eq!(backend.ids.borrow().len(), 3);
+
+        drop(manager);
+    }
+
+    #[test]
+    fn delete() {
+        let (mut manager, backend) = set_up();
+
+        let report = reconcile(
+            &backend,
+            ProviderId::Pkcs11,
+            &mut manager,
+            &ReconciliationMode::Delete,
+        )
+        .unwrap();
+        assert_eq!(report.mappings_removed, vec![key_triple("app", "dangling")]);
+        assert_eq!(report.objects_destroyed, vec![vec![3], vec![4, 0]]);
+        assert!(!manager.exists(&key_triple("app", "dangling")).unwrap());
+        assert!(manager.exists(&key_triple("app", "mapped")).unwrap());
+        assert_eq!(*backend.ids.borrow(), vec![vec![1]]);
+
+        let report = reconcile(
+            &backend,
+            ProviderId::Pkcs11,
+            &mut manager,
+            &ReconciliationMode::DryRun,
+        )
+        .unwrap();
+        assert!(report.is_consistent());
+
+        drop(manager);
+    }
+
+    #[test]
+    fn adopt() {
+        let (mut manager, backend) = set_up();
+
+        let report = reconcile(
+            &backend,
+            ProviderId::Pkcs11,
+            &mut manager,
+            &ReconciliationMode::Adopt(ApplicationName::new(String::from("admin"))),
+        )
+        .unwrap();
+        assert_eq!(report.mappings_removed, vec![key_triple("app", "dangling")]);
+        assert!(report.objects_destroyed.is_empty());
+        // The key without attributes is left alone.
+        assert_eq!(
+            report.keys_adopted,
+            vec![key_triple("admin", &adopted_key_name(&[3]))]
+        );
+        assert_eq!(
+            manager
+                .get(&key_triple("admin", "adopted-03"))
+                .unwrap()
+                .unwrap()
+                .id,
+            vec![3]
+        );
+        assert_eq!(backend.ids.borrow().len(), 3);
+
+        let report = reconcile(
+            &backend,
+            ProviderId::Pkcs11,
+            &mut manager,
+            &ReconciliationMode::DryRun,
+        )
+        .unwrap();
+        assert!(report.dangling_mappings.is_empty());
+        assert_eq!(report.orphaned_objects, vec![vec![4, 0]]);
+
+        drop(manager);
+    }
+}
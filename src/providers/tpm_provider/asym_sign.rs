@@ -6,7 +6,7 @@ use crate::key_info_managers::KeyTriple;
 use log::error;
 use parsec_interface::operations::psa_algorithm::*;
 use parsec_interface::operations::{psa_sign_hash, psa_verify_hash};
-use parsec_interface::requests::{ProviderID, ResponseStatus, Result};
+use parsec_interface::requests::{ProviderId, ResponseStatus, Result};
 
 impl TpmProvider {
     pub(super) fn psa_sign_hash_internal(
@@ -14,19 +14,20 @@ impl TpmProvider {
         app_name: ApplicationName,
         op: psa_sign_hash::Operation,
     ) -> Result<psa_sign_hash::Result> {
-        let key_triple = KeyTriple::new(app_name, ProviderID::Tpm, op.key_name.clone());
+        let key_triple = KeyTriple::new(app_name, ProviderId::Tpm, op.key_name.clone());
 
-        let store_handle = self.key_info_store.read().expect("Key store lock poisoned");
+        let (password_context, key_attributes) = key_management::get_password_context(
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+            key_triple,
+        )?;
         let mut esapi_context = self
             .esapi_context
             .lock()
             .expect("ESAPI Context lock poisoned");
 
-        let (password_context, key_attributes) =
-            key_management::get_password_context(&*store_handle, key_triple)?;
-
         match op.alg {
             AsymmetricSignature::RsaPkcs1v15Sign { .. } => (),
+            AsymmetricSignature::RsaPss { .. } => (),
             AsymmetricSignature::Ecdsa { .. } => (),
             _ => {
                 error!(
@@ -51,7 +52,7 @@ impl TpmProvider {
             })?;
 
         Ok(psa_sign_hash::Result {
-            signature: utils::signature_data_to_bytes(signature.signature, key_attributes)?,
+            signature: utils::signature_data_to_bytes(signature.signature, key_attributes)?.into(),
         })
     }
 
@@ -60,19 +61,20 @@ impl TpmProvider {
         app_name: ApplicationName,
         op: psa_verify_hash::Operation,
     ) -> Result<psa_verify_hash::Result> {
-        let key_triple = KeyTriple::new(app_name, ProviderID::Tpm, op.key_name.clone());
+        let key_triple = KeyTriple::new(app_name, ProviderId::Tpm, op.key_name.clone());
 
-        let store_handle = self.key_info_store.read().expect("Key store lock poisoned");
+        let (password_context, key_attributes) = key_management::get_password_context(
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+            key_triple,
+        )?;
         let mut esapi_context = self
             .esapi_context
             .lock()
             .expect("ESAPI Context lock poisoned");
 
-        let (password_context, key_attributes) =
-            key_management::get_password_context(&*store_handle, key_triple)?;
-
         match op.alg {
             AsymmetricSignature::RsaPkcs1v15Sign { .. } => (),
+            AsymmetricSignature::RsaPss { .. } => (),
             AsymmetricSignature::Ecdsa { .. } => (),
             _ => {
                 error!(
@@ -85,7 +87,8 @@ impl TpmProvider {
 
         op.validate(key_attributes)?;
 
-        let signature = utils::parsec_to_tpm_signature(op.signature, key_attributes, op.alg)?;
+        let signature =
+            utils::parsec_to_tpm_signature(op.signature.to_vec(), key_attributes, op.alg)?;
 
         let _ = esapi_context
             .verify_signature(password_context.context, &op.hash, signature)
@@ -6,42 +6,31 @@ use super::TpmProvider;
 use crate::authenticators::ApplicationName;
 use crate::key_info_managers;
 use crate::key_info_managers::KeyTriple;
-use crate::key_info_managers::{KeyInfo, ManageKeyInfo};
+use crate::key_info_managers::{KeyInfo, KeyTripleReservation, ManageKeyInfo};
 use log::error;
 use parsec_interface::operations::psa_key_attributes::*;
 use parsec_interface::operations::{
     psa_destroy_key, psa_export_public_key, psa_generate_key, psa_import_key,
 };
-use parsec_interface::requests::{ProviderID, ResponseStatus, Result};
+use parsec_interface::requests::{ProviderId, ResponseStatus, Result};
+use parsec_interface::secrecy::ExposeSecret;
 
 // Public exponent value for all RSA keys.
 const PUBLIC_EXPONENT: [u8; 3] = [0x01, 0x00, 0x01];
 const AUTH_VAL_LEN: usize = 32;
 
-// Inserts a new mapping in the Key Info manager that stores the PasswordContext.
-fn insert_password_context(
-    store_handle: &mut dyn ManageKeyInfo,
-    key_triple: KeyTriple,
+// Stores the PasswordContext of the key created as the mapping of the key triple reserved.
+fn commit_password_context(
+    reservation: KeyTripleReservation,
     password_context: PasswordContext,
     key_attributes: Attributes,
 ) -> Result<()> {
-    let error_storing = |e| Err(key_info_managers::to_response_status(e));
-
     let key_info = KeyInfo {
         id: bincode::serialize(&password_context)?,
         attributes: key_attributes,
     };
 
-    if store_handle
-        .insert(key_triple, key_info)
-        .or_else(error_storing)?
-        .is_some()
-    {
-        error!("Inserting a mapping in the Key Info Manager that would overwrite an existing one.");
-        Err(ResponseStatus::PsaErrorAlreadyExists)
-    } else {
-        Ok(())
-    }
+    reservation.commit(key_info)
 }
 
 // Gets a PasswordContext mapping to the KeyTriple given.
@@ -70,12 +59,10 @@ impl TpmProvider {
     ) -> Result<psa_generate_key::Result> {
         let key_name = op.key_name;
         let attributes = op.attributes;
-        let key_triple = KeyTriple::new(app_name, ProviderID::Tpm, key_name);
+        let key_triple = KeyTriple::new(app_name, ProviderId::Tpm, key_name);
 
-        let mut store_handle = self
-            .key_info_store
-            .write()
-            .expect("Key store lock poisoned");
+        // The key info manager is not locked while the key is created, which can take a while.
+        let reservation = KeyTripleReservation::new(&self.key_info_store, key_triple)?;
         let mut esapi_context = self
             .esapi_context
             .lock()
@@ -88,9 +75,8 @@ impl TpmProvider {
                 Err(utils::to_response_status(e))
             })?;
 
-        insert_password_context(
-            &mut *store_handle,
-            key_triple,
+        commit_password_context(
+            reservation,
             PasswordContext {
                 context: key_context,
                 auth_value,
@@ -113,22 +99,20 @@ impl TpmProvider {
 
         let key_name = op.key_name;
         let attributes = op.attributes;
-        let key_triple = KeyTriple::new(app_name, ProviderID::Tpm, key_name);
+        let key_triple = KeyTriple::new(app_name, ProviderId::Tpm, key_name);
         let key_data = op.data;
 
-        let mut store_handle = self
-            .key_info_store
-            .write()
-            .expect("Key store lock poisoned");
+        let reservation = KeyTripleReservation::new(&self.key_info_store, key_triple)?;
         let mut esapi_context = self
             .esapi_context
             .lock()
             .expect("ESAPI Context lock poisoned");
 
-        let public_key: RsaPublicKey = picky_asn1_der::from_bytes(&key_data).or_else(|err| {
-            error!("Could not deserialise key elements: {}.", err);
-            Err(ResponseStatus::PsaErrorInvalidArgument)
-        })?;
+        let public_key: RsaPublicKey = picky_asn1_der::from_bytes(key_data.expose_secret())
+            .or_else(|err| {
+                error!("Could not deserialise key elements: {}.", err);
+                Err(ResponseStatus::PsaErrorInvalidArgument)
+            })?;
 
         if public_key.modulus.is_negative() || public_key.public_exponent.is_negative() {
             error!("Only positive modulus and public exponent are supported.");
@@ -163,9 +147,8 @@ impl TpmProvider {
                 Err(utils::to_response_status(e))
             })?;
 
-        insert_password_context(
-            &mut *store_handle,
-            key_triple,
+        commit_password_context(
+            reservation,
             PasswordContext {
                 context: pub_key_context,
                 auth_value: Vec::new(),
@@ -182,16 +165,17 @@ impl TpmProvider {
         op: psa_export_public_key::Operation,
     ) -> Result<psa_export_public_key::Result> {
         let key_name = op.key_name;
-        let key_triple = KeyTriple::new(app_name, ProviderID::Tpm, key_name);
+        let key_triple = KeyTriple::new(app_name, ProviderId::Tpm, key_name);
 
-        let store_handle = self.key_info_store.read().expect("Key store lock poisoned");
+        let (password_context, key_attributes) = get_password_context(
+            &*self.key_info_store.read().expect("Key store lock poisoned"),
+            key_triple,
+        )?;
         let mut esapi_context = self
             .esapi_context
             .lock()
             .expect("ESAPI Context lock poisoned");
 
-        let (password_context, key_attributes) = get_password_context(&*store_handle, key_triple)?;
-
         let pub_key_data = esapi_context
             .read_public_key(password_context.context)
             .or_else(|e| {
@@ -200,7 +184,7 @@ impl TpmProvider {
             })?;
 
         Ok(psa_export_public_key::Result {
-            data: utils::pub_key_to_bytes(pub_key_data, key_attributes)?,
+            data: utils::pub_key_to_bytes(pub_key_data, key_attributes)?.into(),
         })
     }
 
@@ -210,7 +194,7 @@ impl TpmProvider {
         op: psa_destroy_key::Operation,
     ) -> Result<psa_destroy_key::Result> {
         let key_name = op.key_name;
-        let key_triple = KeyTriple::new(app_name, ProviderID::Tpm, key_name);
+        let key_triple = KeyTriple::new(app_name, ProviderId::Tpm, key_name);
         let mut store_handle = self
             .key_info_store
             .write()
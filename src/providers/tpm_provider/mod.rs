@@ -4,6 +4,11 @@
 //!
 //! Provider allowing clients to use hardware or software TPM 2.0 implementations
 //! for their Parsec operations.
+//!
+//! The asymmetric encryption operations are not supported yet: the `TransientKeyContext` of
+//! tss-esapi 4.0.3-alpha.1 only creates signing keys and does not expose the `RSA_Decrypt`
+//! command. They need a version of tss-esapi creating decryption keys.
+use super::reconciliation::{ReconciliationMode, ReconciliationReport};
 use super::Provide;
 use crate::authenticators::ApplicationName;
 use crate::key_info_managers::ManageKeyInfo;
@@ -14,7 +19,7 @@ use parsec_interface::operations::{
     psa_destroy_key, psa_export_public_key, psa_generate_key, psa_import_key, psa_sign_hash,
     psa_verify_hash,
 };
-use parsec_interface::requests::{Opcode, ProviderID, ResponseStatus, Result};
+use parsec_interface::requests::{Opcode, ProviderId, ResponseStatus, Result};
 use std::collections::HashSet;
 use std::io::ErrorKind;
 use std::sync::{Arc, Mutex, RwLock};
@@ -24,6 +29,7 @@ use uuid::Uuid;
 
 mod asym_sign;
 mod key_management;
+mod reconciliation;
 mod utils;
 
 const SUPPORTED_OPCODES: [Opcode; 6] = [
@@ -82,7 +88,7 @@ impl Provide for TpmProvider {
             version_maj: 0,
             version_min: 1,
             version_rev: 0,
-            id: ProviderID::Tpm,
+            id: ProviderId::Tpm,
         }, SUPPORTED_OPCODES.iter().copied().collect()))
     }
 
@@ -133,6 +139,10 @@ impl Provide for TpmProvider {
     ) -> Result<psa_verify_hash::Result> {
         self.psa_verify_hash_internal(app_name, op)
     }
+
+    fn reconcile(&self, mode: &ReconciliationMode) -> Result<ReconciliationReport> {
+        self.reconcile_internal(mode)
+    }
 }
 
 impl Drop for TpmProvider {
@@ -0,0 +1,86 @@
+// Copyright 2020 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+use super::utils::{self, PasswordContext};
+use super::TpmProvider;
+use crate::key_info_managers::KeyInfo;
+use crate::providers::reconciliation::{self, BackendObject, ManageBackendObjects};
+use crate::providers::reconciliation::{ReconciliationMode, ReconciliationReport};
+use log::{error, info};
+use parsec_interface::requests::{ProviderId, ResponseStatus, Result};
+use std::sync::Mutex;
+use tss_esapi::response_code::{Error, Tss2ResponseCodeKind};
+use tss_esapi::TransientKeyContext;
+
+/// Keys of the TPM provider.
+///
+/// The keys are not stored in the TPM: the key info contains the key context, wrapped by the TPM
+/// with the provider root key. A key exists as long as its context can be loaded again, which
+/// fails if the root key changed, for example after the TPM was cleared. As no key can exist
+/// without its mapping, there are never orphaned objects.
+struct TpmObjects<'a> {
+    esapi_context: &'a Mutex<TransientKeyContext>,
+}
+
+impl ManageBackendObjects for TpmObjects<'_> {
+    fn object_exists(&self, key_info: &KeyInfo) -> Result<bool> {
+        let password_context: PasswordContext = match bincode::deserialize(&key_info.id) {
+            Ok(password_context) => password_context,
+            Err(e) => {
+                error!("Stored key context is not valid ({}).", e);
+                return Ok(false);
+            }
+        };
+        let mut esapi_context = self
+            .esapi_context
+            .lock()
+            .expect("ESAPI Context lock poisoned");
+        match esapi_context.read_public_key(password_context.context) {
+            Ok(_) => Ok(true),
+            // The TPM refuses to load a context which was not wrapped by the current root key.
+            Err(Error::Tss2Error(e))
+                if matches!(
+                    e.kind(),
+                    Some(Tss2ResponseCodeKind::Integrity)
+                        | Some(Tss2ResponseCodeKind::Hierarchy)
+                        | Some(Tss2ResponseCodeKind::Handle)
+                ) =>
+            {
+                Ok(false)
+            }
+            Err(e) => {
+                error!("Error loading a key context: {}.", e);
+                Err(utils::to_response_status(e))
+            }
+        }
+    }
+
+    fn list_objects(&self) -> Result<Vec<BackendObject>> {
+        Ok(Vec::new())
+    }
+
+    fn destroy_object(&self, _id: &[u8]) -> Result<()> {
+        Err(ResponseStatus::PsaErrorNotSupported)
+    }
+}
+
+impl TpmProvider {
+    pub(super) fn reconcile_internal(
+        &self,
+        mode: &ReconciliationMode,
+    ) -> Result<ReconciliationReport> {
+        info!("TPM Provider - Reconcile");
+        let mut store_handle = self
+            .key_info_store
+            .write()
+            .expect("Key store lock poisoned");
+
+        reconciliation::reconcile(
+            &TpmObjects {
+                esapi_context: &self.esapi_context,
+            },
+            ProviderId::Tpm,
+            &mut *store_handle,
+            mode,
+        )
+    }
+}
@@ -118,6 +118,9 @@ fn convert_asym_scheme_to_tpm(algorithm: Algorithm) -> Result<AsymSchemeUnion> {
         Algorithm::AsymmetricSignature(AsymmetricSignature::RsaPkcs1v15Sign {
             hash_alg: SignHash::Specific(hash_alg),
         }) => Ok(AsymSchemeUnion::RSASSA(convert_hash_to_tpm(hash_alg)?)),
+        Algorithm::AsymmetricSignature(AsymmetricSignature::RsaPss {
+            hash_alg: SignHash::Specific(hash_alg),
+        }) => Ok(AsymSchemeUnion::RSAPSS(convert_hash_to_tpm(hash_alg)?)),
         Algorithm::AsymmetricSignature(AsymmetricSignature::Ecdsa {
             hash_alg: SignHash::Specific(hash_alg),
         }) => Ok(AsymSchemeUnion::ECDSA(convert_hash_to_tpm(hash_alg)?)),
@@ -5,27 +5,37 @@
 //! The service builder is required to bootstrap all the components based on a
 //! provided configuration.
 use crate::authenticators::direct_authenticator::DirectAuthenticator;
+use crate::authenticators::jwt_svid_authenticator::JwtSvidAuthenticatorBuilder;
+use crate::authenticators::unix_peer_credentials_authenticator::UnixPeerCredentialsAuthenticator;
+use crate::authenticators::{Admin, AdminList, Authenticate, AuthenticatorConfig};
 use crate::back::{
     backend_handler::{BackEndHandler, BackEndHandlerBuilder},
     dispatcher::DispatcherBuilder,
 };
+use crate::front::access_control::{
+    AccessControlConfig, AccessPolicy, AccessPolicyBuilder, Decision,
+};
 use crate::front::listener::{ListenerConfig, ListenerType};
 use crate::front::{
     domain_socket::DomainSocketListenerBuilder, front_end::FrontEndHandler,
     front_end::FrontEndHandlerBuilder, listener::Listen,
 };
+use crate::key_info_managers::encryption::KeyEncryptionKey;
+use crate::key_info_managers::memory_manager::MemoryKeyInfoManager;
 use crate::key_info_managers::on_disk_manager::{
     OnDiskKeyInfoManagerBuilder, DEFAULT_MAPPINGS_PATH,
 };
+use crate::key_info_managers::sqlite_manager::{SqliteKeyInfoManagerBuilder, DEFAULT_DB_PATH};
 use crate::key_info_managers::{KeyInfoManagerConfig, KeyInfoManagerType, ManageKeyInfo};
 use crate::providers::{core_provider::CoreProviderBuilder, Provide, ProviderConfig};
 use log::{error, warn, LevelFilter};
 use parsec_interface::operations_protobuf::ProtobufConverter;
 use parsec_interface::requests::AuthType;
-use parsec_interface::requests::{BodyType, ProviderID};
+use parsec_interface::requests::{BodyType, ProviderId};
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::io::{Error, ErrorKind, Result};
+use std::os::unix::net::UnixStream;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::RwLock;
@@ -51,16 +61,29 @@ const WIRE_PROTOCOL_VERSION_MAJOR: u8 = 1;
 /// Default value for the limit on the request body size (in bytes) - equal to 1MB
 const DEFAULT_BODY_LEN_LIMIT: usize = 1 << 19;
 
+/// Default value for the maximum number of requests served on a single connection
+const DEFAULT_MAX_REQUESTS_PER_CONNECTION: usize = 100;
+
+/// Default value for the time (in milliseconds) a connection can stay idle between two requests
+const DEFAULT_CONNECTION_IDLE_TIMEOUT: u64 = 1000;
+
+/// Default value for the maximum number of connections being handled or queued at the same time
+const DEFAULT_MAX_CONNECTIONS: usize = 512;
+
 type KeyInfoManager = Arc<RwLock<dyn ManageKeyInfo + Send + Sync>>;
-type Provider = Box<dyn Provide + Send + Sync>;
+type Provider = Arc<dyn Provide + Send + Sync>;
+type Authenticator = Box<dyn Authenticate + Send + Sync>;
 
-#[derive(Copy, Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Debug)]
 pub struct CoreSettings {
     pub thread_pool_size: Option<usize>,
-    pub idle_listener_sleep_duration: Option<u64>,
     pub log_level: Option<LevelFilter>,
     pub log_timestamp: Option<bool>,
     pub body_len_limit: Option<usize>,
+    pub max_requests_per_connection: Option<usize>,
+    pub connection_idle_timeout: Option<u64>,
+    pub max_connections: Option<usize>,
+    pub admins: Option<Vec<Admin>>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -69,6 +92,8 @@ pub struct ServiceConfig {
     pub listener: ListenerConfig,
     pub key_manager: Option<Vec<KeyInfoManagerConfig>>,
     pub provider: Option<Vec<ProviderConfig>>,
+    pub authenticator: Option<Vec<AuthenticatorConfig>>,
+    pub access_control: Option<AccessControlConfig>,
 }
 
 /// Service component builder and assembler
@@ -89,6 +114,10 @@ impl ServiceBuilder {
     /// requested for a certain provider does not exist) or if required fields are missing, an error of kind
     /// `InvalidData` is returned with a string describing the cause more accurately.
     pub fn build_service(config: &ServiceConfig) -> Result<FrontEndHandler> {
+        check_key_info_manager_types(
+            config.key_manager.as_ref().unwrap_or(&Vec::new()),
+            config.provider.as_ref().unwrap_or(&Vec::new()),
+        )?;
         let key_info_managers =
             build_key_info_managers(config.key_manager.as_ref().unwrap_or(&Vec::new()))?;
 
@@ -102,37 +131,145 @@ impl ServiceBuilder {
             return Err(Error::new(ErrorKind::InvalidData, "need one provider"));
         }
 
-        let backend_handlers = build_backend_handlers(providers)?;
+        let authenticators =
+            build_authenticators(config.authenticator.as_ref().unwrap_or(&Vec::new()))?;
+
+        if authenticators.is_empty() {
+            error!("Parsec needs at least one authenticator to start. No authenticator was found in the configuration.");
+            return Err(Error::new(ErrorKind::InvalidData, "need one authenticator"));
+        }
+
+        let admin_list = AdminList::from(config.core_settings.admins.clone().unwrap_or_default());
+
+        let backend_handlers = build_backend_handlers(providers, &authenticators, admin_list)?;
 
         let dispatcher = DispatcherBuilder::new()
             .with_backends(backend_handlers)
             .build()?;
 
-        let direct_authenticator = Box::from(DirectAuthenticator {});
+        let access_policy = build_access_policy(config.access_control.as_ref())?;
+
+        let mut front_end_handler_builder = FrontEndHandlerBuilder::new();
+        for (auth_type, authenticator) in authenticators {
+            front_end_handler_builder =
+                front_end_handler_builder.with_authenticator(auth_type, authenticator);
+        }
 
-        Ok(FrontEndHandlerBuilder::new()
+        Ok(front_end_handler_builder
             .with_dispatcher(dispatcher)
-            .with_authenticator(AuthType::Direct, direct_authenticator)
+            .with_access_policy(access_policy)
             .with_body_len_limit(
                 config
                     .core_settings
                     .body_len_limit
                     .unwrap_or(DEFAULT_BODY_LEN_LIMIT),
             )
+            .with_max_requests_per_connection(
+                config
+                    .core_settings
+                    .max_requests_per_connection
+                    .unwrap_or(DEFAULT_MAX_REQUESTS_PER_CONNECTION),
+            )
+            .with_connection_idle_timeout(Duration::from_millis(
+                config
+                    .core_settings
+                    .connection_idle_timeout
+                    .unwrap_or(DEFAULT_CONNECTION_IDLE_TIMEOUT),
+            ))
             .build()?)
     }
 
+    /// Build the key info managers and the providers described in the configuration, without the
+    /// rest of the service. Used by the maintenance commands run while the service is stopped.
+    ///
+    /// # Errors
+    /// * if a key info manager can not be created, an error is returned. Providers which can not be
+    /// created are logged and left out, as when building the service.
+    pub fn build_providers(config: &ServiceConfig) -> Result<HashMap<ProviderId, Provider>> {
+        check_key_info_manager_types(
+            config.key_manager.as_ref().unwrap_or(&Vec::new()),
+            config.provider.as_ref().unwrap_or(&Vec::new()),
+        )?;
+        let key_info_managers =
+            build_key_info_managers(config.key_manager.as_ref().unwrap_or(&Vec::new()))?;
+
+        Ok(build_providers(
+            config.provider.as_ref().unwrap_or(&Vec::new()),
+            key_info_managers,
+        )
+        .into_iter()
+        .map(|(provider_id, (provider, _))| (provider_id, provider))
+        .collect())
+    }
+
+    /// Build the key info managers described in the configuration, indexed by the providers
+    /// using them, without building the providers. Used by the maintenance commands acting on the
+    /// key mappings only, while the service is stopped.
+    ///
+    /// # Errors
+    /// * if a key info manager can not be created or if a provider uses a key info manager which
+    ///   does not exist, an error is returned.
+    pub fn build_provider_key_info_managers(
+        config: &ServiceConfig,
+    ) -> Result<HashMap<ProviderId, KeyInfoManager>> {
+        let key_info_managers =
+            build_key_info_managers(config.key_manager.as_ref().unwrap_or(&Vec::new()))?;
+
+        let mut map = HashMap::new();
+        for provider_config in config.provider.as_ref().unwrap_or(&Vec::new()) {
+            let key_info_manager = key_info_managers
+                .get(provider_config.key_info_manager())
+                .ok_or_else(|| {
+                    error!(
+                        "Key info manager with specified name was not found ({})",
+                        provider_config.key_info_manager()
+                    );
+                    Error::new(ErrorKind::InvalidData, "key info manager not found")
+                })?;
+            let _ = map.insert(provider_config.provider_id(), key_info_manager.clone());
+        }
+
+        Ok(map)
+    }
+
     /// Construct the service IPC front component and return ownership to it.
-    pub fn start_listener(config: ListenerConfig) -> Result<Box<dyn Listen>> {
+    ///
+    /// The listener blocks waiting for connections and is woken up when `wake_up` becomes
+    /// readable.
+    pub fn start_listener(config: &ListenerConfig, wake_up: UnixStream) -> Result<Box<dyn Listen>> {
         let listener = match config.listener_type {
-            ListenerType::DomainSocket => DomainSocketListenerBuilder::new()
-                .with_timeout(Duration::from_millis(config.timeout))
-                .build(),
+            ListenerType::DomainSocket => {
+                let mut builder = DomainSocketListenerBuilder::new()
+                    .with_timeout(Duration::from_millis(config.timeout))
+                    .with_wake_up(wake_up);
+                if let Some(socket_path) = &config.socket_path {
+                    builder = builder.with_socket_path(PathBuf::from(socket_path));
+                }
+                if let Some(socket_mode) = config.socket_mode {
+                    builder = builder.with_socket_mode(socket_mode);
+                }
+                if let Some(socket_group) = &config.socket_group {
+                    builder = builder.with_socket_group(socket_group.clone());
+                }
+                if let Some(admin_socket_path) = &config.admin_socket_path {
+                    builder = builder.with_admin_socket_path(PathBuf::from(admin_socket_path));
+                }
+                builder.build()
+            }
         }?;
 
         Ok(Box::new(listener))
     }
 
+    /// Maximum number of connections being handled or queued at the same time, as set in the
+    /// configuration or by default.
+    pub fn max_connections(config: &ServiceConfig) -> usize {
+        config
+            .core_settings
+            .max_connections
+            .unwrap_or(DEFAULT_MAX_CONNECTIONS)
+    }
+
     /// Construct the thread pool that will be used to process all service requests.
     pub fn build_threadpool(num_threads: Option<usize>) -> ThreadPool {
         let mut threadpool_builder = ThreadPoolBuilder::new();
@@ -144,21 +281,33 @@ impl ServiceBuilder {
 }
 
 fn build_backend_handlers(
-    mut providers: HashMap<ProviderID, Provider>,
-) -> Result<HashMap<ProviderID, BackEndHandler>> {
+    mut providers: HashMap<ProviderId, (Provider, KeyInfoManager)>,
+    authenticators: &[(AuthType, Authenticator)],
+    admin_list: AdminList,
+) -> Result<HashMap<ProviderId, BackEndHandler>> {
     let mut map = HashMap::new();
 
     let mut core_provider_builder = CoreProviderBuilder::new()?
-        .with_wire_protocol_version(WIRE_PROTOCOL_VERSION_MINOR, WIRE_PROTOCOL_VERSION_MAJOR);
+        .with_wire_protocol_version(WIRE_PROTOCOL_VERSION_MINOR, WIRE_PROTOCOL_VERSION_MAJOR)
+        .with_admin_list(admin_list.clone());
+
+    for (_auth_type, authenticator) in authenticators {
+        let authenticator_info = authenticator
+            .describe()
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "error describing authenticator"))?;
+        core_provider_builder = core_provider_builder.with_authenticator_info(authenticator_info);
+    }
 
-    for (provider_id, provider) in providers.drain() {
+    for (provider_id, (provider, key_info_manager)) in providers.drain() {
         let (info, opcodes) = provider.describe().or_else(|_| {
             Err(Error::new(
                 ErrorKind::InvalidData,
                 "error describing provider",
             ))
         })?;
-        core_provider_builder = core_provider_builder.with_provider_details(info, opcodes);
+        core_provider_builder = core_provider_builder
+            .with_provider_details(info, opcodes)
+            .with_provider(provider_id, provider.clone(), key_info_manager);
 
         let backend_handler = BackEndHandlerBuilder::new()
             .with_provider(provider)
@@ -166,19 +315,21 @@ fn build_backend_handlers(
             .with_provider_id(provider_id)
             .with_content_type(BodyType::Protobuf)
             .with_accept_type(BodyType::Protobuf)
+            .with_admin_list(admin_list.clone())
             .build()?;
         let _ = map.insert(provider_id, backend_handler);
     }
 
     let core_provider_backend = BackEndHandlerBuilder::new()
-        .with_provider(Box::from(core_provider_builder.build()?))
+        .with_provider(Arc::from(core_provider_builder.build()?))
         .with_converter(Box::from(ProtobufConverter {}))
-        .with_provider_id(ProviderID::Core)
+        .with_provider_id(ProviderId::Core)
         .with_content_type(BodyType::Protobuf)
         .with_accept_type(BodyType::Protobuf)
+        .with_admin_list(admin_list)
         .build()?;
 
-    let _ = map.insert(ProviderID::Core, core_provider_backend);
+    let _ = map.insert(ProviderId::Core, core_provider_backend);
 
     Ok(map)
 }
@@ -186,7 +337,7 @@ fn build_backend_handlers(
 fn build_providers(
     configs: &[ProviderConfig],
     key_info_managers: HashMap<String, KeyInfoManager>,
-) -> HashMap<ProviderID, Provider> {
+) -> HashMap<ProviderId, (Provider, KeyInfoManager)> {
     let mut map = HashMap::new();
     for config in configs {
         let provider_id = config.provider_id();
@@ -213,7 +364,7 @@ fn build_providers(
                 continue;
             }
         };
-        let _ = map.insert(provider_id, provider);
+        let _ = map.insert(provider_id, (provider, key_info_manager.clone()));
     }
 
     map
@@ -238,7 +389,7 @@ unsafe fn get_provider(
         #[cfg(feature = "mbed-crypto-provider")]
         ProviderConfig::MbedCrypto { .. } => {
             info!("Creating a Mbed Crypto Provider.");
-            Ok(Box::from(
+            Ok(Arc::from(
                 MbedProviderBuilder::new()
                     .with_key_info_store(key_info_manager)
                     .build()?,
@@ -252,7 +403,7 @@ unsafe fn get_provider(
             ..
         } => {
             info!("Creating a PKCS 11 Provider.");
-            Ok(Box::from(
+            Ok(Arc::from(
                 Pkcs11ProviderBuilder::new()
                     .with_key_info_store(key_info_manager)
                     .with_pkcs11_library_path(library_path.clone())
@@ -268,7 +419,7 @@ unsafe fn get_provider(
             ..
         } => {
             info!("Creating a TPM Provider.");
-            Ok(Box::from(
+            Ok(Arc::from(
                 TpmProviderBuilder::new()
                     .with_key_info_store(key_info_manager)
                     .with_tcti(tcti)
@@ -291,6 +442,62 @@ unsafe fn get_provider(
     }
 }
 
+fn build_authenticators(configs: &[AuthenticatorConfig]) -> Result<Vec<(AuthType, Authenticator)>> {
+    let mut authenticators: Vec<(AuthType, Authenticator)> = Vec::new();
+    for config in configs {
+        let auth_type = config.auth_type();
+        if authenticators
+            .iter()
+            .any(|(existing_type, _)| *existing_type == auth_type)
+        {
+            error!(
+                "Authenticator {:?} is defined more than once in the configuration.",
+                auth_type
+            );
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "duplicate authenticator",
+            ));
+        }
+
+        let authenticator: Authenticator = match config {
+            AuthenticatorConfig::Direct => Box::from(DirectAuthenticator {}),
+            AuthenticatorConfig::UnixPeerCredentials { include_gid } => Box::from(
+                UnixPeerCredentialsAuthenticator::new(include_gid.unwrap_or(false)),
+            ),
+            AuthenticatorConfig::JwtSvid {
+                jwks_path,
+                audience,
+                allowed_algorithms,
+            } => {
+                let mut builder = JwtSvidAuthenticatorBuilder::new()
+                    .with_jwks_path(PathBuf::from(jwks_path))
+                    .with_audience(audience.clone());
+                if let Some(allowed_algorithms) = allowed_algorithms {
+                    builder = builder.with_allowed_algorithms(allowed_algorithms.clone());
+                }
+                Box::from(builder.build()?)
+            }
+        };
+        authenticators.push((auth_type, authenticator));
+    }
+
+    Ok(authenticators)
+}
+
+fn build_access_policy(config: Option<&AccessControlConfig>) -> Result<AccessPolicy> {
+    match config {
+        // Without access control configured, all the requests are allowed.
+        None => Ok(AccessPolicy::allow_all()),
+        // If the access control section is present, requests not matched by any rule are denied,
+        // unless another default decision is given.
+        Some(config) => AccessPolicyBuilder::new()
+            .with_default_decision(Decision::Deny)
+            .with_config(config)?
+            .build(),
+    }
+}
+
 fn build_key_info_managers(
     configs: &[KeyInfoManagerConfig],
 ) -> Result<HashMap<String, KeyInfoManager>> {
@@ -302,8 +509,39 @@ fn build_key_info_managers(
     Ok(map)
 }
 
+/// Refuse the Memory key info manager for the providers storing their keys in their backend: the
+/// keys would be left there without a mapping once the service stops. Only the TPM provider, whose
+/// keys are stored in their mappings, can use it.
+fn check_key_info_manager_types(
+    key_info_managers: &[KeyInfoManagerConfig],
+    providers: &[ProviderConfig],
+) -> Result<()> {
+    for provider in providers {
+        if provider.provider_id() == ProviderId::Tpm {
+            continue;
+        }
+        let uses_memory_manager = key_info_managers.iter().any(|key_info_manager| {
+            key_info_manager.name == *provider.key_info_manager()
+                && matches!(key_info_manager.manager_type, KeyInfoManagerType::Memory)
+        });
+        if uses_memory_manager {
+            error!(
+                "Provider {} can not use the Memory key info manager \"{}\": its keys would be left in its backend without a mapping.",
+                provider.provider_id(),
+                provider.key_info_manager()
+            );
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Memory key info manager used by a provider storing keys in its backend",
+            ));
+        }
+    }
+
+    Ok(())
+}
+
 fn get_key_info_manager(config: &KeyInfoManagerConfig) -> Result<KeyInfoManager> {
-    let manager = match config.manager_type {
+    let manager: KeyInfoManager = match config.manager_type {
         KeyInfoManagerType::OnDisk => {
             let store_path = if let Some(store_path) = &config.store_path {
                 store_path.to_owned()
@@ -311,11 +549,101 @@ fn get_key_info_manager(config: &KeyInfoManagerConfig) -> Result<KeyInfoManager>
                 DEFAULT_MAPPINGS_PATH.to_string()
             };
 
-            OnDiskKeyInfoManagerBuilder::new()
-                .with_mappings_dir_path(PathBuf::from(store_path))
-                .build()?
+            let mut builder = OnDiskKeyInfoManagerBuilder::new()
+                .with_mappings_dir_path(PathBuf::from(store_path));
+            if let Some(encryption_key) = &config.encryption_key {
+                builder =
+                    builder.with_key_encryption_key(KeyEncryptionKey::from_config(encryption_key)?);
+            }
+
+            Arc::new(RwLock::new(builder.build()?))
+        }
+        KeyInfoManagerType::Sqlite => {
+            if config.encryption_key.is_some() {
+                error!(
+                    "Key info manager \"{}\": encryption is only supported by the OnDisk manager.",
+                    config.name
+                );
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "encryption not supported by the Sqlite key info manager",
+                ));
+            }
+            let store_path = if let Some(store_path) = &config.store_path {
+                store_path.to_owned()
+            } else {
+                DEFAULT_DB_PATH.to_string()
+            };
+
+            Arc::new(RwLock::new(
+                SqliteKeyInfoManagerBuilder::new()
+                    .with_db_path(PathBuf::from(store_path))
+                    .build()?,
+            ))
+        }
+        KeyInfoManagerType::Memory => {
+            if config.encryption_key.is_some() {
+                error!(
+                    "Key info manager \"{}\": encryption is only supported by the OnDisk manager.",
+                    config.name
+                );
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "encryption not supported by the Memory key info manager",
+                ));
+            }
+            if config.store_path.is_some() {
+                warn!(
+                    "Key info manager \"{}\": the Memory manager does not persist the mappings, the store path is ignored.",
+                    config.name
+                );
+            }
+            warn!(
+                "Key info manager \"{}\" only keeps the mappings in memory, the keys will not be usable after the service restarts.",
+                config.name
+            );
+
+            Arc::new(RwLock::new(MemoryKeyInfoManager::new()))
         }
     };
 
-    Ok(Arc::new(RwLock::new(manager)))
+    Ok(manager)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key_info_manager(name: &str, manager_type: KeyInfoManagerType) -> KeyInfoManagerConfig {
+        KeyInfoManagerConfig {
+            name: name.to_string(),
+            manager_type,
+            store_path: None,
+            encryption_key: None,
+        }
+    }
+
+    #[test]
+    fn memory_manager_refused_for_backend_keys() {
+        let key_info_managers = vec![
+            key_info_manager("memory", KeyInfoManagerType::Memory),
+            key_info_manager("on-disk", KeyInfoManagerType::OnDisk),
+        ];
+        let mbed_crypto = |key_info_manager: &str| ProviderConfig::MbedCrypto {
+            key_info_manager: key_info_manager.to_string(),
+        };
+        let tpm = ProviderConfig::Tpm {
+            key_info_manager: "memory".to_string(),
+            tcti: "mssim".to_string(),
+            owner_hierarchy_auth: String::new(),
+        };
+
+        check_key_info_manager_types(&key_info_managers, &[mbed_crypto("on-disk"), tpm]).unwrap();
+        assert_eq!(
+            check_key_info_manager_types(&key_info_managers, &[mbed_crypto("memory")])
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidData
+        );
+    }
 }
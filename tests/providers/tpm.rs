@@ -8,21 +8,16 @@ use parsec_interface::operations::{
 };
 use parsec_interface::requests::ResponseStatus;
 use parsec_service::authenticators::ApplicationName;
-use parsec_service::key_info_managers::on_disk_manager::OnDiskKeyInfoManagerBuilder;
+use parsec_service::key_info_managers::memory_manager::MemoryKeyInfoManager;
 use parsec_service::providers::tpm_provider::{TpmProvider, TpmProviderBuilder};
 use parsec_service::providers::Provide;
 use ring::digest;
 use ring::signature::{self, UnparsedPublicKey};
-use std::path::PathBuf;
-use std::str::FromStr;
 use std::sync::{Arc, RwLock};
 
 lazy_static! {
     static ref TPM_PROVIDER: TpmProvider = {
-        let kis = OnDiskKeyInfoManagerBuilder::new()
-            .with_mappings_dir_path(PathBuf::from_str("./mappings").unwrap())
-            .build()
-            .unwrap();
+        let kis = MemoryKeyInfoManager::new();
         unsafe {
             TpmProviderBuilder::new()
                 .with_key_info_store(Arc::from(RwLock::from(kis)))
@@ -48,17 +43,10 @@ fn gen_rsa_sign_key_op(key_name: String) -> psa_generate_key::Operation {
             key_type: Type::RsaKeyPair,
             bits: 2048,
             policy: Policy {
-                usage_flags: UsageFlags {
-                    export: true,
-                    copy: false,
-                    cache: false,
-                    encrypt: false,
-                    decrypt: false,
-                    sign_message: true,
-                    sign_hash: true,
-                    verify_message: true,
-                    verify_hash: true,
-                    derive: false,
+                usage_flags: {
+                    let mut usage_flags = UsageFlags::default();
+                    let _ = usage_flags.set_sign_hash().set_verify_hash().set_export();
+                    usage_flags
                 },
                 permitted_algorithms: Algorithm::AsymmetricSignature(
                     AsymmetricSignature::RsaPkcs1v15Sign {
@@ -80,17 +68,10 @@ fn gen_ecc_sign_key_op(key_name: String) -> psa_generate_key::Operation {
             },
             bits: 256,
             policy: Policy {
-                usage_flags: UsageFlags {
-                    export: true,
-                    copy: false,
-                    cache: false,
-                    encrypt: false,
-                    decrypt: false,
-                    sign_message: true,
-                    sign_hash: true,
-                    verify_message: true,
-                    verify_hash: true,
-                    derive: false,
+                usage_flags: {
+                    let mut usage_flags = UsageFlags::default();
+                    let _ = usage_flags.set_sign_hash().set_verify_hash().set_export();
+                    usage_flags
                 },
                 permitted_algorithms: Algorithm::AsymmetricSignature(AsymmetricSignature::Ecdsa {
                     hash_alg: Hash::Sha256.into(),
@@ -116,7 +97,7 @@ fn verify_with_ring() {
                 alg: AsymmetricSignature::RsaPkcs1v15Sign {
                     hash_alg: Hash::Sha256.into(),
                 },
-                hash: HASH.clone(),
+                hash: HASH.clone().into(),
             },
         )
         .unwrap();
@@ -124,7 +105,7 @@ fn verify_with_ring() {
     let psa_export_public_key::Result { data } = TPM_PROVIDER
         .psa_export_public_key(app_name, psa_export_public_key::Operation { key_name })
         .unwrap();
-    let pk = UnparsedPublicKey::new(&signature::RSA_PKCS1_2048_8192_SHA256, data);
+    let pk = UnparsedPublicKey::new(&signature::RSA_PKCS1_2048_8192_SHA256, data.to_vec());
     pk.verify(&MESSAGE, &sign).unwrap();
 }
 
@@ -144,7 +125,7 @@ fn verify_ecc_with_ring() {
                 alg: AsymmetricSignature::Ecdsa {
                     hash_alg: Hash::Sha256.into(),
                 },
-                hash: HASH.clone(),
+                hash: HASH.clone().into(),
             },
         )
         .unwrap();
@@ -152,7 +133,7 @@ fn verify_ecc_with_ring() {
     let psa_export_public_key::Result { data } = TPM_PROVIDER
         .psa_export_public_key(app_name, psa_export_public_key::Operation { key_name })
         .unwrap();
-    let pk = UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, data);
+    let pk = UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, data.to_vec());
     pk.verify(&MESSAGE, &sign).unwrap();
 }
 
@@ -172,7 +153,7 @@ fn sign_verify_ecc() {
                 alg: AsymmetricSignature::Ecdsa {
                     hash_alg: Hash::Sha256.into(),
                 },
-                hash: HASH.clone(),
+                hash: HASH.clone().into(),
             },
         )
         .unwrap();
@@ -185,7 +166,42 @@ fn sign_verify_ecc() {
                 alg: AsymmetricSignature::Ecdsa {
                     hash_alg: Hash::Sha256.into(),
                 },
-                hash: HASH.clone(),
+                hash: HASH.clone().into(),
+                signature: sign,
+            },
+        )
+        .unwrap();
+}
+
+#[test]
+fn sign_verify_rsa_pss() {
+    let key_name = String::from("key_name");
+    let app_name = ApplicationName::new(String::from("sign_verify_rsa_pss"));
+    let alg = AsymmetricSignature::RsaPss {
+        hash_alg: Hash::Sha256.into(),
+    };
+    let mut op = gen_rsa_sign_key_op(key_name.clone());
+    op.attributes.policy.permitted_algorithms = Algorithm::AsymmetricSignature(alg);
+    let _ = TPM_PROVIDER.psa_generate_key(app_name.clone(), op).unwrap();
+
+    let psa_sign_hash::Result { signature: sign } = TPM_PROVIDER
+        .psa_sign_hash(
+            app_name.clone(),
+            psa_sign_hash::Operation {
+                key_name: key_name.clone(),
+                alg,
+                hash: HASH.clone().into(),
+            },
+        )
+        .unwrap();
+
+    let _ = TPM_PROVIDER
+        .psa_verify_hash(
+            app_name,
+            psa_verify_hash::Operation {
+                key_name,
+                alg,
+                hash: HASH.clone().into(),
                 signature: sign,
             },
         )